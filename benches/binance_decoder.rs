@@ -0,0 +1,40 @@
+//! Binance frame decoding throughput
+//!
+//! Decodes the recorded frames in `tests/fixtures/binance/frames.jsonl` with the
+//! `serde_json::Value` parser and with `BinanceDecoder`. Run with
+//! `cargo bench --bench binance_decoder`.
+
+use chrono::Utc;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use hft_trading_system::data::{binance::BinanceDecoder, BinanceMarketDataStream};
+
+const FRAMES: &str = include_str!("../tests/fixtures/binance/frames.jsonl");
+
+fn decode(c: &mut Criterion) {
+    let frames: Vec<&str> = FRAMES.lines().collect();
+    let mut group = c.benchmark_group("binance_decode");
+    group.throughput(Throughput::Bytes(FRAMES.len() as u64));
+
+    group.bench_function("value", |b| {
+        b.iter(|| {
+            for frame in &frames {
+                black_box(BinanceMarketDataStream::parse_websocket_message(black_box(frame)).ok());
+            }
+        })
+    });
+
+    group.bench_function("typed", |b| {
+        let mut decoder = BinanceDecoder::new();
+        let receipt_time = Utc::now();
+        b.iter(|| {
+            for frame in &frames {
+                black_box(decoder.decode(black_box(frame), receipt_time).ok());
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
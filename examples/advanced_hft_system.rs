@@ -5,7 +5,7 @@
 
 use hft_trading_system::{
     Engine,
-    data::{MarketEvent, MarketDataKind, PublicTrade, OrderBookL1, InstrumentId, ExchangeId, Side},
+    data::{MarketEvent, MarketDataKind, PublicTrade, OrderBookL1, InstrumentId, InstrumentKind, ExchangeId, Side, SubscriptionKind},
     execution::{MockExecutionClient, ExecutionClient},
     strategy::DefaultStrategy,
     risk::{DefaultRiskManager, RiskLimits},
@@ -96,6 +96,7 @@ fn create_system_config() -> SystemConfig {
                     base: "BTC".to_string(),
                     quote: "USDT".to_string(),
                     exchange_symbol: "BTCUSDT".to_string(),
+                    kind: InstrumentKind::Spot,
                 },
                 enabled: true,
                 base_currency: "BTC".to_string(),
@@ -109,6 +110,7 @@ fn create_system_config() -> SystemConfig {
                     base: "ETH".to_string(),
                     quote: "USDT".to_string(),
                     exchange_symbol: "ETHUSDT".to_string(),
+                    kind: InstrumentKind::Spot,
                 },
                 enabled: true,
                 base_currency: "ETH".to_string(),
@@ -126,7 +128,8 @@ fn create_system_config() -> SystemConfig {
         },
         data: DataConfig {
             enable_market_data: true,
-            market_data_types: vec!["trades".to_string(), "orderbook_l1".to_string()],
+            subscriptions: vec![SubscriptionKind::Trades, SubscriptionKind::L1],
+            instrument_subscriptions: vec![],
             update_frequency_ms: 100,
             enable_historical_data: false,
         },
@@ -140,11 +143,13 @@ fn create_sample_instruments() -> Vec<InstrumentId> {
             base: "BTC".to_string(),
             quote: "USDT".to_string(),
             exchange_symbol: "BTCUSDT".to_string(),
+            kind: InstrumentKind::Spot,
         },
         InstrumentId {
             base: "ETH".to_string(),
             quote: "USDT".to_string(),
             exchange_symbol: "ETHUSDT".to_string(),
+            kind: InstrumentKind::Spot,
         },
     ]
 }
@@ -181,6 +186,7 @@ fn create_sample_market_data(instruments: &[InstrumentId]) -> Vec<MarketEvent> {
                 ask_price: Decimal::from_str_exact(&(50010.0 + (i as f64 * 1000.0)).to_string()).unwrap(),
                 ask_quantity: Decimal::from_str_exact("1.0").unwrap(),
                 timestamp: Utc::now(),
+                update_id: None,
             }),
             exchange_time: Utc::now(),
             receipt_time: Utc::now(),
@@ -4,7 +4,7 @@
 //! showcasing live market data capabilities.
 
 use hft_trading_system::{
-    data::{InstrumentId},
+    data::{InstrumentId, InstrumentKind},
 };
 use tokio::time::Duration;
 
@@ -21,6 +21,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         base: "BTC".to_string(),
         quote: "USDT".to_string(),
         exchange_symbol: "BTCUSDT".to_string(),
+        kind: InstrumentKind::Spot,
     };
     
     println!("📈 Initializing BTC/USDT data stream...");
@@ -76,6 +77,7 @@ async fn show_simulated_data() {
         base: "BTC".to_string(),
         quote: "USDT".to_string(),
         exchange_symbol: "BTCUSDT".to_string(),
+        kind: InstrumentKind::Spot,
     };
     
     let sample_events = vec![
@@ -101,6 +103,7 @@ async fn show_simulated_data() {
                 ask_price: Decimal::from_str("67521.80").unwrap(),
                 ask_quantity: Decimal::from_str("0.80").unwrap(),
                 timestamp: Utc::now(),
+                update_id: None,
             }),
             exchange_time: Utc::now(),
             receipt_time: Utc::now(),
@@ -127,6 +130,7 @@ async fn show_simulated_data() {
                 ask_price: Decimal::from_str("67523.20").unwrap(),
                 ask_quantity: Decimal::from_str("1.50").unwrap(),
                 timestamp: Utc::now(),
+                update_id: None,
             }),
             exchange_time: Utc::now(),
             receipt_time: Utc::now(),
@@ -5,7 +5,7 @@
 
 use hft_trading_system::{
     Engine,
-    data::{MarketEvent, MarketDataKind, PublicTrade, OrderBookL1, InstrumentId, ExchangeId, Side},
+    data::{MarketEvent, MarketDataKind, PublicTrade, OrderBookL1, InstrumentId, InstrumentKind, ExchangeId, Side},
     execution::{MockExecutionClient, ExecutionClient},
     strategy::DefaultStrategy,
     risk::DefaultRiskManager,
@@ -38,12 +38,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         base: "BTC".to_string(),
         quote: "USDT".to_string(),
         exchange_symbol: "BTCUSDT".to_string(),
+        kind: InstrumentKind::Spot,
     };
     
     let eth_instrument = InstrumentId {
         base: "ETH".to_string(),
         quote: "USDT".to_string(),
         exchange_symbol: "ETHUSDT".to_string(),
+        kind: InstrumentKind::Spot,
     };
     
     // Create sample market events
@@ -86,6 +88,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 ask_price: Decimal::from_str_exact("50201.25").unwrap(),
                 ask_quantity: Decimal::from_str_exact("1.15").unwrap(),
                 timestamp: Utc::now(),
+                update_id: None,
             }),
             exchange_time: Utc::now(),
             receipt_time: Utc::now(),
@@ -100,6 +103,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 ask_price: Decimal::from_str_exact("2861.00").unwrap(),
                 ask_quantity: Decimal::from_str_exact("7.8").unwrap(),
                 timestamp: Utc::now(),
+                update_id: None,
             }),
             exchange_time: Utc::now(),
             receipt_time: Utc::now(),
@@ -159,6 +163,16 @@ fn get_event_type(event: &MarketEvent) -> &'static str {
         MarketDataKind::OrderBookL1(_) => "OrderBook L1",
         MarketDataKind::OrderBookL2(_) => "OrderBook L2",
         MarketDataKind::Candle(_) => "Candle",
+        MarketDataKind::OrderBookDelta(_) => "OrderBook Delta",
+        MarketDataKind::OrderBookL3(_) => "OrderBook L3",
+        MarketDataKind::ConsolidatedQuote(_) => "Consolidated Quote",
+        MarketDataKind::Ticker(_) => "Ticker",
+        MarketDataKind::DataQuality(_) => "Data Quality",
+        MarketDataKind::FundingRate(_) => "Funding Rate",
+        MarketDataKind::MarkPrice(_) => "Mark Price",
+        MarketDataKind::OpenInterest(_) => "Open Interest",
+        MarketDataKind::Liquidation(_) => "Liquidation",
+        MarketDataKind::BookFeatures(_) => "Book Features",
     }
 }
 
@@ -4,7 +4,7 @@
 
 use hft_trading_system::{
     Engine,
-    data::{MarketEvent, MarketDataKind, PublicTrade, InstrumentId, ExchangeId, Side},
+    data::{MarketEvent, MarketDataKind, PublicTrade, InstrumentId, InstrumentKind, ExchangeId, Side},
     execution::MockExecutionClient,
     strategy::DefaultStrategy,
     risk::DefaultRiskManager,
@@ -37,6 +37,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         base: "BTC".to_string(),
         quote: "USDT".to_string(),
         exchange_symbol: "BTCUSDT".to_string(),
+        kind: InstrumentKind::Spot,
     };
     
     println!("🔧 Trading engine initialized for benchmarking");
@@ -5,7 +5,7 @@
 
 use hft_trading_system::{
     Engine,
-    data::{MarketEvent, InstrumentId, ExchangeId, BinanceMarketDataStream, MarketDataStream},
+    data::{MarketEvent, InstrumentId, InstrumentKind, ExchangeId, BinanceMarketDataStream, MarketDataStream},
     execution::{MockExecutionClient, ExecutionClient},
     strategy::DefaultStrategy,
     risk::DefaultRiskManager,
@@ -55,6 +55,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         base: "BTC".to_string(),
         quote: "USDT".to_string(),
         exchange_symbol: "BTCUSDT".to_string(),
+        kind: InstrumentKind::Spot,
     };
     println!("📈 Configuring real-time BTC/USDT data feed...");
     println!("   └── Instrument: BTC/USDT (BTCUSDT)");
@@ -67,7 +68,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut market_stream = BinanceMarketDataStream::new();
     
     // Subscribe to BTC/USDT market data
-    match market_stream.subscribe(&[btc_usdt.clone()]).await {
+    match market_stream.subscribe(std::slice::from_ref(&btc_usdt)).await {
         Ok(_) => {
             println!("   └── Successfully subscribed to BTC/USDT real-time data");
         }
@@ -130,7 +131,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     println!("   📤 Order {} sent: {}", j + 1, report.client_order_id);
                                     
                                     // Simulate order fill (50% chance)
-                                    if event_count % 2 == 0 {
+                                    if event_count.is_multiple_of(2) {
                                         println!("   💰 Order {} filled: {}", j + 1, report.client_order_id);
                                     }
                                 },
@@ -216,6 +217,7 @@ async fn simulate_btc_data(engine: &mut hft_trading_system::engine::Engine<Defau
         base: "BTC".to_string(),
         quote: "USDT".to_string(),
         exchange_symbol: "BTCUSDT".to_string(),
+        kind: InstrumentKind::Spot,
     };
     
     let sample_events = vec![
@@ -241,6 +243,7 @@ async fn simulate_btc_data(engine: &mut hft_trading_system::engine::Engine<Defau
                 ask_price: Decimal::from_str("67521.80")?,
                 ask_quantity: Decimal::from_str("0.80")?,
                 timestamp: Utc::now(),
+                update_id: None,
             }),
             exchange_time: Utc::now(),
             receipt_time: Utc::now(),
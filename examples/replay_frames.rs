@@ -0,0 +1,49 @@
+//! Raw Frame Replay
+//!
+//! Replays a raw frame capture, written by `WebSocketMarketDataStream::with_frame_capture`,
+//! through the Binance decoder with the original receipt times and reports every frame
+//! that fails to decode.
+//!
+//! Usage: cargo run --example replay_frames -- <capture> [spot|futures]
+
+use hft_trading_system::data::{
+    binance::{BinanceConnector, BinanceFuturesConnector},
+    frames::{replay_frames, FrameReplay},
+    FrameCaptureReader,
+};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let Some(path) = args.next() else {
+        eprintln!("usage: replay_frames <capture> [spot|futures]");
+        std::process::exit(2);
+    };
+    let reader = FrameCaptureReader::open(&path)?;
+
+    let replay: FrameReplay = match args.next().as_deref() {
+        None | Some("spot") => replay_frames(&mut BinanceConnector::new(), reader)?,
+        Some("futures") => replay_frames(&mut BinanceFuturesConnector::new(), reader)?,
+        Some(other) => {
+            eprintln!("unknown market {}, expected spot or futures", other);
+            std::process::exit(2);
+        }
+    };
+
+    println!("frames:        {}", replay.frames);
+    println!("binary frames: {}", replay.binary_frames);
+    println!("events:        {}", replay.events.len());
+    println!("failures:      {}", replay.failures.len());
+    for failure in &replay.failures {
+        println!();
+        println!(
+            "#{} at {}: {}",
+            failure.index, failure.receipt_time, failure.error
+        );
+        println!("{}", failure.frame);
+    }
+
+    if !replay.failures.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -5,7 +5,7 @@
 
 use hft_trading_system::{
     Engine,
-    data::{MarketEvent, MarketDataKind, PublicTrade, OrderBookL1, InstrumentId, ExchangeId, Side},
+    data::{MarketEvent, MarketDataKind, PublicTrade, OrderBookL1, InstrumentId, InstrumentKind, ExchangeId, Side, SubscriptionKind},
     execution::{MockExecutionClient, ExecutionClient},
     strategy::DefaultStrategy,
     risk::{DefaultRiskManager, RiskLimits},
@@ -183,6 +183,7 @@ fn create_system_config() -> SystemConfig {
                     base: "BTC".to_string(),
                     quote: "USDT".to_string(),
                     exchange_symbol: "BTCUSDT".to_string(),
+                    kind: InstrumentKind::Spot,
                 },
                 enabled: true,
                 base_currency: "BTC".to_string(),
@@ -196,6 +197,7 @@ fn create_system_config() -> SystemConfig {
                     base: "ETH".to_string(),
                     quote: "USDT".to_string(),
                     exchange_symbol: "ETHUSDT".to_string(),
+                    kind: InstrumentKind::Spot,
                 },
                 enabled: true,
                 base_currency: "ETH".to_string(),
@@ -213,7 +215,8 @@ fn create_system_config() -> SystemConfig {
         },
         data: DataConfig {
             enable_market_data: true,
-            market_data_types: vec!["trades".to_string(), "orderbook_l1".to_string()],
+            subscriptions: vec![SubscriptionKind::Trades, SubscriptionKind::L1],
+            instrument_subscriptions: vec![],
             update_frequency_ms: 50,
             enable_historical_data: false,
         },
@@ -227,107 +230,106 @@ fn create_sample_instruments() -> Vec<InstrumentId> {
             base: "BTC".to_string(),
             quote: "USDT".to_string(),
             exchange_symbol: "BTCUSDT".to_string(),
+            kind: InstrumentKind::Spot,
         },
         InstrumentId {
             base: "ETH".to_string(),
             quote: "USDT".to_string(),
             exchange_symbol: "ETHUSDT".to_string(),
+            kind: InstrumentKind::Spot,
         },
     ]
 }
 
 /// Create demonstration market data
 fn create_demonstration_market_data(instruments: &[InstrumentId]) -> Vec<MarketEvent> {
-    let mut events = Vec::new();
-    
-    // Create trade events
-    events.push(MarketEvent {
-        exchange: ExchangeId::Binance,
-        instrument: instruments[0].clone(),
-        kind: MarketDataKind::Trade(PublicTrade {
-            id: "trade_001".to_string(),
-            price: Decimal::from_str_exact("50125.50").unwrap(),
-            quantity: Decimal::from_str_exact("0.15").unwrap(),
-            side: Side::Buy,
-            timestamp: Utc::now(),
-        }),
-        exchange_time: Utc::now(),
-        receipt_time: Utc::now(),
-    });
-    
-    events.push(MarketEvent {
-        exchange: ExchangeId::Binance,
-        instrument: instruments[1].clone(),
-        kind: MarketDataKind::Trade(PublicTrade {
-            id: "trade_002".to_string(),
-            price: Decimal::from_str_exact("2850.75").unwrap(),
-            quantity: Decimal::from_str_exact("2.3").unwrap(),
-            side: Side::Sell,
-            timestamp: Utc::now(),
-        }),
-        exchange_time: Utc::now(),
-        receipt_time: Utc::now(),
-    });
-    
-    // Create order book events
-    events.push(MarketEvent {
-        exchange: ExchangeId::Binance,
-        instrument: instruments[0].clone(),
-        kind: MarketDataKind::OrderBookL1(OrderBookL1 {
-            bid_price: Decimal::from_str_exact("50124.20").unwrap(),
-            bid_quantity: Decimal::from_str_exact("1.25").unwrap(),
-            ask_price: Decimal::from_str_exact("50126.80").unwrap(),
-            ask_quantity: Decimal::from_str_exact("0.80").unwrap(),
-            timestamp: Utc::now(),
-        }),
-        exchange_time: Utc::now(),
-        receipt_time: Utc::now(),
-    });
-    
-    events.push(MarketEvent {
-        exchange: ExchangeId::Binance,
-        instrument: instruments[1].clone(),
-        kind: MarketDataKind::OrderBookL1(OrderBookL1 {
-            bid_price: Decimal::from_str_exact("2849.50").unwrap(),
-            bid_quantity: Decimal::from_str_exact("15.7").unwrap(),
-            ask_price: Decimal::from_str_exact("2851.25").unwrap(),
-            ask_quantity: Decimal::from_str_exact("8.3").unwrap(),
-            timestamp: Utc::now(),
-        }),
-        exchange_time: Utc::now(),
-        receipt_time: Utc::now(),
-    });
-    
-    // More trade events
-    events.push(MarketEvent {
-        exchange: ExchangeId::Binance,
-        instrument: instruments[0].clone(),
-        kind: MarketDataKind::Trade(PublicTrade {
-            id: "trade_003".to_string(),
-            price: Decimal::from_str_exact("50127.10").unwrap(),
-            quantity: Decimal::from_str_exact("0.08").unwrap(),
-            side: Side::Sell,
-            timestamp: Utc::now(),
-        }),
-        exchange_time: Utc::now(),
-        receipt_time: Utc::now(),
-    });
-    
-    events.push(MarketEvent {
-        exchange: ExchangeId::Binance,
-        instrument: instruments[1].clone(),
-        kind: MarketDataKind::Trade(PublicTrade {
-            id: "trade_004".to_string(),
-            price: Decimal::from_str_exact("2848.90").unwrap(),
-            quantity: Decimal::from_str_exact("1.8").unwrap(),
-            side: Side::Buy,
-            timestamp: Utc::now(),
-        }),
-        exchange_time: Utc::now(),
-        receipt_time: Utc::now(),
-    });
-    
-    events
+    vec![
+        // Trade events
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: instruments[0].clone(),
+            kind: MarketDataKind::Trade(PublicTrade {
+                id: "trade_001".to_string(),
+                price: Decimal::from_str_exact("50125.50").unwrap(),
+                quantity: Decimal::from_str_exact("0.15").unwrap(),
+                side: Side::Buy,
+                timestamp: Utc::now(),
+            }),
+            exchange_time: Utc::now(),
+            receipt_time: Utc::now(),
+        },
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: instruments[1].clone(),
+            kind: MarketDataKind::Trade(PublicTrade {
+                id: "trade_002".to_string(),
+                price: Decimal::from_str_exact("2850.75").unwrap(),
+                quantity: Decimal::from_str_exact("2.3").unwrap(),
+                side: Side::Sell,
+                timestamp: Utc::now(),
+            }),
+            exchange_time: Utc::now(),
+            receipt_time: Utc::now(),
+        },
+        
+        // Order book events
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: instruments[0].clone(),
+            kind: MarketDataKind::OrderBookL1(OrderBookL1 {
+                bid_price: Decimal::from_str_exact("50124.20").unwrap(),
+                bid_quantity: Decimal::from_str_exact("1.25").unwrap(),
+                ask_price: Decimal::from_str_exact("50126.80").unwrap(),
+                ask_quantity: Decimal::from_str_exact("0.80").unwrap(),
+                timestamp: Utc::now(),
+                update_id: None,
+            }),
+            exchange_time: Utc::now(),
+            receipt_time: Utc::now(),
+        },
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: instruments[1].clone(),
+            kind: MarketDataKind::OrderBookL1(OrderBookL1 {
+                bid_price: Decimal::from_str_exact("2849.50").unwrap(),
+                bid_quantity: Decimal::from_str_exact("15.7").unwrap(),
+                ask_price: Decimal::from_str_exact("2851.25").unwrap(),
+                ask_quantity: Decimal::from_str_exact("8.3").unwrap(),
+                timestamp: Utc::now(),
+                update_id: None,
+            }),
+            exchange_time: Utc::now(),
+            receipt_time: Utc::now(),
+        },
+        
+        // More trade events
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: instruments[0].clone(),
+            kind: MarketDataKind::Trade(PublicTrade {
+                id: "trade_003".to_string(),
+                price: Decimal::from_str_exact("50127.10").unwrap(),
+                quantity: Decimal::from_str_exact("0.08").unwrap(),
+                side: Side::Sell,
+                timestamp: Utc::now(),
+            }),
+            exchange_time: Utc::now(),
+            receipt_time: Utc::now(),
+        },
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: instruments[1].clone(),
+            kind: MarketDataKind::Trade(PublicTrade {
+                id: "trade_004".to_string(),
+                price: Decimal::from_str_exact("2848.90").unwrap(),
+                quantity: Decimal::from_str_exact("1.8").unwrap(),
+                side: Side::Buy,
+                timestamp: Utc::now(),
+            }),
+            exchange_time: Utc::now(),
+            receipt_time: Utc::now(),
+        },
+    ]
 }
 
 /// Print market event details
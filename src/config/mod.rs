@@ -3,13 +3,14 @@
 //! This module provides configuration structures for the trading system.
 
 use crate::{
-    data::InstrumentId,
+    data::{InstrumentId, InstrumentSubscription, SubscriptionKind},
     execution::OrderType,
     risk::RiskLimits,
 };
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::time::Duration;
 
 /// System configuration
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
@@ -61,14 +62,48 @@ pub struct ExecutionConfig {
 pub struct DataConfig {
     /// Enable market data streaming
     pub enable_market_data: bool,
-    /// Market data types to subscribe to
-    pub market_data_types: Vec<String>, // e.g., "trades", "orderbook_l1", "orderbook_l2"
-    /// Market data update frequency in milliseconds
+    /// Market data subscribed for every instrument without an override
+    pub subscriptions: Vec<SubscriptionKind>,
+    /// Instruments subscribing to other market data than `subscriptions`
+    #[serde(default)]
+    pub instrument_subscriptions: Vec<InstrumentSubscription>,
+    /// Minimum interval between book and ticker updates per instrument in
+    /// milliseconds; 0 forwards every update
     pub update_frequency_ms: u64,
     /// Enable historical data loading
     pub enable_historical_data: bool,
 }
 
+impl DataConfig {
+    /// Market data subscribed for an instrument
+    pub fn subscriptions_for(&self, instrument: &InstrumentId) -> &[SubscriptionKind] {
+        self.instrument_subscriptions
+            .iter()
+            .find(|subscription| subscription.instrument == *instrument)
+            .map_or(&self.subscriptions, |subscription| &subscription.kinds)
+    }
+
+    /// Every kind subscribed for any instrument, without duplicates
+    pub fn all_subscriptions(&self) -> Vec<SubscriptionKind> {
+        let mut kinds: Vec<SubscriptionKind> = Vec::new();
+        let overrides = self
+            .instrument_subscriptions
+            .iter()
+            .flat_map(|subscription| &subscription.kinds);
+        for kind in self.subscriptions.iter().chain(overrides) {
+            if !kinds.contains(kind) {
+                kinds.push(*kind);
+            }
+        }
+        kinds
+    }
+
+    /// Conflation interval, if updates are limited
+    pub fn update_interval(&self) -> Option<Duration> {
+        (self.update_frequency_ms > 0).then(|| Duration::from_millis(self.update_frequency_ms))
+    }
+}
+
 impl Default for SystemConfig {
     fn default() -> Self {
         Self {
@@ -82,7 +117,8 @@ impl Default for SystemConfig {
             },
             data: DataConfig {
                 enable_market_data: true,
-                market_data_types: vec!["trades".to_string(), "orderbook_l1".to_string()],
+                subscriptions: vec![SubscriptionKind::Trades, SubscriptionKind::L1],
+                instrument_subscriptions: vec![],
                 update_frequency_ms: 100,
                 enable_historical_data: false,
             },
@@ -0,0 +1,450 @@
+//! Order book microstructure features
+//!
+//! `BookAnalytics` computes `BookFeatures` from every `OrderBookL1` and `OrderBookL2`
+//! event. Per exchange and instrument it keeps only the previous top of book and the
+//! order-flow window, so each update costs O(depth). L1 events carry just the touch, so
+//! their depth features equal the top of book ones.
+//!
+//! Order-flow imbalance follows Cont, Kukanov and Stoikov (2014): a bid that rises or
+//! holds adds its new quantity, a bid that falls or holds removes its previous
+//! quantity, and the reverse for asks.
+//!
+//! `BookAnalyticsStream` wraps any `MarketDataStream` and delivers a
+//! `MarketDataKind::BookFeatures` event right after each book event, so strategies can
+//! consume the features instead of recomputing them.
+
+use super::{
+    BookFeatures, ExchangeId, InstrumentId, MarketDataKind, MarketDataStream, MarketEvent,
+    PriceLevel,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::{HashMap, VecDeque};
+
+/// Errors produced when building a `BookAnalytics`
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum BookAnalyticsError {
+    /// Pressure decay outside `(0, 1]`, which can cancel the weighted book out
+    #[error("pressure decay {0} is outside (0, 1]")]
+    PressureDecay(Decimal),
+}
+
+/// Feature parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookAnalyticsConfig {
+    /// Levels per side used by the depth features
+    pub depth: usize,
+    /// Weight ratio between consecutive levels in the book pressure, in `(0, 1]`
+    pub pressure_decay: Decimal,
+    /// Number of updates summed into `BookFeatures::ofi_window`
+    pub ofi_window: usize,
+    /// Tick size per instrument, for `BookFeatures::spread_ticks`
+    pub tick_sizes: HashMap<InstrumentId, Decimal>,
+}
+
+impl Default for BookAnalyticsConfig {
+    fn default() -> Self {
+        Self {
+            depth: 5,
+            pressure_decay: Decimal::new(5, 1),
+            ofi_window: 100,
+            tick_sizes: HashMap::new(),
+        }
+    }
+}
+
+impl BookAnalyticsConfig {
+    /// Levels per side used by the depth features
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth.max(1);
+        self
+    }
+
+    /// Weight ratio between consecutive levels in the book pressure, in `(0, 1]`
+    pub fn with_pressure_decay(mut self, pressure_decay: Decimal) -> Self {
+        self.pressure_decay = pressure_decay;
+        self
+    }
+
+    /// Number of updates summed into the windowed order-flow imbalance
+    pub fn with_ofi_window(mut self, ofi_window: usize) -> Self {
+        self.ofi_window = ofi_window.max(1);
+        self
+    }
+
+    /// Tick size of an instrument
+    pub fn with_tick_size(mut self, instrument: InstrumentId, tick_size: Decimal) -> Self {
+        self.tick_sizes.insert(instrument, tick_size);
+        self
+    }
+}
+
+/// Best bid and ask with their quantities
+#[derive(Debug, Copy, Clone)]
+struct Top {
+    bid: Decimal,
+    bid_quantity: Decimal,
+    ask: Decimal,
+    ask_quantity: Decimal,
+}
+
+impl Top {
+    /// Order-flow imbalance of moving from `previous` to `self`
+    fn ofi(&self, previous: &Top) -> Decimal {
+        let bid = if self.bid > previous.bid {
+            self.bid_quantity
+        } else if self.bid == previous.bid {
+            self.bid_quantity - previous.bid_quantity
+        } else {
+            -previous.bid_quantity
+        };
+        let ask = if self.ask < previous.ask {
+            self.ask_quantity
+        } else if self.ask == previous.ask {
+            self.ask_quantity - previous.ask_quantity
+        } else {
+            -previous.ask_quantity
+        };
+        bid - ask
+    }
+}
+
+/// Per exchange and instrument state
+#[derive(Debug, Clone, Default)]
+struct Series {
+    previous: Option<Top>,
+    ofi: VecDeque<Decimal>,
+    ofi_sum: Decimal,
+    features: Option<BookFeatures>,
+}
+
+/// Computes book features for every exchange and instrument it sees
+#[derive(Debug, Clone, Default)]
+pub struct BookAnalytics {
+    config: BookAnalyticsConfig,
+    series: HashMap<(ExchangeId, InstrumentId), Series>,
+}
+
+impl BookAnalytics {
+    /// Create an analytics engine with the given parameters; fails unless the pressure
+    /// decay lies in `(0, 1]`
+    pub fn new(config: BookAnalyticsConfig) -> Result<Self, BookAnalyticsError> {
+        let decay = config.pressure_decay;
+        if decay <= Decimal::ZERO || decay > Decimal::ONE {
+            return Err(BookAnalyticsError::PressureDecay(decay));
+        }
+        Ok(Self {
+            config,
+            series: HashMap::new(),
+        })
+    }
+
+    /// Latest features of an instrument on an exchange
+    pub fn features(
+        &self,
+        exchange: ExchangeId,
+        instrument: &InstrumentId,
+    ) -> Option<&BookFeatures> {
+        self.series
+            .get(&(exchange, instrument.clone()))
+            .and_then(|series| series.features.as_ref())
+    }
+
+    /// Apply a book event, returning a `BookFeatures` event for it.
+    ///
+    /// Other events, and books missing a side, produce nothing.
+    pub fn update(&mut self, event: &MarketEvent) -> Option<MarketEvent> {
+        let features = match &event.kind {
+            MarketDataKind::OrderBookL1(book) => {
+                let bid = [PriceLevel {
+                    price: book.bid_price,
+                    quantity: book.bid_quantity,
+                }];
+                let ask = [PriceLevel {
+                    price: book.ask_price,
+                    quantity: book.ask_quantity,
+                }];
+                self.compute(event, &bid, &ask, book.timestamp)?
+            }
+            MarketDataKind::OrderBookL2(book) => {
+                self.compute(event, &book.bids, &book.asks, book.timestamp)?
+            }
+            _ => return None,
+        };
+        Some(MarketEvent {
+            exchange: event.exchange,
+            instrument: event.instrument.clone(),
+            kind: MarketDataKind::BookFeatures(features),
+            exchange_time: event.exchange_time,
+            receipt_time: event.receipt_time,
+        })
+    }
+
+    fn compute(
+        &mut self,
+        event: &MarketEvent,
+        bids: &[PriceLevel],
+        asks: &[PriceLevel],
+        timestamp: DateTime<Utc>,
+    ) -> Option<BookFeatures> {
+        let (best_bid, best_ask) = (bids.first()?, asks.first()?);
+        if best_bid.quantity <= Decimal::ZERO || best_ask.quantity <= Decimal::ZERO {
+            return None;
+        }
+        let top = Top {
+            bid: best_bid.price,
+            bid_quantity: best_bid.quantity,
+            ask: best_ask.price,
+            ask_quantity: best_ask.quantity,
+        };
+        let top_quantity = top.bid_quantity + top.ask_quantity;
+        let spread = top.ask - top.bid;
+
+        let depth = self.config.depth;
+        let bid_depth = Depth::of(&bids[..bids.len().min(depth)], self.config.pressure_decay);
+        let ask_depth = Depth::of(&asks[..asks.len().min(depth)], self.config.pressure_decay);
+        let total = bid_depth.quantity + ask_depth.quantity;
+        let weighted_total = bid_depth.weighted_quantity + ask_depth.weighted_quantity;
+
+        let series = self
+            .series
+            .entry((event.exchange, event.instrument.clone()))
+            .or_default();
+        let ofi = series
+            .previous
+            .map_or(Decimal::ZERO, |previous| top.ofi(&previous));
+        series.previous = Some(top);
+        series.ofi.push_back(ofi);
+        series.ofi_sum += ofi;
+        if series.ofi.len() > self.config.ofi_window {
+            series.ofi_sum -= series.ofi.pop_front().unwrap_or_default();
+        }
+
+        let features = BookFeatures {
+            mid: (top.bid + top.ask) / Decimal::TWO,
+            spread,
+            spread_ticks: self
+                .config
+                .tick_sizes
+                .get(&event.instrument)
+                .filter(|tick_size| !tick_size.is_zero())
+                .map(|tick_size| spread / tick_size),
+            top_imbalance: (top.bid_quantity - top.ask_quantity) / top_quantity,
+            depth_imbalance: (bid_depth.quantity - ask_depth.quantity) / total,
+            microprice: (top.bid * top.ask_quantity + top.ask * top.bid_quantity) / top_quantity,
+            weighted_mid: (bid_depth.average_price() * ask_depth.quantity
+                + ask_depth.average_price() * bid_depth.quantity)
+                / total,
+            book_pressure: (bid_depth.weighted_quantity - ask_depth.weighted_quantity)
+                / weighted_total,
+            ofi,
+            ofi_window: series.ofi_sum,
+            timestamp,
+        };
+        series.features = Some(features.clone());
+        Some(features)
+    }
+}
+
+/// Quantity sums over the first levels of one side
+struct Depth {
+    quantity: Decimal,
+    notional: Decimal,
+    weighted_quantity: Decimal,
+}
+
+impl Depth {
+    fn of(levels: &[PriceLevel], decay: Decimal) -> Self {
+        let mut depth = Depth {
+            quantity: Decimal::ZERO,
+            notional: Decimal::ZERO,
+            weighted_quantity: Decimal::ZERO,
+        };
+        let mut weight = Decimal::ONE;
+        for level in levels {
+            depth.quantity += level.quantity;
+            depth.notional += level.price * level.quantity;
+            depth.weighted_quantity += weight * level.quantity;
+            weight *= decay;
+        }
+        depth
+    }
+
+    /// Quantity-weighted average price, never called on an empty side
+    fn average_price(&self) -> Decimal {
+        self.notional / self.quantity
+    }
+}
+
+/// Market data stream that forwards every event and adds book features after each
+/// book update
+pub struct BookAnalyticsStream<S> {
+    inner: S,
+    analytics: BookAnalytics,
+    pending: Option<MarketEvent>,
+}
+
+impl<S> BookAnalyticsStream<S> {
+    /// Wrap a stream
+    pub fn new(inner: S, analytics: BookAnalytics) -> Self {
+        Self {
+            inner,
+            analytics,
+            pending: None,
+        }
+    }
+
+    /// Analytics state, e.g. to read the latest features of an instrument
+    pub fn analytics(&self) -> &BookAnalytics {
+        &self.analytics
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> MarketDataStream for BookAnalyticsStream<S>
+where
+    S: MarketDataStream + Send,
+    S::Error: Send,
+{
+    type Error = S::Error;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        if let Some(event) = self.pending.take() {
+            return Ok(Some(event));
+        }
+        let event = self.inner.next().await?;
+        if let Some(event) = &event {
+            self.pending = self.analytics.update(event);
+        }
+        Ok(event)
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.inner.subscribe(instruments).await
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.inner.unsubscribe(instruments).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{InstrumentKind, MockMarketDataStream, OrderBookL1, OrderBookL2};
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    fn instrument() -> InstrumentId {
+        InstrumentId {
+            base: "BTC".to_string(),
+            quote: "USDT".to_string(),
+            exchange_symbol: "BTCUSDT".to_string(),
+            kind: InstrumentKind::Spot,
+        }
+    }
+
+    fn event(kind: MarketDataKind) -> MarketEvent {
+        let timestamp = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: instrument(),
+            kind,
+            exchange_time: timestamp,
+            receipt_time: timestamp,
+        }
+    }
+
+    fn l1(bid: Decimal, bid_quantity: Decimal, ask: Decimal, ask_quantity: Decimal) -> MarketEvent {
+        event(MarketDataKind::OrderBookL1(OrderBookL1 {
+            bid_price: bid,
+            bid_quantity,
+            ask_price: ask,
+            ask_quantity,
+            timestamp: Utc::now(),
+            update_id: None,
+        }))
+    }
+
+    fn features(event: Option<MarketEvent>) -> BookFeatures {
+        match event.map(|event| event.kind) {
+            Some(MarketDataKind::BookFeatures(features)) => features,
+            other => panic!("expected features, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_l1_features_and_ofi() {
+        let config = BookAnalyticsConfig::default()
+            .with_ofi_window(2)
+            .with_tick_size(instrument(), dec!(0.5));
+        let mut analytics = BookAnalytics::new(config).unwrap();
+
+        let first = features(analytics.update(&l1(dec!(100), dec!(3), dec!(101), dec!(1))));
+        assert_eq!(first.mid, dec!(100.5));
+        assert_eq!(first.spread_ticks, Some(dec!(2)));
+        assert_eq!(first.top_imbalance, dec!(0.5));
+        // Heavier bids pull the microprice towards the ask
+        assert_eq!(first.microprice, dec!(100.75));
+        assert_eq!(first.weighted_mid, first.microprice);
+        assert_eq!(first.ofi, Decimal::ZERO);
+
+        // Bid size grows by 2 at the same price, ask improves with size 4
+        let second = features(analytics.update(&l1(dec!(100), dec!(5), dec!(100.5), dec!(4))));
+        assert_eq!(second.ofi, dec!(2) - dec!(4));
+        // Bid drops a level, losing its 5; ask backs off, removing the previous 4
+        let third = features(analytics.update(&l1(dec!(99.5), dec!(7), dec!(101), dec!(1))));
+        assert_eq!(third.ofi, dec!(-5) + dec!(4));
+        assert_eq!(third.ofi_window, second.ofi + third.ofi);
+        assert_eq!(
+            analytics.features(ExchangeId::Binance, &instrument()),
+            Some(&third)
+        );
+
+        assert!(analytics
+            .update(&l1(dec!(100), dec!(0), dec!(101), dec!(1)))
+            .is_none());
+    }
+
+    #[test]
+    fn test_pressure_decay_outside_unit_interval_is_rejected() {
+        for decay in [dec!(-1), Decimal::ZERO, dec!(1.5)] {
+            assert_eq!(
+                BookAnalytics::new(BookAnalyticsConfig::default().with_pressure_decay(decay))
+                    .unwrap_err(),
+                BookAnalyticsError::PressureDecay(decay)
+            );
+        }
+        assert!(BookAnalytics::new(
+            BookAnalyticsConfig::default().with_pressure_decay(Decimal::ONE)
+        )
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_stream_adds_depth_features_after_book() {
+        let level = |price: Decimal, quantity: Decimal| PriceLevel { price, quantity };
+        let book = event(MarketDataKind::OrderBookL2(OrderBookL2 {
+            bids: vec![level(dec!(100), dec!(1)), level(dec!(99), dec!(4))],
+            asks: vec![level(dec!(101), dec!(2)), level(dec!(102), dec!(2))],
+            timestamp: Utc::now(),
+        }));
+        let inner = MockMarketDataStream::new(vec![book.clone()]);
+        let analytics = BookAnalytics::new(BookAnalyticsConfig::default()).unwrap();
+        let mut stream = BookAnalyticsStream::new(inner, analytics);
+
+        assert_eq!(stream.next().await.unwrap(), Some(book));
+        let features = features(stream.next().await.unwrap());
+        assert!(stream.next().await.unwrap().is_none());
+
+        assert_eq!(features.top_imbalance, dec!(-1) / dec!(3));
+        assert_eq!(features.depth_imbalance, Decimal::ONE / dec!(9));
+        // Bids average 99.2, asks 101.5, each weighted by the other side's 4 and 5
+        assert_eq!(
+            features.weighted_mid,
+            (dec!(99.2) * dec!(4) + dec!(101.5) * dec!(5)) / dec!(9)
+        );
+        // Second levels count half: bids 1 + 2, asks 2 + 1
+        assert_eq!(features.book_pressure, Decimal::ZERO);
+    }
+}
@@ -0,0 +1,619 @@
+//! Bar aggregation
+//!
+//! `BarAggregator` turns `MarketDataKind::Trade` events into `MarketDataKind::Candle`
+//! events. Time bars are aligned to multiples of their duration since the Unix epoch
+//! and close as soon as time moves past their end, whether that is signalled by a
+//! later trade or by `BarAggregator::advance_to`. Tick, volume and dollar bars close
+//! on the trade that reaches their threshold.
+//!
+//! The aggregator itself is synchronous and driven entirely by the timestamps it is
+//! given, so backtests get identical bars to live trading. Trades stamped before the
+//! end of a bar that has already closed are late; they are counted and left out of
+//! the bars. `BarAggregatorStream` wraps any `MarketDataStream` and closes idle time
+//! bars as the exchange time of its other events moves on, live and in backtests over
+//! archived or replayed data. Events without an `exchange_clock`, such as Binance
+//! book tickers, never close bars.
+
+use super::{Candle, ExchangeId, InstrumentId, MarketDataKind, MarketDataStream, MarketEvent};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Errors produced when building a `BarAggregator`
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum BarError {
+    /// Zero duration, trade count or threshold
+    #[error("bar parameters must be positive: {0:?}")]
+    NonPositive(BarKind),
+}
+
+/// Rule deciding when a bar closes
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum BarKind {
+    /// Fixed time interval
+    Time { duration_secs: u64 },
+    /// Fixed number of trades
+    Tick { trades: u64 },
+    /// Fixed traded base quantity
+    Volume { volume: Decimal },
+    /// Fixed traded notional (price × quantity)
+    Dollar { notional: Decimal },
+}
+
+/// Bar under construction
+#[derive(Debug, Clone)]
+struct OpenBar {
+    open: Decimal,
+    high: Decimal,
+    low: Decimal,
+    close: Decimal,
+    volume: Decimal,
+    notional: Decimal,
+    trades: u64,
+    start: DateTime<Utc>,
+    last: DateTime<Utc>,
+}
+
+/// Per exchange and instrument aggregation state
+#[derive(Debug, Clone, Default)]
+struct Series {
+    bar: Option<OpenBar>,
+    last_close: Option<Decimal>,
+    /// Start of the next time bar after the last one emitted; earlier trades are late
+    next_start: Option<DateTime<Utc>>,
+}
+
+/// Builds candles from trades for every exchange and instrument it sees
+#[derive(Debug, Clone)]
+pub struct BarAggregator {
+    kind: BarKind,
+    fill_gaps: bool,
+    series: HashMap<(ExchangeId, InstrumentId), Series>,
+    late_trades: u64,
+}
+
+impl BarAggregator {
+    /// Create an aggregator producing bars of the given kind, whose duration, trade
+    /// count or threshold must be positive
+    pub fn new(kind: BarKind) -> Result<Self, BarError> {
+        let valid = match kind {
+            BarKind::Time { duration_secs } => duration_secs > 0,
+            BarKind::Tick { trades } => trades > 0,
+            BarKind::Volume { volume } => volume > Decimal::ZERO,
+            BarKind::Dollar { notional } => notional > Decimal::ZERO,
+        };
+        if !valid {
+            return Err(BarError::NonPositive(kind));
+        }
+        Ok(Self {
+            kind,
+            fill_gaps: false,
+            series: HashMap::new(),
+            late_trades: 0,
+        })
+    }
+
+    /// Emit flat, zero-volume time bars for intervals without trades
+    pub fn with_gap_filling(mut self, fill_gaps: bool) -> Self {
+        self.fill_gaps = fill_gaps;
+        self
+    }
+
+    /// Bar kind produced by this aggregator
+    pub fn kind(&self) -> BarKind {
+        self.kind
+    }
+
+    /// Trades dropped because the time bar they belong to had already closed
+    pub fn late_trades(&self) -> u64 {
+        self.late_trades
+    }
+
+    /// Feed a market event, returning any candles it completes.
+    ///
+    /// Events other than trades are ignored, as are late trades. Candles closed because
+    /// the trade falls into a later interval are returned before the candle the trade
+    /// itself completes.
+    pub fn update(&mut self, event: &MarketEvent) -> Vec<MarketEvent> {
+        let MarketDataKind::Trade(trade) = &event.kind else {
+            return Vec::new();
+        };
+
+        let kind = self.kind;
+        let fill_gaps = self.fill_gaps;
+        let key = (event.exchange, event.instrument.clone());
+        let series = self.series.entry(key).or_default();
+
+        if let BarKind::Time { .. } = kind {
+            let closed_until = series
+                .bar
+                .as_ref()
+                .map(|bar| bar.start)
+                .or(series.next_start);
+            if closed_until.is_some_and(|start| trade.timestamp < start) {
+                self.late_trades += 1;
+                return Vec::new();
+            }
+        }
+
+        let mut output = Vec::new();
+        let mut closed = Vec::new();
+        if let BarKind::Time { duration_secs } = kind {
+            close_elapsed(
+                series,
+                duration_secs,
+                fill_gaps,
+                trade.timestamp,
+                &mut closed,
+            );
+        }
+
+        let bar = series.bar.get_or_insert_with(|| OpenBar {
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: Decimal::ZERO,
+            notional: Decimal::ZERO,
+            trades: 0,
+            start: match kind {
+                BarKind::Time { duration_secs } => bucket_start(trade.timestamp, duration_secs),
+                _ => trade.timestamp,
+            },
+            last: trade.timestamp,
+        });
+        bar.high = bar.high.max(trade.price);
+        bar.low = bar.low.min(trade.price);
+        bar.close = trade.price;
+        bar.volume += trade.quantity;
+        bar.notional += trade.price * trade.quantity;
+        bar.trades += 1;
+        bar.last = bar.last.max(trade.timestamp);
+
+        let complete = match kind {
+            BarKind::Time { .. } => false,
+            BarKind::Tick { trades } => bar.trades >= trades,
+            BarKind::Volume { volume } => bar.volume >= volume,
+            BarKind::Dollar { notional } => bar.notional >= notional,
+        };
+        if complete {
+            if let Some(bar) = series.bar.take() {
+                series.last_close = Some(bar.close);
+                closed.push(candle(&bar, kind));
+            }
+        }
+
+        for (candle, end) in closed {
+            output.push(candle_event(
+                event.exchange,
+                &event.instrument,
+                candle,
+                end,
+                event.receipt_time,
+            ));
+        }
+        output
+    }
+
+    /// Close every time bar that ends at or before `time`.
+    ///
+    /// Pass event time, e.g. the exchange time of the latest book update, or the
+    /// simulated clock in backtests. Has no effect on tick, volume or dollar bars.
+    pub fn advance_to(&mut self, time: DateTime<Utc>) -> Vec<MarketEvent> {
+        self.advance_series(None, time)
+    }
+
+    /// Close every time bar of one exchange's instruments that ends at or before `time`
+    pub fn advance_exchange_to(
+        &mut self,
+        exchange: ExchangeId,
+        time: DateTime<Utc>,
+    ) -> Vec<MarketEvent> {
+        self.advance_series(Some(exchange), time)
+    }
+
+    fn advance_series(
+        &mut self,
+        only: Option<ExchangeId>,
+        time: DateTime<Utc>,
+    ) -> Vec<MarketEvent> {
+        let BarKind::Time { duration_secs } = self.kind else {
+            return Vec::new();
+        };
+
+        let mut output = Vec::new();
+        for ((exchange, instrument), series) in &mut self.series {
+            if only.is_some_and(|only| only != *exchange) {
+                continue;
+            }
+            let mut closed = Vec::new();
+            close_elapsed(series, duration_secs, self.fill_gaps, time, &mut closed);
+            for (candle, end) in closed {
+                output.push(candle_event(*exchange, instrument, candle, end, time));
+            }
+        }
+        output.sort_by_key(|event| event.exchange_time);
+        output
+    }
+
+    /// Earliest end time of any open time bar, useful for scheduling `advance_to`
+    pub fn next_close(&self) -> Option<DateTime<Utc>> {
+        let BarKind::Time { duration_secs } = self.kind else {
+            return None;
+        };
+        self.series
+            .values()
+            .filter_map(|series| {
+                let start = series.bar.as_ref().map(|bar| bar.start);
+                let start = if self.fill_gaps {
+                    start.or(series.next_start)
+                } else {
+                    start
+                };
+                start.map(|start| start + ChronoDuration::seconds(duration_secs as i64))
+            })
+            .min()
+    }
+}
+
+/// Close the open time bar and, if enabled, emit flat bars for every interval up to `time`
+fn close_elapsed(
+    series: &mut Series,
+    duration_secs: u64,
+    fill_gaps: bool,
+    time: DateTime<Utc>,
+    closed: &mut Vec<(Candle, DateTime<Utc>)>,
+) {
+    let duration = ChronoDuration::seconds(duration_secs as i64);
+    let kind = BarKind::Time { duration_secs };
+
+    if let Some(bar) = series.bar.take_if(|bar| bar.start + duration <= time) {
+        series.last_close = Some(bar.close);
+        series.next_start = Some(bar.start + duration);
+        closed.push(candle(&bar, kind));
+    }
+
+    if !fill_gaps || series.bar.is_some() {
+        return;
+    }
+    while let (Some(start), Some(close)) = (series.next_start, series.last_close) {
+        if start + duration > time {
+            break;
+        }
+        let flat = Candle {
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: Decimal::ZERO,
+            timestamp: start,
+            duration_secs,
+        };
+        closed.push((flat, start + duration));
+        series.next_start = Some(start + duration);
+    }
+}
+
+/// Finished candle and the time it closed
+fn candle(bar: &OpenBar, kind: BarKind) -> (Candle, DateTime<Utc>) {
+    let (duration_secs, end) = match kind {
+        BarKind::Time { duration_secs } => (
+            duration_secs,
+            bar.start + ChronoDuration::seconds(duration_secs as i64),
+        ),
+        _ => ((bar.last - bar.start).num_seconds().max(0) as u64, bar.last),
+    };
+    let candle = Candle {
+        open: bar.open,
+        high: bar.high,
+        low: bar.low,
+        close: bar.close,
+        volume: bar.volume,
+        timestamp: bar.start,
+        duration_secs,
+    };
+    (candle, end)
+}
+
+fn candle_event(
+    exchange: ExchangeId,
+    instrument: &InstrumentId,
+    candle: Candle,
+    end: DateTime<Utc>,
+    receipt_time: DateTime<Utc>,
+) -> MarketEvent {
+    MarketEvent {
+        exchange,
+        instrument: instrument.clone(),
+        kind: MarketDataKind::Candle(candle),
+        exchange_time: end,
+        receipt_time,
+    }
+}
+
+/// Start of the epoch-aligned interval containing `time`
+fn bucket_start(time: DateTime<Utc>, duration_secs: u64) -> DateTime<Utc> {
+    let duration_millis = duration_secs as i64 * 1000;
+    let millis = time.timestamp_millis();
+    let start = millis - millis.rem_euclid(duration_millis);
+    DateTime::from_timestamp_millis(start).unwrap_or(time)
+}
+
+/// Market data stream that forwards every event of an inner stream and adds candles.
+///
+/// Candles are delivered before the event that caused them to close. Every event with
+/// an `exchange_clock`, not just trades, advances the time bars of its exchange to
+/// that time, so an instrument's idle bar closes once any other data from the venue
+/// moves past its end. Events stamped on receipt, such as bookTicker and partial
+/// depth, run ahead of trades still in flight and are not used as a clock.
+pub struct BarAggregatorStream<S> {
+    inner: S,
+    aggregator: BarAggregator,
+    pending: VecDeque<MarketEvent>,
+}
+
+impl<S> BarAggregatorStream<S> {
+    /// Wrap a stream
+    pub fn new(inner: S, aggregator: BarAggregator) -> Self {
+        Self {
+            inner,
+            aggregator,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Aggregator building the candles
+    pub fn aggregator(&self) -> &BarAggregator {
+        &self.aggregator
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> MarketDataStream for BarAggregatorStream<S>
+where
+    S: MarketDataStream + Send,
+    S::Error: Send,
+{
+    type Error = S::Error;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Some(event));
+            }
+
+            let Some(event) = self.inner.next().await? else {
+                return Ok(None);
+            };
+            if let Some(time) = event.exchange_clock() {
+                self.pending
+                    .extend(self.aggregator.advance_exchange_to(event.exchange, time));
+            }
+            self.pending.extend(self.aggregator.update(&event));
+            self.pending.push_back(event);
+        }
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.inner.subscribe(instruments).await
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.inner.unsubscribe(instruments).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{spot_instrument, OrderBookL1, PublicTrade, Side};
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_040 + secs, 0).unwrap()
+    }
+
+    fn trade(secs: i64, price: Decimal, quantity: Decimal) -> MarketEvent {
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: spot_instrument("BTC", "USDT"),
+            kind: MarketDataKind::Trade(PublicTrade {
+                id: secs.to_string(),
+                price,
+                quantity,
+                side: Side::Buy,
+                timestamp: at(secs),
+            }),
+            exchange_time: at(secs),
+            receipt_time: at(secs),
+        }
+    }
+
+    fn candles(events: Vec<MarketEvent>) -> Vec<Candle> {
+        events
+            .into_iter()
+            .map(|event| match event.kind {
+                MarketDataKind::Candle(candle) => candle,
+                other => panic!("expected candle, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_time_bars_close_on_idle_and_fill_gaps() {
+        let mut aggregator = BarAggregator::new(BarKind::Time { duration_secs: 60 })
+            .unwrap()
+            .with_gap_filling(true);
+
+        assert!(aggregator.update(&trade(0, dec!(100), dec!(1))).is_empty());
+        assert!(aggregator.update(&trade(30, dec!(105), dec!(2))).is_empty());
+        assert_eq!(aggregator.next_close(), Some(at(60)));
+
+        // No trades arrive; the clock closes the first bar and one empty interval
+        let closed = candles(aggregator.advance_to(at(125)));
+        assert_eq!(closed.len(), 2);
+        assert_eq!(closed[0].timestamp, at(0));
+        assert_eq!(
+            (closed[0].open, closed[0].high, closed[0].close),
+            (dec!(100), dec!(105), dec!(105))
+        );
+        assert_eq!(closed[0].volume, dec!(3));
+        assert_eq!(closed[1].timestamp, at(60));
+        assert_eq!(closed[1].volume, Decimal::ZERO);
+        assert_eq!(closed[1].open, dec!(105));
+
+        // A trade three intervals later fills the remaining gap before opening its bar
+        let closed = candles(aggregator.update(&trade(250, dec!(98), dec!(1))));
+        assert_eq!(
+            closed.iter().map(|c| c.timestamp).collect::<Vec<_>>(),
+            vec![at(120), at(180)]
+        );
+        assert!(aggregator.advance_to(at(299)).is_empty());
+        assert_eq!(candles(aggregator.advance_to(at(300)))[0].close, dec!(98));
+    }
+
+    #[test]
+    fn test_late_trades_are_dropped() {
+        let mut aggregator = BarAggregator::new(BarKind::Time { duration_secs: 60 }).unwrap();
+        aggregator.update(&trade(10, dec!(100), dec!(1)));
+        assert_eq!(candles(aggregator.advance_to(at(60)))[0].close, dec!(100));
+
+        // Belongs to the bar that has already closed
+        assert!(aggregator.update(&trade(59, dec!(90), dec!(1))).is_empty());
+        aggregator.update(&trade(130, dec!(101), dec!(1)));
+        assert!(aggregator.update(&trade(70, dec!(90), dec!(1))).is_empty());
+        assert_eq!(aggregator.late_trades(), 2);
+
+        let closed = candles(aggregator.advance_to(at(180)));
+        assert_eq!((closed[0].timestamp, closed[0].low), (at(120), dec!(101)));
+    }
+
+    #[test]
+    fn test_threshold_bars() {
+        let mut ticks = BarAggregator::new(BarKind::Tick { trades: 2 }).unwrap();
+        assert!(ticks.update(&trade(0, dec!(100), dec!(1))).is_empty());
+        let bar = candles(ticks.update(&trade(5, dec!(101), dec!(1))));
+        assert_eq!(bar[0].duration_secs, 5);
+        assert_eq!(bar[0].close, dec!(101));
+
+        let mut volume = BarAggregator::new(BarKind::Volume { volume: dec!(3) }).unwrap();
+        assert!(volume.update(&trade(0, dec!(100), dec!(2))).is_empty());
+        assert_eq!(
+            candles(volume.update(&trade(1, dec!(99), dec!(2))))[0].volume,
+            dec!(4)
+        );
+
+        let mut dollar = BarAggregator::new(BarKind::Dollar {
+            notional: dec!(250),
+        })
+        .unwrap();
+        assert!(dollar.update(&trade(0, dec!(100), dec!(2))).is_empty());
+        let bar = candles(dollar.update(&trade(1, dec!(100), dec!(1))));
+        assert_eq!((bar[0].low, bar[0].volume), (dec!(100), dec!(3)));
+        assert!(dollar.advance_to(at(1000)).is_empty());
+    }
+
+    #[test]
+    fn test_non_positive_parameters_are_rejected() {
+        for kind in [
+            BarKind::Time { duration_secs: 0 },
+            BarKind::Tick { trades: 0 },
+            BarKind::Volume { volume: dec!(0) },
+            BarKind::Dollar { notional: dec!(-1) },
+        ] {
+            assert_eq!(
+                BarAggregator::new(kind).unwrap_err(),
+                BarError::NonPositive(kind)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_emits_candles_before_closing_trade() {
+        let source = crate::data::MockMarketDataStream::new(vec![
+            trade(0, dec!(100), dec!(1)),
+            trade(61, dec!(102), dec!(1)),
+        ]);
+        let mut stream = BarAggregatorStream::new(
+            source,
+            BarAggregator::new(BarKind::Time { duration_secs: 60 }).unwrap(),
+        );
+
+        let mut kinds = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            kinds.push(matches!(event.kind, MarketDataKind::Candle(_)));
+        }
+        assert_eq!(kinds, vec![false, true, false]);
+    }
+
+    #[tokio::test]
+    async fn test_archived_trades_close_idle_bars() {
+        // Archives and backfills carry receipt times equal to exchange times
+        let mut idle = trade(125, dec!(50), dec!(1));
+        idle.instrument = spot_instrument("ETH", "USDT");
+        let source = crate::data::MockMarketDataStream::new(vec![
+            trade(0, dec!(100), dec!(1)),
+            trade(10, dec!(101), dec!(1)),
+            idle,
+        ]);
+        let mut stream = BarAggregatorStream::new(
+            source,
+            BarAggregator::new(BarKind::Time { duration_secs: 60 }).unwrap(),
+        );
+
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            events.push(event);
+        }
+        let MarketDataKind::Candle(candle) = &events[2].kind else {
+            panic!("expected the BTC bar to close before the ETH trade");
+        };
+        assert_eq!(events[2].instrument, spot_instrument("BTC", "USDT"));
+        assert_eq!((candle.timestamp, candle.close), (at(0), dec!(101)));
+        assert_eq!(events[3].instrument, spot_instrument("ETH", "USDT"));
+    }
+
+    #[tokio::test]
+    async fn test_receipt_stamped_quotes_do_not_close_bars() {
+        // A bookTicker received after the bar's end, ahead of a trade still in flight
+        let quote = MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: spot_instrument("BTC", "USDT"),
+            kind: MarketDataKind::OrderBookL1(OrderBookL1 {
+                bid_price: dec!(100),
+                bid_quantity: dec!(1),
+                ask_price: dec!(101),
+                ask_quantity: dec!(1),
+                timestamp: at(61),
+                update_id: Some(1),
+            }),
+            exchange_time: at(61),
+            receipt_time: at(61),
+        };
+        let mut in_flight = trade(59, dec!(103), dec!(2));
+        in_flight.receipt_time = at(62);
+
+        let source = crate::data::MockMarketDataStream::new(vec![
+            trade(0, dec!(100), dec!(1)),
+            quote,
+            in_flight,
+            trade(65, dec!(102), dec!(1)),
+        ]);
+        let mut stream = BarAggregatorStream::new(
+            source,
+            BarAggregator::new(BarKind::Time { duration_secs: 60 }).unwrap(),
+        );
+
+        let mut closed = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            if let MarketDataKind::Candle(candle) = event.kind {
+                closed.push(candle);
+            }
+        }
+        assert_eq!(stream.aggregator().late_trades(), 0);
+        assert_eq!(closed.len(), 1);
+        assert_eq!((closed[0].close, closed[0].volume), (dec!(103), dec!(3)));
+    }
+}
@@ -0,0 +1,441 @@
+//! Binance public data archives
+//!
+//! Reads the extracted CSV files published on data.binance.vision one row at a time.
+//! `trades`, `aggTrades` and kline files become `PublicTrade` and `Candle` events with
+//! `exchange_time` set to the trade time or, for klines, the candle close time. Archives
+//! carry no receipt time, so `receipt_time` mirrors `exchange_time` to keep replay and
+//! merging ordered by when the data became available.
+//!
+//! Spot files switched from millisecond to microsecond timestamps in 2025; both are
+//! accepted. Futures files start with a header row, which is skipped.
+//!
+//! Spot and futures archives share file names, so `BinanceArchiveReader::open` tells
+//! them apart by the `futures` directory of the data.binance.vision layout, e.g.
+//! `futures/um/daily/trades/BTCUSDT/`.
+//!
+//! `BinanceArchiveReader` reads synchronously; `BinanceArchiveStream` moves a reader to
+//! a dedicated thread so archives can feed a `MarketDataStream` without blocking the
+//! async runtime.
+
+use super::usdm::futures_instrument_from_symbol;
+use super::{instrument_from_symbol, KlineInterval};
+use crate::data::{
+    Candle, ExchangeId, InstrumentId, MarketDataKind, MarketDataStream, MarketEvent, PublicTrade,
+    Side,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+use tokio::sync::mpsc;
+
+/// Timestamps at or above this value are in microseconds rather than milliseconds
+const MICROS_THRESHOLD: i64 = 100_000_000_000_000;
+
+/// Rows the reader thread of a `BinanceArchiveStream` may read ahead
+const READ_AHEAD: usize = 1024;
+
+/// Errors produced while reading a Binance archive
+#[derive(Debug, thiserror::Error)]
+pub enum BinanceArchiveError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// Row that does not match the expected layout
+    #[error("line {line}: {reason}")]
+    Malformed { line: usize, reason: String },
+    /// File name that does not follow the `SYMBOL-kind-date.csv` convention
+    #[error("unrecognised archive file name: {0}")]
+    UnknownFileName(String),
+}
+
+/// Dataset contained in an archive file
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BinanceArchiveKind {
+    /// `id,price,qty,quote_qty,time,is_buyer_maker,is_best_match`
+    Trades,
+    /// `agg_id,price,qty,first_id,last_id,time,is_buyer_maker,is_best_match`
+    AggTrades,
+    /// `open_time,open,high,low,close,volume,close_time,quote_volume,count,...`
+    Klines(KlineInterval),
+}
+
+/// Streaming reader over one archive file
+pub struct BinanceArchiveReader<R> {
+    lines: io::Lines<R>,
+    kind: BinanceArchiveKind,
+    instrument: InstrumentId,
+    line: usize,
+}
+
+impl BinanceArchiveReader<BufReader<File>> {
+    /// Open an archive, inferring symbol and dataset from a file name such as
+    /// `BTCUSDT-trades-2024-01-01.csv`, `BTCUSDT-aggTrades-2024-01.csv` or
+    /// `BTCUSDT-1m-2024-01-01.csv`. Files below a `futures` directory belong to
+    /// futures contracts, all others to spot pairs.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BinanceArchiveError> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        let unknown = || BinanceArchiveError::UnknownFileName(name.to_string());
+
+        let mut parts = name.split('-');
+        let symbol = parts.next().filter(|s| !s.is_empty()).ok_or_else(unknown)?;
+        let kind = match parts.next().ok_or_else(unknown)? {
+            "trades" => BinanceArchiveKind::Trades,
+            "aggTrades" => BinanceArchiveKind::AggTrades,
+            interval => BinanceArchiveKind::Klines(
+                KlineInterval::from_str(interval).map_err(|_| unknown())?,
+            ),
+        };
+
+        let futures = path
+            .components()
+            .any(|component| component.as_os_str() == "futures");
+        let instrument = if futures {
+            futures_instrument_from_symbol(symbol)
+        } else {
+            instrument_from_symbol(symbol)
+        };
+
+        let file = File::open(path)?;
+        Ok(Self::new(BufReader::new(file), kind, instrument))
+    }
+}
+
+impl<R: BufRead> BinanceArchiveReader<R> {
+    /// Read rows of `kind` for `instrument` from any buffered reader, such as a zip entry
+    pub fn new(reader: R, kind: BinanceArchiveKind, instrument: InstrumentId) -> Self {
+        Self {
+            lines: reader.lines(),
+            kind,
+            instrument,
+            line: 0,
+        }
+    }
+
+    /// Instrument the rows belong to
+    pub fn instrument(&self) -> &InstrumentId {
+        &self.instrument
+    }
+
+    /// Read the next row, or `None` at the end of the file
+    pub fn next_event(&mut self) -> Result<Option<MarketEvent>, BinanceArchiveError> {
+        loop {
+            let Some(text) = self.lines.next().transpose()? else {
+                return Ok(None);
+            };
+            self.line += 1;
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = text.split(',').collect();
+            // Header rows start with a column name instead of a number
+            if self.line == 1 && fields[0].parse::<i64>().is_err() {
+                continue;
+            }
+
+            return self.parse_row(&fields).map(Some).map_err(|reason| {
+                BinanceArchiveError::Malformed {
+                    line: self.line,
+                    reason,
+                }
+            });
+        }
+    }
+
+    fn parse_row(&self, fields: &[&str]) -> Result<MarketEvent, String> {
+        let (kind, exchange_time) = match self.kind {
+            BinanceArchiveKind::Trades => {
+                let trade = trade_row(fields, 0, 1, 2, 4, 5)?;
+                let time = trade.timestamp;
+                (MarketDataKind::Trade(trade), time)
+            }
+            BinanceArchiveKind::AggTrades => {
+                let trade = trade_row(fields, 0, 1, 2, 5, 6)?;
+                let time = trade.timestamp;
+                (MarketDataKind::Trade(trade), time)
+            }
+            BinanceArchiveKind::Klines(interval) => {
+                let candle = Candle {
+                    open: decimal(fields, 1)?,
+                    high: decimal(fields, 2)?,
+                    low: decimal(fields, 3)?,
+                    close: decimal(fields, 4)?,
+                    volume: decimal(fields, 5)?,
+                    timestamp: timestamp(fields, 0)?,
+                    duration_secs: interval.duration_secs(),
+                };
+                // A kline is only known once it closes
+                (MarketDataKind::Candle(candle), timestamp(fields, 6)?)
+            }
+        };
+
+        Ok(MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: self.instrument.clone(),
+            kind,
+            exchange_time,
+            receipt_time: exchange_time,
+        })
+    }
+}
+
+impl<R: BufRead> Iterator for BinanceArchiveReader<R> {
+    type Item = Result<MarketEvent, BinanceArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
+}
+
+impl<R: BufRead + Send + 'static> BinanceArchiveReader<R> {
+    /// Read rows on a dedicated thread, delivering them as a `MarketDataStream`
+    pub fn into_stream(self) -> Result<BinanceArchiveStream, BinanceArchiveError> {
+        let instrument = self.instrument.clone();
+        let (sender, receiver) = mpsc::channel(READ_AHEAD);
+        std::thread::Builder::new()
+            .name("binance-archive".to_string())
+            .spawn(move || {
+                for row in self {
+                    let failed = row.is_err();
+                    if sender.blocking_send(row).is_err() || failed {
+                        break;
+                    }
+                }
+            })?;
+        Ok(BinanceArchiveStream {
+            instrument,
+            receiver,
+        })
+    }
+}
+
+/// Archive rows read by a `BinanceArchiveReader` on its own thread
+pub struct BinanceArchiveStream {
+    instrument: InstrumentId,
+    receiver: mpsc::Receiver<Result<MarketEvent, BinanceArchiveError>>,
+}
+
+impl BinanceArchiveStream {
+    /// Instrument the rows belong to
+    pub fn instrument(&self) -> &InstrumentId {
+        &self.instrument
+    }
+}
+
+#[async_trait::async_trait]
+impl MarketDataStream for BinanceArchiveStream {
+    type Error = BinanceArchiveError;
+
+    /// Returns the first malformed row as an error, after which the stream ends
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        self.receiver.recv().await.transpose()
+    }
+
+    async fn subscribe(&mut self, _instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, _instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+fn field<'a>(fields: &[&'a str], index: usize) -> Result<&'a str, String> {
+    fields
+        .get(index)
+        .map(|value| value.trim())
+        .ok_or_else(|| format!("missing column {}", index))
+}
+
+fn decimal(fields: &[&str], index: usize) -> Result<Decimal, String> {
+    let value = field(fields, index)?;
+    Decimal::from_str(value)
+        .map_err(|e| format!("column {}: invalid decimal {:?}: {}", index, value, e))
+}
+
+fn timestamp(fields: &[&str], index: usize) -> Result<DateTime<Utc>, String> {
+    let value = field(fields, index)?;
+    let raw: i64 = value
+        .parse()
+        .map_err(|_| format!("column {}: invalid timestamp {:?}", index, value))?;
+    let time = if raw >= MICROS_THRESHOLD {
+        DateTime::from_timestamp_micros(raw)
+    } else {
+        DateTime::from_timestamp_millis(raw)
+    };
+    time.ok_or_else(|| format!("column {}: timestamp out of range", index))
+}
+
+/// Build a trade from the given columns of a trades or aggTrades row
+fn trade_row(
+    fields: &[&str],
+    id: usize,
+    price: usize,
+    quantity: usize,
+    time: usize,
+    buyer_maker: usize,
+) -> Result<PublicTrade, String> {
+    // The buyer being the maker means the aggressor sold
+    let side = match field(fields, buyer_maker)? {
+        "True" | "true" => Side::Sell,
+        "False" | "false" => Side::Buy,
+        other => return Err(format!("column {}: invalid flag {:?}", buyer_maker, other)),
+    };
+    Ok(PublicTrade {
+        id: field(fields, id)?.to_string(),
+        price: decimal(fields, price)?,
+        quantity: decimal(fields, quantity)?,
+        side,
+        timestamp: timestamp(fields, time)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_trades_and_agg_trades() {
+        let trades = "\
+3370348716,42283.58000000,0.00104000,43.97492320,1704067200018,True,True
+3370348717,42283.59000000,0.02940000,1243.13754600,1704067200018123,False,True
+";
+        let events: Vec<_> = BinanceArchiveReader::new(
+            trades.as_bytes(),
+            BinanceArchiveKind::Trades,
+            instrument_from_symbol("BTCUSDT"),
+        )
+        .collect::<Result<_, _>>()
+        .unwrap();
+        assert_eq!(events.len(), 2);
+        let MarketDataKind::Trade(first) = &events[0].kind else {
+            panic!("expected trade");
+        };
+        assert_eq!(first.id, "3370348716");
+        assert_eq!(first.price, dec!(42283.58000000));
+        assert_eq!(first.side, Side::Sell);
+        assert_eq!(
+            events[0].exchange_time,
+            Utc.timestamp_millis_opt(1_704_067_200_018).unwrap()
+        );
+        // Microsecond timestamps from newer spot files
+        assert_eq!(
+            events[1].exchange_time,
+            DateTime::from_timestamp_micros(1_704_067_200_018_123).unwrap()
+        );
+
+        let agg_trades = "\
+agg_trade_id,price,quantity,first_trade_id,last_trade_id,transact_time,is_buyer_maker
+1,0.5,10,1,3,1704067200000,false
+";
+        let mut reader = BinanceArchiveReader::new(
+            agg_trades.as_bytes(),
+            BinanceArchiveKind::AggTrades,
+            instrument_from_symbol("XRPUSDT"),
+        );
+        let event = reader.next_event().unwrap().unwrap();
+        assert_eq!(event.instrument.base, "XRP");
+        assert!(matches!(
+            event.kind,
+            MarketDataKind::Trade(PublicTrade {
+                side: Side::Buy,
+                ..
+            })
+        ));
+        assert!(reader.next_event().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_klines_from_file() {
+        let path =
+            std::env::temp_dir().join(format!("ETHUSDT-1m-2024-01-01-{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "1704067200000,2281.87000000,2283.00000000,2281.01000000,2282.40000000,\
+             313.60720000,1704067259999,715803.14540900,1016,143.64620000,327878.73011500,0\n\
+             1704067260000,2282.40,x,2281.01,2282.40,1,1704067319999,1,1,1,1,0\n",
+        )
+        .unwrap();
+
+        let mut reader = BinanceArchiveReader::open(&path).unwrap();
+        let event = reader.next_event().unwrap().unwrap();
+        let MarketDataKind::Candle(candle) = &event.kind else {
+            panic!("expected candle");
+        };
+        assert_eq!(candle.high, dec!(2283.00000000));
+        assert_eq!(candle.volume, dec!(313.60720000));
+        assert_eq!(candle.duration_secs, 60);
+        assert_eq!(
+            candle.timestamp,
+            Utc.timestamp_millis_opt(1_704_067_200_000).unwrap()
+        );
+        assert_eq!(
+            event.exchange_time,
+            Utc.timestamp_millis_opt(1_704_067_259_999).unwrap()
+        );
+        assert!(matches!(
+            reader.next_event(),
+            Err(BinanceArchiveError::Malformed { line: 2, .. })
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stream_reads_on_reader_thread() {
+        let trades = "\
+1,0.5,10,5,1704067200000,false,true
+2,0.6,x,6,1704067200001,false,true
+";
+        let mut stream = BinanceArchiveReader::new(
+            trades.as_bytes(),
+            BinanceArchiveKind::Trades,
+            instrument_from_symbol("XRPUSDT"),
+        )
+        .into_stream()
+        .unwrap();
+        assert_eq!(stream.instrument().base, "XRP");
+        let event = stream.next().await.unwrap().unwrap();
+        assert_eq!(
+            event.exchange_time,
+            Utc.timestamp_millis_opt(1_704_067_200_000).unwrap()
+        );
+        assert!(matches!(
+            stream.next().await,
+            Err(BinanceArchiveError::Malformed { line: 2, .. })
+        ));
+        assert!(stream.next().await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_futures_archive_path_sets_kind() {
+        let root = std::env::temp_dir().join(format!("binance-archive-{}", std::process::id()));
+        let dir = root.join("futures/um/daily/aggTrades/BTCUSDT");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("BTCUSDT-aggTrades-2024-01-01.csv");
+        std::fs::write(&path, "1,42000.1,0.5,1,1,1704067200000,false\n").unwrap();
+
+        let mut reader = BinanceArchiveReader::open(&path).unwrap();
+        assert_eq!(
+            reader.instrument().kind,
+            crate::data::InstrumentKind::Perpetual
+        );
+        let event = reader.next_event().unwrap().unwrap();
+        assert_eq!(event.instrument.base, "BTC");
+        assert_eq!(
+            event.instrument.kind,
+            crate::data::InstrumentKind::Perpetual
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
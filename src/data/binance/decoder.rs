@@ -0,0 +1,355 @@
+//! Typed Binance frame decoder
+//!
+//! `BinanceDecoder` decodes combined stream frames with borrowed `Deserialize` structs
+//! instead of building a `serde_json::Value` tree. The envelope is read first with the
+//! payload kept as a `RawValue`; the payload is then decoded straight into the struct
+//! for its channel, with prices and quantities parsed into `Decimal` from the borrowed
+//! JSON strings. Instruments are cached per symbol so the base/quote split happens once.
+//!
+//! `benches/binance_decoder.rs` compares it with `parse_websocket_message`.
+
+use super::{BinanceError, KlineInterval};
+use crate::data::{
+    Candle, ExchangeId, InstrumentId, LevelAction, LevelUpdate, MarketDataKind, MarketEvent,
+    OrderBookDelta, OrderBookL1, OrderBookL2, PriceLevel, PublicTrade, Side, Ticker,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use std::collections::HashMap;
+
+/// Errors produced while decoding a frame
+#[derive(Debug, thiserror::Error)]
+pub enum BinanceDecodeError {
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    /// Frame that is neither a stream payload nor a request response
+    #[error("unknown stream: {0}")]
+    UnknownStream(String),
+    /// Millisecond timestamp outside the representable range
+    #[error("invalid timestamp: {0}")]
+    InvalidTimestamp(i64),
+    #[error(transparent)]
+    Binance(#[from] BinanceError),
+}
+
+/// Stateful decoder for Binance combined stream frames
+#[derive(Debug, Clone, Default)]
+pub struct BinanceDecoder {
+    instruments: HashMap<String, InstrumentId>,
+}
+
+impl BinanceDecoder {
+    /// Create a decoder with an empty instrument cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode a frame into a market event.
+    ///
+    /// Returns `Ok(None)` for frames that carry no event, such as subscription
+    /// acknowledgements and klines that have not closed yet.
+    pub fn decode(
+        &mut self,
+        message: &str,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Option<MarketEvent>, BinanceDecodeError> {
+        let frame: Frame<'_> = serde_json::from_str(message)?;
+        let (stream, data) = match (frame.stream, frame.data) {
+            (Some(stream), Some(data)) => (stream, data.get()),
+            // Subscription responses look like {"result":null,"id":1}
+            (None, _) if frame.id.is_some() => return Ok(None),
+            _ => return Err(BinanceDecodeError::UnknownStream(String::new())),
+        };
+        let (symbol, channel) = stream
+            .split_once('@')
+            .ok_or_else(|| BinanceDecodeError::UnknownStream(stream.to_string()))?;
+
+        let (kind, exchange_time) = match channel {
+            "trade" | "aggTrade" => {
+                let trade: TradePayload = serde_json::from_str(data)?;
+                let timestamp = millis(trade.time)?;
+                let trade = PublicTrade {
+                    id: trade.id.to_string(),
+                    price: trade.price,
+                    quantity: trade.quantity,
+                    // The buyer being the maker means the aggressor sold
+                    side: if trade.buyer_is_maker {
+                        Side::Sell
+                    } else {
+                        Side::Buy
+                    },
+                    timestamp,
+                };
+                (MarketDataKind::Trade(trade), timestamp)
+            }
+            "bookTicker" => {
+                let ticker: BookTickerPayload = serde_json::from_str(data)?;
+                let book = OrderBookL1 {
+                    bid_price: ticker.bid_price,
+                    bid_quantity: ticker.bid_quantity,
+                    ask_price: ticker.ask_price,
+                    ask_quantity: ticker.ask_quantity,
+                    timestamp: receipt_time,
+                    update_id: ticker.update_id,
+                };
+                (MarketDataKind::OrderBookL1(book), receipt_time)
+            }
+            channel if channel == "depth" || channel.starts_with("depth@") => {
+                let depth: DepthUpdatePayload = serde_json::from_str(data)?;
+                let exchange_time = millis(depth.event_time)?;
+                let updates = level_updates(depth.bids, Side::Buy)
+                    .chain(level_updates(depth.asks, Side::Sell))
+                    .collect();
+                let delta = OrderBookDelta {
+                    updates,
+                    first_update_id: depth.first_update_id,
+                    last_update_id: depth.last_update_id,
+                    snapshot: false,
+                    timestamp: exchange_time,
+                };
+                (MarketDataKind::OrderBookDelta(delta), exchange_time)
+            }
+            channel if channel.starts_with("depth") => {
+                let depth: DepthPayload = serde_json::from_str(data)?;
+                let book = OrderBookL2 {
+                    bids: depth.bids.into_iter().map(PriceLevel::from).collect(),
+                    asks: depth.asks.into_iter().map(PriceLevel::from).collect(),
+                    timestamp: receipt_time,
+                };
+                (MarketDataKind::OrderBookL2(book), receipt_time)
+            }
+            channel if channel.starts_with("kline_") => {
+                let payload: KlinePayload<'_> = serde_json::from_str(data)?;
+                let kline = payload.kline;
+                if !kline.closed {
+                    return Ok(None);
+                }
+                let interval: KlineInterval = kline.interval.parse()?;
+                let candle = Candle {
+                    open: kline.open,
+                    high: kline.high,
+                    low: kline.low,
+                    close: kline.close,
+                    volume: kline.volume,
+                    timestamp: millis(kline.start_time)?,
+                    duration_secs: interval.duration_secs(),
+                };
+                (MarketDataKind::Candle(candle), millis(payload.event_time)?)
+            }
+            "miniTicker" => {
+                let ticker: MiniTickerPayload = serde_json::from_str(data)?;
+                let timestamp = millis(ticker.event_time)?;
+                let ticker = Ticker {
+                    open: ticker.open,
+                    high: ticker.high,
+                    low: ticker.low,
+                    close: ticker.close,
+                    volume: ticker.volume,
+                    quote_volume: ticker.quote_volume,
+                    timestamp,
+                };
+                (MarketDataKind::Ticker(ticker), timestamp)
+            }
+            _ => return Err(BinanceDecodeError::UnknownStream(stream.to_string())),
+        };
+
+        Ok(Some(MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: self.instrument(symbol),
+            kind,
+            exchange_time,
+            receipt_time,
+        }))
+    }
+
+    /// Instrument for a lowercase stream symbol, split on first use
+    fn instrument(&mut self, symbol: &str) -> InstrumentId {
+        if let Some(instrument) = self.instruments.get(symbol) {
+            return instrument.clone();
+        }
+        let instrument = super::instrument_from_symbol(symbol);
+        self.instruments
+            .insert(symbol.to_string(), instrument.clone());
+        instrument
+    }
+}
+
+pub(super) fn millis(timestamp: i64) -> Result<DateTime<Utc>, BinanceDecodeError> {
+    DateTime::from_timestamp_millis(timestamp)
+        .ok_or(BinanceDecodeError::InvalidTimestamp(timestamp))
+}
+
+/// Depth changes, where a zero quantity removes the level
+fn level_updates(levels: Vec<Level>, side: Side) -> impl Iterator<Item = LevelUpdate> {
+    levels
+        .into_iter()
+        .map(move |Level(price, quantity)| LevelUpdate {
+            side,
+            price,
+            quantity,
+            action: if quantity.is_zero() {
+                LevelAction::Delete
+            } else {
+                LevelAction::Update
+            },
+        })
+}
+
+/// Combined stream envelope, or a request response when only `id` is set
+#[derive(Deserialize)]
+pub(super) struct Frame<'a> {
+    #[serde(borrow, default)]
+    pub(super) stream: Option<&'a str>,
+    #[serde(borrow, default)]
+    pub(super) data: Option<&'a RawValue>,
+    #[serde(default)]
+    pub(super) id: Option<u64>,
+}
+
+/// `["price", "quantity"]` pair
+#[derive(Deserialize)]
+struct Level(Decimal, Decimal);
+
+impl From<Level> for PriceLevel {
+    fn from(Level(price, quantity): Level) -> Self {
+        PriceLevel { price, quantity }
+    }
+}
+
+/// `@trade` and `@aggTrade` payload
+#[derive(Deserialize)]
+struct TradePayload {
+    #[serde(rename = "t", alias = "a")]
+    id: u64,
+    #[serde(rename = "p")]
+    price: Decimal,
+    #[serde(rename = "q")]
+    quantity: Decimal,
+    #[serde(rename = "T")]
+    time: i64,
+    #[serde(rename = "m")]
+    buyer_is_maker: bool,
+}
+
+#[derive(Deserialize)]
+struct BookTickerPayload {
+    #[serde(rename = "u", default)]
+    update_id: Option<u64>,
+    #[serde(rename = "b")]
+    bid_price: Decimal,
+    #[serde(rename = "B")]
+    bid_quantity: Decimal,
+    #[serde(rename = "a")]
+    ask_price: Decimal,
+    #[serde(rename = "A")]
+    ask_quantity: Decimal,
+}
+
+/// Partial book depth payload
+#[derive(Deserialize)]
+struct DepthPayload {
+    bids: Vec<Level>,
+    asks: Vec<Level>,
+}
+
+/// Diff depth payload
+#[derive(Deserialize)]
+struct DepthUpdatePayload {
+    #[serde(rename = "E")]
+    event_time: i64,
+    #[serde(rename = "U", default)]
+    first_update_id: Option<u64>,
+    #[serde(rename = "u", default)]
+    last_update_id: Option<u64>,
+    #[serde(rename = "b")]
+    bids: Vec<Level>,
+    #[serde(rename = "a")]
+    asks: Vec<Level>,
+}
+
+#[derive(Deserialize)]
+struct KlinePayload<'a> {
+    #[serde(rename = "E")]
+    event_time: i64,
+    #[serde(rename = "k", borrow)]
+    kline: Kline<'a>,
+}
+
+#[derive(Deserialize)]
+struct Kline<'a> {
+    #[serde(rename = "t")]
+    start_time: i64,
+    #[serde(rename = "i")]
+    interval: &'a str,
+    #[serde(rename = "o")]
+    open: Decimal,
+    #[serde(rename = "h")]
+    high: Decimal,
+    #[serde(rename = "l")]
+    low: Decimal,
+    #[serde(rename = "c")]
+    close: Decimal,
+    #[serde(rename = "v")]
+    volume: Decimal,
+    #[serde(rename = "x")]
+    closed: bool,
+}
+
+#[derive(Deserialize)]
+struct MiniTickerPayload {
+    #[serde(rename = "E")]
+    event_time: i64,
+    #[serde(rename = "o")]
+    open: Decimal,
+    #[serde(rename = "h")]
+    high: Decimal,
+    #[serde(rename = "l")]
+    low: Decimal,
+    #[serde(rename = "c")]
+    close: Decimal,
+    #[serde(rename = "v")]
+    volume: Decimal,
+    #[serde(rename = "q")]
+    quote_volume: Decimal,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::binance::parse_value_message;
+
+    const FRAMES: &str = include_str!("../../../tests/fixtures/binance/frames.jsonl");
+
+    #[test]
+    fn test_decoder_matches_value_parser() {
+        let mut decoder = BinanceDecoder::new();
+        let receipt_time = Utc::now();
+        let mut events = 0;
+        for frame in FRAMES.lines() {
+            let decoded = decoder.decode(frame, receipt_time).unwrap();
+            let expected = parse_value_message(frame, receipt_time).unwrap();
+            assert_eq!(decoded, expected, "frame {}", frame);
+            events += decoded.is_some() as usize;
+        }
+        assert_eq!(events, 9);
+
+        // Diff depth at 1000ms has no speed suffix
+        let frame = r#"{"stream":"btcusdt@depth","data":{"e":"depthUpdate","E":1710770535000,"s":"BTCUSDT","U":161,"u":163,"b":[],"a":[["67890.13","1.25"]]}}"#;
+        let decoded = decoder.decode(frame, receipt_time).unwrap().unwrap();
+        assert!(matches!(decoded.kind, MarketDataKind::OrderBookDelta(_)));
+        assert_eq!(
+            Some(decoded),
+            parse_value_message(frame, receipt_time).unwrap()
+        );
+
+        assert!(matches!(
+            decoder.decode(r#"{"stream":"btcusdt@bogus","data":{}}"#, receipt_time),
+            Err(BinanceDecodeError::UnknownStream(stream)) if stream == "btcusdt@bogus"
+        ));
+        assert!(decoder
+            .decode(r#"{"stream":"btcusdt@trade","data":{"t":1}}"#, receipt_time)
+            .is_err());
+    }
+}
@@ -0,0 +1,780 @@
+//! Binance spot market data
+//!
+//! `BinanceConnector` normalizes the trade, aggregate trade, book ticker, partial
+//! depth, kline and mini ticker streams of the Binance combined stream endpoint into
+//! `MarketEvent`s; `BinanceMarketDataStream` runs it over the shared WebSocket
+//! transport. Which streams are subscribed per symbol is driven by
+//! `BinanceSubscription`, typically derived from the `SubscriptionKind`s of a
+//! `DataConfig`, whose update frequency also selects the 100ms or 1000ms depth streams.
+//! Frames are decoded by the allocation-light `BinanceDecoder`. Historical CSV
+//! archives are read by the `archive` submodule and REST backfills are provided by
+//! `rest`. USDⓈ-M perpetual and dated futures streams live in `usdm`.
+
+use super::connector::{ExchangeConnector, Heartbeat, RateLimit, WebSocketMarketDataStream};
+use super::{
+    Candle, ExchangeId, InstrumentId, InstrumentKind, LevelAction, LevelUpdate, MarketDataKind,
+    MarketEvent, OrderBookDelta, OrderBookL1, OrderBookL2, PriceLevel, PublicTrade, Side,
+    SubscriptionKind, Ticker,
+};
+use crate::config::DataConfig;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub mod archive;
+pub mod decoder;
+pub mod rest;
+pub mod usdm;
+
+pub use archive::{
+    BinanceArchiveError, BinanceArchiveKind, BinanceArchiveReader, BinanceArchiveStream,
+};
+pub use decoder::{BinanceDecodeError, BinanceDecoder};
+pub use rest::{BackfillKind, BinanceBackfillStream, BinanceHistoricalClient, BinanceRestError};
+pub use usdm::{
+    BinanceFuturesConnector, BinanceFuturesDecoder, BinanceFuturesMarketDataStream,
+    BinanceFuturesSubscription,
+};
+
+/// Default Binance combined stream endpoint
+pub const BINANCE_WS_URL: &str = "wss://stream.binance.com:9443/stream";
+
+/// Quote assets recognised when splitting a Binance symbol into base and quote
+const QUOTE_ASSETS: [&str; 10] = [
+    "USDT", "USDC", "FDUSD", "TUSD", "BUSD", "BTC", "ETH", "BNB", "EUR", "TRY",
+];
+
+/// Errors produced while configuring Binance subscriptions
+#[derive(Debug, thiserror::Error)]
+pub enum BinanceError {
+    /// Subscription without a Binance stream equivalent
+    #[error("unsupported subscription: {0:?}")]
+    UnsupportedSubscription(SubscriptionKind),
+    /// Kline interval not offered by Binance
+    #[error("unsupported kline interval: {0}")]
+    UnsupportedInterval(String),
+}
+
+/// Kline intervals offered by Binance
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum KlineInterval {
+    Second1,
+    Minute1,
+    Minute3,
+    Minute5,
+    Minute15,
+    Minute30,
+    Hour1,
+    Hour2,
+    Hour4,
+    Hour6,
+    Hour8,
+    Hour12,
+    Day1,
+    Day3,
+    Week1,
+}
+
+impl KlineInterval {
+    /// Every interval, shortest first
+    pub const ALL: [KlineInterval; 15] = [
+        KlineInterval::Second1,
+        KlineInterval::Minute1,
+        KlineInterval::Minute3,
+        KlineInterval::Minute5,
+        KlineInterval::Minute15,
+        KlineInterval::Minute30,
+        KlineInterval::Hour1,
+        KlineInterval::Hour2,
+        KlineInterval::Hour4,
+        KlineInterval::Hour6,
+        KlineInterval::Hour8,
+        KlineInterval::Hour12,
+        KlineInterval::Day1,
+        KlineInterval::Day3,
+        KlineInterval::Week1,
+    ];
+
+    /// Interval lasting exactly `secs` seconds
+    pub fn from_secs(secs: u64) -> Result<Self, BinanceError> {
+        Self::ALL
+            .into_iter()
+            .find(|candidate| candidate.duration_secs() == secs)
+            .ok_or_else(|| BinanceError::UnsupportedInterval(format!("{}s", secs)))
+    }
+
+    /// Binance interval code, e.g. `1m`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KlineInterval::Second1 => "1s",
+            KlineInterval::Minute1 => "1m",
+            KlineInterval::Minute3 => "3m",
+            KlineInterval::Minute5 => "5m",
+            KlineInterval::Minute15 => "15m",
+            KlineInterval::Minute30 => "30m",
+            KlineInterval::Hour1 => "1h",
+            KlineInterval::Hour2 => "2h",
+            KlineInterval::Hour4 => "4h",
+            KlineInterval::Hour6 => "6h",
+            KlineInterval::Hour8 => "8h",
+            KlineInterval::Hour12 => "12h",
+            KlineInterval::Day1 => "1d",
+            KlineInterval::Day3 => "3d",
+            KlineInterval::Week1 => "1w",
+        }
+    }
+
+    /// Length of the interval in seconds
+    pub fn duration_secs(&self) -> u64 {
+        match self {
+            KlineInterval::Second1 => 1,
+            KlineInterval::Minute1 => 60,
+            KlineInterval::Minute3 => 3 * 60,
+            KlineInterval::Minute5 => 5 * 60,
+            KlineInterval::Minute15 => 15 * 60,
+            KlineInterval::Minute30 => 30 * 60,
+            KlineInterval::Hour1 => 3600,
+            KlineInterval::Hour2 => 2 * 3600,
+            KlineInterval::Hour4 => 4 * 3600,
+            KlineInterval::Hour6 => 6 * 3600,
+            KlineInterval::Hour8 => 8 * 3600,
+            KlineInterval::Hour12 => 12 * 3600,
+            KlineInterval::Day1 => 86400,
+            KlineInterval::Day3 => 3 * 86400,
+            KlineInterval::Week1 => 7 * 86400,
+        }
+    }
+}
+
+impl FromStr for KlineInterval {
+    type Err = BinanceError;
+
+    fn from_str(interval: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|candidate| candidate.as_str() == interval)
+            .ok_or_else(|| BinanceError::UnsupportedInterval(interval.to_string()))
+    }
+}
+
+/// Binance stream kinds that can be subscribed per symbol
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum BinanceSubscription {
+    /// Raw trades (`@trade`)
+    Trade,
+    /// Aggregate trades (`@aggTrade`)
+    AggTrade,
+    /// Best bid/ask with update id (`@bookTicker`)
+    BookTicker,
+    /// Top 5, 10 or 20 levels of the book every 100 or 1000ms (`@depth20@100ms`)
+    Depth { levels: u16, update_ms: u16 },
+    /// Incremental depth updates every 100 or 1000ms (`@depth@100ms`), to be seeded
+    /// from a REST snapshot
+    DepthDiff { update_ms: u16 },
+    /// Closed candles at the given interval (`@kline_<interval>`)
+    Kline(KlineInterval),
+    /// Rolling 24 hour statistics (`@miniTicker`)
+    MiniTicker,
+}
+
+impl BinanceSubscription {
+    /// Map a subscription kind to a Binance stream.
+    ///
+    /// Depth streams update every 100ms unless `update_frequency_ms` is a second or
+    /// more. `L2` uses the smallest partial depth stream covering the requested depth;
+    /// deeper books need `BookDeltas`.
+    pub fn from_kind(
+        kind: SubscriptionKind,
+        update_frequency_ms: u64,
+    ) -> Result<Self, BinanceError> {
+        let update_ms = if update_frequency_ms >= 1000 {
+            1000
+        } else {
+            100
+        };
+        match kind {
+            SubscriptionKind::Trades => Ok(BinanceSubscription::Trade),
+            SubscriptionKind::L1 => Ok(BinanceSubscription::BookTicker),
+            SubscriptionKind::L2 { depth } => [5, 10, 20]
+                .into_iter()
+                .find(|levels| usize::from(*levels) >= depth)
+                .map(|levels| BinanceSubscription::Depth { levels, update_ms })
+                .ok_or(BinanceError::UnsupportedSubscription(kind)),
+            SubscriptionKind::BookDeltas => Ok(BinanceSubscription::DepthDiff { update_ms }),
+            SubscriptionKind::Candles { interval_secs } => Ok(BinanceSubscription::Kline(
+                KlineInterval::from_secs(interval_secs)?,
+            )),
+            SubscriptionKind::Ticker => Ok(BinanceSubscription::MiniTicker),
+            _ => Err(BinanceError::UnsupportedSubscription(kind)),
+        }
+    }
+
+    /// Stream name for a lowercase symbol, e.g. `btcusdt@bookTicker`
+    pub fn stream_name(&self, symbol: &str) -> String {
+        match self {
+            BinanceSubscription::Trade => format!("{}@trade", symbol),
+            BinanceSubscription::AggTrade => format!("{}@aggTrade", symbol),
+            BinanceSubscription::BookTicker => format!("{}@bookTicker", symbol),
+            BinanceSubscription::Depth { levels, update_ms } => {
+                format!("{}@depth{}{}", symbol, levels, update_speed(*update_ms))
+            }
+            BinanceSubscription::DepthDiff { update_ms } => {
+                format!("{}@depth{}", symbol, update_speed(*update_ms))
+            }
+            BinanceSubscription::Kline(interval) => {
+                format!("{}@kline_{}", symbol, interval.as_str())
+            }
+            BinanceSubscription::MiniTicker => format!("{}@miniTicker", symbol),
+        }
+    }
+}
+
+/// Depth stream suffix; 1000ms is the unsuffixed default
+fn update_speed(update_ms: u16) -> &'static str {
+    if update_ms < 1000 {
+        "@100ms"
+    } else {
+        ""
+    }
+}
+
+/// Binance real-time market data stream
+pub type BinanceMarketDataStream = WebSocketMarketDataStream<BinanceConnector>;
+
+impl WebSocketMarketDataStream<BinanceConnector> {
+    /// Create a new Binance market data stream subscribing to trades and the book ticker
+    pub fn new() -> Self {
+        Self::from_connector(BinanceConnector::new())
+    }
+
+    /// Parse Binance WebSocket message into MarketEvent.
+    ///
+    /// Returns `Ok(None)` for recognised messages that carry no event, such as
+    /// subscription acknowledgements and klines that have not closed yet. This walks a
+    /// `serde_json::Value` tree; `BinanceDecoder` produces the same events faster.
+    pub fn parse_websocket_message(
+        message: &str,
+    ) -> Result<Option<MarketEvent>, Box<dyn std::error::Error + Send + Sync>> {
+        parse_value_message(message, Utc::now())
+    }
+}
+
+/// Binance spot `ExchangeConnector`
+#[derive(Debug, Clone)]
+pub struct BinanceConnector {
+    url: String,
+    subscriptions: Vec<BinanceSubscription>,
+    /// Overrides of `subscriptions` by lowercase symbol
+    instrument_subscriptions: HashMap<String, Vec<BinanceSubscription>>,
+    decoder: BinanceDecoder,
+}
+
+impl BinanceConnector {
+    /// Create a connector subscribing to trades and the book ticker
+    pub fn new() -> Self {
+        Self {
+            url: BINANCE_WS_URL.to_string(),
+            subscriptions: vec![BinanceSubscription::Trade, BinanceSubscription::BookTicker],
+            instrument_subscriptions: HashMap::new(),
+            decoder: BinanceDecoder::new(),
+        }
+    }
+
+    /// Create a connector subscribing to the market data listed in the configuration,
+    /// including per-instrument overrides
+    pub fn from_config(config: &DataConfig) -> Result<Self, BinanceError> {
+        let streams = |kinds: &[SubscriptionKind]| {
+            kinds
+                .iter()
+                .map(|kind| BinanceSubscription::from_kind(*kind, config.update_frequency_ms))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let mut connector = Self::new().with_subscriptions(streams(&config.subscriptions)?);
+        for subscription in &config.instrument_subscriptions {
+            connector = connector.with_instrument_subscriptions(
+                &subscription.instrument,
+                streams(&subscription.kinds)?,
+            );
+        }
+        Ok(connector)
+    }
+
+    /// Replace the streams subscribed for each instrument
+    pub fn with_subscriptions(mut self, subscriptions: Vec<BinanceSubscription>) -> Self {
+        self.subscriptions = subscriptions;
+        self
+    }
+
+    /// Subscribe to other streams for one instrument
+    pub fn with_instrument_subscriptions(
+        mut self,
+        instrument: &InstrumentId,
+        subscriptions: Vec<BinanceSubscription>,
+    ) -> Self {
+        self.instrument_subscriptions
+            .insert(instrument.exchange_symbol.to_lowercase(), subscriptions);
+        self
+    }
+
+    /// Use a different WebSocket endpoint, e.g. a mock server in tests
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Streams subscribed for each instrument without an override
+    pub fn subscriptions(&self) -> &[BinanceSubscription] {
+        &self.subscriptions
+    }
+
+    /// Streams subscribed for an instrument
+    pub fn subscriptions_for(&self, instrument: &InstrumentId) -> &[BinanceSubscription] {
+        self.instrument_subscriptions
+            .get(&instrument.exchange_symbol.to_lowercase())
+            .unwrap_or(&self.subscriptions)
+    }
+
+    /// One request per instrument listing its streams
+    fn requests(&self, method: &str, instruments: &[InstrumentId]) -> Vec<String> {
+        instruments
+            .iter()
+            .map(|instrument| {
+                let symbol = instrument.exchange_symbol.to_lowercase();
+                let params: Vec<String> = self
+                    .subscriptions_for(instrument)
+                    .iter()
+                    .map(|subscription| subscription.stream_name(&symbol))
+                    .collect();
+                serde_json::json!({ "method": method, "params": params, "id": 1 }).to_string()
+            })
+            .collect()
+    }
+}
+
+impl Default for BinanceConnector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExchangeConnector for BinanceConnector {
+    type Error = BinanceDecodeError;
+
+    fn exchange(&self) -> ExchangeId {
+        ExchangeId::Binance
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn subscribe_messages(&self, instruments: &[InstrumentId]) -> Result<Vec<String>, Self::Error> {
+        Ok(self.requests("SUBSCRIBE", instruments))
+    }
+
+    fn unsubscribe_messages(
+        &self,
+        instruments: &[InstrumentId],
+    ) -> Result<Vec<String>, Self::Error> {
+        Ok(self.requests("UNSUBSCRIBE", instruments))
+    }
+
+    fn decode(
+        &mut self,
+        frame: &str,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Vec<MarketEvent>, Self::Error> {
+        Ok(self
+            .decoder
+            .decode(frame, receipt_time)?
+            .into_iter()
+            .collect())
+    }
+
+    /// The server pings every 20 seconds and drops connections that stop answering
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat {
+            ping: None,
+            idle_timeout: Some(Duration::from_secs(60)),
+        }
+    }
+
+    /// Binance accepts 5 incoming messages per second per connection
+    fn rate_limit(&self) -> Option<RateLimit> {
+        Some(RateLimit {
+            messages: 5,
+            per: Duration::from_secs(1),
+        })
+    }
+}
+
+/// `serde_json::Value` based frame parser behind `parse_websocket_message`
+pub(crate) fn parse_value_message(
+    message: &str,
+    receipt_time: DateTime<Utc>,
+) -> Result<Option<MarketEvent>, Box<dyn std::error::Error + Send + Sync>> {
+    let v: Value = serde_json::from_str(message)?;
+
+    // Subscription responses look like {"result":null,"id":1}
+    if v.get("result").is_some() && v.get("id").is_some() {
+        return Ok(None);
+    }
+
+    let stream = v
+        .get("stream")
+        .and_then(|s| s.as_str())
+        .ok_or("Unknown message format")?;
+    let data = v.get("data").ok_or("Missing data field")?;
+    let (symbol, channel) = stream.split_once('@').ok_or("Unknown message format")?;
+
+    let instrument = instrument_from_symbol(symbol);
+
+    let (kind, exchange_time) = match channel {
+        "trade" => {
+            let trade = parse_trade(data, "t")?;
+            let exchange_time = trade.timestamp;
+            (MarketDataKind::Trade(trade), exchange_time)
+        }
+        "aggTrade" => {
+            let trade = parse_trade(data, "a")?;
+            let exchange_time = trade.timestamp;
+            (MarketDataKind::Trade(trade), exchange_time)
+        }
+        "bookTicker" => {
+            let book = OrderBookL1 {
+                bid_price: decimal_field(data, "b")?,
+                bid_quantity: decimal_field(data, "B")?,
+                ask_price: decimal_field(data, "a")?,
+                ask_quantity: decimal_field(data, "A")?,
+                timestamp: receipt_time,
+                update_id: data.get("u").and_then(|u| u.as_u64()),
+            };
+            (MarketDataKind::OrderBookL1(book), receipt_time)
+        }
+        channel if channel == "depth" || channel.starts_with("depth@") => {
+            let mut updates = parse_level_updates(data.get("b"), Side::Buy)?;
+            updates.extend(parse_level_updates(data.get("a"), Side::Sell)?);
+            let exchange_time = time_field(data, "E")?;
+            let delta = OrderBookDelta {
+                updates,
+                first_update_id: data.get("U").and_then(|u| u.as_u64()),
+                last_update_id: data.get("u").and_then(|u| u.as_u64()),
+                snapshot: false,
+                timestamp: exchange_time,
+            };
+            (MarketDataKind::OrderBookDelta(delta), exchange_time)
+        }
+        channel if channel.starts_with("depth") => {
+            let book = OrderBookL2 {
+                bids: parse_levels(data.get("bids"))?,
+                asks: parse_levels(data.get("asks"))?,
+                timestamp: receipt_time,
+            };
+            (MarketDataKind::OrderBookL2(book), receipt_time)
+        }
+        channel if channel.starts_with("kline_") => {
+            let kline = data.get("k").ok_or("Missing kline field")?;
+            if !kline.get("x").and_then(|x| x.as_bool()).unwrap_or(false) {
+                return Ok(None);
+            }
+            let interval: KlineInterval = kline
+                .get("i")
+                .and_then(|i| i.as_str())
+                .unwrap_or_default()
+                .parse()?;
+            let candle = Candle {
+                open: decimal_field(kline, "o")?,
+                high: decimal_field(kline, "h")?,
+                low: decimal_field(kline, "l")?,
+                close: decimal_field(kline, "c")?,
+                volume: decimal_field(kline, "v")?,
+                timestamp: time_field(kline, "t")?,
+                duration_secs: interval.duration_secs(),
+            };
+            (MarketDataKind::Candle(candle), time_field(data, "E")?)
+        }
+        "miniTicker" => {
+            let timestamp = time_field(data, "E")?;
+            let ticker = Ticker {
+                open: decimal_field(data, "o")?,
+                high: decimal_field(data, "h")?,
+                low: decimal_field(data, "l")?,
+                close: decimal_field(data, "c")?,
+                volume: decimal_field(data, "v")?,
+                quote_volume: decimal_field(data, "q")?,
+                timestamp,
+            };
+            (MarketDataKind::Ticker(ticker), timestamp)
+        }
+        _ => return Err("Unknown message format".into()),
+    };
+
+    Ok(Some(MarketEvent {
+        exchange: ExchangeId::Binance,
+        instrument,
+        kind,
+        exchange_time,
+        receipt_time,
+    }))
+}
+
+/// Split a Binance symbol such as `btcusdt` into base and quote assets
+fn instrument_from_symbol(symbol: &str) -> InstrumentId {
+    let exchange_symbol = symbol.to_uppercase();
+    let (base, quote) = QUOTE_ASSETS
+        .iter()
+        .find_map(|quote| {
+            exchange_symbol
+                .strip_suffix(quote)
+                .filter(|base| !base.is_empty())
+                .map(|base| (base.to_string(), quote.to_string()))
+        })
+        .unwrap_or_else(|| (exchange_symbol.clone(), String::new()));
+    InstrumentId {
+        base,
+        quote,
+        exchange_symbol,
+        kind: InstrumentKind::Spot,
+    }
+}
+
+/// Parse a `@trade` or `@aggTrade` payload, taking the trade id from `id_field`
+fn parse_trade(
+    data: &Value,
+    id_field: &str,
+) -> Result<PublicTrade, Box<dyn std::error::Error + Send + Sync>> {
+    // The buyer being the maker means the aggressor sold
+    let side = if data.get("m").and_then(|m| m.as_bool()).unwrap_or(false) {
+        Side::Sell
+    } else {
+        Side::Buy
+    };
+
+    Ok(PublicTrade {
+        id: data
+            .get(id_field)
+            .and_then(|t| t.as_u64())
+            .unwrap_or(0)
+            .to_string(),
+        price: decimal_field(data, "p")?,
+        quantity: decimal_field(data, "q")?,
+        side,
+        timestamp: time_field(data, "T")?,
+    })
+}
+
+/// Parse `[["price", "quantity"], ...]` depth levels
+fn parse_levels(
+    levels: Option<&Value>,
+) -> Result<Vec<PriceLevel>, Box<dyn std::error::Error + Send + Sync>> {
+    let levels = levels
+        .and_then(|l| l.as_array())
+        .ok_or("Missing depth levels")?;
+    levels
+        .iter()
+        .map(|level| {
+            let price = level.get(0).and_then(|p| p.as_str()).unwrap_or("0");
+            let quantity = level.get(1).and_then(|q| q.as_str()).unwrap_or("0");
+            Ok(PriceLevel {
+                price: Decimal::from_str(price)?,
+                quantity: Decimal::from_str(quantity)?,
+            })
+        })
+        .collect()
+}
+
+/// Parse `[["price", "quantity"], ...]` depth changes, where a zero quantity removes the level
+fn parse_level_updates(
+    levels: Option<&Value>,
+    side: Side,
+) -> Result<Vec<LevelUpdate>, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(parse_levels(levels)?
+        .into_iter()
+        .map(|level| LevelUpdate {
+            side,
+            action: if level.quantity.is_zero() {
+                LevelAction::Delete
+            } else {
+                LevelAction::Update
+            },
+            price: level.price,
+            quantity: level.quantity,
+        })
+        .collect())
+}
+
+/// Parse a string-encoded decimal field
+fn decimal_field(
+    data: &Value,
+    field: &str,
+) -> Result<Decimal, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(Decimal::from_str(
+        data.get(field).and_then(|v| v.as_str()).unwrap_or("0"),
+    )?)
+}
+
+/// Parse a millisecond timestamp field
+fn time_field(
+    data: &Value,
+    field: &str,
+) -> Result<DateTime<Utc>, Box<dyn std::error::Error + Send + Sync>> {
+    data.get(field)
+        .and_then(|v| v.as_i64())
+        .and_then(DateTime::from_timestamp_millis)
+        .ok_or_else(|| format!("Missing or invalid {} timestamp", field).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::connector::decode_frames;
+    use rust_decimal_macros::dec;
+
+    const FRAMES: &str = include_str!("../../../tests/fixtures/binance/frames.jsonl");
+
+    fn parse(message: &str) -> MarketEvent {
+        BinanceMarketDataStream::parse_websocket_message(message)
+            .unwrap()
+            .expect("message should produce an event")
+    }
+
+    #[test]
+    fn test_parse_book_ticker() {
+        let event = parse(
+            r#"{"stream":"btcusdt@bookTicker","data":{"u":400900217,"s":"BTCUSDT","b":"67890.12000000","B":"1.50000000","a":"67890.13000000","A":"0.25000000"}}"#,
+        );
+        let MarketDataKind::OrderBookL1(book) = event.kind else {
+            panic!("expected L1, got {:?}", event.kind);
+        };
+        assert_eq!(event.instrument.base, "BTC");
+        assert_eq!(event.instrument.quote, "USDT");
+        assert_eq!(book.bid_price, dec!(67890.12));
+        assert_eq!(book.ask_quantity, dec!(0.25));
+        assert_eq!(book.update_id, Some(400900217));
+    }
+
+    #[test]
+    fn test_parse_agg_trade() {
+        let event = parse(
+            r#"{"stream":"ethbtc@aggTrade","data":{"e":"aggTrade","E":1710770531002,"s":"ETHBTC","a":455120,"p":"0.05412000","q":"1.20000000","f":500100,"l":500102,"T":1710770531001,"m":true,"M":true}}"#,
+        );
+        let MarketDataKind::Trade(trade) = event.kind else {
+            panic!("expected trade, got {:?}", event.kind);
+        };
+        assert_eq!(event.instrument.base, "ETH");
+        assert_eq!(event.instrument.quote, "BTC");
+        assert_eq!(trade.id, "455120");
+        assert_eq!(trade.side, Side::Sell);
+        assert_eq!(event.exchange_time.timestamp_millis(), 1710770531001);
+    }
+
+    #[test]
+    fn test_parse_kline_only_when_closed() {
+        let open = r#"{"stream":"btcusdt@kline_1m","data":{"e":"kline","E":1710770535000,"s":"BTCUSDT","k":{"t":1710770520000,"T":1710770579999,"s":"BTCUSDT","i":"1m","f":1,"L":9,"o":"67880.00","c":"67890.00","h":"67895.00","l":"67875.00","v":"12.5","n":9,"x":false,"q":"848625.0","V":"6.0","Q":"407340.0","B":"0"}}}"#;
+        assert!(BinanceMarketDataStream::parse_websocket_message(open)
+            .unwrap()
+            .is_none());
+
+        let closed = open.replace("\"x\":false", "\"x\":true");
+        let MarketDataKind::Candle(candle) = parse(&closed).kind else {
+            panic!("expected candle");
+        };
+        assert_eq!(candle.duration_secs, 60);
+        assert_eq!(candle.high, dec!(67895.00));
+        assert_eq!(candle.timestamp.timestamp_millis(), 1710770520000);
+    }
+
+    #[test]
+    fn test_parse_mini_ticker_and_depth() {
+        let ticker = parse(
+            r#"{"stream":"bnbusdt@miniTicker","data":{"e":"24hrMiniTicker","E":1710770535000,"s":"BNBUSDT","c":"560.1","o":"540.0","h":"565.0","l":"538.2","v":"102345.1","q":"56700000.5"}}"#,
+        );
+        assert!(matches!(ticker.kind, MarketDataKind::Ticker(ref t) if t.close == dec!(560.1)));
+
+        let depth = parse(
+            r#"{"stream":"btcusdt@depth20@100ms","data":{"lastUpdateId":160,"bids":[["67890.12","1.5"],["67890.00","2"]],"asks":[["67890.13","0.25"]]}}"#,
+        );
+        let MarketDataKind::OrderBookL2(book) = depth.kind else {
+            panic!("expected L2");
+        };
+        assert_eq!(book.bids.len(), 2);
+        assert_eq!(book.asks[0].price, dec!(67890.13));
+
+        let diff = parse(
+            r#"{"stream":"btcusdt@depth@100ms","data":{"e":"depthUpdate","E":1710770535000,"s":"BTCUSDT","U":161,"u":163,"b":[["67890.12","0.00000000"]],"a":[["67890.13","1.25"]]}}"#,
+        );
+        let MarketDataKind::OrderBookDelta(delta) = diff.kind else {
+            panic!("expected delta");
+        };
+        assert_eq!(
+            (delta.first_update_id, delta.last_update_id),
+            (Some(161), Some(163))
+        );
+        assert_eq!(delta.updates[0].action, LevelAction::Delete);
+        assert_eq!(delta.updates[1].side, Side::Sell);
+        assert_eq!(delta.updates[1].quantity, dec!(1.25));
+    }
+
+    #[test]
+    fn test_connector_decodes_fixture() {
+        let mut connector = BinanceConnector::new();
+        let events = decode_frames(&mut connector, FRAMES, Utc::now()).unwrap();
+        assert_eq!(events.len(), 9);
+        assert!(events
+            .iter()
+            .all(|event| event.instrument.kind == InstrumentKind::Spot));
+
+        let instruments = [
+            instrument_from_symbol("btcusdt"),
+            instrument_from_symbol("ethbtc"),
+        ];
+        let messages = connector.subscribe_messages(&instruments).unwrap();
+        assert_eq!(
+            messages[1],
+            r#"{"id":1,"method":"SUBSCRIBE","params":["ethbtc@trade","ethbtc@bookTicker"]}"#
+        );
+        assert!(connector.unsubscribe_messages(&instruments).unwrap()[0].contains("UNSUBSCRIBE"));
+    }
+
+    #[test]
+    fn test_subscriptions_from_config() {
+        let mut config = crate::config::SystemConfig::default().data;
+        config.subscriptions = vec![
+            SubscriptionKind::L1,
+            SubscriptionKind::Candles { interval_secs: 300 },
+        ];
+        let ethbtc = instrument_from_symbol("ethbtc");
+        config.instrument_subscriptions = vec![crate::data::InstrumentSubscription {
+            instrument: ethbtc.clone(),
+            kinds: vec![
+                SubscriptionKind::L2 { depth: 8 },
+                SubscriptionKind::BookDeltas,
+            ],
+        }];
+        config.update_frequency_ms = 1000;
+        let connector = BinanceConnector::from_config(&config).unwrap();
+        assert_eq!(
+            connector.subscriptions(),
+            [
+                BinanceSubscription::BookTicker,
+                BinanceSubscription::Kline(KlineInterval::Minute5)
+            ]
+        );
+        assert_eq!(
+            connector.subscriptions()[1].stream_name("btcusdt"),
+            "btcusdt@kline_5m"
+        );
+
+        let messages = connector.subscribe_messages(&[ethbtc]).unwrap();
+        assert!(messages[0].contains(r#"["ethbtc@depth10","ethbtc@depth"]"#));
+
+        config.subscriptions = vec![SubscriptionKind::L2 { depth: 50 }];
+        assert!(BinanceConnector::from_config(&config).is_err());
+        config.subscriptions = vec![SubscriptionKind::Candles { interval_secs: 7 }];
+        assert!(BinanceConnector::from_config(&config).is_err());
+    }
+}
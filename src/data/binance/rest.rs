@@ -0,0 +1,730 @@
+//! Binance REST historical backfill
+//!
+//! `BinanceHistoricalClient` pages through `/api/v3/klines` and `/api/v3/aggTrades`
+//! for a time range. Request weight is tracked from the `X-MBX-USED-WEIGHT-1M` header
+//! and the client waits for the next minute before a request would exceed the limit.
+//! `429` (rate limited) and `418` (IP banned) responses are retried after the
+//! `Retry-After` delay, falling back to exponential backoff.
+//!
+//! `depth_snapshot` fetches `/api/v3/depth` to seed an `OrderBook` kept up to date
+//! from `@depth` diff updates.
+//!
+//! Backfills are exposed as `BinanceBackfillStream`s so they can be consumed, merged
+//! or recorded like any live `MarketDataStream`.
+
+use super::{
+    instrument_from_symbol, parse_level_updates, parse_trade, BinanceError, KlineInterval,
+};
+use crate::config::DataConfig;
+use crate::data::{
+    Candle, ExchangeId, InstrumentId, MarketDataKind, MarketDataStream, MarketEvent,
+    OrderBookDelta, Side, SubscriptionKind,
+};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use rust_decimal::Decimal;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+/// Default Binance spot REST endpoint
+pub const BINANCE_REST_URL: &str = "https://api.binance.com";
+
+/// Default request weight allowed per minute
+pub const BINANCE_WEIGHT_LIMIT: u32 = 6000;
+
+/// Rows requested per page, the maximum both endpoints allow
+const PAGE_LIMIT: usize = 1000;
+const KLINES_WEIGHT: u32 = 2;
+const AGG_TRADES_WEIGHT: u32 = 4;
+/// `aggTrades` rejects time windows of an hour or more
+const AGG_TRADES_WINDOW_MS: i64 = 60 * 60 * 1000 - 1;
+
+/// Errors produced by the REST client
+#[derive(Debug, thiserror::Error)]
+pub enum BinanceRestError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    /// Non-success response other than rate limiting
+    #[error("unexpected status {status}: {body}")]
+    Status { status: u16, body: String },
+    /// Still rate limited after every retry
+    #[error("rate limited after {attempts} attempts")]
+    RateLimited { attempts: u32 },
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid response: {0}")]
+    InvalidResponse(String),
+}
+
+/// Dataset fetched by a backfill
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BackfillKind {
+    /// Aggregate trades from `/api/v3/aggTrades`
+    AggTrades,
+    /// Candles from `/api/v3/klines`
+    Klines(KlineInterval),
+}
+
+impl BackfillKind {
+    /// Map a subscription kind to a backfillable dataset.
+    ///
+    /// Trades are backfilled as aggregate trades; book, ticker and futures kinds have
+    /// no REST history and map to `None`.
+    pub fn from_kind(kind: SubscriptionKind) -> Result<Option<Self>, BinanceError> {
+        Ok(match kind {
+            SubscriptionKind::Trades => Some(BackfillKind::AggTrades),
+            SubscriptionKind::Candles { interval_secs } => Some(BackfillKind::Klines(
+                KlineInterval::from_secs(interval_secs)?,
+            )),
+            _ => None,
+        })
+    }
+}
+
+/// Request weight used in the current minute
+#[derive(Debug, Default)]
+struct WeightTracker {
+    minute: i64,
+    used: u32,
+}
+
+/// Client for Binance historical market data endpoints
+#[derive(Clone)]
+pub struct BinanceHistoricalClient {
+    http: reqwest::Client,
+    base_url: String,
+    weight_limit: u32,
+    max_retries: u32,
+    weight: Arc<Mutex<WeightTracker>>,
+}
+
+impl BinanceHistoricalClient {
+    /// Create a client for the public Binance endpoint
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: BINANCE_REST_URL.to_string(),
+            weight_limit: BINANCE_WEIGHT_LIMIT,
+            max_retries: 5,
+            weight: Arc::new(Mutex::new(WeightTracker::default())),
+        }
+    }
+
+    /// Create a client if historical data is enabled in the configuration
+    pub fn from_config(config: &DataConfig) -> Option<Self> {
+        config.enable_historical_data.then(Self::new)
+    }
+
+    /// Use a different REST endpoint, e.g. a mock server in tests
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Request weight to stay under per minute
+    pub fn with_weight_limit(mut self, weight_limit: u32) -> Self {
+        self.weight_limit = weight_limit;
+        self
+    }
+
+    /// Retries after `429`/`418` responses before giving up
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Backfill streams for every backfillable kind each instrument subscribes to
+    pub fn backfill_from_config(
+        &self,
+        config: &DataConfig,
+        instruments: &[InstrumentId],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<BinanceBackfillStream>, BinanceError> {
+        let mut streams = Vec::new();
+        for instrument in instruments {
+            let mut kinds = Vec::new();
+            for subscription in config.subscriptions_for(instrument) {
+                if let Some(kind) = BackfillKind::from_kind(*subscription)? {
+                    if !kinds.contains(&kind) {
+                        kinds.push(kind);
+                    }
+                }
+            }
+            streams.extend(
+                kinds
+                    .into_iter()
+                    .map(|kind| self.backfill(instrument, kind, start, end)),
+            );
+        }
+        Ok(streams)
+    }
+
+    /// Stream `kind` events for an instrument between `start` and `end` inclusive
+    pub fn backfill(
+        &self,
+        instrument: &InstrumentId,
+        kind: BackfillKind,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> BinanceBackfillStream {
+        BinanceBackfillStream {
+            client: self.clone(),
+            symbol: instrument.exchange_symbol.to_uppercase(),
+            kind,
+            cursor: Cursor::Time(start.timestamp_millis()),
+            end: end.timestamp_millis(),
+            buffer: VecDeque::new(),
+        }
+    }
+
+    /// Fetch up to 1000 closed candles opening at or after `start`
+    pub async fn klines(
+        &self,
+        symbol: &str,
+        interval: KlineInterval,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<MarketEvent>, BinanceRestError> {
+        let query = [
+            ("symbol", symbol.to_string()),
+            ("interval", interval.as_str().to_string()),
+            ("startTime", start.timestamp_millis().to_string()),
+            ("endTime", end.timestamp_millis().to_string()),
+            ("limit", PAGE_LIMIT.to_string()),
+        ];
+        let body = self.get("/api/v3/klines", &query, KLINES_WEIGHT).await?;
+        let rows: Vec<KlineRow> = serde_json::from_str(&body)?;
+
+        let now = Utc::now();
+        let instrument = instrument_from_symbol(symbol);
+        let mut events = Vec::with_capacity(rows.len());
+        for row in rows {
+            let close_time = millis(row.6)?;
+            // The current interval is still open and would be revised later
+            if close_time > now {
+                break;
+            }
+            let candle = Candle {
+                open: row.1,
+                high: row.2,
+                low: row.3,
+                close: row.4,
+                volume: row.5,
+                timestamp: millis(row.0)?,
+                duration_secs: interval.duration_secs(),
+            };
+            events.push(MarketEvent {
+                exchange: ExchangeId::Binance,
+                instrument: instrument.clone(),
+                kind: MarketDataKind::Candle(candle),
+                exchange_time: close_time,
+                receipt_time: close_time,
+            });
+        }
+        Ok(events)
+    }
+
+    /// Fetch a depth snapshot as an `OrderBookDelta` that replaces the book.
+    ///
+    /// Used to seed an `OrderBook` before applying `@depth` diff updates; `limit` is
+    /// capped at 5000 levels per side.
+    pub async fn depth_snapshot(
+        &self,
+        symbol: &str,
+        limit: usize,
+    ) -> Result<MarketEvent, BinanceRestError> {
+        let limit = limit.clamp(1, 5000);
+        let weight = match limit {
+            0..=100 => 5,
+            101..=500 => 25,
+            501..=1000 => 50,
+            _ => 250,
+        };
+        let query = [("symbol", symbol.to_string()), ("limit", limit.to_string())];
+        let body = self.get("/api/v3/depth", &query, weight).await?;
+        let data: Value = serde_json::from_str(&body)?;
+
+        let invalid = |e: Box<dyn std::error::Error + Send + Sync>| {
+            BinanceRestError::InvalidResponse(e.to_string())
+        };
+        let mut updates = parse_level_updates(data.get("bids"), Side::Buy).map_err(invalid)?;
+        updates.extend(parse_level_updates(data.get("asks"), Side::Sell).map_err(invalid)?);
+        let update_id = data.get("lastUpdateId").and_then(|id| id.as_u64());
+
+        let now = Utc::now();
+        Ok(MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: instrument_from_symbol(symbol),
+            kind: MarketDataKind::OrderBookDelta(OrderBookDelta {
+                updates,
+                first_update_id: update_id,
+                last_update_id: update_id,
+                snapshot: true,
+                timestamp: now,
+            }),
+            exchange_time: now,
+            receipt_time: now,
+        })
+    }
+
+    /// Fetch up to 1000 aggregate trades, either from `from_id` or within a time window
+    /// of less than an hour
+    pub async fn agg_trades(
+        &self,
+        symbol: &str,
+        from_id: Option<u64>,
+        window: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> Result<Vec<(u64, MarketEvent)>, BinanceRestError> {
+        let mut query = vec![
+            ("symbol", symbol.to_string()),
+            ("limit", PAGE_LIMIT.to_string()),
+        ];
+        if let Some(from_id) = from_id {
+            query.push(("fromId", from_id.to_string()));
+        }
+        if let Some((start, end)) = window {
+            query.push(("startTime", start.timestamp_millis().to_string()));
+            query.push(("endTime", end.timestamp_millis().to_string()));
+        }
+        let body = self
+            .get("/api/v3/aggTrades", &query, AGG_TRADES_WEIGHT)
+            .await?;
+        let rows: Vec<Value> = serde_json::from_str(&body)?;
+
+        let instrument = instrument_from_symbol(symbol);
+        rows.iter()
+            .map(|row| {
+                let id = row
+                    .get("a")
+                    .and_then(|a| a.as_u64())
+                    .ok_or_else(|| BinanceRestError::InvalidResponse("missing trade id".into()))?;
+                let trade = parse_trade(row, "a")
+                    .map_err(|e| BinanceRestError::InvalidResponse(e.to_string()))?;
+                let time = trade.timestamp;
+                Ok((
+                    id,
+                    MarketEvent {
+                        exchange: ExchangeId::Binance,
+                        instrument: instrument.clone(),
+                        kind: MarketDataKind::Trade(trade),
+                        exchange_time: time,
+                        receipt_time: time,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    /// Issue a GET request, honouring the weight limit and retrying when rate limited
+    async fn get(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+        weight: u32,
+    ) -> Result<String, BinanceRestError> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut attempt = 0;
+        loop {
+            self.reserve_weight(weight).await;
+            let response = self.http.get(&url).query(query).send().await?;
+            let status = response.status().as_u16();
+
+            if let Some(used) = header_u64(&response, "x-mbx-used-weight-1m") {
+                let mut tracker = self.weight.lock();
+                tracker.minute = current_minute();
+                tracker.used = used as u32;
+            }
+
+            if status == 429 || status == 418 {
+                attempt += 1;
+                if attempt > self.max_retries {
+                    return Err(BinanceRestError::RateLimited { attempts: attempt });
+                }
+                let delay = header_u64(&response, "retry-after")
+                    .map(Duration::from_secs)
+                    .unwrap_or_else(|| Duration::from_secs(1 << attempt.min(6)));
+                warn!(
+                    "Binance returned {} for {}, retrying in {:?}",
+                    status, path, delay
+                );
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+
+            let body = response.text().await?;
+            if !(200..300).contains(&status) {
+                return Err(BinanceRestError::Status { status, body });
+            }
+            return Ok(body);
+        }
+    }
+
+    /// Wait until `weight` fits within the current minute's limit, then claim it
+    async fn reserve_weight(&self, weight: u32) {
+        loop {
+            let wait = {
+                let mut tracker = self.weight.lock();
+                let minute = current_minute();
+                if tracker.minute != minute {
+                    tracker.minute = minute;
+                    tracker.used = 0;
+                }
+                if tracker.used + weight <= self.weight_limit {
+                    tracker.used += weight;
+                    return;
+                }
+                let next_minute = (minute + 1) * 60_000;
+                Duration::from_millis((next_minute - Utc::now().timestamp_millis()).max(1) as u64)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+impl Default for BinanceHistoricalClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `[open_time, open, high, low, close, volume, close_time, ...]` kline row
+#[derive(Deserialize)]
+struct KlineRow(
+    i64,
+    Decimal,
+    Decimal,
+    Decimal,
+    Decimal,
+    Decimal,
+    i64,
+    IgnoredAny,
+    IgnoredAny,
+    IgnoredAny,
+    IgnoredAny,
+    IgnoredAny,
+);
+
+fn millis(value: i64) -> Result<DateTime<Utc>, BinanceRestError> {
+    DateTime::from_timestamp_millis(value)
+        .ok_or_else(|| BinanceRestError::InvalidResponse(format!("invalid timestamp {}", value)))
+}
+
+fn current_minute() -> i64 {
+    Utc::now().timestamp_millis().div_euclid(60_000)
+}
+
+fn header_u64(response: &reqwest::Response, name: &str) -> Option<u64> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Position of a backfill within its range
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Cursor {
+    /// Next request starts at this time in milliseconds
+    Time(i64),
+    /// Next aggregate trade id to request
+    TradeId(u64),
+    Done,
+}
+
+/// Market data stream paging through a historical range
+pub struct BinanceBackfillStream {
+    client: BinanceHistoricalClient,
+    symbol: String,
+    kind: BackfillKind,
+    cursor: Cursor,
+    end: i64,
+    buffer: VecDeque<MarketEvent>,
+}
+
+impl BinanceBackfillStream {
+    /// Fetch the next page into the buffer
+    async fn fetch_page(&mut self) -> Result<(), BinanceRestError> {
+        let end = millis(self.end)?;
+        match (self.kind, self.cursor) {
+            (_, Cursor::Done) => {}
+            (BackfillKind::Klines(interval), Cursor::Time(start)) => {
+                let page = self
+                    .client
+                    .klines(&self.symbol, interval, millis(start)?, end)
+                    .await?;
+                let interval_ms = interval.duration_secs() as i64 * 1000;
+                self.cursor = match page.last() {
+                    Some(MarketEvent {
+                        kind: MarketDataKind::Candle(candle),
+                        ..
+                    }) if page.len() == PAGE_LIMIT => {
+                        Cursor::Time(candle.timestamp.timestamp_millis() + interval_ms)
+                    }
+                    _ => Cursor::Done,
+                };
+                self.buffer.extend(page);
+            }
+            (BackfillKind::AggTrades, Cursor::Time(start)) => {
+                // Walk forward in hour windows until the first trade is found
+                let window_end = (start + AGG_TRADES_WINDOW_MS).min(self.end);
+                let page = self
+                    .client
+                    .agg_trades(
+                        &self.symbol,
+                        None,
+                        Some((millis(start)?, millis(window_end)?)),
+                    )
+                    .await?;
+                self.cursor = match page.last() {
+                    Some((id, _)) => Cursor::TradeId(id + 1),
+                    None if window_end < self.end => Cursor::Time(window_end + 1),
+                    None => Cursor::Done,
+                };
+                self.buffer.extend(page.into_iter().map(|(_, event)| event));
+            }
+            (BackfillKind::AggTrades, Cursor::TradeId(from_id)) => {
+                let page = self
+                    .client
+                    .agg_trades(&self.symbol, Some(from_id), None)
+                    .await?;
+                let full = page.len() == PAGE_LIMIT;
+                let mut next = Cursor::Done;
+                for (id, event) in page {
+                    if event.exchange_time > end {
+                        break;
+                    }
+                    next = Cursor::TradeId(id + 1);
+                    self.buffer.push_back(event);
+                }
+                self.cursor = if full { next } else { Cursor::Done };
+            }
+            (BackfillKind::Klines(_), Cursor::TradeId(_)) => self.cursor = Cursor::Done,
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl MarketDataStream for BinanceBackfillStream {
+    type Error = BinanceRestError;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        loop {
+            if let Some(event) = self.buffer.pop_front() {
+                return Ok(Some(event));
+            }
+            if self.cursor == Cursor::Done {
+                return Ok(None);
+            }
+            self.fetch_page().await?;
+        }
+    }
+
+    async fn subscribe(&mut self, _instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, _instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::mock_server::{MockHttpResponse, MockHttpServer};
+    use crate::data::PublicTrade;
+    use chrono::{Duration as ChronoDuration, TimeZone};
+    use rust_decimal_macros::dec;
+
+    const START_MS: i64 = 1_704_067_200_000;
+
+    fn kline_row(open_time: i64) -> String {
+        format!(
+            r#"[{},"42000.10","42010.00","41990.00","42005.50","12.5",{},"525000","100","6","252000","0"]"#,
+            open_time,
+            open_time + 59_999
+        )
+    }
+
+    fn agg_trade(id: u64, time: i64, buyer_maker: bool) -> String {
+        format!(
+            r#"{{"a":{},"p":"42000.00","q":"0.125","f":{},"l":{},"T":{},"m":{},"M":true}}"#,
+            id, id, id, time, buyer_maker
+        )
+    }
+
+    #[tokio::test]
+    async fn test_klines_backfill_pages_through_range() {
+        let first_page: Vec<_> = (0..PAGE_LIMIT as i64)
+            .map(|i| kline_row(START_MS + i * 60_000))
+            .collect();
+        let second_start = START_MS + PAGE_LIMIT as i64 * 60_000;
+        let server = MockHttpServer::start(vec![
+            MockHttpResponse::json(format!("[{}]", first_page.join(",")))
+                .with_header("X-MBX-USED-WEIGHT-1M", "2"),
+            MockHttpResponse::json(format!("[{}]", kline_row(second_start))),
+        ])
+        .await
+        .unwrap();
+
+        let client = BinanceHistoricalClient::new().with_base_url(server.url());
+        let instrument = instrument_from_symbol("BTCUSDT");
+        let start = Utc.timestamp_millis_opt(START_MS).unwrap();
+        let mut stream = client.backfill(
+            &instrument,
+            BackfillKind::Klines(KlineInterval::Minute1),
+            start,
+            start + ChronoDuration::days(1),
+        );
+
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            events.push(event);
+        }
+        assert_eq!(events.len(), PAGE_LIMIT + 1);
+        let MarketDataKind::Candle(candle) = &events[0].kind else {
+            panic!("expected candle");
+        };
+        assert_eq!(candle.open, dec!(42000.10));
+        assert_eq!(candle.timestamp, start);
+        assert_eq!(
+            events[0].exchange_time.timestamp_millis(),
+            START_MS + 59_999
+        );
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].starts_with("/api/v3/klines?symbol=BTCUSDT&interval=1m"));
+        assert!(requests[1].contains(&format!("startTime={}", second_start)));
+    }
+
+    #[tokio::test]
+    async fn test_agg_trades_backfill_retries_and_stops_at_end() {
+        let end_ms = START_MS + 10_000;
+        let server = MockHttpServer::start(vec![
+            MockHttpResponse::status(429).with_header("Retry-After", "0"),
+            MockHttpResponse::json(format!(
+                "[{},{}]",
+                agg_trade(7, START_MS + 1_000, true),
+                agg_trade(8, START_MS + 2_000, false)
+            )),
+            MockHttpResponse::json(format!(
+                "[{},{}]",
+                agg_trade(9, START_MS + 9_000, false),
+                agg_trade(10, end_ms + 1, false)
+            )),
+        ])
+        .await
+        .unwrap();
+
+        let client = BinanceHistoricalClient::new().with_base_url(server.url());
+        let mut stream = client.backfill(
+            &instrument_from_symbol("ETHBTC"),
+            BackfillKind::AggTrades,
+            Utc.timestamp_millis_opt(START_MS).unwrap(),
+            Utc.timestamp_millis_opt(end_ms).unwrap(),
+        );
+
+        let mut trades = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            let MarketDataKind::Trade(trade) = event.kind else {
+                panic!("expected trade");
+            };
+            trades.push(trade);
+        }
+        let ids: Vec<_> = trades
+            .iter()
+            .map(|trade: &PublicTrade| trade.id.as_str())
+            .collect();
+        assert_eq!(ids, ["7", "8", "9"]);
+        assert_eq!(trades[0].side, crate::data::Side::Sell);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].contains(&format!("startTime={}", START_MS)));
+        assert!(requests[2].contains("fromId=9"));
+    }
+
+    #[tokio::test]
+    async fn test_depth_snapshot_seeds_order_book() {
+        let server = MockHttpServer::start(vec![MockHttpResponse::json(
+            r#"{"lastUpdateId":160,"bids":[["100.00","1.5"],["99.99","2"]],"asks":[["100.01","0.5"]]}"#,
+        )])
+        .await
+        .unwrap();
+        let client = BinanceHistoricalClient::new().with_base_url(server.url());
+        let snapshot = client.depth_snapshot("BTCUSDT", 100).await.unwrap();
+
+        let mut book = crate::data::OrderBook::new(
+            crate::data::InstrumentScale::new(dec!(0.01), dec!(0.00001)).unwrap(),
+        );
+        assert!(book.update(&snapshot).unwrap());
+        assert_eq!(book.last_update_id(), Some(160));
+        assert_eq!(book.best_bid().unwrap().price, dec!(100.00));
+        assert_eq!(book.best_ask().unwrap().quantity, dec!(0.5));
+        assert_eq!(
+            server.requests(),
+            ["/api/v3/depth?symbol=BTCUSDT&limit=100"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_retries_are_bounded() {
+        let server = MockHttpServer::start(vec![
+            MockHttpResponse::status(418).with_header("Retry-After", "0"),
+            MockHttpResponse::status(418).with_header("Retry-After", "0"),
+        ])
+        .await
+        .unwrap();
+        let client = BinanceHistoricalClient::new()
+            .with_base_url(server.url())
+            .with_max_retries(1);
+        let start = Utc.timestamp_millis_opt(START_MS).unwrap();
+        let result = client
+            .klines("BTCUSDT", KlineInterval::Hour1, start, start)
+            .await;
+        assert!(matches!(
+            result,
+            Err(BinanceRestError::RateLimited { attempts: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_backfill_from_config() {
+        let mut config = DataConfig {
+            enable_market_data: true,
+            subscriptions: vec![
+                SubscriptionKind::Trades,
+                SubscriptionKind::L1,
+                SubscriptionKind::Candles {
+                    interval_secs: 3600,
+                },
+            ],
+            instrument_subscriptions: vec![],
+            update_frequency_ms: 100,
+            enable_historical_data: false,
+        };
+        assert!(BinanceHistoricalClient::from_config(&config).is_none());
+        config.enable_historical_data = true;
+        let client = BinanceHistoricalClient::from_config(&config).unwrap();
+        let start = Utc.timestamp_millis_opt(START_MS).unwrap();
+        let streams = client
+            .backfill_from_config(&config, &[instrument_from_symbol("BTCUSDT")], start, start)
+            .unwrap();
+        let kinds: Vec<_> = streams.iter().map(|stream| stream.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                BackfillKind::AggTrades,
+                BackfillKind::Klines(KlineInterval::Hour1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,895 @@
+//! Binance USDⓈ-M futures market data
+//!
+//! `BinanceFuturesConnector` normalizes the USDⓈ-M futures combined stream endpoint
+//! over the shared WebSocket transport. `@markPrice` frames carry both the mark price
+//! and the current funding rate and are split into a `MarkPrice` and a `FundingRate`
+//! event; `@forceOrder` frames become `Liquidation`s. Trade, book ticker, depth, kline
+//! and mini ticker frames share the spot payload layout and are decoded by
+//! `BinanceDecoder`. Open interest has no stream and is polled from
+//! `/fapi/v1/openInterest` when enabled.
+//!
+//! `from_config` maps `SubscriptionKind::Funding` to `@markPrice`,
+//! `SubscriptionKind::Liquidations` to `@forceOrder` and polls open interest for
+//! instruments subscribing to `SubscriptionKind::OpenInterest`, at most once a second.
+//!
+//! Perpetual symbols such as `BTCUSDT` map to `InstrumentKind::Perpetual`; quarterly
+//! contracts such as `BTCUSDT_250926` map to `InstrumentKind::DatedFuture`, expiring at
+//! 08:00 UTC on the delivery date.
+
+use super::decoder::{millis, Frame};
+use super::{
+    BinanceDecodeError, BinanceDecoder, BinanceError, BinanceRestError, BinanceSubscription,
+};
+use crate::config::DataConfig;
+use crate::data::connector::{ExchangeConnector, Heartbeat, RateLimit, WebSocketMarketDataStream};
+use crate::data::{
+    ExchangeId, FundingRate, InstrumentId, InstrumentKind, Liquidation, MarkPrice, MarketDataKind,
+    MarketDataStream, MarketEvent, OpenInterest, Side, SubscriptionKind,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use parking_lot::Mutex;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Default Binance USDⓈ-M futures combined stream endpoint
+pub const BINANCE_FUTURES_WS_URL: &str = "wss://fstream.binance.com/stream";
+
+/// Default Binance USDⓈ-M futures REST endpoint
+pub const BINANCE_FUTURES_REST_URL: &str = "https://fapi.binance.com";
+
+/// Shortest open interest polling interval used by `from_config`
+const OPEN_INTEREST_MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Binance futures stream kinds that can be subscribed per symbol
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BinanceFuturesSubscription {
+    /// Mark price, index price and funding rate every second (`@markPrice@1s`)
+    MarkPrice,
+    /// Liquidation orders (`@forceOrder`)
+    ForceOrder,
+    /// Stream with the same layout as on spot, such as `@aggTrade` or `@bookTicker`
+    Market(BinanceSubscription),
+}
+
+impl BinanceFuturesSubscription {
+    /// Map a subscription kind to a futures stream.
+    ///
+    /// Open interest has no stream and maps to `None`; other kinds map as on spot.
+    pub fn from_kind(
+        kind: SubscriptionKind,
+        update_frequency_ms: u64,
+    ) -> Result<Option<Self>, BinanceError> {
+        Ok(Some(match kind {
+            SubscriptionKind::Funding => BinanceFuturesSubscription::MarkPrice,
+            SubscriptionKind::Liquidations => BinanceFuturesSubscription::ForceOrder,
+            SubscriptionKind::OpenInterest => return Ok(None),
+            kind => BinanceFuturesSubscription::Market(BinanceSubscription::from_kind(
+                kind,
+                update_frequency_ms,
+            )?),
+        }))
+    }
+
+    /// Stream name for a lowercase symbol, e.g. `btcusdt@markPrice@1s`
+    pub fn stream_name(&self, symbol: &str) -> String {
+        match self {
+            BinanceFuturesSubscription::MarkPrice => format!("{}@markPrice@1s", symbol),
+            BinanceFuturesSubscription::ForceOrder => format!("{}@forceOrder", symbol),
+            BinanceFuturesSubscription::Market(subscription) => subscription.stream_name(symbol),
+        }
+    }
+}
+
+/// Decoder for Binance USDⓈ-M futures combined stream frames
+#[derive(Debug, Clone, Default)]
+pub struct BinanceFuturesDecoder {
+    market: BinanceDecoder,
+    instruments: HashMap<String, InstrumentId>,
+}
+
+impl BinanceFuturesDecoder {
+    /// Create a decoder with an empty instrument cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode a frame into market events.
+    ///
+    /// A `@markPrice` frame yields a `MarkPrice` and, for perpetuals, a `FundingRate`.
+    /// Subscription acknowledgements and open klines yield no events.
+    pub fn decode(
+        &mut self,
+        message: &str,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Vec<MarketEvent>, BinanceDecodeError> {
+        let frame: Frame<'_> = serde_json::from_str(message)?;
+        let (stream, data) = match (frame.stream, frame.data) {
+            (Some(stream), Some(data)) => (stream, data.get()),
+            (None, _) if frame.id.is_some() => return Ok(Vec::new()),
+            _ => return Err(BinanceDecodeError::UnknownStream(String::new())),
+        };
+        // The all-market liquidation stream has no symbol, so match it before splitting
+        let (symbol, channel) = match stream {
+            "!forceOrder@arr" => ("", "forceOrder"),
+            _ => stream
+                .split_once('@')
+                .ok_or_else(|| BinanceDecodeError::UnknownStream(stream.to_string()))?,
+        };
+
+        if channel.starts_with("markPrice") {
+            let payload: MarkPricePayload = serde_json::from_str(data)?;
+            let timestamp = millis(payload.event_time)?;
+            let instrument = self.instrument(symbol);
+            let mut events = vec![self.event(
+                &instrument,
+                MarketDataKind::MarkPrice(MarkPrice {
+                    price: payload.mark_price,
+                    index_price: payload.index_price,
+                    timestamp,
+                }),
+                timestamp,
+                receipt_time,
+            )];
+            // Delivery contracts publish an empty funding rate
+            if let Some(rate) = payload.funding_rate {
+                let funding = FundingRate {
+                    rate,
+                    next_funding_time: millis(payload.next_funding_time)?,
+                    timestamp,
+                };
+                events.push(self.event(
+                    &instrument,
+                    MarketDataKind::FundingRate(funding),
+                    timestamp,
+                    receipt_time,
+                ));
+            }
+            return Ok(events);
+        }
+
+        if channel == "forceOrder" {
+            let payload: ForceOrderPayload<'_> = serde_json::from_str(data)?;
+            let order = payload.order;
+            let timestamp = millis(order.trade_time)?;
+            let liquidation = Liquidation {
+                side: order.side.into(),
+                price: order.price,
+                quantity: order.quantity,
+                average_price: order.average_price,
+                filled_quantity: order.filled_quantity,
+                timestamp,
+            };
+            // `!forceOrder@arr` covers every symbol, so always take it from the order
+            let instrument = self.instrument(order.symbol);
+            return Ok(vec![self.event(
+                &instrument,
+                MarketDataKind::Liquidation(liquidation),
+                timestamp,
+                receipt_time,
+            )]);
+        }
+
+        let event = self.market.decode(message, receipt_time)?;
+        Ok(event
+            .map(|mut event| {
+                event.instrument = self.instrument(symbol);
+                event
+            })
+            .into_iter()
+            .collect())
+    }
+
+    fn event(
+        &self,
+        instrument: &InstrumentId,
+        kind: MarketDataKind,
+        exchange_time: DateTime<Utc>,
+        receipt_time: DateTime<Utc>,
+    ) -> MarketEvent {
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: instrument.clone(),
+            kind,
+            exchange_time,
+            receipt_time,
+        }
+    }
+
+    /// Futures instrument for a stream symbol, resolved on first use
+    fn instrument(&mut self, symbol: &str) -> InstrumentId {
+        if let Some(instrument) = self.instruments.get(symbol) {
+            return instrument.clone();
+        }
+        let instrument = futures_instrument_from_symbol(symbol);
+        self.instruments
+            .insert(symbol.to_string(), instrument.clone());
+        instrument
+    }
+}
+
+/// Split a futures symbol such as `btcusdt` or `btcusdt_250926` into an instrument
+pub fn futures_instrument_from_symbol(symbol: &str) -> InstrumentId {
+    let exchange_symbol = symbol.to_uppercase();
+    let (pair, suffix) = match exchange_symbol.split_once('_') {
+        Some((pair, suffix)) => (pair, Some(suffix)),
+        None => (exchange_symbol.as_str(), None),
+    };
+    let kind = suffix
+        .and_then(|suffix| NaiveDate::parse_from_str(suffix, "%y%m%d").ok())
+        .and_then(|date| date.and_hms_opt(8, 0, 0))
+        .map(|expiry| InstrumentKind::DatedFuture {
+            expiry: expiry.and_utc(),
+        })
+        .unwrap_or(InstrumentKind::Perpetual);
+    let InstrumentId { base, quote, .. } = super::instrument_from_symbol(pair);
+    InstrumentId {
+        base,
+        quote,
+        exchange_symbol,
+        kind,
+    }
+}
+
+/// Fetch the current open interest of a futures instrument from `base_url`
+pub async fn fetch_open_interest(
+    http: &reqwest::Client,
+    base_url: &str,
+    instrument: &InstrumentId,
+) -> Result<MarketEvent, BinanceRestError> {
+    let url = format!(
+        "{}/fapi/v1/openInterest?symbol={}",
+        base_url, instrument.exchange_symbol
+    );
+    let response = http.get(&url).send().await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(BinanceRestError::Status {
+            status: status.as_u16(),
+            body,
+        });
+    }
+    let payload: OpenInterestPayload = serde_json::from_str(&body)?;
+    let timestamp = DateTime::from_timestamp_millis(payload.time).ok_or_else(|| {
+        BinanceRestError::InvalidResponse(format!("invalid timestamp: {}", payload.time))
+    })?;
+    Ok(MarketEvent {
+        exchange: ExchangeId::Binance,
+        instrument: instrument.clone(),
+        kind: MarketDataKind::OpenInterest(OpenInterest {
+            quantity: payload.open_interest,
+            timestamp,
+        }),
+        exchange_time: timestamp,
+        receipt_time: Utc::now(),
+    })
+}
+
+/// Binance USDⓈ-M futures `ExchangeConnector`
+#[derive(Debug, Clone)]
+pub struct BinanceFuturesConnector {
+    url: String,
+    subscriptions: Vec<BinanceFuturesSubscription>,
+    /// Overrides of `subscriptions` by lowercase symbol
+    instrument_subscriptions: HashMap<String, Vec<BinanceFuturesSubscription>>,
+    decoder: BinanceFuturesDecoder,
+}
+
+impl BinanceFuturesConnector {
+    /// Create a connector subscribing to mark price, liquidations and aggregate trades
+    pub fn new() -> Self {
+        Self {
+            url: BINANCE_FUTURES_WS_URL.to_string(),
+            subscriptions: vec![
+                BinanceFuturesSubscription::MarkPrice,
+                BinanceFuturesSubscription::ForceOrder,
+                BinanceFuturesSubscription::Market(BinanceSubscription::AggTrade),
+            ],
+            instrument_subscriptions: HashMap::new(),
+            decoder: BinanceFuturesDecoder::new(),
+        }
+    }
+
+    /// Create a connector subscribing to the market data listed in the configuration,
+    /// including per-instrument overrides
+    pub fn from_config(config: &DataConfig) -> Result<Self, BinanceError> {
+        let streams = |kinds: &[SubscriptionKind]| {
+            let mut streams = Vec::new();
+            for kind in kinds {
+                streams.extend(BinanceFuturesSubscription::from_kind(
+                    *kind,
+                    config.update_frequency_ms,
+                )?);
+            }
+            Ok::<_, BinanceError>(streams)
+        };
+        let mut connector = Self::new().with_subscriptions(streams(&config.subscriptions)?);
+        for subscription in &config.instrument_subscriptions {
+            connector = connector.with_instrument_subscriptions(
+                &subscription.instrument,
+                streams(&subscription.kinds)?,
+            );
+        }
+        Ok(connector)
+    }
+
+    /// Replace the streams subscribed for each instrument
+    pub fn with_subscriptions(mut self, subscriptions: Vec<BinanceFuturesSubscription>) -> Self {
+        self.subscriptions = subscriptions;
+        self
+    }
+
+    /// Subscribe to other streams for one instrument
+    pub fn with_instrument_subscriptions(
+        mut self,
+        instrument: &InstrumentId,
+        subscriptions: Vec<BinanceFuturesSubscription>,
+    ) -> Self {
+        self.instrument_subscriptions
+            .insert(instrument.exchange_symbol.to_lowercase(), subscriptions);
+        self
+    }
+
+    /// Streams subscribed for an instrument
+    pub fn subscriptions_for(&self, instrument: &InstrumentId) -> &[BinanceFuturesSubscription] {
+        self.instrument_subscriptions
+            .get(&instrument.exchange_symbol.to_lowercase())
+            .unwrap_or(&self.subscriptions)
+    }
+
+    /// Use a different WebSocket endpoint, e.g. a mock server in tests
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// One request listing every stream of `instruments`
+    fn request(&self, method: &str, instruments: &[InstrumentId]) -> Vec<String> {
+        let params: Vec<String> = instruments
+            .iter()
+            .flat_map(|instrument| {
+                let symbol = instrument.exchange_symbol.to_lowercase();
+                self.subscriptions_for(instrument)
+                    .iter()
+                    .map(move |subscription| subscription.stream_name(&symbol))
+            })
+            .collect();
+        vec![serde_json::json!({ "method": method, "params": params, "id": 1 }).to_string()]
+    }
+}
+
+impl Default for BinanceFuturesConnector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExchangeConnector for BinanceFuturesConnector {
+    type Error = BinanceDecodeError;
+
+    fn exchange(&self) -> ExchangeId {
+        ExchangeId::Binance
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn subscribe_messages(&self, instruments: &[InstrumentId]) -> Result<Vec<String>, Self::Error> {
+        Ok(self.request("SUBSCRIBE", instruments))
+    }
+
+    fn unsubscribe_messages(
+        &self,
+        instruments: &[InstrumentId],
+    ) -> Result<Vec<String>, Self::Error> {
+        Ok(self.request("UNSUBSCRIBE", instruments))
+    }
+
+    fn decode(
+        &mut self,
+        frame: &str,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Vec<MarketEvent>, Self::Error> {
+        self.decoder.decode(frame, receipt_time)
+    }
+
+    /// The futures server pings every 3 minutes
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat {
+            ping: None,
+            idle_timeout: Some(Duration::from_secs(10 * 60)),
+        }
+    }
+
+    /// USDⓈ-M accepts 10 incoming messages per second per connection
+    fn rate_limit(&self) -> Option<RateLimit> {
+        Some(RateLimit {
+            messages: 10,
+            per: Duration::from_secs(1),
+        })
+    }
+}
+
+/// Binance USDⓈ-M futures real-time market data stream
+///
+/// Streams come from a `BinanceFuturesConnector` over the shared WebSocket transport;
+/// open interest, when enabled, is polled over REST and interleaved with them. One
+/// polling task serves the stream, reading the instruments to poll from a set shared
+/// with `subscribe` and `unsubscribe`, and is stopped when the stream is dropped.
+pub struct BinanceFuturesMarketDataStream {
+    inner: WebSocketMarketDataStream<BinanceFuturesConnector>,
+    open_interest: Option<mpsc::Receiver<MarketEvent>>,
+    /// Instruments the open interest poller requests on each tick
+    polled: Arc<Mutex<Vec<InstrumentId>>>,
+    poller: Option<tokio::task::JoinHandle<()>>,
+    rest_url: String,
+    open_interest_interval: Option<Duration>,
+    /// Configuration the stream was created from, selecting the instruments polled for
+    /// open interest
+    config: Option<DataConfig>,
+}
+
+impl BinanceFuturesMarketDataStream {
+    /// Create a stream subscribing to mark price, liquidations and aggregate trades
+    pub fn new() -> Self {
+        Self::from_connector(BinanceFuturesConnector::new())
+    }
+
+    /// Create a stream driving `connector`
+    pub fn from_connector(connector: BinanceFuturesConnector) -> Self {
+        Self {
+            inner: WebSocketMarketDataStream::from_connector(connector),
+            open_interest: None,
+            polled: Arc::new(Mutex::new(Vec::new())),
+            poller: None,
+            rest_url: BINANCE_FUTURES_REST_URL.to_string(),
+            open_interest_interval: None,
+            config: None,
+        }
+    }
+
+    /// Create a stream subscribing to the market data listed in the configuration
+    pub fn from_config(config: &DataConfig) -> Result<Self, BinanceError> {
+        let mut stream = Self::from_connector(BinanceFuturesConnector::from_config(config)?);
+        if config
+            .all_subscriptions()
+            .contains(&SubscriptionKind::OpenInterest)
+        {
+            let interval = config
+                .update_interval()
+                .unwrap_or_default()
+                .max(OPEN_INTEREST_MIN_INTERVAL);
+            stream = stream.with_open_interest_interval(interval);
+        }
+        stream.config = Some(config.clone());
+        Ok(stream)
+    }
+
+    /// Connector used for new connections
+    pub fn connector(&self) -> &BinanceFuturesConnector {
+        self.inner.connector()
+    }
+
+    /// Mutable connector, to adjust configuration before connecting
+    pub fn connector_mut(&mut self) -> &mut BinanceFuturesConnector {
+        self.inner.connector_mut()
+    }
+
+    /// Use a different REST endpoint for open interest polling
+    pub fn with_rest_url(mut self, rest_url: impl Into<String>) -> Self {
+        self.rest_url = rest_url.into();
+        self
+    }
+
+    /// Poll open interest at this interval, for every subscribed instrument unless the
+    /// stream was created from a configuration
+    pub fn with_open_interest_interval(mut self, interval: Duration) -> Self {
+        self.open_interest_interval = Some(interval);
+        self
+    }
+
+    /// Whether open interest is polled for a subscribed instrument
+    fn polls_open_interest(&self, instrument: &InstrumentId) -> bool {
+        self.config.as_ref().is_none_or(|config| {
+            config
+                .subscriptions_for(instrument)
+                .contains(&SubscriptionKind::OpenInterest)
+        })
+    }
+
+    /// Spawn the open interest poller unless it is already running
+    fn start_poller(&mut self, interval: Duration) {
+        if self.poller.is_some() {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel(100);
+        self.open_interest = Some(receiver);
+        let rest_url = self.rest_url.clone();
+        let polled = self.polled.clone();
+        self.poller = Some(tokio::spawn(async move {
+            let http = reqwest::Client::new();
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let instruments = polled.lock().clone();
+                for instrument in &instruments {
+                    match fetch_open_interest(&http, &rest_url, instrument).await {
+                        Ok(event) => {
+                            if sender.send(event).await.is_err() {
+                                return;
+                            }
+                        }
+                        Err(error) => {
+                            tracing::warn!(symbol = %instrument.exchange_symbol, %error, "open interest poll failed");
+                        }
+                    }
+                }
+            }
+        }));
+    }
+}
+
+impl Drop for BinanceFuturesMarketDataStream {
+    fn drop(&mut self) {
+        if let Some(poller) = &self.poller {
+            poller.abort();
+        }
+    }
+}
+
+impl Default for BinanceFuturesMarketDataStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl MarketDataStream for BinanceFuturesMarketDataStream {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        let Some(open_interest) = &mut self.open_interest else {
+            return self.inner.next().await;
+        };
+        tokio::select! {
+            event = self.inner.next() => event,
+            Some(event) = open_interest.recv() => Ok(Some(event)),
+        }
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.inner.subscribe(instruments).await?;
+
+        if let Some(interval) = self.open_interest_interval {
+            {
+                let mut polled = self.polled.lock();
+                for instrument in instruments {
+                    if !self.polls_open_interest(instrument)
+                        || polled
+                            .iter()
+                            .any(|p| p.exchange_symbol == instrument.exchange_symbol)
+                    {
+                        continue;
+                    }
+                    polled.push(futures_instrument_from_symbol(&instrument.exchange_symbol));
+                }
+            }
+            self.start_poller(interval);
+        }
+
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.polled.lock().retain(|polled| {
+            !instruments
+                .iter()
+                .any(|instrument| instrument.exchange_symbol == polled.exchange_symbol)
+        });
+        self.inner.unsubscribe(instruments).await
+    }
+}
+
+impl futures::Stream for BinanceFuturesMarketDataStream {
+    type Item = Result<MarketEvent, <Self as MarketDataStream>::Error>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        use futures::StreamExt;
+
+        if let Some(open_interest) = &mut self.open_interest {
+            if let std::task::Poll::Ready(Some(event)) = open_interest.poll_recv(cx) {
+                return std::task::Poll::Ready(Some(Ok(event)));
+            }
+        }
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+/// `@markPrice` payload
+#[derive(Deserialize)]
+struct MarkPricePayload {
+    #[serde(rename = "E")]
+    event_time: i64,
+    #[serde(rename = "p")]
+    mark_price: Decimal,
+    #[serde(rename = "i", default, deserialize_with = "optional_decimal")]
+    index_price: Option<Decimal>,
+    #[serde(rename = "r", default, deserialize_with = "optional_decimal")]
+    funding_rate: Option<Decimal>,
+    #[serde(rename = "T", default)]
+    next_funding_time: i64,
+}
+
+/// `@forceOrder` payload
+#[derive(Deserialize)]
+struct ForceOrderPayload<'a> {
+    #[serde(rename = "o", borrow)]
+    order: ForceOrder<'a>,
+}
+
+#[derive(Deserialize)]
+struct ForceOrder<'a> {
+    #[serde(rename = "s")]
+    symbol: &'a str,
+    #[serde(rename = "S")]
+    side: OrderSide,
+    #[serde(rename = "p")]
+    price: Decimal,
+    #[serde(rename = "q")]
+    quantity: Decimal,
+    #[serde(rename = "ap")]
+    average_price: Decimal,
+    #[serde(rename = "z")]
+    filled_quantity: Decimal,
+    #[serde(rename = "T")]
+    trade_time: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum OrderSide {
+    Buy,
+    Sell,
+}
+
+impl From<OrderSide> for Side {
+    fn from(side: OrderSide) -> Self {
+        match side {
+            OrderSide::Buy => Side::Buy,
+            OrderSide::Sell => Side::Sell,
+        }
+    }
+}
+
+/// `/fapi/v1/openInterest` response
+#[derive(Deserialize)]
+struct OpenInterestPayload {
+    #[serde(rename = "openInterest")]
+    open_interest: Decimal,
+    time: i64,
+}
+
+/// Decimal string where an empty string means no value
+fn optional_decimal<'de, D>(deserializer: D) -> Result<Option<Decimal>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: &str = Deserialize::deserialize(deserializer)?;
+    if value.is_empty() {
+        return Ok(None);
+    }
+    value.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::mock_server::{MockHttpResponse, MockHttpServer, MockWebSocketServer};
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    const FRAMES: &str = include_str!("../../../tests/fixtures/binance/futures.jsonl");
+
+    #[test]
+    fn test_decode_mark_price_and_liquidation() {
+        let mut decoder = BinanceFuturesDecoder::new();
+        let receipt_time = Utc::now();
+
+        let events = decoder
+            .decode(
+                r#"{"stream":"btcusdt@markPrice@1s","data":{"e":"markPriceUpdate","E":1700000000000,"s":"BTCUSDT","p":"37012.50000000","i":"36998.10000000","P":"37001.2","r":"0.00010000","T":1700006400000}}"#,
+                receipt_time,
+            )
+            .unwrap();
+        assert_eq!(events.len(), 2);
+        let instrument = &events[0].instrument;
+        assert_eq!(instrument.kind, InstrumentKind::Perpetual);
+        assert_eq!(
+            (instrument.base.as_str(), instrument.quote.as_str()),
+            ("BTC", "USDT")
+        );
+        assert_ne!(
+            *instrument,
+            crate::data::binance::instrument_from_symbol("btcusdt")
+        );
+        match (&events[0].kind, &events[1].kind) {
+            (MarketDataKind::MarkPrice(mark), MarketDataKind::FundingRate(funding)) => {
+                assert_eq!(mark.price, dec!(37012.5));
+                assert_eq!(mark.index_price, Some(dec!(36998.1)));
+                assert_eq!(funding.rate, dec!(0.0001));
+                assert_eq!(
+                    funding.next_funding_time,
+                    Utc.timestamp_millis_opt(1700006400000).unwrap()
+                );
+            }
+            other => panic!("unexpected events {:?}", other),
+        }
+
+        // Delivery contracts have no funding
+        let events = decoder
+            .decode(
+                r#"{"stream":"btcusdt_250926@markPrice@1s","data":{"e":"markPriceUpdate","E":1700000000000,"s":"BTCUSDT_250926","p":"38100.0","i":"36998.1","P":"38090.0","r":"","T":0}}"#,
+                receipt_time,
+            )
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].instrument.kind,
+            InstrumentKind::DatedFuture {
+                expiry: Utc.with_ymd_and_hms(2025, 9, 26, 8, 0, 0).unwrap()
+            }
+        );
+
+        let events = decoder
+            .decode(
+                r#"{"stream":"btcusdt@forceOrder","data":{"e":"forceOrder","E":1700000001000,"o":{"s":"BTCUSDT","S":"SELL","o":"LIMIT","f":"IOC","q":"0.014","p":"36900","ap":"36910.5","X":"FILLED","l":"0.014","z":"0.014","T":1700000000900}}}"#,
+                receipt_time,
+            )
+            .unwrap();
+        match &events[..] {
+            [MarketEvent {
+                kind: MarketDataKind::Liquidation(liquidation),
+                instrument,
+                ..
+            }] => {
+                assert_eq!(instrument.kind, InstrumentKind::Perpetual);
+                assert_eq!(liquidation.side, Side::Sell);
+                assert_eq!(liquidation.average_price, dec!(36910.5));
+                assert_eq!(liquidation.filled_quantity, dec!(0.014));
+            }
+            other => panic!("unexpected events {:?}", other),
+        }
+
+        // Streams shared with spot are tagged as futures instruments
+        let events = decoder
+            .decode(
+                r#"{"stream":"btcusdt@aggTrade","data":{"e":"aggTrade","E":1700000002000,"s":"BTCUSDT","a":7,"p":"37000.1","q":"0.5","f":1,"l":2,"T":1700000001990,"m":false}}"#,
+                receipt_time,
+            )
+            .unwrap();
+        assert_eq!(events[0].instrument.kind, InstrumentKind::Perpetual);
+        assert!(matches!(events[0].kind, MarketDataKind::Trade(_)));
+    }
+
+    #[test]
+    fn test_decode_all_market_liquidations() {
+        let mut decoder = BinanceFuturesDecoder::new();
+        let receipt_time = Utc::now();
+        let events: Vec<_> = FRAMES
+            .lines()
+            .flat_map(|frame| decoder.decode(frame, receipt_time).unwrap())
+            .collect();
+        let liquidations: Vec<_> = events
+            .iter()
+            .filter(|event| matches!(event.kind, MarketDataKind::Liquidation(_)))
+            .map(|event| event.instrument.exchange_symbol.as_str())
+            .collect();
+        assert_eq!(liquidations, ["BTCUSDT", "ETHUSDT", "BTCUSDT_250926"]);
+        assert_eq!(
+            events.last().unwrap().instrument.kind,
+            InstrumentKind::DatedFuture {
+                expiry: Utc.with_ymd_and_hms(2025, 9, 26, 8, 0, 0).unwrap()
+            }
+        );
+        assert_eq!(events.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_open_interest() {
+        let server = MockHttpServer::start(vec![MockHttpResponse::json(
+            r#"{"openInterest":"10659.509","symbol":"BTCUSDT","time":1700000000000}"#,
+        )])
+        .await
+        .unwrap();
+        let instrument = futures_instrument_from_symbol("BTCUSDT");
+
+        let event = fetch_open_interest(&reqwest::Client::new(), &server.url(), &instrument)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            server.requests(),
+            vec!["/fapi/v1/openInterest?symbol=BTCUSDT"]
+        );
+        assert_eq!(event.instrument, instrument);
+        assert_eq!(
+            event.kind,
+            MarketDataKind::OpenInterest(OpenInterest {
+                quantity: dec!(10659.509),
+                timestamp: Utc.timestamp_millis_opt(1700000000000).unwrap(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_config_polls_open_interest() {
+        let instrument = futures_instrument_from_symbol("BTCUSDT");
+        let mut config = crate::config::SystemConfig::default().data;
+        config.subscriptions = vec![
+            SubscriptionKind::Funding,
+            SubscriptionKind::Liquidations,
+            SubscriptionKind::OpenInterest,
+        ];
+        let stream = BinanceFuturesMarketDataStream::from_config(&config).unwrap();
+        assert_eq!(
+            stream.connector().subscriptions_for(&instrument),
+            [
+                BinanceFuturesSubscription::MarkPrice,
+                BinanceFuturesSubscription::ForceOrder
+            ]
+        );
+        assert_eq!(stream.open_interest_interval, Some(Duration::from_secs(1)));
+        assert!(stream.polls_open_interest(&instrument));
+    }
+
+    #[tokio::test]
+    async fn test_one_open_interest_poller_follows_subscriptions() {
+        let body = r#"{"openInterest":"1","symbol":"X","time":1700000000000}"#;
+        let rest = MockHttpServer::start(vec![
+            MockHttpResponse::json(body),
+            MockHttpResponse::json(body),
+        ])
+        .await
+        .unwrap();
+        let ws = MockWebSocketServer::start_held_open(Vec::new())
+            .await
+            .unwrap();
+        let mut stream = BinanceFuturesMarketDataStream::from_connector(
+            BinanceFuturesConnector::new().with_url(ws.url()),
+        )
+        .with_rest_url(rest.url())
+        .with_open_interest_interval(Duration::from_secs(3600));
+        let [btc, eth, sol] = ["BTCUSDT", "ETHUSDT", "SOLUSDT"].map(futures_instrument_from_symbol);
+
+        // The poller first runs once these calls yield, seeing the final instrument set
+        stream.subscribe(&[btc.clone(), eth.clone()]).await.unwrap();
+        stream.subscribe(std::slice::from_ref(&sol)).await.unwrap();
+        stream.unsubscribe(&[btc]).await.unwrap();
+
+        let mut polled = Vec::new();
+        while polled.len() < 2 {
+            let event = tokio::time::timeout(Duration::from_secs(5), stream.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            polled.push(event.instrument);
+        }
+        assert_eq!(polled, [eth, sol]);
+        assert_eq!(
+            rest.requests(),
+            [
+                "/fapi/v1/openInterest?symbol=ETHUSDT",
+                "/fapi/v1/openInterest?symbol=SOLUSDT"
+            ]
+        );
+    }
+}
@@ -0,0 +1,835 @@
+//! Local order book maintenance
+//!
+//! `OrderBook` is the public book built from `OrderBookDelta` and `OrderBookL2` events.
+//! Each side is a ladder indexed by price in ticks, so level updates near the touch
+//! are a bounds check and an array write and the best level is always at hand; only
+//! removing the best level scans, and only as far as the next populated tick. The
+//! array covers a bounded window of ticks around the touch, and levels further away
+//! are kept in a sorted map, so memory stays bounded however wide the book is.
+//! Quantities are stored as `Qty` lots of the instrument's `InstrumentScale`, so
+//! queries run on integers and only convert to `Decimal` on the way out; the
+//! `*_fixed` methods skip that conversion.
+//!
+//! `LevelBook` is a simpler price-sorted book connectors use internally to publish
+//! `OrderBookL2` snapshots and verify venue checksums.
+
+use super::{
+    FixedPointError, InstrumentScale, LevelAction, MarketDataKind, MarketEvent, OrderBookDelta,
+    OrderBookL2, Price, PriceLevel, Qty, Side,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Errors produced while maintaining an `OrderBook`
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum OrderBookError {
+    /// Price or quantity off the instrument's tick or lot grid
+    #[error(transparent)]
+    FixedPoint(#[from] FixedPointError),
+    /// Delta that does not follow on from the last applied update id
+    #[error("update id gap: expected {expected}, received {received}")]
+    SequenceGap { expected: u64, received: u64 },
+}
+
+/// Widest range of ticks a ladder stores densely
+const MAX_DENSE_TICKS: usize = 4096;
+
+/// One side of an `OrderBook`, indexed by price in ticks.
+///
+/// Levels within a window of at most `MAX_DENSE_TICKS` ticks around the touch live in
+/// a dense array; levels outside it are kept in a sparse map, so a far away order
+/// costs one map entry rather than every tick in between. No tick is in both.
+#[derive(Debug, Clone)]
+struct Ladder {
+    /// Bids are best at the highest tick, asks at the lowest
+    bid: bool,
+    /// Tick of `levels[0]`
+    origin: i64,
+    levels: VecDeque<Qty>,
+    /// Index of the best populated dense level
+    best: Option<usize>,
+    /// Number of populated dense levels
+    dense: usize,
+    /// Populated levels outside the dense window
+    sparse: BTreeMap<i64, Qty>,
+}
+
+impl Ladder {
+    fn new(bid: bool) -> Self {
+        Self {
+            bid,
+            origin: 0,
+            levels: VecDeque::new(),
+            best: None,
+            dense: 0,
+            sparse: BTreeMap::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.clear_dense();
+        self.sparse.clear();
+    }
+
+    fn clear_dense(&mut self) {
+        self.levels.clear();
+        self.best = None;
+        self.dense = 0;
+    }
+
+    /// Number of populated levels
+    fn count(&self) -> usize {
+        self.dense + self.sparse.len()
+    }
+
+    /// End of the dense window, exclusive
+    fn end(&self) -> i64 {
+        self.origin + self.levels.len() as i64
+    }
+
+    fn is_better(&self, tick: i64, than: i64) -> bool {
+        if self.bid {
+            tick > than
+        } else {
+            tick < than
+        }
+    }
+
+    fn set(&mut self, tick: i64, quantity: Qty) {
+        if quantity.is_zero() {
+            self.remove(tick);
+            return;
+        }
+
+        if self.levels.is_empty() {
+            self.recenter(tick);
+        } else if tick < self.origin {
+            if self.end() - tick <= MAX_DENSE_TICKS as i64 {
+                self.grow_to(tick);
+            } else if self
+                .best()
+                .is_none_or(|(best, _)| self.is_better(tick, best))
+            {
+                self.recenter(tick);
+            }
+        } else if tick >= self.end() {
+            if tick - self.origin < MAX_DENSE_TICKS as i64 {
+                self.grow_to(tick);
+            } else if self
+                .best()
+                .is_none_or(|(best, _)| self.is_better(tick, best))
+            {
+                self.recenter(tick);
+            }
+        }
+
+        if tick < self.origin || tick >= self.end() {
+            self.sparse.insert(tick, quantity);
+            return;
+        }
+        let index = (tick - self.origin) as usize;
+        if self.levels[index].is_zero() {
+            self.dense += 1;
+        }
+        self.levels[index] = quantity;
+        if self
+            .best
+            .is_none_or(|best| self.is_better(tick, self.origin + best as i64))
+        {
+            self.best = Some(index);
+        }
+    }
+
+    /// Extend the dense window to `tick`, moving sparse levels it now covers
+    fn grow_to(&mut self, tick: i64) {
+        let (from, to) = if tick < self.origin {
+            let grow = (self.origin - tick) as usize;
+            for _ in 0..grow {
+                self.levels.push_front(Qty::ZERO);
+            }
+            self.best = self.best.map(|best| best + grow);
+            let from = tick;
+            let to = self.origin;
+            self.origin = tick;
+            (from, to)
+        } else {
+            let from = self.end();
+            self.levels
+                .resize((tick - self.origin) as usize + 1, Qty::ZERO);
+            (from, tick + 1)
+        };
+
+        let covered: Vec<_> = self.sparse.range(from..to).map(|(tick, _)| *tick).collect();
+        for tick in covered {
+            if let Some(quantity) = self.sparse.remove(&tick) {
+                self.set(tick, quantity);
+            }
+        }
+    }
+
+    /// Move the dense window to be centred on `tick`
+    fn recenter(&mut self, tick: i64) {
+        for (index, quantity) in self.levels.iter().enumerate() {
+            if !quantity.is_zero() {
+                self.sparse.insert(self.origin + index as i64, *quantity);
+            }
+        }
+        self.clear_dense();
+
+        let low = tick - (MAX_DENSE_TICKS / 2) as i64;
+        let covered: Vec<_> = self
+            .sparse
+            .range(low..low + MAX_DENSE_TICKS as i64)
+            .map(|(tick, _)| *tick)
+            .collect();
+        let first = covered.first().map_or(tick, |first| tick.min(*first));
+        let last = covered.last().map_or(tick, |last| tick.max(*last));
+        self.origin = first;
+        self.levels.resize((last - first) as usize + 1, Qty::ZERO);
+        for tick in covered {
+            if let Some(quantity) = self.sparse.remove(&tick) {
+                self.set(tick, quantity);
+            }
+        }
+    }
+
+    fn remove(&mut self, tick: i64) {
+        if tick < self.origin || tick >= self.end() {
+            self.sparse.remove(&tick);
+            return;
+        }
+        let index = (tick - self.origin) as usize;
+        if self.levels[index].is_zero() {
+            return;
+        }
+        self.levels[index] = Qty::ZERO;
+        self.dense -= 1;
+
+        if self.dense == 0 {
+            self.clear_dense();
+        } else if self.best == Some(index) {
+            self.best = self
+                .indices_from(index)
+                .find(|i| !self.levels[*i].is_zero());
+        }
+    }
+
+    /// Dense indices from `start` towards worse prices
+    fn indices_from(&self, start: usize) -> impl Iterator<Item = usize> {
+        let bid = self.bid;
+        let steps = if bid {
+            start + 1
+        } else {
+            self.levels.len().saturating_sub(start)
+        };
+        (0..steps).map(move |step| if bid { start - step } else { start + step })
+    }
+
+    /// Populated levels as `(tick, quantity)` from best to worst
+    fn iter(&self) -> Box<dyn Iterator<Item = (i64, Qty)> + '_> {
+        let dense = self
+            .best
+            .into_iter()
+            .flat_map(|best| self.indices_from(best))
+            .filter(|index| !self.levels[*index].is_zero())
+            .map(|index| (self.origin + index as i64, self.levels[index]));
+        let above = self
+            .sparse
+            .range(self.end()..)
+            .map(|(tick, quantity)| (*tick, *quantity));
+        let below = self
+            .sparse
+            .range(..self.origin)
+            .map(|(tick, quantity)| (*tick, *quantity));
+        if self.bid {
+            Box::new(above.rev().chain(dense).chain(below.rev()))
+        } else {
+            Box::new(below.chain(dense).chain(above))
+        }
+    }
+
+    fn best(&self) -> Option<(i64, Qty)> {
+        let dense = self
+            .best
+            .map(|index| (self.origin + index as i64, self.levels[index]));
+        let sparse = if self.bid {
+            self.sparse.last_key_value()
+        } else {
+            self.sparse.first_key_value()
+        };
+        match (dense, sparse.map(|(tick, quantity)| (*tick, *quantity))) {
+            (Some(dense), Some(sparse)) if self.is_better(sparse.0, dense.0) => Some(sparse),
+            (Some(dense), _) => Some(dense),
+            (None, sparse) => sparse,
+        }
+    }
+}
+
+/// Local level 2 order book for a single instrument
+#[derive(Debug, Clone)]
+pub struct OrderBook {
+    scale: InstrumentScale,
+    bids: Ladder,
+    asks: Ladder,
+    last_update_id: Option<u64>,
+    timestamp: Option<DateTime<Utc>>,
+}
+
+impl OrderBook {
+    /// Create an empty book for an instrument with the given tick and lot size
+    pub fn new(scale: InstrumentScale) -> Self {
+        Self {
+            scale,
+            bids: Ladder::new(true),
+            asks: Ladder::new(false),
+            last_update_id: None,
+            timestamp: None,
+        }
+    }
+
+    /// Tick and lot size levels are stored in
+    pub fn scale(&self) -> &InstrumentScale {
+        &self.scale
+    }
+
+    /// Tick size prices are indexed by
+    pub fn tick_size(&self) -> Decimal {
+        self.scale.tick_size()
+    }
+
+    /// Last exchange update id applied
+    pub fn last_update_id(&self) -> Option<u64> {
+        self.last_update_id
+    }
+
+    /// Timestamp of the last applied update
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        self.timestamp
+    }
+
+    /// Remove all levels and forget the update id
+    pub fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+        self.last_update_id = None;
+    }
+
+    /// Apply an order book event; other event kinds are ignored.
+    ///
+    /// Returns whether the book changed.
+    pub fn update(&mut self, event: &MarketEvent) -> Result<bool, OrderBookError> {
+        match &event.kind {
+            MarketDataKind::OrderBookDelta(delta) => self.apply_delta(delta),
+            MarketDataKind::OrderBookL2(book) => self.apply_snapshot(book).map(|_| true),
+            _ => Ok(false),
+        }
+    }
+
+    /// Replace the book with a full snapshot; an off-grid level leaves the book untouched
+    pub fn apply_snapshot(&mut self, book: &OrderBookL2) -> Result<(), OrderBookError> {
+        let bids = book.bids.iter().map(|level| (Side::Buy, level));
+        let asks = book.asks.iter().map(|level| (Side::Sell, level));
+        let levels = bids
+            .chain(asks)
+            .map(|(side, level)| Ok((side, level.to_fixed(&self.scale)?)))
+            .collect::<Result<Vec<_>, OrderBookError>>()?;
+
+        self.clear();
+        for (side, level) in levels {
+            self.set_fixed(side, level.price, level.quantity);
+        }
+        self.timestamp = Some(book.timestamp);
+        Ok(())
+    }
+
+    /// Apply an incremental update.
+    ///
+    /// Deltas whose update ids were already applied are skipped and return `false`.
+    /// A delta starting after the next expected id is rejected with
+    /// `OrderBookError::SequenceGap` and leaves the book untouched; the caller should
+    /// resynchronise from a snapshot. So does a delta with a price or quantity off the
+    /// instrument's grid: every update is checked before any is applied.
+    pub fn apply_delta(&mut self, delta: &OrderBookDelta) -> Result<bool, OrderBookError> {
+        if !delta.snapshot {
+            if let Some(last) = self.last_update_id {
+                if delta.last_update_id.is_some_and(|id| id <= last) {
+                    return Ok(false);
+                }
+                if let Some(first) = delta.first_update_id {
+                    if first > last + 1 {
+                        return Err(OrderBookError::SequenceGap {
+                            expected: last + 1,
+                            received: first,
+                        });
+                    }
+                }
+            }
+        }
+
+        let updates = delta
+            .updates
+            .iter()
+            .map(|update| {
+                let quantity = match update.action {
+                    LevelAction::Delete => Qty::ZERO,
+                    LevelAction::Insert | LevelAction::Update => self.scale.qty(update.quantity)?,
+                };
+                Ok((update.side, self.scale.price(update.price)?, quantity))
+            })
+            .collect::<Result<Vec<_>, OrderBookError>>()?;
+
+        if delta.snapshot {
+            self.clear();
+        }
+        for (side, price, quantity) in updates {
+            self.set_fixed(side, price, quantity);
+        }
+        if delta.last_update_id.is_some() {
+            self.last_update_id = delta.last_update_id;
+        }
+        self.timestamp = Some(delta.timestamp);
+        Ok(true)
+    }
+
+    /// Set the quantity at a price level, removing the level if the quantity is zero
+    pub fn set(
+        &mut self,
+        side: Side,
+        price: Decimal,
+        quantity: Decimal,
+    ) -> Result<(), OrderBookError> {
+        let price = self.scale.price(price)?;
+        let quantity = self.scale.qty(quantity)?;
+        self.set_fixed(side, price, quantity);
+        Ok(())
+    }
+
+    /// Set the quantity at a fixed-point price level
+    pub fn set_fixed(&mut self, side: Side, price: Price, quantity: Qty) {
+        match side {
+            Side::Buy => self.bids.set(price.ticks(), quantity),
+            Side::Sell => self.asks.set(price.ticks(), quantity),
+        }
+    }
+
+    fn ladder(&self, side: Side) -> &Ladder {
+        match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        }
+    }
+
+    /// Best bid
+    pub fn best_bid(&self) -> Option<PriceLevel> {
+        self.best(Side::Buy)
+    }
+
+    /// Best ask
+    pub fn best_ask(&self) -> Option<PriceLevel> {
+        self.best(Side::Sell)
+    }
+
+    /// Best level of a side, `Buy` for bids and `Sell` for asks
+    pub fn best(&self, side: Side) -> Option<PriceLevel> {
+        self.best_fixed(side)
+            .map(|level| level.to_decimal(&self.scale))
+    }
+
+    /// Best level of a side in fixed point
+    pub fn best_fixed(&self, side: Side) -> Option<PriceLevel<Price, Qty>> {
+        self.ladder(side).best().map(fixed_level)
+    }
+
+    /// Midpoint of the best bid and ask
+    pub fn mid_price(&self) -> Option<Decimal> {
+        let bid = self.best_fixed(Side::Buy)?.price.ticks() as i128;
+        let ask = self.best_fixed(Side::Sell)?.price.ticks() as i128;
+        Some(Decimal::from(bid + ask) * self.scale.tick_size() / Decimal::TWO)
+    }
+
+    /// Best ask minus best bid
+    pub fn spread(&self) -> Option<Decimal> {
+        let spread = self
+            .best_fixed(Side::Sell)?
+            .price
+            .checked_sub(self.best_fixed(Side::Buy)?.price)?;
+        Some(self.scale.price_to_decimal(spread))
+    }
+
+    /// Number of populated levels on a side
+    pub fn depth(&self, side: Side) -> usize {
+        self.ladder(side).count()
+    }
+
+    /// Levels of a side from best to worst
+    pub fn levels(&self, side: Side) -> impl Iterator<Item = PriceLevel> + '_ {
+        self.levels_fixed(side)
+            .map(|level| level.to_decimal(&self.scale))
+    }
+
+    /// Levels of a side from best to worst in fixed point
+    pub fn levels_fixed(&self, side: Side) -> impl Iterator<Item = PriceLevel<Price, Qty>> + '_ {
+        self.ladder(side).iter().map(fixed_level)
+    }
+
+    /// Total quantity in the best `levels` levels of a side
+    pub fn cumulative_quantity(&self, side: Side, levels: usize) -> Decimal {
+        Decimal::from(self.cumulative_lots(side, levels)) * self.scale.lot_size()
+    }
+
+    fn cumulative_lots(&self, side: Side, levels: usize) -> i128 {
+        self.ladder(side)
+            .iter()
+            .take(levels)
+            .map(|(_, quantity)| quantity.lots() as i128)
+            .sum()
+    }
+
+    /// Average price paid by an order of `side` sweeping `quantity` from the opposite side.
+    ///
+    /// Returns `None` if the book does not hold enough liquidity or the quantity is not
+    /// a positive multiple of the lot size.
+    pub fn vwap(&self, side: Side, quantity: Decimal) -> Option<Decimal> {
+        let quantity = self.scale.qty(quantity).ok()?;
+        let notional = self.sweep_notional(side, quantity)?;
+        Some(self.scale.notional_to_decimal(notional) / self.scale.qty_to_decimal(quantity))
+    }
+
+    /// Notional in tick-lot units of an order of `side` sweeping `quantity` from the
+    /// opposite side, as from `Price::notional`
+    pub fn sweep_notional(&self, side: Side, quantity: Qty) -> Option<i128> {
+        if quantity <= Qty::ZERO {
+            return None;
+        }
+        let opposite = match side {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        };
+        let mut remaining = quantity;
+        let mut notional = 0i128;
+        for level in self.levels_fixed(opposite) {
+            let fill = remaining.min(level.quantity);
+            notional += level.price.notional(fill);
+            remaining = remaining.checked_sub(fill)?;
+            if remaining.is_zero() {
+                return Some(notional);
+            }
+        }
+        None
+    }
+
+    /// `(bid - ask) / (bid + ask)` quantity over the best `levels` levels of each side,
+    /// from -1 (all asks) to 1 (all bids)
+    pub fn imbalance(&self, levels: usize) -> Option<Decimal> {
+        let bid = self.cumulative_lots(Side::Buy, levels);
+        let ask = self.cumulative_lots(Side::Sell, levels);
+        let total = bid + ask;
+        (total != 0).then(|| Decimal::from(bid - ask) / Decimal::from(total))
+    }
+
+    /// Snapshot the top `depth` levels of each side
+    pub fn to_l2(&self, depth: usize) -> OrderBookL2 {
+        OrderBookL2 {
+            bids: self.levels(Side::Buy).take(depth).collect(),
+            asks: self.levels(Side::Sell).take(depth).collect(),
+            timestamp: self.timestamp.unwrap_or_default(),
+        }
+    }
+}
+
+fn fixed_level((tick, quantity): (i64, Qty)) -> PriceLevel<Price, Qty> {
+    PriceLevel {
+        price: Price::from_ticks(tick),
+        quantity,
+    }
+}
+
+/// Price-sorted bid and ask ladders for a single instrument
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LevelBook {
+    bids: BTreeMap<Reverse<Decimal>, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+}
+
+impl LevelBook {
+    /// Set the quantity at a price level, removing the level if the quantity is zero
+    pub(crate) fn apply(&mut self, side: Side, price: Decimal, quantity: Decimal) {
+        match side {
+            Side::Buy if quantity.is_zero() => {
+                self.bids.remove(&Reverse(price));
+            }
+            Side::Buy => {
+                self.bids.insert(Reverse(price), quantity);
+            }
+            Side::Sell if quantity.is_zero() => {
+                self.asks.remove(&price);
+            }
+            Side::Sell => {
+                self.asks.insert(price, quantity);
+            }
+        }
+    }
+
+    /// Remove all levels
+    pub(crate) fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+    }
+
+    /// Drop levels beyond the given depth on each side
+    pub(crate) fn truncate(&mut self, depth: usize) {
+        while self.bids.len() > depth {
+            self.bids.pop_last();
+        }
+        while self.asks.len() > depth {
+            self.asks.pop_last();
+        }
+    }
+
+    /// Iterate bids from best to worst
+    pub(crate) fn bids(&self) -> impl Iterator<Item = (Decimal, Decimal)> + '_ {
+        self.bids
+            .iter()
+            .map(|(price, quantity)| (price.0, *quantity))
+    }
+
+    /// Iterate asks from best to worst
+    pub(crate) fn asks(&self) -> impl Iterator<Item = (Decimal, Decimal)> + '_ {
+        self.asks
+            .iter()
+            .map(|(price, quantity)| (*price, *quantity))
+    }
+
+    /// Snapshot the top `depth` levels of each side
+    pub(crate) fn to_l2(&self, depth: usize, timestamp: DateTime<Utc>) -> OrderBookL2 {
+        let level = |(price, quantity)| PriceLevel { price, quantity };
+        OrderBookL2 {
+            bids: self.bids().take(depth).map(level).collect(),
+            asks: self.asks().take(depth).map(level).collect(),
+            timestamp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::LevelUpdate;
+    use rust_decimal_macros::dec;
+
+    fn update(side: Side, price: Decimal, quantity: Decimal, action: LevelAction) -> LevelUpdate {
+        LevelUpdate {
+            side,
+            price,
+            quantity,
+            action,
+        }
+    }
+
+    fn delta(first: u64, last: u64, updates: Vec<LevelUpdate>) -> OrderBookDelta {
+        OrderBookDelta {
+            updates,
+            first_update_id: Some(first),
+            last_update_id: Some(last),
+            snapshot: false,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_order_book_ladder_and_queries() {
+        let mut book = OrderBook::new(InstrumentScale::new(dec!(0.5), dec!(1)).unwrap());
+        let snapshot = OrderBookDelta {
+            snapshot: true,
+            ..delta(
+                0,
+                10,
+                vec![
+                    update(Side::Buy, dec!(100.0), dec!(2), LevelAction::Insert),
+                    update(Side::Buy, dec!(99.5), dec!(3), LevelAction::Insert),
+                    update(Side::Buy, dec!(97.0), dec!(5), LevelAction::Insert),
+                    update(Side::Sell, dec!(100.5), dec!(1), LevelAction::Insert),
+                    update(Side::Sell, dec!(102.0), dec!(4), LevelAction::Insert),
+                ],
+            )
+        };
+        assert!(book.apply_delta(&snapshot).unwrap());
+        assert_eq!(book.best_bid().unwrap().price, dec!(100.0));
+        assert_eq!(book.best_ask().unwrap().price, dec!(100.5));
+        assert_eq!(book.spread(), Some(dec!(0.5)));
+        assert_eq!(book.cumulative_quantity(Side::Buy, 2), dec!(5));
+        // 1 @ 100.5 + 2 @ 102
+        assert_eq!(
+            book.vwap(Side::Buy, dec!(3)).unwrap().round_dp(4),
+            dec!(101.5)
+        );
+        assert_eq!(book.vwap(Side::Buy, dec!(6)), None);
+        assert_eq!(book.imbalance(1), Some(dec!(1) / dec!(3)));
+
+        // Removing the best bid falls back to the next populated tick
+        book.apply_delta(&delta(
+            11,
+            12,
+            vec![
+                update(Side::Buy, dec!(100.0), dec!(0), LevelAction::Delete),
+                update(Side::Sell, dec!(99.0), dec!(0), LevelAction::Delete),
+                update(Side::Buy, dec!(96.0), dec!(1), LevelAction::Update),
+            ],
+        ))
+        .unwrap();
+        assert_eq!(book.best_bid().unwrap().price, dec!(99.5));
+        assert_eq!(book.depth(Side::Buy), 3);
+        let bids: Vec<_> = book.levels(Side::Buy).map(|level| level.price).collect();
+        assert_eq!(bids, [dec!(99.5), dec!(97.0), dec!(96.0)]);
+        assert_eq!(book.to_l2(1).asks[0].price, dec!(100.5));
+
+        assert!(matches!(
+            book.set(Side::Sell, dec!(100.25), dec!(1)),
+            Err(OrderBookError::FixedPoint(FixedPointError::OffTick { .. }))
+        ));
+        assert!(matches!(
+            book.set(Side::Sell, dec!(100.5), dec!(0.5)),
+            Err(OrderBookError::FixedPoint(FixedPointError::OffLot { .. }))
+        ));
+
+        // Fixed-point queries see the same book in ticks and lots
+        let best = book.best_fixed(Side::Sell).unwrap();
+        assert_eq!((best.price.ticks(), best.quantity.lots()), (201, 1));
+        // 1 @ 201 ticks + 1 @ 204 ticks
+        assert_eq!(book.sweep_notional(Side::Buy, Qty::from_lots(2)), Some(405));
+    }
+
+    #[test]
+    fn test_order_book_update_ids() {
+        let mut book = OrderBook::new(InstrumentScale::new(dec!(0.01), dec!(1)).unwrap());
+        book.apply_snapshot(&OrderBookL2 {
+            bids: vec![PriceLevel {
+                price: dec!(10.00),
+                quantity: dec!(1),
+            }],
+            asks: vec![],
+            timestamp: Utc::now(),
+        })
+        .unwrap();
+        book.apply_delta(&delta(
+            5,
+            7,
+            vec![update(
+                Side::Sell,
+                dec!(10.02),
+                dec!(1),
+                LevelAction::Insert,
+            )],
+        ))
+        .unwrap();
+        assert_eq!(book.last_update_id(), Some(7));
+
+        // Stale deltas are skipped, gaps rejected without touching the book
+        let stale = delta(
+            6,
+            7,
+            vec![update(
+                Side::Sell,
+                dec!(10.01),
+                dec!(1),
+                LevelAction::Insert,
+            )],
+        );
+        assert!(!book.apply_delta(&stale).unwrap());
+        let gap = delta(
+            9,
+            9,
+            vec![update(
+                Side::Sell,
+                dec!(10.01),
+                dec!(1),
+                LevelAction::Insert,
+            )],
+        );
+        assert_eq!(
+            book.apply_delta(&gap),
+            Err(OrderBookError::SequenceGap {
+                expected: 8,
+                received: 9
+            })
+        );
+        assert_eq!(book.best_ask().unwrap().price, dec!(10.02));
+        assert_eq!(book.mid_price(), Some(dec!(10.01)));
+    }
+
+    #[test]
+    fn test_far_levels_stay_sparse_and_deltas_apply_atomically() {
+        let mut book = OrderBook::new(InstrumentScale::new(dec!(0.01), dec!(1)).unwrap());
+        book.set(Side::Buy, dec!(100.00), dec!(1)).unwrap();
+        book.set(Side::Buy, dec!(0.01), dec!(1)).unwrap();
+        book.set(Side::Sell, dec!(100.01), dec!(1)).unwrap();
+        book.set(Side::Sell, dec!(1000000.00), dec!(1)).unwrap();
+        assert!(book.bids.levels.len() <= MAX_DENSE_TICKS);
+        assert!(book.asks.levels.len() <= MAX_DENSE_TICKS);
+        let asks: Vec<_> = book.levels(Side::Sell).map(|level| level.price).collect();
+        assert_eq!(asks, [dec!(100.01), dec!(1000000.00)]);
+
+        // The touch jumps far away; the window follows it
+        book.set(Side::Buy, dec!(500.00), dec!(2)).unwrap();
+        assert_eq!(book.best_bid().unwrap().price, dec!(500.00));
+        book.set(Side::Buy, dec!(500.00), dec!(0)).unwrap();
+        assert_eq!(book.best_bid().unwrap().price, dec!(100.00));
+        assert_eq!(book.depth(Side::Buy), 2);
+
+        let mut rejected = delta(
+            1,
+            1,
+            vec![
+                update(Side::Buy, dec!(100.00), dec!(0), LevelAction::Delete),
+                update(Side::Buy, dec!(99.995), dec!(1), LevelAction::Insert),
+            ],
+        );
+        rejected.snapshot = true;
+        assert!(matches!(
+            book.apply_delta(&rejected),
+            Err(OrderBookError::FixedPoint(FixedPointError::OffTick { .. }))
+        ));
+        assert_eq!(book.best_bid().unwrap().price, dec!(100.00));
+        assert_eq!(book.depth(Side::Sell), 2);
+    }
+
+    #[test]
+    fn test_ladder_matches_sorted_book() {
+        let mut book = OrderBook::new(InstrumentScale::new(Decimal::ONE, Decimal::ONE).unwrap());
+        let mut model = LevelBook::default();
+        let mut seed = 7u64;
+        for _ in 0..5_000 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let side = if seed >> 63 == 0 {
+                Side::Buy
+            } else {
+                Side::Sell
+            };
+            // Mostly near the touch, sometimes tens of thousands of ticks away
+            let spread = if (seed >> 20).is_multiple_of(16) {
+                50_000
+            } else {
+                3_000
+            };
+            let price = Decimal::from(10_000 + ((seed >> 24) % spread) as i64 - spread as i64 / 2);
+            let quantity = Decimal::from((seed >> 40) % 3);
+            book.set(side, price, quantity).unwrap();
+            model.apply(side, price, quantity);
+        }
+        let bids: Vec<_> = book
+            .levels(Side::Buy)
+            .map(|l| (l.price, l.quantity))
+            .collect();
+        let asks: Vec<_> = book
+            .levels(Side::Sell)
+            .map(|l| (l.price, l.quantity))
+            .collect();
+        assert_eq!(bids, model.bids().collect::<Vec<_>>());
+        assert_eq!(asks, model.asks().collect::<Vec<_>>());
+        assert!(book.bids.levels.len() <= MAX_DENSE_TICKS);
+    }
+}
@@ -0,0 +1,444 @@
+//! Market data fan-out bus
+//!
+//! `MarketDataBus` delivers every published `MarketEvent` to any number of subscribers,
+//! e.g. the engine, a recorder and a dashboard. Each subscriber has its own bounded
+//! queue and `SlowConsumerPolicy`, so a slow dashboard cannot hold back the engine
+//! unless it was explicitly registered with `SlowConsumerPolicy::Block`.
+//!
+//! Subscribers are `MarketDataStream`s themselves. Their `subscribe` and `unsubscribe`
+//! narrow the instruments delivered to them; with no instruments selected everything is
+//! delivered.
+
+use super::{ExchangeId, InstrumentId, MarketDataStream, MarketEvent};
+use indexmap::IndexMap;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// What a subscriber's queue does when it is full
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SlowConsumerPolicy {
+    /// Publishing waits for the subscriber, applying backpressure to the source
+    Block,
+    /// The oldest queued event is discarded to make room
+    DropOldest,
+    /// Only the latest event per instrument and stream is kept.
+    ///
+    /// Suited to snapshot data such as quotes and tickers; incremental data such as
+    /// trades and book deltas loses intermediate events.
+    Conflate,
+}
+
+/// Delivery counters of one subscriber
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriberStats {
+    /// Name given when subscribing
+    pub name: String,
+    /// Slow consumer policy
+    pub policy: SlowConsumerPolicy,
+    /// Events accepted into the queue
+    pub published: u64,
+    /// Events taken by the subscriber
+    pub delivered: u64,
+    /// Events discarded by `DropOldest`
+    pub dropped: u64,
+    /// Events replaced by a newer one under `Conflate`
+    pub conflated: u64,
+    /// Events currently queued
+    pub queued: usize,
+    /// Highest queue length seen
+    pub max_queued: usize,
+    /// Time the oldest queued event has been waiting
+    pub lag: Duration,
+}
+
+type ConflationKey = (ExchangeId, InstrumentId, &'static str);
+
+#[derive(Debug)]
+struct QueueState {
+    events: VecDeque<(Instant, MarketEvent)>,
+    latest: IndexMap<ConflationKey, (Instant, MarketEvent)>,
+    instruments: Vec<InstrumentId>,
+    stats: SubscriberStats,
+    closed: bool,
+}
+
+impl QueueState {
+    fn len(&self) -> usize {
+        self.events.len() + self.latest.len()
+    }
+
+    fn pop(&mut self) -> Option<(Instant, MarketEvent)> {
+        let event = match self.stats.policy {
+            SlowConsumerPolicy::Conflate => self.latest.shift_remove_index(0).map(|(_, v)| v),
+            _ => self.events.pop_front(),
+        };
+        if event.is_some() {
+            self.stats.delivered += 1;
+        }
+        event
+    }
+
+    fn oldest(&self) -> Option<Instant> {
+        match self.stats.policy {
+            SlowConsumerPolicy::Conflate => self.latest.values().map(|(at, _)| *at).min(),
+            _ => self.events.front().map(|(at, _)| *at),
+        }
+    }
+}
+
+/// Queue shared between the bus and one subscriber
+#[derive(Debug)]
+struct SubscriberQueue {
+    state: Mutex<QueueState>,
+    capacity: usize,
+    readable: Notify,
+    writable: Notify,
+}
+
+impl SubscriberQueue {
+    fn close(&self) {
+        self.state.lock().closed = true;
+        self.readable.notify_one();
+        self.writable.notify_one();
+    }
+
+    /// Queue an event according to the policy, waiting for space under `Block`
+    async fn push(&self, event: &MarketEvent) {
+        loop {
+            {
+                let mut state = self.state.lock();
+                if state.closed {
+                    return;
+                }
+                if !state.instruments.is_empty() && !state.instruments.contains(&event.instrument) {
+                    return;
+                }
+                let now = Instant::now();
+                match state.stats.policy {
+                    SlowConsumerPolicy::Block if state.events.len() >= self.capacity => {}
+                    SlowConsumerPolicy::Conflate => {
+                        let key = (event.exchange, event.instrument.clone(), event.kind.name());
+                        // A replaced event keeps its queue position and original age
+                        match state.latest.get_mut(&key) {
+                            Some(slot) => {
+                                slot.1 = event.clone();
+                                state.stats.conflated += 1;
+                            }
+                            None => {
+                                state.latest.insert(key, (now, event.clone()));
+                            }
+                        }
+                        self.accepted(&mut state);
+                        return;
+                    }
+                    policy => {
+                        if policy == SlowConsumerPolicy::DropOldest
+                            && state.events.len() >= self.capacity
+                        {
+                            state.events.pop_front();
+                            state.stats.dropped += 1;
+                        }
+                        state.events.push_back((now, event.clone()));
+                        self.accepted(&mut state);
+                        return;
+                    }
+                }
+            }
+            self.writable.notified().await;
+        }
+    }
+
+    fn accepted(&self, state: &mut QueueState) {
+        state.stats.published += 1;
+        state.stats.max_queued = state.stats.max_queued.max(state.len());
+        self.readable.notify_one();
+    }
+}
+
+/// Publishes market events to independently buffered subscribers
+#[derive(Debug, Clone, Default)]
+pub struct MarketDataBus {
+    subscribers: Arc<Mutex<Vec<Arc<SubscriberQueue>>>>,
+}
+
+impl MarketDataBus {
+    /// Create a bus with no subscribers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a subscriber with a queue of `capacity` events.
+    ///
+    /// Conflating subscribers hold one event per instrument and stream instead.
+    pub fn subscribe(
+        &self,
+        name: impl Into<String>,
+        policy: SlowConsumerPolicy,
+        capacity: usize,
+    ) -> BusSubscriber {
+        let queue = Arc::new(SubscriberQueue {
+            state: Mutex::new(QueueState {
+                events: VecDeque::new(),
+                latest: IndexMap::new(),
+                instruments: Vec::new(),
+                stats: SubscriberStats {
+                    name: name.into(),
+                    policy,
+                    published: 0,
+                    delivered: 0,
+                    dropped: 0,
+                    conflated: 0,
+                    queued: 0,
+                    max_queued: 0,
+                    lag: Duration::ZERO,
+                },
+                closed: false,
+            }),
+            capacity: capacity.max(1),
+            readable: Notify::new(),
+            writable: Notify::new(),
+        });
+        self.subscribers.lock().push(queue.clone());
+        BusSubscriber { queue }
+    }
+
+    /// Deliver an event to every subscriber
+    pub async fn publish(&self, event: &MarketEvent) {
+        let subscribers = {
+            let mut subscribers = self.subscribers.lock();
+            subscribers.retain(|queue| !queue.state.lock().closed);
+            subscribers.clone()
+        };
+        for queue in subscribers {
+            queue.push(event).await;
+        }
+    }
+
+    /// Publish everything `stream` produces, closing the bus when it ends
+    pub async fn pump<S: MarketDataStream>(&self, stream: &mut S) -> Result<(), S::Error> {
+        let result = async {
+            while let Some(event) = stream.next().await? {
+                self.publish(&event).await;
+            }
+            Ok(())
+        }
+        .await;
+        self.close();
+        result
+    }
+
+    /// Stop publishing; subscribers end after draining their queues
+    pub fn close(&self) {
+        for queue in self.subscribers.lock().drain(..) {
+            queue.close();
+        }
+    }
+
+    /// Delivery counters of every active subscriber
+    pub fn stats(&self) -> Vec<SubscriberStats> {
+        self.subscribers
+            .lock()
+            .iter()
+            .map(|queue| BusSubscriber::snapshot(queue))
+            .collect()
+    }
+}
+
+/// Receiving end of a `MarketDataBus` subscription
+#[derive(Debug)]
+pub struct BusSubscriber {
+    queue: Arc<SubscriberQueue>,
+}
+
+impl BusSubscriber {
+    /// Delivery counters of this subscriber
+    pub fn stats(&self) -> SubscriberStats {
+        Self::snapshot(&self.queue)
+    }
+
+    fn snapshot(queue: &SubscriberQueue) -> SubscriberStats {
+        let state = queue.state.lock();
+        SubscriberStats {
+            queued: state.len(),
+            lag: state.oldest().map(|at| at.elapsed()).unwrap_or_default(),
+            ..state.stats.clone()
+        }
+    }
+}
+
+impl Drop for BusSubscriber {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
+
+#[async_trait::async_trait]
+impl MarketDataStream for BusSubscriber {
+    type Error = std::io::Error;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        loop {
+            {
+                let mut state = self.queue.state.lock();
+                if let Some((_, event)) = state.pop() {
+                    drop(state);
+                    self.queue.writable.notify_one();
+                    return Ok(Some(event));
+                }
+                if state.closed {
+                    return Ok(None);
+                }
+            }
+            self.queue.readable.notified().await;
+        }
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        let mut state = self.queue.state.lock();
+        for instrument in instruments {
+            if !state.instruments.contains(instrument) {
+                state.instruments.push(instrument.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.queue
+            .state
+            .lock()
+            .instruments
+            .retain(|instrument| !instruments.contains(instrument));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{InstrumentKind, MarketDataKind, MockMarketDataStream, OrderBookL1};
+    use chrono::Utc;
+    use rust_decimal::Decimal;
+
+    fn quote(symbol: &str, bid_price: i64) -> MarketEvent {
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: InstrumentId {
+                base: symbol.to_string(),
+                quote: "USDT".to_string(),
+                exchange_symbol: format!("{}USDT", symbol),
+                kind: InstrumentKind::Spot,
+            },
+            kind: MarketDataKind::OrderBookL1(OrderBookL1 {
+                bid_price: Decimal::from(bid_price),
+                bid_quantity: Decimal::ONE,
+                ask_price: Decimal::from(bid_price + 1),
+                ask_quantity: Decimal::ONE,
+                timestamp: Utc::now(),
+                update_id: None,
+            }),
+            exchange_time: Utc::now(),
+            receipt_time: Utc::now(),
+        }
+    }
+
+    fn bid(event: &MarketEvent) -> i64 {
+        match &event.kind {
+            MarketDataKind::OrderBookL1(book) => book.bid_price.try_into().unwrap(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_policies_for_slow_subscribers() {
+        let bus = MarketDataBus::new();
+        let mut dropping = bus.subscribe("dashboard", SlowConsumerPolicy::DropOldest, 3);
+        let mut conflating = bus.subscribe("ui", SlowConsumerPolicy::Conflate, 3);
+        let mut eth_only = bus.subscribe("eth", SlowConsumerPolicy::DropOldest, 10);
+        eth_only
+            .subscribe(&[quote("ETH", 0).instrument])
+            .await
+            .unwrap();
+
+        for i in 0..5 {
+            bus.publish(&quote("BTC", 100 + i)).await;
+            bus.publish(&quote("ETH", 10 + i)).await;
+        }
+        bus.close();
+
+        let stats = dropping.stats();
+        assert_eq!((stats.published, stats.dropped, stats.queued), (10, 7, 3));
+        let mut received = Vec::new();
+        while let Some(event) = dropping.next().await.unwrap() {
+            received.push(bid(&event));
+        }
+        assert_eq!(received, [13, 104, 14]);
+
+        // Latest quote per instrument, in order of first arrival
+        assert_eq!(conflating.stats().conflated, 8);
+        assert_eq!(bid(&conflating.next().await.unwrap().unwrap()), 104);
+        assert_eq!(bid(&conflating.next().await.unwrap().unwrap()), 14);
+        assert!(conflating.next().await.unwrap().is_none());
+
+        assert_eq!(eth_only.stats().published, 5);
+    }
+
+    #[tokio::test]
+    async fn test_conflate_keeps_spot_and_perpetual_apart() {
+        let bus = MarketDataBus::new();
+        let mut conflating = bus.subscribe("ui", SlowConsumerPolicy::Conflate, 3);
+        // Spot and perpetual BTCUSDT share an exchange symbol
+        let perpetual = |bid_price| {
+            let mut event = quote("BTC", bid_price);
+            event.instrument.kind = InstrumentKind::Perpetual;
+            event
+        };
+
+        bus.publish(&quote("BTC", 100)).await;
+        bus.publish(&perpetual(200)).await;
+        bus.publish(&quote("BTC", 101)).await;
+        bus.publish(&perpetual(201)).await;
+        bus.close();
+
+        assert_eq!(conflating.stats().conflated, 2);
+        let spot = conflating.next().await.unwrap().unwrap();
+        assert_eq!(
+            (spot.instrument.kind, bid(&spot)),
+            (InstrumentKind::Spot, 101)
+        );
+        let perp = conflating.next().await.unwrap().unwrap();
+        assert_eq!(
+            (perp.instrument.kind, bid(&perp)),
+            (InstrumentKind::Perpetual, 201)
+        );
+        assert!(conflating.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_block_policy_applies_backpressure() {
+        let bus = MarketDataBus::new();
+        let mut engine = bus.subscribe("engine", SlowConsumerPolicy::Block, 2);
+        let events: Vec<_> = (0..20).map(|i| quote("BTC", i)).collect();
+
+        let publisher = bus.clone();
+        let pump = tokio::spawn(async move {
+            let mut stream = MockMarketDataStream::new(events);
+            publisher.pump(&mut stream).await
+        });
+
+        let mut received = Vec::new();
+        while let Some(event) = engine.next().await.unwrap() {
+            received.push(bid(&event));
+            tokio::task::yield_now().await;
+        }
+        pump.await.unwrap().unwrap();
+
+        assert_eq!(received, (0..20).collect::<Vec<_>>());
+        let stats = engine.stats();
+        assert_eq!((stats.delivered, stats.dropped), (20, 0));
+        assert!(stats.max_queued <= 2);
+    }
+}
@@ -0,0 +1,993 @@
+//! Binary market data capture format
+//!
+//! A capture file holds `MarketEvent`s in receipt order, grouped into blocks:
+//!
+//! ```text
+//! file header   magic "MDCAPv2\0" | flags u32 | reserved u32
+//! block header  stored_len u32 | raw_len u32 | count u32 | reserved u32 |
+//!               first_receipt_nanos i64 | last_receipt_nanos i64
+//! block payload count × (len u32 | bincode MarketEvent), LZ4 compressed if flagged
+//! ```
+//!
+//! Decimals are encoded as strings. All integers are little endian. A sidecar `.idx`
+//! file holds one fixed-size entry per block (first and last receipt time, file offset,
+//! event count) so readers can binary search to a point in time without decoding earlier
+//! blocks. Index entries are only written once their block has been flushed to the
+//! capture. The index is rebuilt from block headers if it is missing, shorter than the
+//! capture or points past its end, e.g. after a crash, and a block torn by a crash is
+//! cut off before appending.
+
+use super::{
+    BookFeatures, Candle, ConsolidatedQuote, DataQualityEvent, DataQualityIssue, ExchangeId,
+    FundingRate, InstrumentId, L3Action, LevelAction, LevelUpdate, Liquidation, MarkPrice,
+    MarketDataKind, MarketEvent, OpenInterest, OrderBookDelta, OrderBookL1, OrderBookL2,
+    OrderBookL3Update, PriceLevel, PublicTrade, Side, Ticker,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::serde::{str as decimal, str_option as optional_decimal};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// File extension of capture files
+pub const CAPTURE_EXTENSION: &str = "mdc";
+
+/// Bumped whenever the bincode layout of `MarketEvent` changes; v2 added `InstrumentId::kind`
+const MAGIC: &[u8; 8] = b"MDCAPv2\0";
+const FILE_HEADER_LEN: u64 = 16;
+const BLOCK_HEADER_LEN: usize = 32;
+const INDEX_ENTRY_LEN: usize = 32;
+const FLAG_LZ4: u32 = 1;
+
+/// Block compression
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum Compression {
+    /// Blocks are stored as-is
+    #[default]
+    None,
+    /// Blocks are LZ4 block-compressed
+    Lz4,
+}
+
+/// Index entry describing one block
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BlockIndexEntry {
+    /// Receipt time of the first event, in nanoseconds since the epoch
+    pub first_nanos: i64,
+    /// Receipt time of the last event, in nanoseconds since the epoch
+    pub last_nanos: i64,
+    /// Offset of the block header in the capture file
+    pub offset: u64,
+    /// Number of events in the block
+    pub count: u32,
+}
+
+impl BlockIndexEntry {
+    fn encode(&self) -> [u8; INDEX_ENTRY_LEN] {
+        let mut bytes = [0u8; INDEX_ENTRY_LEN];
+        bytes[0..8].copy_from_slice(&self.first_nanos.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.last_nanos.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.offset.to_le_bytes());
+        bytes[24..28].copy_from_slice(&self.count.to_le_bytes());
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        Self {
+            first_nanos: i64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            last_nanos: i64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            offset: u64::from_le_bytes(bytes[16..24].try_into().unwrap()),
+            count: u32::from_le_bytes(bytes[24..28].try_into().unwrap()),
+        }
+    }
+}
+
+/// Path of the index belonging to a capture file
+pub fn index_path(path: &Path) -> PathBuf {
+    let mut index = path.as_os_str().to_owned();
+    index.push(".idx");
+    PathBuf::from(index)
+}
+
+fn timestamp_nanos(time: DateTime<Utc>) -> i64 {
+    time.timestamp_nanos_opt().unwrap_or(i64::MAX)
+}
+
+fn invalid_data(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Appends events to a single capture file
+pub struct CaptureWriter {
+    path: PathBuf,
+    file: BufWriter<File>,
+    index: BufWriter<File>,
+    compression: Compression,
+    block_size: usize,
+    offset: u64,
+    block: Vec<u8>,
+    block_count: u32,
+    block_first: i64,
+    block_last: i64,
+}
+
+impl CaptureWriter {
+    /// Create a capture file, or append to an existing one written with the same compression.
+    ///
+    /// A partially written final block left by a crash is truncated before appending.
+    /// Events are buffered until a block holds at least `block_size` bytes.
+    pub fn open(
+        path: impl AsRef<Path>,
+        compression: Compression,
+        block_size: usize,
+    ) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let len = file.metadata()?.len();
+
+        let mut entries = Vec::new();
+        let offset = if len == 0 {
+            let flags = match compression {
+                Compression::None => 0,
+                Compression::Lz4 => FLAG_LZ4,
+            };
+            let mut header = Vec::with_capacity(FILE_HEADER_LEN as usize);
+            header.extend_from_slice(MAGIC);
+            header.extend_from_slice(&flags.to_le_bytes());
+            header.extend_from_slice(&0u32.to_le_bytes());
+            file.write_all(&header)?;
+            FILE_HEADER_LEN
+        } else {
+            if read_header(&mut file)? != compression {
+                return Err(invalid_data(format!(
+                    "{} was written with a different compression",
+                    path.display()
+                )));
+            }
+
+            // Appending needs an index covering every existing block, and must start
+            // right after the last complete one
+            entries = load_index(&path)?;
+            let end = match entries.last() {
+                Some(last) => block_end(&mut file, last.offset, len)?
+                    .ok_or_else(|| invalid_data("capture index points past the file"))?,
+                None => FILE_HEADER_LEN,
+            };
+            if end < len {
+                file.set_len(end)?;
+            }
+            end
+        };
+
+        let index = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(index_path(&path))?;
+        let mut index = BufWriter::new(index);
+        for entry in &entries {
+            index.write_all(&entry.encode())?;
+        }
+        index.flush()?;
+
+        Ok(Self {
+            path,
+            file: BufWriter::new(file),
+            index,
+            compression,
+            block_size,
+            offset,
+            block: Vec::with_capacity(block_size),
+            block_count: 0,
+            block_first: 0,
+            block_last: 0,
+        })
+    }
+
+    /// Path of the capture file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Buffer an event, writing out the current block once it is full
+    pub fn write(&mut self, event: &MarketEvent) -> io::Result<()> {
+        let encoded = bincode::serialize(&EventRef(event)).map_err(invalid_data)?;
+        let receipt_nanos = timestamp_nanos(event.receipt_time);
+        if self.block_count == 0 {
+            self.block_first = receipt_nanos;
+        }
+        self.block_last = receipt_nanos;
+        self.block_count += 1;
+        self.block
+            .extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+        self.block.extend_from_slice(&encoded);
+
+        if self.block.len() >= self.block_size {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    /// Write any buffered events and flush the file and index to the OS
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.file.flush()?;
+        self.index.flush()
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.block_count == 0 {
+            return Ok(());
+        }
+
+        let compressed;
+        let stored: &[u8] = match self.compression {
+            Compression::None => &self.block,
+            Compression::Lz4 => {
+                compressed = lz4_flex::block::compress(&self.block);
+                &compressed
+            }
+        };
+
+        let mut header = [0u8; BLOCK_HEADER_LEN];
+        header[0..4].copy_from_slice(&(stored.len() as u32).to_le_bytes());
+        header[4..8].copy_from_slice(&(self.block.len() as u32).to_le_bytes());
+        header[8..12].copy_from_slice(&self.block_count.to_le_bytes());
+        header[16..24].copy_from_slice(&self.block_first.to_le_bytes());
+        header[24..32].copy_from_slice(&self.block_last.to_le_bytes());
+        self.file.write_all(&header)?;
+        self.file.write_all(stored)?;
+        // The entry must never reach disk ahead of the block it points to
+        self.file.flush()?;
+
+        let entry = BlockIndexEntry {
+            first_nanos: self.block_first,
+            last_nanos: self.block_last,
+            offset: self.offset,
+            count: self.block_count,
+        };
+        self.index.write_all(&entry.encode())?;
+
+        self.offset += (BLOCK_HEADER_LEN + stored.len()) as u64;
+        self.block.clear();
+        self.block_count = 0;
+        Ok(())
+    }
+}
+
+impl Drop for CaptureWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Read and validate a file header, returning the compression it declares
+fn read_header(file: &mut File) -> io::Result<Compression> {
+    let mut header = [0u8; FILE_HEADER_LEN as usize];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+    if &header[0..8] != MAGIC {
+        if header[0..6] == MAGIC[0..6] {
+            return Err(invalid_data("unsupported market data capture version"));
+        }
+        return Err(invalid_data("not a market data capture file"));
+    }
+    let flags = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    Ok(if flags & FLAG_LZ4 != 0 {
+        Compression::Lz4
+    } else {
+        Compression::None
+    })
+}
+
+/// Load the block index of a capture file, rebuilding it from block headers if needed
+pub fn load_index(path: &Path) -> io::Result<Vec<BlockIndexEntry>> {
+    let mut entries = Vec::new();
+    if let Ok(bytes) = std::fs::read(index_path(path)) {
+        entries = bytes
+            .chunks_exact(INDEX_ENTRY_LEN)
+            .map(BlockIndexEntry::decode)
+            .collect();
+    }
+
+    // Drop entries whose block is not complete in the file, then resume scanning
+    // after the last indexed block in case the index is stale
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut offset = FILE_HEADER_LEN;
+    while let Some(last) = entries.last() {
+        match block_end(&mut file, last.offset, len)? {
+            Some(end) => {
+                offset = end;
+                break;
+            }
+            None => {
+                entries.pop();
+            }
+        }
+    }
+
+    // A block that does not fit is truncated by an interrupted write
+    while let Some(end) = block_end(&mut file, offset, len)? {
+        file.seek(SeekFrom::Start(offset))?;
+        let (_, _, count, first_nanos, last_nanos) = read_block_header(&mut file)?;
+        entries.push(BlockIndexEntry {
+            first_nanos,
+            last_nanos,
+            offset,
+            count,
+        });
+        offset = end;
+    }
+    Ok(entries)
+}
+
+/// End of the block starting at `offset`, or `None` unless all of it lies within `len`
+fn block_end(file: &mut File, offset: u64, len: u64) -> io::Result<Option<u64>> {
+    if offset + BLOCK_HEADER_LEN as u64 > len {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::Start(offset))?;
+    let (stored_len, ..) = read_block_header(file)?;
+    let end = offset + BLOCK_HEADER_LEN as u64 + u64::from(stored_len);
+    Ok((end <= len).then_some(end))
+}
+
+fn read_block_header(reader: &mut impl Read) -> io::Result<(u32, u32, u32, i64, i64)> {
+    let mut header = [0u8; BLOCK_HEADER_LEN];
+    reader.read_exact(&mut header)?;
+    Ok((
+        u32::from_le_bytes(header[0..4].try_into().unwrap()),
+        u32::from_le_bytes(header[4..8].try_into().unwrap()),
+        u32::from_le_bytes(header[8..12].try_into().unwrap()),
+        i64::from_le_bytes(header[16..24].try_into().unwrap()),
+        i64::from_le_bytes(header[24..32].try_into().unwrap()),
+    ))
+}
+
+/// Sequential reader over a capture file with indexed time seeks
+pub struct CaptureReader {
+    file: BufReader<File>,
+    compression: Compression,
+    index: Vec<BlockIndexEntry>,
+    next_block: usize,
+    block: Vec<u8>,
+    position: usize,
+}
+
+impl CaptureReader {
+    /// Open a capture file and load its index
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+        let compression = read_header(&mut file)?;
+        let index = load_index(path)?;
+        Ok(Self {
+            file: BufReader::new(file),
+            compression,
+            index,
+            next_block: 0,
+            block: Vec::new(),
+            position: 0,
+        })
+    }
+
+    /// Block index of the file
+    pub fn index(&self) -> &[BlockIndexEntry] {
+        &self.index
+    }
+
+    /// Receipt time range covered by the file
+    pub fn time_range(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let first = self.index.first()?;
+        let last = self.index.last()?;
+        Some((
+            DateTime::from_timestamp_nanos(first.first_nanos),
+            DateTime::from_timestamp_nanos(last.last_nanos),
+        ))
+    }
+
+    /// Position the reader at the first event received at or after `time`
+    pub fn seek(&mut self, time: DateTime<Utc>) -> io::Result<()> {
+        let nanos = timestamp_nanos(time);
+        self.next_block = self.index.partition_point(|entry| entry.last_nanos < nanos);
+        self.block.clear();
+        self.position = 0;
+
+        while self.load_block()? {
+            while self.position < self.block.len() {
+                let start = self.position;
+                let event = self.decode_next()?;
+                if event.receipt_time >= time {
+                    self.position = start;
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the next event, or `None` at the end of the file
+    pub fn next_event(&mut self) -> io::Result<Option<MarketEvent>> {
+        if self.position >= self.block.len() && !self.load_block()? {
+            return Ok(None);
+        }
+        self.decode_next().map(Some)
+    }
+
+    /// Load the next block into memory, returning false at the end of the file
+    fn load_block(&mut self) -> io::Result<bool> {
+        let Some(entry) = self.index.get(self.next_block).copied() else {
+            return Ok(false);
+        };
+        self.next_block += 1;
+
+        self.file.seek(SeekFrom::Start(entry.offset))?;
+        let (stored_len, raw_len, _, _, _) = read_block_header(&mut self.file)?;
+        let mut stored = vec![0u8; stored_len as usize];
+        self.file.read_exact(&mut stored)?;
+
+        self.block = match self.compression {
+            Compression::None => stored,
+            Compression::Lz4 => {
+                lz4_flex::block::decompress(&stored, raw_len as usize).map_err(invalid_data)?
+            }
+        };
+        self.position = 0;
+        Ok(true)
+    }
+
+    fn decode_next(&mut self) -> io::Result<MarketEvent> {
+        let header_end = self.position + 4;
+        let len_bytes = self
+            .block
+            .get(self.position..header_end)
+            .ok_or_else(|| invalid_data("truncated record length"))?;
+        let len = u32::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+        let record = self
+            .block
+            .get(header_end..header_end + len)
+            .ok_or_else(|| invalid_data("truncated record"))?;
+        let EventRecord(event) = bincode::deserialize(record).map_err(invalid_data)?;
+        self.position = header_end + len;
+        Ok(event)
+    }
+}
+
+impl Iterator for CaptureReader {
+    type Item = io::Result<MarketEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
+}
+
+// Record layout. bincode cannot drive `Decimal`'s own `Deserialize`, which asks the
+// format what type comes next, so the capture mirrors `MarketEvent` with every decimal
+// field read back explicitly as a string.
+
+struct EventRef<'a>(&'a MarketEvent);
+
+impl Serialize for EventRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        MarketEventDef::serialize(self.0, serializer)
+    }
+}
+
+#[derive(Deserialize)]
+struct EventRecord(#[serde(with = "MarketEventDef")] MarketEvent);
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "MarketEvent")]
+struct MarketEventDef {
+    exchange: ExchangeId,
+    instrument: InstrumentId,
+    #[serde(with = "MarketDataKindDef")]
+    kind: MarketDataKind,
+    exchange_time: DateTime<Utc>,
+    receipt_time: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "MarketDataKind")]
+enum MarketDataKindDef {
+    Trade(#[serde(with = "PublicTradeDef")] PublicTrade),
+    OrderBookL1(#[serde(with = "OrderBookL1Def")] OrderBookL1),
+    OrderBookL2(#[serde(with = "OrderBookL2Def")] OrderBookL2),
+    OrderBookDelta(#[serde(with = "OrderBookDeltaDef")] OrderBookDelta),
+    OrderBookL3(#[serde(with = "OrderBookL3UpdateDef")] OrderBookL3Update),
+    Candle(#[serde(with = "CandleDef")] Candle),
+    ConsolidatedQuote(#[serde(with = "ConsolidatedQuoteDef")] ConsolidatedQuote),
+    Ticker(#[serde(with = "TickerDef")] Ticker),
+    DataQuality(#[serde(with = "DataQualityEventDef")] DataQualityEvent),
+    FundingRate(#[serde(with = "FundingRateDef")] FundingRate),
+    MarkPrice(#[serde(with = "MarkPriceDef")] MarkPrice),
+    OpenInterest(#[serde(with = "OpenInterestDef")] OpenInterest),
+    Liquidation(#[serde(with = "LiquidationDef")] Liquidation),
+    BookFeatures(#[serde(with = "BookFeaturesDef")] BookFeatures),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "PublicTrade")]
+struct PublicTradeDef {
+    id: String,
+    #[serde(with = "decimal")]
+    price: Decimal,
+    #[serde(with = "decimal")]
+    quantity: Decimal,
+    side: Side,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "OrderBookL1")]
+struct OrderBookL1Def {
+    #[serde(with = "decimal")]
+    bid_price: Decimal,
+    #[serde(with = "decimal")]
+    bid_quantity: Decimal,
+    #[serde(with = "decimal")]
+    ask_price: Decimal,
+    #[serde(with = "decimal")]
+    ask_quantity: Decimal,
+    timestamp: DateTime<Utc>,
+    update_id: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "PriceLevel")]
+struct PriceLevelDef {
+    #[serde(with = "decimal")]
+    price: Decimal,
+    #[serde(with = "decimal")]
+    quantity: Decimal,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "OrderBookL2")]
+struct OrderBookL2Def {
+    #[serde(with = "price_levels")]
+    bids: Vec<PriceLevel>,
+    #[serde(with = "price_levels")]
+    asks: Vec<PriceLevel>,
+    timestamp: DateTime<Utc>,
+}
+
+mod price_levels {
+    use super::*;
+
+    struct LevelRef<'a>(&'a PriceLevel);
+
+    impl Serialize for LevelRef<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            PriceLevelDef::serialize(self.0, serializer)
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct LevelRecord(#[serde(with = "PriceLevelDef")] PriceLevel);
+
+    pub fn serialize<S: Serializer>(
+        levels: &[PriceLevel],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(levels.iter().map(LevelRef))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<PriceLevel>, D::Error> {
+        let levels = Vec::<LevelRecord>::deserialize(deserializer)?;
+        Ok(levels.into_iter().map(|level| level.0).collect())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "LevelUpdate")]
+struct LevelUpdateDef {
+    side: Side,
+    #[serde(with = "decimal")]
+    price: Decimal,
+    #[serde(with = "decimal")]
+    quantity: Decimal,
+    action: LevelAction,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "OrderBookDelta")]
+struct OrderBookDeltaDef {
+    #[serde(with = "level_updates")]
+    updates: Vec<LevelUpdate>,
+    first_update_id: Option<u64>,
+    last_update_id: Option<u64>,
+    snapshot: bool,
+    timestamp: DateTime<Utc>,
+}
+
+mod level_updates {
+    use super::*;
+
+    struct UpdateRef<'a>(&'a LevelUpdate);
+
+    impl Serialize for UpdateRef<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            LevelUpdateDef::serialize(self.0, serializer)
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct UpdateRecord(#[serde(with = "LevelUpdateDef")] LevelUpdate);
+
+    pub fn serialize<S: Serializer>(
+        updates: &[LevelUpdate],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(updates.iter().map(UpdateRef))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<LevelUpdate>, D::Error> {
+        let updates = Vec::<UpdateRecord>::deserialize(deserializer)?;
+        Ok(updates.into_iter().map(|update| update.0).collect())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "OrderBookL3Update")]
+struct OrderBookL3UpdateDef {
+    order_id: String,
+    action: L3Action,
+    side: Side,
+    #[serde(with = "decimal")]
+    price: Decimal,
+    #[serde(with = "decimal")]
+    quantity: Decimal,
+    sequence: Option<u64>,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Candle")]
+struct CandleDef {
+    #[serde(with = "decimal")]
+    open: Decimal,
+    #[serde(with = "decimal")]
+    high: Decimal,
+    #[serde(with = "decimal")]
+    low: Decimal,
+    #[serde(with = "decimal")]
+    close: Decimal,
+    #[serde(with = "decimal")]
+    volume: Decimal,
+    timestamp: DateTime<Utc>,
+    duration_secs: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ConsolidatedQuote")]
+struct ConsolidatedQuoteDef {
+    #[serde(with = "decimal")]
+    bid_price: Decimal,
+    #[serde(with = "decimal")]
+    bid_quantity: Decimal,
+    bid_exchange: ExchangeId,
+    #[serde(with = "decimal")]
+    ask_price: Decimal,
+    #[serde(with = "decimal")]
+    ask_quantity: Decimal,
+    ask_exchange: ExchangeId,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Ticker")]
+struct TickerDef {
+    #[serde(with = "decimal")]
+    open: Decimal,
+    #[serde(with = "decimal")]
+    high: Decimal,
+    #[serde(with = "decimal")]
+    low: Decimal,
+    #[serde(with = "decimal")]
+    close: Decimal,
+    #[serde(with = "decimal")]
+    volume: Decimal,
+    #[serde(with = "decimal")]
+    quote_volume: Decimal,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "DataQualityIssue")]
+enum DataQualityIssueDef {
+    CrossedBook {
+        #[serde(with = "decimal")]
+        bid_price: Decimal,
+        #[serde(with = "decimal")]
+        ask_price: Decimal,
+    },
+    LockedBook {
+        #[serde(with = "decimal")]
+        price: Decimal,
+    },
+    OutlierTrade {
+        #[serde(with = "decimal")]
+        price: Decimal,
+        #[serde(with = "decimal")]
+        reference: Decimal,
+    },
+    DuplicateTrade {
+        id: String,
+    },
+    Stale {
+        last_update: DateTime<Utc>,
+    },
+    SequenceGap {
+        expected: u64,
+        received: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "DataQualityEvent")]
+struct DataQualityEventDef {
+    #[serde(with = "DataQualityIssueDef")]
+    issue: DataQualityIssue,
+    dropped: bool,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "FundingRate")]
+struct FundingRateDef {
+    #[serde(with = "decimal")]
+    rate: Decimal,
+    next_funding_time: DateTime<Utc>,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "MarkPrice")]
+struct MarkPriceDef {
+    #[serde(with = "decimal")]
+    price: Decimal,
+    #[serde(with = "optional_decimal")]
+    index_price: Option<Decimal>,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "OpenInterest")]
+struct OpenInterestDef {
+    #[serde(with = "decimal")]
+    quantity: Decimal,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Liquidation")]
+struct LiquidationDef {
+    side: Side,
+    #[serde(with = "decimal")]
+    price: Decimal,
+    #[serde(with = "decimal")]
+    quantity: Decimal,
+    #[serde(with = "decimal")]
+    average_price: Decimal,
+    #[serde(with = "decimal")]
+    filled_quantity: Decimal,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "BookFeatures")]
+struct BookFeaturesDef {
+    #[serde(with = "decimal")]
+    mid: Decimal,
+    #[serde(with = "decimal")]
+    spread: Decimal,
+    #[serde(with = "optional_decimal")]
+    spread_ticks: Option<Decimal>,
+    #[serde(with = "decimal")]
+    top_imbalance: Decimal,
+    #[serde(with = "decimal")]
+    depth_imbalance: Decimal,
+    #[serde(with = "decimal")]
+    microprice: Decimal,
+    #[serde(with = "decimal")]
+    weighted_mid: Decimal,
+    #[serde(with = "decimal")]
+    book_pressure: Decimal,
+    #[serde(with = "decimal")]
+    ofi: Decimal,
+    #[serde(with = "decimal")]
+    ofi_window: Decimal,
+    timestamp: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::spot_instrument;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_decimal_events_round_trip() {
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let event = |kind| MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: spot_instrument("BTC", "USDT"),
+            kind,
+            exchange_time: time,
+            receipt_time: time,
+        };
+        let events = vec![
+            event(MarketDataKind::OrderBookDelta(OrderBookDelta {
+                updates: vec![LevelUpdate {
+                    side: Side::Sell,
+                    price: dec!(42000.10),
+                    quantity: dec!(0.000),
+                    action: LevelAction::Delete,
+                }],
+                first_update_id: Some(7),
+                last_update_id: Some(9),
+                snapshot: false,
+                timestamp: time,
+            })),
+            event(MarketDataKind::MarkPrice(MarkPrice {
+                price: dec!(42001.5),
+                index_price: None,
+                timestamp: time,
+            })),
+            event(MarketDataKind::DataQuality(DataQualityEvent {
+                issue: DataQualityIssue::CrossedBook {
+                    bid_price: dec!(101),
+                    ask_price: dec!(100.99),
+                },
+                dropped: true,
+                timestamp: time,
+            })),
+        ];
+
+        let path = std::env::temp_dir().join(format!("capture-{}.mdc", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(index_path(&path));
+        let mut writer = CaptureWriter::open(&path, Compression::Lz4, 64).unwrap();
+        for event in &events {
+            writer.write(event).unwrap();
+        }
+        drop(writer);
+
+        let read = CaptureReader::open(&path)
+            .unwrap()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read, events);
+        // Trailing zeros survive the string encoding
+        let MarketDataKind::OrderBookDelta(delta) = &read[0].kind else {
+            panic!("expected delta");
+        };
+        assert_eq!(delta.updates[0].quantity.to_string(), "0.000");
+    }
+
+    #[test]
+    fn test_append_after_torn_block() {
+        let event = |second| {
+            let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, second).unwrap();
+            MarketEvent {
+                exchange: ExchangeId::Binance,
+                instrument: spot_instrument("BTC", "USDT"),
+                kind: MarketDataKind::MarkPrice(MarkPrice {
+                    price: Decimal::from(42000 + second),
+                    index_price: None,
+                    timestamp: time,
+                }),
+                exchange_time: time,
+                receipt_time: time,
+            }
+        };
+        let first: Vec<_> = (0..3).map(event).collect();
+        let second: Vec<_> = (3..6).map(event).collect();
+
+        let path = std::env::temp_dir().join(format!("capture-torn-{}.mdc", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(index_path(&path));
+        let mut writer = CaptureWriter::open(&path, Compression::None, 1).unwrap();
+        for event in &first {
+            writer.write(event).unwrap();
+        }
+        drop(writer);
+
+        // A crash mid-block leaves a header promising more payload than was written
+        let mut torn = [0u8; BLOCK_HEADER_LEN + 8];
+        torn[0..4].copy_from_slice(&1000u32.to_le_bytes());
+        torn[8..12].copy_from_slice(&5u32.to_le_bytes());
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&torn)
+            .unwrap();
+
+        let mut writer = CaptureWriter::open(&path, Compression::None, 1).unwrap();
+        for event in &second {
+            writer.write(event).unwrap();
+        }
+        drop(writer);
+
+        let read = CaptureReader::open(&path)
+            .unwrap()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read, [first, second].concat());
+    }
+
+    #[test]
+    fn test_reopen_with_index_past_end_of_file() {
+        let event = |second| {
+            let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, second).unwrap();
+            MarketEvent {
+                exchange: ExchangeId::Binance,
+                instrument: spot_instrument("BTC", "USDT"),
+                kind: MarketDataKind::MarkPrice(MarkPrice {
+                    price: Decimal::from(42000 + second),
+                    index_price: None,
+                    timestamp: time,
+                }),
+                exchange_time: time,
+                receipt_time: time,
+            }
+        };
+        let first: Vec<_> = (0..3).map(event).collect();
+        let second: Vec<_> = (3..6).map(event).collect();
+
+        let path = std::env::temp_dir().join(format!("capture-stale-{}.mdc", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(index_path(&path));
+        let mut writer = CaptureWriter::open(&path, Compression::Lz4, 1).unwrap();
+        for event in &first {
+            writer.write(event).unwrap();
+        }
+        drop(writer);
+
+        // The index reached disk, the last block and one more did not
+        let len = std::fs::metadata(&path).unwrap().len();
+        let index = load_index(&path).unwrap();
+        let mut stale: Vec<u8> = index.iter().flat_map(|entry| entry.encode()).collect();
+        for offset in [len, len + 100] {
+            let entry = BlockIndexEntry { offset, ..index[2] };
+            stale.extend_from_slice(&entry.encode());
+        }
+        std::fs::write(index_path(&path), &stale).unwrap();
+        let last = index[2].offset;
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(last + 10)
+            .unwrap();
+
+        assert_eq!(load_index(&path).unwrap(), index[..2]);
+        let mut writer = CaptureWriter::open(&path, Compression::Lz4, 1).unwrap();
+        for event in &second {
+            writer.write(event).unwrap();
+        }
+        drop(writer);
+
+        let read = CaptureReader::open(&path)
+            .unwrap()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read, [&first[..2], &second[..]].concat());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(index_path(&path)).unwrap();
+    }
+}
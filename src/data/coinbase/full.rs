@@ -0,0 +1,852 @@
+//! Coinbase Exchange `full` channel
+//!
+//! The full channel publishes every order event on the book. `open`, `change`, `match`
+//! and `done` messages are normalized into `OrderBookL3Update`s that an `L3OrderBook`
+//! can apply directly, and each `match` additionally produces a `PublicTrade`.
+//! `received` messages describe orders that are not yet resting and are skipped.
+//!
+//! The channel only reports changes, so each product starts from a level 3 snapshot
+//! fetched from the REST API. Messages are buffered until the snapshot arrives and
+//! those it already covers are skipped. After a sequence gap or an undecodable message
+//! `CoinbaseFullConnector` publishes a `DataQualityIssue::SequenceGap` event, which
+//! makes an `L3OrderBook` discard its orders, and `CoinbaseFullChannelStream`
+//! resynchronises the product the same way.
+//!
+//! The channel always carries both orders and trades. A stream created with
+//! `from_config` accepts `SubscriptionKind::L3` and `SubscriptionKind::Trades` and
+//! drops the events an instrument's configuration did not ask for.
+
+use super::{instrument_from_product_id, CoinbaseError};
+use crate::config::DataConfig;
+use crate::data::connector::{ExchangeConnector, Heartbeat, WebSocketMarketDataStream};
+use crate::data::subscription::is_subscribed;
+use crate::data::{
+    DataQualityEvent, DataQualityIssue, ExchangeId, InstrumentId, L3Action, MarketDataKind,
+    MarketDataStream, MarketEvent, OrderBookL3Update, PublicTrade, Side, SubscriptionKind,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::task::Poll;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Default Coinbase Exchange WebSocket endpoint
+pub const COINBASE_EXCHANGE_WS_URL: &str = "wss://ws-feed.exchange.coinbase.com";
+
+/// Default Coinbase Exchange REST endpoint
+pub const COINBASE_EXCHANGE_REST_URL: &str = "https://api.exchange.coinbase.com";
+
+/// Wait before fetching a snapshot again after a failed request
+const SNAPSHOT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Coinbase Exchange `full` channel `ExchangeConnector`.
+///
+/// Sequence gaps and undecodable messages of a product are reported as
+/// `DataQualityIssue::SequenceGap` events rather than errors, so the stream can fetch
+/// a new snapshot for it.
+#[derive(Debug, Clone)]
+pub struct CoinbaseFullConnector {
+    url: String,
+    parser: CoinbaseFullParser,
+}
+
+impl CoinbaseFullConnector {
+    /// Create a connector for the public endpoint
+    pub fn new() -> Self {
+        Self {
+            url: COINBASE_EXCHANGE_WS_URL.to_string(),
+            parser: CoinbaseFullParser::new(),
+        }
+    }
+
+    /// Connect to a different endpoint, e.g. the sandbox or a local mock server
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    fn subscription_message(kind: &str, instruments: &[InstrumentId]) -> String {
+        let product_ids: Vec<&str> = instruments
+            .iter()
+            .map(|instrument| instrument.exchange_symbol.as_str())
+            .collect();
+        serde_json::json!({
+            "type": kind,
+            "product_ids": product_ids,
+            "channels": ["heartbeat", "full"],
+        })
+        .to_string()
+    }
+}
+
+impl Default for CoinbaseFullConnector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExchangeConnector for CoinbaseFullConnector {
+    type Error = CoinbaseError;
+
+    fn exchange(&self) -> ExchangeId {
+        ExchangeId::Coinbase
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn subscribe_messages(&self, instruments: &[InstrumentId]) -> Result<Vec<String>, Self::Error> {
+        Ok(vec![Self::subscription_message("subscribe", instruments)])
+    }
+
+    fn unsubscribe_messages(
+        &self,
+        instruments: &[InstrumentId],
+    ) -> Result<Vec<String>, Self::Error> {
+        Ok(vec![Self::subscription_message("unsubscribe", instruments)])
+    }
+
+    fn decode(
+        &mut self,
+        frame: &str,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Vec<MarketEvent>, Self::Error> {
+        let error = match self.parser.parse_message(frame, receipt_time) {
+            Ok(events) => return Ok(events),
+            Err(error) => error,
+        };
+        let Some(product_id) = serde_json::from_str::<ProductMessage<'_>>(frame)
+            .ok()
+            .and_then(|message| message.product_id)
+        else {
+            return Err(error);
+        };
+        match error {
+            CoinbaseError::SequenceGap { expected, received } => {
+                // Continue from the message that revealed the gap
+                self.parser.reset(product_id);
+                let mut events = vec![gap_event(product_id, expected, received, receipt_time)];
+                events.extend(self.parser.parse_message(frame, receipt_time)?);
+                Ok(events)
+            }
+            error => {
+                tracing::warn!(%error, product_id, "resyncing Coinbase full channel");
+                let expected = self.parser.expected(product_id);
+                Ok(vec![gap_event(
+                    product_id,
+                    expected,
+                    expected,
+                    receipt_time,
+                )])
+            }
+        }
+    }
+
+    /// Coinbase sends a heartbeat per product every second
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat {
+            ping: None,
+            idle_timeout: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+/// Resynchronisation state of a product
+enum ProductSync {
+    /// Snapshot requested; the product's events are held back until it arrives
+    Fetching(Vec<MarketEvent>),
+    /// Snapshot published at this sequence; events it already covers are skipped
+    Resuming(u64),
+}
+
+type SnapshotResult = (String, Result<L3Snapshot, CoinbaseError>);
+
+/// Coinbase Exchange order-by-order market data stream
+///
+/// Messages come from a `CoinbaseFullConnector` over the shared WebSocket transport;
+/// level 3 snapshots are fetched over REST for each new product and after every gap.
+pub struct CoinbaseFullChannelStream {
+    inner: WebSocketMarketDataStream<CoinbaseFullConnector>,
+    http: reqwest::Client,
+    rest_url: String,
+    /// Products not yet live, absent once their events pass straight through
+    sync: HashMap<String, ProductSync>,
+    snapshot_tx: mpsc::UnboundedSender<SnapshotResult>,
+    snapshots: mpsc::UnboundedReceiver<SnapshotResult>,
+    pending: VecDeque<Result<MarketEvent, CoinbaseError>>,
+    /// Whether the connection has ended; the next subscription reconnects
+    closed: bool,
+    /// Configuration selecting the events delivered per instrument, all when absent
+    config: Option<DataConfig>,
+}
+
+impl CoinbaseFullChannelStream {
+    /// Create a new full channel stream
+    pub fn new() -> Self {
+        Self::from_connector(CoinbaseFullConnector::new())
+    }
+
+    /// Create a stream driving `connector`
+    pub fn from_connector(connector: CoinbaseFullConnector) -> Self {
+        let (snapshot_tx, snapshots) = mpsc::unbounded_channel();
+        Self {
+            inner: WebSocketMarketDataStream::from_connector(connector),
+            http: reqwest::Client::new(),
+            rest_url: COINBASE_EXCHANGE_REST_URL.to_string(),
+            sync: HashMap::new(),
+            snapshot_tx,
+            snapshots,
+            pending: VecDeque::new(),
+            closed: false,
+            config: None,
+        }
+    }
+
+    /// Create a stream delivering the kinds listed in the configuration, including
+    /// per-instrument overrides
+    pub fn from_config(config: &DataConfig) -> Result<Self, CoinbaseError> {
+        if let Some(kind) = config
+            .all_subscriptions()
+            .into_iter()
+            .find(|kind| !matches!(kind, SubscriptionKind::L3 | SubscriptionKind::Trades))
+        {
+            return Err(CoinbaseError::UnsupportedSubscription(kind));
+        }
+        let mut stream = Self::new();
+        stream.config = Some(config.clone());
+        Ok(stream)
+    }
+
+    /// Drive a different connector, e.g. one pointed at a local mock server
+    pub fn with_connector(mut self, connector: CoinbaseFullConnector) -> Self {
+        self.inner = WebSocketMarketDataStream::from_connector(connector);
+        self
+    }
+
+    /// Fetch level 3 snapshots from a different REST endpoint
+    pub fn with_rest_url(mut self, rest_url: impl Into<String>) -> Self {
+        self.rest_url = rest_url.into();
+        self
+    }
+
+    /// Connector used for new connections
+    pub fn connector(&self) -> &CoinbaseFullConnector {
+        self.inner.connector()
+    }
+
+    /// Discard what is known about a product and fetch a new snapshot for it
+    fn resync(&mut self, product_id: String) {
+        self.sync
+            .insert(product_id.clone(), ProductSync::Fetching(Vec::new()));
+        self.fetch(product_id, Duration::ZERO);
+    }
+
+    fn fetch(&self, product_id: String, delay: Duration) {
+        let http = self.http.clone();
+        let url = format!("{}/products/{}/book?level=3", self.rest_url, product_id);
+        let snapshots = self.snapshot_tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let snapshot = fetch_snapshot(&http, &url).await;
+            let _ = snapshots.send((product_id, snapshot));
+        });
+    }
+
+    /// Route an event from the connection according to its product's state
+    fn handle(&mut self, event: MarketEvent) {
+        let product_id = event.instrument.exchange_symbol.clone();
+        let gap = match &event.kind {
+            MarketDataKind::DataQuality(DataQualityEvent {
+                issue: DataQualityIssue::SequenceGap { received, .. },
+                ..
+            }) => Some(*received),
+            _ => None,
+        };
+        match self.sync.get_mut(&product_id) {
+            Some(ProductSync::Fetching(buffer)) => buffer.push(event),
+            Some(ProductSync::Resuming(snapshot)) => {
+                let snapshot = *snapshot;
+                let sequence = match &event.kind {
+                    MarketDataKind::OrderBookL3(update) => update.sequence,
+                    _ => None,
+                };
+                match (gap, sequence) {
+                    // Messages missed before the snapshot are covered by it
+                    (Some(received), _) if received <= snapshot + 1 => {}
+                    (Some(_), _) => {
+                        self.resync(product_id);
+                        self.pending.push_back(Ok(event));
+                    }
+                    (None, Some(sequence)) if sequence <= snapshot => {}
+                    (None, Some(sequence)) if sequence > snapshot + 1 => {
+                        let gap = gap_event(&product_id, snapshot + 1, sequence, Utc::now());
+                        self.pending.push_back(Ok(gap));
+                        self.resync(product_id);
+                        self.handle(event);
+                    }
+                    (None, Some(_)) => {
+                        self.sync.remove(&product_id);
+                        self.pending.push_back(Ok(event));
+                    }
+                    // Trades follow the match they belong to, which was skipped
+                    (None, None) => {}
+                }
+            }
+            None => {
+                if gap.is_some() {
+                    self.resync(product_id);
+                }
+                self.pending.push_back(Ok(event));
+            }
+        }
+    }
+
+    /// Publish a snapshot's orders and replay the events held back meanwhile
+    fn apply_snapshot(&mut self, (product_id, snapshot): SnapshotResult) {
+        let Some(ProductSync::Fetching(_)) = self.sync.get(&product_id) else {
+            // Unsubscribed, or superseded by a later snapshot
+            return;
+        };
+        let snapshot = match snapshot {
+            Ok(snapshot) => snapshot,
+            Err(error) => {
+                self.pending.push_back(Err(error));
+                if self.closed {
+                    self.sync.remove(&product_id);
+                } else {
+                    self.fetch(product_id, SNAPSHOT_RETRY_DELAY);
+                }
+                return;
+            }
+        };
+        let Some(ProductSync::Fetching(buffered)) = self
+            .sync
+            .insert(product_id.clone(), ProductSync::Resuming(snapshot.sequence))
+        else {
+            return;
+        };
+        self.pending.extend(
+            snapshot_events(&product_id, &snapshot, Utc::now())
+                .into_iter()
+                .map(Ok),
+        );
+        for event in buffered {
+            self.handle(event);
+        }
+    }
+
+    fn poll_event(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Result<MarketEvent, Box<dyn std::error::Error + Send + Sync>>>> {
+        use futures::StreamExt;
+
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                // Filtered on the way out so resynchronisation still sees every order event
+                if let (Ok(event), Some(config)) = (&item, &self.config) {
+                    if !is_subscribed(config.subscriptions_for(&event.instrument), &event.kind) {
+                        continue;
+                    }
+                }
+                return Poll::Ready(Some(item.map_err(Into::into)));
+            }
+            if let Poll::Ready(Some(snapshot)) = self.snapshots.poll_recv(cx) {
+                self.apply_snapshot(snapshot);
+                continue;
+            }
+            if self.closed {
+                // Deliver what was held back before the connection ended
+                let fetching = self
+                    .sync
+                    .values()
+                    .any(|sync| matches!(sync, ProductSync::Fetching(_)));
+                return if fetching {
+                    Poll::Pending
+                } else {
+                    Poll::Ready(None)
+                };
+            }
+            match self.inner.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(event))) => self.handle(event),
+                Poll::Ready(Some(Err(error))) => return Poll::Ready(Some(Err(error))),
+                Poll::Ready(None) => self.closed = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl Default for CoinbaseFullChannelStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl MarketDataStream for CoinbaseFullChannelStream {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        futures::future::poll_fn(|cx| self.poll_event(cx))
+            .await
+            .transpose()
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.inner.subscribe(instruments).await?;
+        // A new connection starts without orders, so every product needs a snapshot
+        let products: Vec<String> = if std::mem::take(&mut self.closed) {
+            self.inner
+                .instruments()
+                .iter()
+                .map(|instrument| instrument.exchange_symbol.clone())
+                .collect()
+        } else {
+            instruments
+                .iter()
+                .map(|instrument| instrument.exchange_symbol.clone())
+                .collect()
+        };
+        for product_id in products {
+            self.resync(product_id);
+        }
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        for instrument in instruments {
+            self.sync.remove(&instrument.exchange_symbol);
+        }
+        self.inner.unsubscribe(instruments).await
+    }
+}
+
+impl futures::Stream for CoinbaseFullChannelStream {
+    type Item = Result<MarketEvent, <Self as MarketDataStream>::Error>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.poll_event(cx)
+    }
+}
+
+async fn fetch_snapshot(http: &reqwest::Client, url: &str) -> Result<L3Snapshot, CoinbaseError> {
+    let response = http.get(url).send().await?.error_for_status()?;
+    Ok(response.json().await?)
+}
+
+/// Status event telling consumers to discard the product's level 3 book
+fn gap_event(
+    product_id: &str,
+    expected: u64,
+    received: u64,
+    receipt_time: DateTime<Utc>,
+) -> MarketEvent {
+    MarketEvent {
+        exchange: ExchangeId::Coinbase,
+        instrument: instrument_from_product_id(product_id),
+        kind: MarketDataKind::DataQuality(DataQualityEvent {
+            issue: DataQualityIssue::SequenceGap { expected, received },
+            dropped: false,
+            timestamp: receipt_time,
+        }),
+        exchange_time: receipt_time,
+        receipt_time,
+    }
+}
+
+/// Order additions rebuilding a product's book from a level 3 snapshot
+fn snapshot_events(
+    product_id: &str,
+    snapshot: &L3Snapshot,
+    receipt_time: DateTime<Utc>,
+) -> Vec<MarketEvent> {
+    let instrument = instrument_from_product_id(product_id);
+    let bids = snapshot.bids.iter().map(|order| (Side::Buy, order));
+    let asks = snapshot.asks.iter().map(|order| (Side::Sell, order));
+    bids.chain(asks)
+        .map(|(side, (price, quantity, order_id))| MarketEvent {
+            exchange: ExchangeId::Coinbase,
+            instrument: instrument.clone(),
+            kind: MarketDataKind::OrderBookL3(OrderBookL3Update {
+                order_id: order_id.clone(),
+                action: L3Action::Add,
+                side,
+                price: *price,
+                quantity: *quantity,
+                sequence: Some(snapshot.sequence),
+                timestamp: receipt_time,
+            }),
+            exchange_time: receipt_time,
+            receipt_time,
+        })
+        .collect()
+}
+
+/// Response of `GET /products/{id}/book?level=3`
+#[derive(Debug, Deserialize)]
+pub(crate) struct L3Snapshot {
+    sequence: u64,
+    /// `[price, size, order_id]` in priority order, best price first
+    bids: Vec<(Decimal, Decimal, String)>,
+    asks: Vec<(Decimal, Decimal, String)>,
+}
+
+#[derive(Deserialize)]
+struct ProductMessage<'a> {
+    #[serde(borrow, default)]
+    product_id: Option<&'a str>,
+}
+
+/// Normalizer for full channel messages, checking per-product sequence numbers
+#[derive(Debug, Clone)]
+pub(crate) struct CoinbaseFullParser {
+    sequences: HashMap<String, u64>,
+}
+
+impl CoinbaseFullParser {
+    pub(crate) fn new() -> Self {
+        Self {
+            sequences: HashMap::new(),
+        }
+    }
+
+    /// Sequence number expected next for a product
+    fn expected(&self, product_id: &str) -> u64 {
+        self.sequences.get(product_id).map_or(0, |last| last + 1)
+    }
+
+    /// Forget a product's sequence, accepting whatever message comes next
+    fn reset(&mut self, product_id: &str) {
+        self.sequences.remove(product_id);
+    }
+
+    /// Parse a single WebSocket text frame into zero or more market events.
+    ///
+    /// A sequence gap is reported as an error; L3 books for the product should be
+    /// rebuilt because orders may have been missed.
+    pub(crate) fn parse_message(
+        &mut self,
+        message: &str,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Vec<MarketEvent>, CoinbaseError> {
+        let message: FullMessage<'_> = serde_json::from_str(message)?;
+        let Some(product_id) = message.product_id else {
+            return Ok(Vec::new());
+        };
+
+        if let Some(sequence) = message.sequence {
+            let previous = self.sequences.insert(product_id.to_string(), sequence);
+            if let Some(expected) = previous.map(|last| last + 1) {
+                if sequence > expected {
+                    return Err(CoinbaseError::SequenceGap {
+                        expected,
+                        received: sequence,
+                    });
+                }
+                if sequence < expected {
+                    // Replayed message
+                    self.sequences.insert(product_id.to_string(), expected - 1);
+                    return Ok(Vec::new());
+                }
+            }
+        }
+
+        let exchange_time = message.time.unwrap_or(receipt_time);
+        let side = match message.side {
+            Some(FullSide::Buy) => Side::Buy,
+            Some(FullSide::Sell) => Side::Sell,
+            None => return Ok(Vec::new()),
+        };
+        let instrument = instrument_from_product_id(product_id);
+        let l3 = |order_id: &str, action, price, quantity| MarketEvent {
+            exchange: ExchangeId::Coinbase,
+            instrument: instrument.clone(),
+            kind: MarketDataKind::OrderBookL3(OrderBookL3Update {
+                order_id: order_id.to_string(),
+                action,
+                side,
+                price,
+                quantity,
+                sequence: message.sequence,
+                timestamp: exchange_time,
+            }),
+            exchange_time,
+            receipt_time,
+        };
+        let missing = |field: &str| {
+            CoinbaseError::UnknownMessage(format!("{} message without {}", message.kind, field))
+        };
+
+        let events = match message.kind {
+            "open" => vec![l3(
+                message.order_id.ok_or_else(|| missing("order_id"))?,
+                L3Action::Add,
+                message.price.ok_or_else(|| missing("price"))?,
+                message
+                    .remaining_size
+                    .ok_or_else(|| missing("remaining_size"))?,
+            )],
+            "change" => {
+                let order_id = message.order_id.ok_or_else(|| missing("order_id"))?;
+                let price = message
+                    .new_price
+                    .or(message.price)
+                    .ok_or_else(|| missing("price"))?;
+                let quantity = message.new_size.ok_or_else(|| missing("new_size"))?;
+                vec![l3(order_id, L3Action::Modify, price, quantity)]
+            }
+            "done" => {
+                // Filled orders were already reduced to zero by their matches
+                let order_id = message.order_id.ok_or_else(|| missing("order_id"))?;
+                vec![l3(
+                    order_id,
+                    L3Action::Cancel,
+                    message.price.unwrap_or_default(),
+                    Decimal::ZERO,
+                )]
+            }
+            "match" => {
+                let maker = message
+                    .maker_order_id
+                    .ok_or_else(|| missing("maker_order_id"))?;
+                let price = message.price.ok_or_else(|| missing("price"))?;
+                let size = message.size.ok_or_else(|| missing("size"))?;
+                let trade = MarketEvent {
+                    exchange: ExchangeId::Coinbase,
+                    instrument: instrument.clone(),
+                    // The message side is the maker's, so the aggressor took the other side
+                    kind: MarketDataKind::Trade(PublicTrade {
+                        id: message.trade_id.unwrap_or_default().to_string(),
+                        price,
+                        quantity: size,
+                        side: match side {
+                            Side::Buy => Side::Sell,
+                            Side::Sell => Side::Buy,
+                        },
+                        timestamp: exchange_time,
+                    }),
+                    exchange_time,
+                    receipt_time,
+                };
+                vec![l3(maker, L3Action::Execute, price, size), trade]
+            }
+            _ => Vec::new(),
+        };
+        Ok(events)
+    }
+}
+
+#[derive(Deserialize)]
+struct FullMessage<'a> {
+    #[serde(rename = "type", borrow)]
+    kind: &'a str,
+    #[serde(borrow, default)]
+    product_id: Option<&'a str>,
+    #[serde(default)]
+    sequence: Option<u64>,
+    #[serde(default)]
+    time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    side: Option<FullSide>,
+    #[serde(borrow, default)]
+    order_id: Option<&'a str>,
+    #[serde(borrow, default)]
+    maker_order_id: Option<&'a str>,
+    #[serde(default)]
+    trade_id: Option<u64>,
+    #[serde(default)]
+    price: Option<Decimal>,
+    #[serde(default)]
+    new_price: Option<Decimal>,
+    #[serde(default)]
+    size: Option<Decimal>,
+    #[serde(default)]
+    remaining_size: Option<Decimal>,
+    #[serde(default)]
+    new_size: Option<Decimal>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FullSide {
+    Buy,
+    Sell,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::mock_server::{MockHttpResponse, MockHttpServer, MockWebSocketServer};
+    use crate::data::L3OrderBook;
+    use rust_decimal_macros::dec;
+
+    const SESSION: &str = include_str!("../../../tests/fixtures/coinbase/full.jsonl");
+
+    #[test]
+    fn test_full_channel_builds_l3_book() {
+        let mut parser = CoinbaseFullParser::new();
+        let mut book = L3OrderBook::new();
+        let mut trades = Vec::new();
+        for line in SESSION.lines() {
+            for event in parser.parse_message(line, Utc::now()).unwrap() {
+                if let MarketDataKind::Trade(trade) = &event.kind {
+                    trades.push(trade.clone());
+                }
+                book.update(&event);
+            }
+        }
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].side, Side::Sell);
+        assert_eq!(trades[0].quantity, dec!(0.4));
+
+        // Order "a" was filled, "b" shrank in place and "c" rests behind it
+        assert!(book.order("a").is_none());
+        assert_eq!(book.order("b").unwrap().quantity, dec!(1.5));
+        assert_eq!(book.queue_position("c").unwrap().quantity_ahead, dec!(1.5));
+        assert_eq!(book.best_bid().unwrap().quantity, dec!(2.5));
+        assert_eq!(book.best_ask().unwrap().price, dec!(100.05));
+        assert_eq!(book.last_sequence(), Some(109));
+    }
+
+    #[test]
+    fn test_full_channel_sequence_gap() {
+        let mut parser = CoinbaseFullParser::new();
+        let lines: Vec<&str> = SESSION.lines().collect();
+        parser.parse_message(lines[1], Utc::now()).unwrap();
+        assert!(matches!(
+            parser.parse_message(lines[3], Utc::now()),
+            Err(CoinbaseError::SequenceGap {
+                expected: 102,
+                received: 103
+            })
+        ));
+        // Replays of earlier sequence numbers are ignored
+        assert!(parser
+            .parse_message(lines[1], Utc::now())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_full_channel_stream_against_mock_server() {
+        let server = MockWebSocketServer::from_fixture(SESSION).await.unwrap();
+        let rest = MockHttpServer::start(vec![MockHttpResponse::json(
+            r#"{"sequence":100,"bids":[],"asks":[]}"#,
+        )])
+        .await
+        .unwrap();
+        let mut stream = CoinbaseFullChannelStream::from_connector(
+            CoinbaseFullConnector::new().with_url(server.url()),
+        )
+        .with_rest_url(rest.url());
+        stream
+            .subscribe(&[instrument_from_product_id("BTC-USD")])
+            .await
+            .unwrap();
+
+        let mut count = 0;
+        while stream.next().await.unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 9);
+        assert!(server.received_messages()[0].contains("\"full\""));
+        assert_eq!(rest.requests(), ["/products/BTC-USD/book?level=3"]);
+    }
+
+    #[tokio::test]
+    async fn test_from_config_drops_unrequested_kinds() {
+        let server = MockWebSocketServer::from_fixture(SESSION).await.unwrap();
+        let rest = MockHttpServer::start(vec![MockHttpResponse::json(
+            r#"{"sequence":100,"bids":[],"asks":[]}"#,
+        )])
+        .await
+        .unwrap();
+        let mut config = crate::config::SystemConfig::default().data;
+        config.subscriptions = vec![SubscriptionKind::Trades];
+        let mut stream = CoinbaseFullChannelStream::from_config(&config)
+            .unwrap()
+            .with_connector(CoinbaseFullConnector::new().with_url(server.url()))
+            .with_rest_url(rest.url());
+        stream
+            .subscribe(&[instrument_from_product_id("BTC-USD")])
+            .await
+            .unwrap();
+
+        let mut kinds = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            kinds.push(event.kind);
+        }
+        assert_eq!(kinds.len(), 1);
+        assert!(matches!(kinds[0], MarketDataKind::Trade(_)));
+
+        config.subscriptions = vec![SubscriptionKind::L2 { depth: 10 }];
+        assert!(matches!(
+            CoinbaseFullChannelStream::from_config(&config),
+            Err(CoinbaseError::UnsupportedSubscription(
+                SubscriptionKind::L2 { depth: 10 }
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_full_channel_resyncs_after_gap() {
+        // 103 is missing, and 101 is already covered by the first snapshot
+        let lines: Vec<&str> = SESSION.lines().collect();
+        let server = MockWebSocketServer::start(
+            [lines[0], lines[1], lines[2], lines[4]]
+                .map(str::to_string)
+                .to_vec(),
+        )
+        .await
+        .unwrap();
+        let rest = MockHttpServer::start(vec![
+            MockHttpResponse::json(
+                r#"{"sequence":101,"bids":[["100.00","0.4","a"],["99.00","1","z"]],"asks":[]}"#,
+            ),
+            MockHttpResponse::json(
+                r#"{"sequence":104,"bids":[["100.00","0.4","a"],["100.00","2.0","b"],["100.00","1.0","c"]],"asks":[["100.05","3.0","x"]]}"#,
+            ),
+        ])
+        .await
+        .unwrap();
+        let mut stream = CoinbaseFullChannelStream::from_connector(
+            CoinbaseFullConnector::new().with_url(server.url()),
+        )
+        .with_rest_url(rest.url());
+        stream
+            .subscribe(&[instrument_from_product_id("BTC-USD")])
+            .await
+            .unwrap();
+
+        let mut book = L3OrderBook::new();
+        let mut gaps = 0;
+        while let Some(event) = stream.next().await.unwrap() {
+            if let MarketDataKind::DataQuality(quality) = &event.kind {
+                assert_eq!(
+                    quality.issue,
+                    DataQualityIssue::SequenceGap {
+                        expected: 103,
+                        received: 104
+                    }
+                );
+                gaps += 1;
+            }
+            book.update(&event);
+        }
+        assert_eq!(gaps, 1);
+        assert_eq!(rest.requests().len(), 2);
+        // "z" disappeared with the gap; the second snapshot already held "x"
+        assert!(book.order("z").is_none());
+        assert_eq!(book.order_count(), 4);
+        assert_eq!(book.queue_position("c").unwrap().quantity_ahead, dec!(2.4));
+        assert_eq!(book.last_sequence(), Some(104));
+    }
+}
@@ -0,0 +1,649 @@
+//! Coinbase Advanced Trade market data
+//!
+//! Connects to the Advanced Trade WebSocket feed, subscribes to the `market_trades`
+//! and `level2` channels and normalizes both into `MarketEvent`s. Level 2 updates are
+//! applied to a local book per product and published as `OrderBookL2` snapshots; after
+//! a sequence gap `CoinbaseConnector::recover` resubscribes to `level2` for fresh
+//! snapshots. `CoinbaseMarketDataStream` runs the connector over the shared WebSocket
+//! transport. Order-by-order data from the Exchange `full` channel is handled by `full`.
+//!
+//! `from_config` maps `SubscriptionKind::Trades` to `market_trades` and
+//! `SubscriptionKind::L2` to `level2`, publishing the deepest requested depth; other
+//! kinds are rejected. Coinbase has no update rate setting, so `update_frequency_ms` is
+//! left to `ConflatedMarketDataStream::from_config`.
+
+use super::connector::{ExchangeConnector, Heartbeat, WebSocketMarketDataStream};
+use super::{
+    book::LevelBook, ExchangeId, InstrumentId, InstrumentKind, MarketDataKind, MarketEvent,
+    PublicTrade, Side, SubscriptionKind,
+};
+use crate::config::DataConfig;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub mod full;
+
+pub use full::{CoinbaseFullChannelStream, CoinbaseFullConnector};
+
+/// Default Advanced Trade WebSocket endpoint
+pub const COINBASE_WS_URL: &str = "wss://advanced-trade-ws.coinbase.com";
+
+/// Default number of levels published per side for level 2 books
+pub const COINBASE_DEFAULT_DEPTH: usize = 20;
+
+/// Errors produced while normalizing Coinbase messages
+#[derive(Debug, thiserror::Error)]
+pub enum CoinbaseError {
+    /// Message was not valid JSON or did not match the expected schema
+    #[error("invalid Coinbase message: {0}")]
+    Json(#[from] serde_json::Error),
+    /// Message without a channel, e.g. an error response
+    #[error("unknown Coinbase message: {0}")]
+    UnknownMessage(String),
+    /// One or more messages were missed and the local books are no longer reliable
+    #[error("Coinbase sequence gap: expected {expected}, received {received}")]
+    SequenceGap { expected: u64, received: u64 },
+    /// REST request for a book snapshot failed
+    #[error("Coinbase REST request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// Subscription without a Coinbase channel equivalent
+    #[error("unsupported Coinbase subscription: {0:?}")]
+    UnsupportedSubscription(SubscriptionKind),
+}
+
+/// Advanced Trade channels that can be subscribed per product
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CoinbaseChannel {
+    /// Public trades (`market_trades`)
+    MarketTrades,
+    /// Book published as `OrderBookL2` at the connector's depth (`level2`)
+    Level2,
+}
+
+impl CoinbaseChannel {
+    /// Channel name used in subscription messages
+    pub fn name(&self) -> &'static str {
+        match self {
+            CoinbaseChannel::MarketTrades => "market_trades",
+            CoinbaseChannel::Level2 => "level2",
+        }
+    }
+}
+
+/// Coinbase Advanced Trade real-time market data stream
+pub type CoinbaseMarketDataStream = WebSocketMarketDataStream<CoinbaseConnector>;
+
+impl WebSocketMarketDataStream<CoinbaseConnector> {
+    /// Create a new Coinbase market data stream
+    pub fn new() -> Self {
+        Self::from_connector(CoinbaseConnector::new())
+    }
+}
+
+/// Coinbase Advanced Trade `ExchangeConnector`
+#[derive(Debug, Clone)]
+pub struct CoinbaseConnector {
+    url: String,
+    channels: Vec<CoinbaseChannel>,
+    /// Overrides of `channels` by product id
+    instrument_channels: HashMap<String, Vec<CoinbaseChannel>>,
+    parser: CoinbaseParser,
+}
+
+impl CoinbaseConnector {
+    /// Create a connector for the public endpoint subscribing to trades and level 2,
+    /// publishing the default book depth
+    pub fn new() -> Self {
+        Self {
+            url: COINBASE_WS_URL.to_string(),
+            channels: vec![CoinbaseChannel::MarketTrades, CoinbaseChannel::Level2],
+            instrument_channels: HashMap::new(),
+            parser: CoinbaseParser::new(COINBASE_DEFAULT_DEPTH),
+        }
+    }
+
+    /// Create a connector subscribing to the market data listed in the configuration,
+    /// including per-instrument overrides
+    pub fn from_config(config: &DataConfig) -> Result<Self, CoinbaseError> {
+        let mut depth = None;
+        let mut channels = |kinds: &[SubscriptionKind]| {
+            let mut channels = Vec::new();
+            for kind in kinds {
+                let channel = match *kind {
+                    SubscriptionKind::Trades => CoinbaseChannel::MarketTrades,
+                    SubscriptionKind::L2 { depth: levels } => {
+                        depth = depth.max(Some(levels));
+                        CoinbaseChannel::Level2
+                    }
+                    other => return Err(CoinbaseError::UnsupportedSubscription(other)),
+                };
+                if !channels.contains(&channel) {
+                    channels.push(channel);
+                }
+            }
+            Ok(channels)
+        };
+        let mut connector = Self::new().with_channels(channels(&config.subscriptions)?);
+        for subscription in &config.instrument_subscriptions {
+            connector = connector
+                .with_instrument_channels(&subscription.instrument, channels(&subscription.kinds)?);
+        }
+        Ok(connector.with_depth(depth.unwrap_or(COINBASE_DEFAULT_DEPTH)))
+    }
+
+    /// Replace the channels subscribed for each product
+    pub fn with_channels(mut self, channels: Vec<CoinbaseChannel>) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Subscribe to other channels for one instrument
+    pub fn with_instrument_channels(
+        mut self,
+        instrument: &InstrumentId,
+        channels: Vec<CoinbaseChannel>,
+    ) -> Self {
+        self.instrument_channels
+            .insert(instrument.exchange_symbol.clone(), channels);
+        self
+    }
+
+    /// Channels subscribed for `instrument`
+    pub fn channels_for(&self, instrument: &InstrumentId) -> &[CoinbaseChannel] {
+        self.instrument_channels
+            .get(&instrument.exchange_symbol)
+            .unwrap_or(&self.channels)
+    }
+
+    /// Subscription messages for every channel with at least one product
+    fn channel_messages(&self, kind: &str, instruments: &[InstrumentId]) -> Vec<String> {
+        [CoinbaseChannel::MarketTrades, CoinbaseChannel::Level2]
+            .into_iter()
+            .filter_map(|channel| {
+                let product_ids: Vec<String> = instruments
+                    .iter()
+                    .filter(|instrument| self.channels_for(instrument).contains(&channel))
+                    .map(|instrument| instrument.exchange_symbol.clone())
+                    .collect();
+                (!product_ids.is_empty())
+                    .then(|| subscription_message(kind, channel.name(), &product_ids))
+            })
+            .collect()
+    }
+
+    /// Connect to a different endpoint, e.g. a sandbox or a local mock server
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Number of levels per side published in `OrderBookL2` events
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.parser.depth = depth;
+        self
+    }
+}
+
+impl Default for CoinbaseConnector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExchangeConnector for CoinbaseConnector {
+    type Error = CoinbaseError;
+
+    fn exchange(&self) -> ExchangeId {
+        ExchangeId::Coinbase
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Heartbeats keep the connection open while products are quiet
+    fn subscribe_messages(&self, instruments: &[InstrumentId]) -> Result<Vec<String>, Self::Error> {
+        let product_ids: Vec<String> = instruments
+            .iter()
+            .map(|instrument| instrument.exchange_symbol.clone())
+            .collect();
+        let mut messages = vec![subscription_message(
+            "subscribe",
+            "heartbeats",
+            &product_ids,
+        )];
+        messages.extend(self.channel_messages("subscribe", instruments));
+        Ok(messages)
+    }
+
+    fn unsubscribe_messages(
+        &self,
+        instruments: &[InstrumentId],
+    ) -> Result<Vec<String>, Self::Error> {
+        Ok(self.channel_messages("unsubscribe", instruments))
+    }
+
+    fn decode(
+        &mut self,
+        frame: &str,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Vec<MarketEvent>, Self::Error> {
+        self.parser.parse_message(frame, receipt_time)
+    }
+
+    /// Level 2 snapshots are only sent on subscription, so a gap resubscribes every
+    /// product the venue last confirmed on the `level2` channel
+    fn recover(&mut self, error: &Self::Error) -> Vec<String> {
+        if !matches!(error, CoinbaseError::SequenceGap { .. }) {
+            return Vec::new();
+        }
+        let product_ids = &self.parser.level2_products;
+        if product_ids.is_empty() {
+            return Vec::new();
+        }
+        tracing::warn!(%error, "resubscribing to Coinbase level2");
+        ["unsubscribe", "subscribe"]
+            .into_iter()
+            .map(|kind| subscription_message(kind, "level2", product_ids))
+            .collect()
+    }
+
+    /// The `heartbeats` channel sends a message every second
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat {
+            ping: None,
+            idle_timeout: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+/// Build a subscription message for the given channel
+fn subscription_message(kind: &str, channel: &str, product_ids: &[String]) -> String {
+    serde_json::json!({
+        "type": kind,
+        "product_ids": product_ids,
+        "channel": channel,
+    })
+    .to_string()
+}
+
+/// Stateful normalizer for Advanced Trade messages
+#[derive(Debug, Clone)]
+pub(crate) struct CoinbaseParser {
+    depth: usize,
+    /// Books keyed by product id; a product is absent until its snapshot arrives
+    books: HashMap<String, LevelBook>,
+    last_sequence: Option<u64>,
+    /// Products on the `level2` channel according to the latest `subscriptions` message
+    level2_products: Vec<String>,
+}
+
+impl CoinbaseParser {
+    pub(crate) fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            books: HashMap::new(),
+            last_sequence: None,
+            level2_products: Vec::new(),
+        }
+    }
+
+    /// Parse a single WebSocket text frame into zero or more market events
+    pub(crate) fn parse_message(
+        &mut self,
+        message: &str,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Vec<MarketEvent>, CoinbaseError> {
+        let envelope: CoinbaseEnvelope<'_> = serde_json::from_str(message)?;
+        let Some(channel) = envelope.channel else {
+            return Err(CoinbaseError::UnknownMessage(message.to_string()));
+        };
+
+        if let Some(sequence) = envelope.sequence_num {
+            let expected = self.last_sequence.map(|last| last + 1);
+            self.last_sequence = Some(sequence);
+            if let Some(expected) = expected.filter(|expected| *expected != sequence) {
+                self.books.clear();
+                return Err(CoinbaseError::SequenceGap {
+                    expected,
+                    received: sequence,
+                });
+            }
+        }
+
+        let exchange_time = envelope.timestamp.unwrap_or(receipt_time);
+        match (channel, envelope.events) {
+            ("market_trades", Some(events)) => {
+                let events: Vec<CoinbaseTradeEvent> = serde_json::from_str(events.get())?;
+                Ok(events
+                    .into_iter()
+                    .flat_map(|event| event.trades)
+                    .map(|trade| MarketEvent {
+                        exchange: ExchangeId::Coinbase,
+                        instrument: instrument_from_product_id(&trade.product_id),
+                        kind: MarketDataKind::Trade(PublicTrade {
+                            id: trade.trade_id,
+                            price: trade.price,
+                            quantity: trade.size,
+                            side: trade.side.into(),
+                            timestamp: trade.time,
+                        }),
+                        exchange_time: trade.time,
+                        receipt_time,
+                    })
+                    .collect())
+            }
+            ("l2_data", Some(events)) => {
+                let events: Vec<CoinbaseLevel2Event> = serde_json::from_str(events.get())?;
+                let mut output = Vec::with_capacity(events.len());
+                for event in events {
+                    let book = match event.kind {
+                        CoinbaseEventType::Snapshot => {
+                            let book = self.books.entry(event.product_id.clone()).or_default();
+                            book.clear();
+                            book
+                        }
+                        CoinbaseEventType::Update => match self.books.get_mut(&event.product_id) {
+                            Some(book) => book,
+                            // Updates before a snapshot cannot be applied
+                            None => continue,
+                        },
+                    };
+                    for update in event.updates {
+                        let side = match update.side {
+                            CoinbaseBookSide::Bid => Side::Buy,
+                            CoinbaseBookSide::Offer => Side::Sell,
+                        };
+                        book.apply(side, update.price_level, update.new_quantity);
+                    }
+                    output.push(MarketEvent {
+                        exchange: ExchangeId::Coinbase,
+                        instrument: instrument_from_product_id(&event.product_id),
+                        kind: MarketDataKind::OrderBookL2(book.to_l2(self.depth, exchange_time)),
+                        exchange_time,
+                        receipt_time,
+                    });
+                }
+                Ok(output)
+            }
+            ("subscriptions", Some(events)) => {
+                let events: Vec<CoinbaseSubscriptionsEvent> = serde_json::from_str(events.get())?;
+                if let Some(event) = events.into_iter().last() {
+                    self.level2_products = event.subscriptions.level2;
+                }
+                Ok(Vec::new())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Map a Coinbase product id such as `BTC-USD` to an instrument
+fn instrument_from_product_id(product_id: &str) -> InstrumentId {
+    let (base, quote) = product_id.split_once('-').unwrap_or((product_id, ""));
+    InstrumentId {
+        base: base.to_string(),
+        quote: quote.to_string(),
+        exchange_symbol: product_id.to_string(),
+        kind: InstrumentKind::Spot,
+    }
+}
+
+#[derive(Deserialize)]
+struct CoinbaseEnvelope<'a> {
+    #[serde(borrow, default)]
+    channel: Option<&'a str>,
+    #[serde(default)]
+    timestamp: Option<DateTime<Utc>>,
+    #[serde(default)]
+    sequence_num: Option<u64>,
+    #[serde(borrow, default)]
+    events: Option<&'a RawValue>,
+}
+
+#[derive(Deserialize)]
+struct CoinbaseSubscriptionsEvent {
+    subscriptions: CoinbaseSubscriptions,
+}
+
+#[derive(Deserialize)]
+struct CoinbaseSubscriptions {
+    #[serde(default)]
+    level2: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CoinbaseEventType {
+    Snapshot,
+    Update,
+}
+
+#[derive(Deserialize)]
+struct CoinbaseTradeEvent {
+    trades: Vec<CoinbaseTrade>,
+}
+
+#[derive(Deserialize)]
+struct CoinbaseTrade {
+    trade_id: String,
+    product_id: String,
+    price: Decimal,
+    size: Decimal,
+    side: CoinbaseTradeSide,
+    time: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum CoinbaseTradeSide {
+    Buy,
+    Sell,
+}
+
+impl From<CoinbaseTradeSide> for Side {
+    fn from(side: CoinbaseTradeSide) -> Self {
+        match side {
+            CoinbaseTradeSide::Buy => Side::Buy,
+            CoinbaseTradeSide::Sell => Side::Sell,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CoinbaseLevel2Event {
+    #[serde(rename = "type")]
+    kind: CoinbaseEventType,
+    product_id: String,
+    updates: Vec<CoinbaseLevel2Update>,
+}
+
+#[derive(Deserialize)]
+struct CoinbaseLevel2Update {
+    side: CoinbaseBookSide,
+    price_level: Decimal,
+    new_quantity: Decimal,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CoinbaseBookSide {
+    Bid,
+    Offer,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::mock_server::MockWebSocketServer;
+    use crate::data::MarketDataStream;
+    use rust_decimal_macros::dec;
+
+    const SESSION: &str = include_str!("../../../tests/fixtures/coinbase/session.jsonl");
+
+    #[test]
+    fn test_parse_recorded_session() {
+        let mut parser = CoinbaseParser::new(2);
+        let events: Vec<MarketEvent> = SESSION
+            .lines()
+            .flat_map(|line| parser.parse_message(line, Utc::now()).unwrap())
+            .collect();
+
+        assert_eq!(events.len(), 5);
+        assert!(events
+            .iter()
+            .all(|event| event.exchange == ExchangeId::Coinbase));
+        assert_eq!(events[0].instrument.base, "BTC");
+        assert_eq!(events[0].instrument.quote, "USD");
+
+        let MarketDataKind::Trade(trade) = &events[1].kind else {
+            panic!("expected trade, got {:?}", events[1].kind);
+        };
+        assert_eq!(trade.id, "628735102");
+        assert_eq!(trade.price, dec!(67890.55));
+        assert_eq!(trade.side, Side::Buy);
+
+        let MarketDataKind::OrderBookL2(book) = &events[3].kind else {
+            panic!("expected book, got {:?}", events[3].kind);
+        };
+        assert_eq!(book.bids.len(), 2);
+        assert_eq!(book.bids[0].price, dec!(67890.30));
+        assert_eq!(book.bids[1].price, dec!(67889.50));
+        assert_eq!(book.asks[0].quantity, dec!(0.15));
+    }
+
+    #[test]
+    fn test_sequence_gap_invalidates_books() {
+        let mut parser = CoinbaseParser::new(10);
+        let lines: Vec<&str> = SESSION.lines().collect();
+        parser.parse_message(lines[0], Utc::now()).unwrap();
+
+        let gap = parser.parse_message(lines[3], Utc::now());
+        assert!(matches!(
+            gap,
+            Err(CoinbaseError::SequenceGap {
+                expected: 1,
+                received: 3
+            })
+        ));
+        // Updates are dropped until a fresh snapshot arrives
+        let update = lines[3].replace("\"sequence_num\":3", "\"sequence_num\":4");
+        assert!(parser
+            .parse_message(&update, Utc::now())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_sequence_gap_resubscribes_level2() {
+        let mut connector = CoinbaseConnector::new();
+        let lines: Vec<&str> = SESSION.lines().collect();
+        for line in &lines[..3] {
+            connector.decode(line, Utc::now()).unwrap();
+        }
+
+        let error = connector.decode(lines[4], Utc::now()).unwrap_err();
+        let messages = connector.recover(&error);
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("\"unsubscribe\""));
+        assert!(messages[1].contains("\"subscribe\""));
+        assert!(messages
+            .iter()
+            .all(|message| message.contains("\"level2\"") && message.contains("BTC-USD")));
+    }
+
+    #[tokio::test]
+    async fn test_stream_against_mock_server() {
+        let server = MockWebSocketServer::from_fixture(SESSION).await.unwrap();
+        let mut stream = CoinbaseMarketDataStream::from_connector(
+            CoinbaseConnector::new().with_url(server.url()),
+        );
+        let instrument = instrument_from_product_id("BTC-USD");
+        stream
+            .subscribe(std::slice::from_ref(&instrument))
+            .await
+            .unwrap();
+
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            events.push(event);
+        }
+
+        assert_eq!(events.len(), 5);
+        assert!(events.iter().all(|event| event.instrument == instrument));
+        let received = server.received_messages();
+        assert_eq!(received.len(), 3);
+        assert!(received[1].contains("\"market_trades\""));
+        assert!(received[2].contains("\"level2\""));
+    }
+
+    #[test]
+    fn test_from_config_maps_channels_per_instrument() {
+        let btc = instrument_from_product_id("BTC-USD");
+        let eth = instrument_from_product_id("ETH-USD");
+        let mut config = crate::config::SystemConfig::default().data;
+        config.subscriptions = vec![SubscriptionKind::Trades];
+        config.instrument_subscriptions = vec![crate::data::InstrumentSubscription {
+            instrument: eth.clone(),
+            kinds: vec![SubscriptionKind::Trades, SubscriptionKind::L2 { depth: 50 }],
+        }];
+        let connector = CoinbaseConnector::from_config(&config).unwrap();
+        assert_eq!(
+            connector.channels_for(&btc),
+            [CoinbaseChannel::MarketTrades]
+        );
+        assert_eq!(
+            connector.channels_for(&eth),
+            [CoinbaseChannel::MarketTrades, CoinbaseChannel::Level2]
+        );
+        assert_eq!(connector.parser.depth, 50);
+
+        let messages = connector.subscribe_messages(&[btc, eth]).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].contains("\"heartbeats\""));
+        assert!(messages[1].contains("\"market_trades\"") && messages[1].contains("BTC-USD"));
+        assert!(messages[2].contains("\"level2\"") && !messages[2].contains("BTC-USD"));
+
+        config.subscriptions = vec![SubscriptionKind::BookDeltas];
+        assert!(matches!(
+            CoinbaseConnector::from_config(&config),
+            Err(CoinbaseError::UnsupportedSubscription(
+                SubscriptionKind::BookDeltas
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_share_one_connection() {
+        let server = MockWebSocketServer::start_held_open(Vec::new())
+            .await
+            .unwrap();
+        let mut stream = CoinbaseMarketDataStream::from_connector(
+            CoinbaseConnector::new().with_url(server.url()),
+        );
+        let btc = instrument_from_product_id("BTC-USD");
+        let eth = instrument_from_product_id("ETH-USD");
+        stream.subscribe(std::slice::from_ref(&btc)).await.unwrap();
+        stream.subscribe(std::slice::from_ref(&eth)).await.unwrap();
+        stream
+            .unsubscribe(std::slice::from_ref(&btc))
+            .await
+            .unwrap();
+
+        // Both subscriptions and the unsubscription go out over the first connection
+        let received = server.wait_for_messages(8).await;
+        assert_eq!(server.connections(), 1);
+        assert_eq!(stream.instruments(), [eth]);
+        assert!(received[3..6]
+            .iter()
+            .all(|message| message.contains("ETH-USD")));
+        assert_eq!(received.len(), 8);
+        assert!(received[6..]
+            .iter()
+            .all(|message| message.contains("\"unsubscribe\"") && message.contains("BTC-USD")));
+    }
+}
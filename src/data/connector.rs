@@ -0,0 +1,670 @@
+//! Exchange connectors over a shared WebSocket transport
+//!
+//! An `ExchangeConnector` holds everything that differs between venues: the endpoint,
+//! subscription messages, frame decoding, keepalive rules and outgoing rate limits. It
+//! performs no I/O, so a connector can be exercised by feeding it recorded frames with
+//! `decode_frames`.
+//!
+//! `WebSocketMarketDataStream` owns the connection. It connects on the first
+//! `subscribe`, sends subscription messages through the connection task so they
+//! respect the connector's rate limit, answers WebSocket pings, sends application
+//! pings and ends the stream when the venue goes quiet for longer than the idle
+//! timeout.
+//!
+//! Frames that fail to decode are counted and a sample of them is logged, at most
+//! one every `PARSE_FAILURE_LOG_INTERVAL`. With `with_frame_capture` every text and
+//! binary frame is also written to a raw frame capture before decoding, so a failure
+//! can be reproduced offline with `frames::replay_frames`. The capture is written on
+//! its own thread so disk writes never stall the connection task.
+
+use super::frames::{FrameCaptureWriter, FrameKind};
+use super::{ExchangeId, InstrumentId, MarketDataStream, MarketEvent};
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::warn;
+
+/// Minimum gap between two logged decode failures of a connection
+pub const PARSE_FAILURE_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Longest frame excerpt included in a decode failure log
+const LOGGED_FRAME_LEN: usize = 512;
+
+/// Keepalive rules of a venue
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Heartbeat {
+    /// Application-level ping message and how often to send it
+    pub ping: Option<(Duration, String)>,
+    /// Close the connection when no frame, including pings, arrives for this long
+    pub idle_timeout: Option<Duration>,
+}
+
+/// Outgoing message limit of a venue
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Messages allowed per `per`
+    pub messages: u32,
+    /// Window the limit applies to
+    pub per: Duration,
+}
+
+impl RateLimit {
+    /// Minimum gap between two messages to stay within the limit
+    pub fn interval(&self) -> Duration {
+        self.per / self.messages.max(1)
+    }
+}
+
+/// Venue specific half of a WebSocket market data connection
+pub trait ExchangeConnector: Clone + Send + 'static {
+    /// Error produced while building messages or decoding frames
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Venue the connector normalizes
+    fn exchange(&self) -> ExchangeId;
+
+    /// WebSocket endpoint to connect to
+    fn url(&self) -> &str;
+
+    /// Messages sent once on every new connection, ahead of its subscriptions
+    fn connect_messages(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Messages subscribing to market data for `instruments`
+    fn subscribe_messages(&self, instruments: &[InstrumentId]) -> Result<Vec<String>, Self::Error>;
+
+    /// Messages unsubscribing from `instruments`; venues without one send nothing
+    fn unsubscribe_messages(
+        &self,
+        _instruments: &[InstrumentId],
+    ) -> Result<Vec<String>, Self::Error> {
+        Ok(Vec::new())
+    }
+
+    /// Decode a text frame into zero or more market events
+    fn decode(
+        &mut self,
+        frame: &str,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Vec<MarketEvent>, Self::Error>;
+
+    /// Messages to send after a decode error, e.g. to resubscribe a corrupted book
+    fn recover(&mut self, _error: &Self::Error) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Keepalive rules
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat::default()
+    }
+
+    /// Outgoing message limit, if the venue enforces one
+    fn rate_limit(&self) -> Option<RateLimit> {
+        None
+    }
+}
+
+/// Decode newline separated frames, e.g. a fixture file, skipping blank lines
+pub fn decode_frames<C: ExchangeConnector>(
+    connector: &mut C,
+    frames: &str,
+    receipt_time: DateTime<Utc>,
+) -> Result<Vec<MarketEvent>, C::Error> {
+    let mut events = Vec::new();
+    for frame in frames.lines().filter(|frame| !frame.trim().is_empty()) {
+        events.extend(connector.decode(frame, receipt_time)?);
+    }
+    Ok(events)
+}
+
+/// Market data stream driving an `ExchangeConnector` over one WebSocket connection
+pub struct WebSocketMarketDataStream<C> {
+    connector: C,
+    outgoing: Option<mpsc::UnboundedSender<String>>,
+    receiver: Option<mpsc::Receiver<MarketEvent>>,
+    instruments: Vec<InstrumentId>,
+    frame_capture: Option<PathBuf>,
+    parse_failures: Arc<AtomicU64>,
+}
+
+impl<C: ExchangeConnector> WebSocketMarketDataStream<C> {
+    /// Create a stream that connects with `connector` on the first subscription
+    pub fn from_connector(connector: C) -> Self {
+        Self {
+            connector,
+            outgoing: None,
+            receiver: None,
+            instruments: Vec::new(),
+            frame_capture: None,
+            parse_failures: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Append every received frame to a raw frame capture at `path`.
+    ///
+    /// The capture is flushed after every decode failure and when the connection ends.
+    pub fn with_frame_capture(mut self, path: impl Into<PathBuf>) -> Self {
+        self.frame_capture = Some(path.into());
+        self
+    }
+
+    /// Connector used for new connections
+    pub fn connector(&self) -> &C {
+        &self.connector
+    }
+
+    /// Mutable connector, to adjust configuration before connecting
+    pub fn connector_mut(&mut self) -> &mut C {
+        &mut self.connector
+    }
+
+    /// Instruments currently subscribed
+    pub fn instruments(&self) -> &[InstrumentId] {
+        &self.instruments
+    }
+
+    /// Frames that failed to decode since the stream was created
+    pub fn parse_failures(&self) -> u64 {
+        self.parse_failures.load(Ordering::Relaxed)
+    }
+
+    /// Connect and spawn the connection task, returning its outgoing message queue
+    async fn connect(
+        &mut self,
+    ) -> Result<mpsc::UnboundedSender<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let capture = match self.frame_capture.clone() {
+            Some(path) => {
+                let writer =
+                    tokio::task::spawn_blocking(move || FrameCaptureWriter::open(path)).await??;
+                Some(FrameCapture::start(writer)?)
+            }
+            None => None,
+        };
+        let (ws_stream, _) = tokio_tungstenite::connect_async(self.connector.url()).await?;
+        let (sender, receiver) = mpsc::channel(100);
+        let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+        let failures = ParseFailures {
+            count: self.parse_failures.clone(),
+            next_log: Instant::now(),
+        };
+        tokio::spawn(run_connection(
+            self.connector.clone(),
+            ws_stream,
+            sender,
+            outgoing.clone(),
+            outgoing_rx,
+            capture,
+            failures,
+        ));
+        self.receiver = Some(receiver);
+        self.outgoing = Some(outgoing.clone());
+        Self::send_all(&outgoing, self.connector.connect_messages())?;
+        Ok(outgoing)
+    }
+
+    fn send_all(
+        outgoing: &mpsc::UnboundedSender<String>,
+        messages: Vec<String>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        for message in messages {
+            outgoing
+                .send(message)
+                .map_err(|_| "WebSocket connection closed")?;
+        }
+        Ok(())
+    }
+}
+
+impl<C: ExchangeConnector + Default> Default for WebSocketMarketDataStream<C> {
+    fn default() -> Self {
+        Self::from_connector(C::default())
+    }
+}
+
+#[async_trait::async_trait]
+impl<C: ExchangeConnector + Sync> MarketDataStream for WebSocketMarketDataStream<C> {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        match &mut self.receiver {
+            Some(receiver) => Ok(receiver.recv().await),
+            None => Ok(None),
+        }
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        let (outgoing, messages) = match self
+            .outgoing
+            .as_ref()
+            .filter(|outgoing| !outgoing.is_closed())
+        {
+            Some(outgoing) => (
+                outgoing.clone(),
+                self.connector.subscribe_messages(instruments)?,
+            ),
+            // A new connection starts without subscriptions, so restore the earlier ones
+            None => {
+                let all = [self.instruments.as_slice(), instruments].concat();
+                let messages = self.connector.subscribe_messages(&all)?;
+                (self.connect().await?, messages)
+            }
+        };
+        Self::send_all(&outgoing, messages)?;
+        self.instruments.extend_from_slice(instruments);
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.instruments.retain(|i| !instruments.contains(i));
+        // A closed connection has no subscriptions left to cancel
+        if let Some(outgoing) = self
+            .outgoing
+            .as_ref()
+            .filter(|outgoing| !outgoing.is_closed())
+        {
+            let messages = self.connector.unsubscribe_messages(instruments)?;
+            for message in messages {
+                if outgoing.send(message).is_err() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The connector is only cloned into the connection task, never pinned
+impl<C> Unpin for WebSocketMarketDataStream<C> {}
+
+impl<C: ExchangeConnector + Sync> futures::Stream for WebSocketMarketDataStream<C> {
+    type Item = Result<MarketEvent, <Self as MarketDataStream>::Error>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        super::stream::poll_receiver(&mut self.receiver, cx)
+    }
+}
+
+/// Decode failure counter of a connection, logging a sample of the failures
+struct ParseFailures {
+    count: Arc<AtomicU64>,
+    next_log: Instant,
+}
+
+impl ParseFailures {
+    fn record(&mut self, exchange: ExchangeId, error: &dyn std::error::Error, frame: &str) {
+        let failures = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = Instant::now();
+        if now < self.next_log {
+            return;
+        }
+        self.next_log = now + PARSE_FAILURE_LOG_INTERVAL;
+        let end = (0..=frame.len().min(LOGGED_FRAME_LEN))
+            .rev()
+            .find(|end| frame.is_char_boundary(*end))
+            .unwrap_or(0);
+        warn!(
+            ?exchange,
+            failures,
+            %error,
+            frame = &frame[..end],
+            "failed to decode market data frame"
+        );
+    }
+}
+
+/// Work queued for the capture writer thread
+enum CaptureCommand {
+    Frame(DateTime<Utc>, FrameKind, Vec<u8>),
+    Flush,
+}
+
+/// Frame capture of a connection, written by a dedicated thread
+struct FrameCapture {
+    commands: std::sync::mpsc::Sender<CaptureCommand>,
+    finished: tokio::sync::oneshot::Receiver<()>,
+}
+
+impl FrameCapture {
+    /// Spawn the writer thread, which abandons the capture if it cannot be written
+    fn start(mut writer: FrameCaptureWriter) -> io::Result<Self> {
+        let (commands, receiver) = std::sync::mpsc::channel();
+        let (done, finished) = tokio::sync::oneshot::channel();
+        std::thread::Builder::new()
+            .name("frame-capture".to_string())
+            .spawn(move || {
+                for command in receiver {
+                    let result = match command {
+                        CaptureCommand::Frame(receipt_time, kind, payload) => {
+                            writer.write(receipt_time, kind, &payload)
+                        }
+                        CaptureCommand::Flush => writer.flush(),
+                    };
+                    if let Err(error) = result {
+                        warn!(path = %writer.path().display(), %error, "stopping frame capture");
+                        return;
+                    }
+                }
+                let _ = writer.flush();
+                let _ = done.send(());
+            })?;
+        Ok(Self { commands, finished })
+    }
+
+    fn write(&self, receipt_time: DateTime<Utc>, kind: FrameKind, payload: &[u8]) {
+        let _ = self
+            .commands
+            .send(CaptureCommand::Frame(receipt_time, kind, payload.to_vec()));
+    }
+
+    fn flush(&self) {
+        let _ = self.commands.send(CaptureCommand::Flush);
+    }
+
+    /// Wait until every queued frame is on disk
+    async fn finish(self) {
+        drop(self.commands);
+        let _ = self.finished.await;
+    }
+}
+
+/// Run a connection, then wait for its capture so it is complete once the stream ends
+async fn run_connection<C, S>(
+    connector: C,
+    ws_stream: tokio_tungstenite::WebSocketStream<S>,
+    sender: mpsc::Sender<MarketEvent>,
+    outgoing: mpsc::UnboundedSender<String>,
+    outgoing_rx: mpsc::UnboundedReceiver<String>,
+    capture: Option<FrameCapture>,
+    failures: ParseFailures,
+) where
+    C: ExchangeConnector,
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    drive_connection(
+        connector,
+        ws_stream,
+        &sender,
+        outgoing,
+        outgoing_rx,
+        capture.as_ref(),
+        failures,
+    )
+    .await;
+    if let Some(capture) = capture {
+        capture.finish().await;
+    }
+}
+
+/// Read frames into events and write queued messages until either side closes
+async fn drive_connection<C, S>(
+    mut connector: C,
+    ws_stream: tokio_tungstenite::WebSocketStream<S>,
+    sender: &mpsc::Sender<MarketEvent>,
+    outgoing: mpsc::UnboundedSender<String>,
+    mut outgoing_rx: mpsc::UnboundedReceiver<String>,
+    capture: Option<&FrameCapture>,
+    mut failures: ParseFailures,
+) where
+    C: ExchangeConnector,
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    use futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::protocol::Message;
+
+    let (mut write, mut read) = ws_stream.split();
+    let heartbeat = connector.heartbeat();
+    let send_interval = connector.rate_limit().map(|limit| limit.interval());
+    // Messages wait here for the rate limit so frames and pings keep flowing meanwhile
+    let mut queued = VecDeque::new();
+    let mut next_send = Instant::now();
+    let mut last_frame = Instant::now();
+    let (ping_interval, ping_message) = match heartbeat.ping {
+        Some((interval, message)) => (Some(interval), message),
+        None => (None, String::new()),
+    };
+    let mut next_ping = ping_interval.map(|interval| Instant::now() + interval);
+    // Deadlines of disabled timers are never reached
+    let never = Instant::now() + Duration::from_secs(365 * 24 * 60 * 60);
+
+    loop {
+        let idle_deadline = heartbeat
+            .idle_timeout
+            .map_or(never, |timeout| last_frame + timeout);
+        tokio::select! {
+            msg = read.next() => {
+                last_frame = Instant::now();
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        let receipt_time = Utc::now();
+                        if let Some(capture) = capture {
+                            capture.write(receipt_time, FrameKind::Text, text.as_bytes());
+                        }
+                        match connector.decode(&text, receipt_time) {
+                            Ok(events) => {
+                                for event in events {
+                                    if sender.send(event).await.is_err() {
+                                        return;
+                                    }
+                                }
+                            }
+                            Err(error) => {
+                                failures.record(connector.exchange(), &error, &text);
+                                // Keep the failing frame on disk even if the process dies
+                                if let Some(capture) = capture {
+                                    capture.flush();
+                                }
+                                for message in connector.recover(&error) {
+                                    let _ = outgoing.send(message);
+                                }
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Binary(data))) => {
+                        if let Some(capture) = capture {
+                            capture.write(Utc::now(), FrameKind::Binary, &data);
+                        }
+                    }
+                    Some(Ok(Message::Ping(data))) => {
+                        if write.send(Message::Pong(data)).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    Some(Ok(_)) => {}
+                }
+            }
+            Some(message) = outgoing_rx.recv() => queued.push_back(message),
+            _ = tokio::time::sleep_until(next_send), if !queued.is_empty() => {
+                if let Some(interval) = send_interval {
+                    next_send = Instant::now() + interval;
+                }
+                let Some(message) = queued.pop_front() else {
+                    continue;
+                };
+                if write.send(Message::Text(message.into())).await.is_err() {
+                    return;
+                }
+            }
+            _ = tokio::time::sleep_until(next_ping.unwrap_or(never)) => {
+                next_ping = ping_interval.map(|interval| Instant::now() + interval);
+                if write.send(Message::Text(ping_message.clone().into())).await.is_err() {
+                    return;
+                }
+            }
+            _ = tokio::time::sleep_until(idle_deadline) => {
+                warn!(exchange = ?connector.exchange(), "closing idle market data connection");
+                let _ = write.send(Message::Close(None)).await;
+                return;
+            }
+            _ = sender.closed() => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::binance::BinanceConnector;
+    use crate::data::frames::{replay_frames, FrameCaptureReader};
+    use crate::data::mock_server::MockWebSocketServer;
+    use crate::data::{spot_instrument, MarketDataKind};
+
+    const FRAMES: &str = include_str!("../../tests/fixtures/binance/frames.jsonl");
+
+    #[tokio::test]
+    async fn test_transport_drives_connector() {
+        let mut frames: Vec<String> = FRAMES.lines().map(str::to_string).collect();
+        frames.insert(
+            3,
+            r#"{"stream":"btcusdt@trade","data":{"p":"bad"}}"#.to_string(),
+        );
+        let server = MockWebSocketServer::start(frames).await.unwrap();
+        let capture = std::env::temp_dir().join(format!("transport-{}.raw", std::process::id()));
+        let _ = std::fs::remove_file(&capture);
+        let connector = BinanceConnector::new().with_url(server.url());
+        let mut stream =
+            WebSocketMarketDataStream::from_connector(connector).with_frame_capture(&capture);
+
+        stream
+            .subscribe(&[
+                spot_instrument("BTC", "USDT"),
+                spot_instrument("ETH", "BTC"),
+            ])
+            .await
+            .unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            events.push(event);
+        }
+
+        assert_eq!(events.len(), 9);
+        assert!(matches!(events[0].kind, MarketDataKind::OrderBookL1(_)));
+        assert_eq!(stream.instruments().len(), 2);
+        assert_eq!(stream.parse_failures(), 1);
+
+        // The capture reproduces the live events and the failure exactly
+        let replay = replay_frames(
+            &mut BinanceConnector::new(),
+            FrameCaptureReader::open(&capture).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(replay.frames, 12);
+        assert_eq!(replay.events, events);
+        assert_eq!(replay.failures[0].index, 3);
+        std::fs::remove_file(&capture).unwrap();
+        // The server replays and closes after the first request; the second is held
+        // back by Binance's message rate limit
+        let received = server.received_messages();
+        assert_eq!(received.len(), 1);
+        assert!(received[0].contains("btcusdt@trade"));
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_restores_subscriptions() {
+        let server = MockWebSocketServer::from_fixture(FRAMES).await.unwrap();
+        let connector = BinanceConnector::new().with_url(server.url());
+        let mut stream = WebSocketMarketDataStream::from_connector(connector);
+        stream
+            .subscribe(&[spot_instrument("BTC", "USDT")])
+            .await
+            .unwrap();
+        while stream.next().await.unwrap().is_some() {}
+
+        // The server has closed the connection; the next subscription reconnects and
+        // subscribes to BTC again before SOL
+        stream
+            .subscribe(&[spot_instrument("SOL", "USDT")])
+            .await
+            .unwrap();
+        while stream.next().await.unwrap().is_some() {}
+        let received = server.received_messages();
+        assert!(received.len() >= 2);
+        assert!(received[0].contains("btcusdt@trade"));
+        assert!(received[1].contains("btcusdt@trade"));
+
+        // Unsubscribing on a closed connection only updates local state
+        stream
+            .unsubscribe(&[spot_instrument("BTC", "USDT")])
+            .await
+            .unwrap();
+        assert_eq!(stream.instruments(), [spot_instrument("SOL", "USDT")]);
+    }
+
+    /// Binance connector throttled to one message a minute
+    #[derive(Debug, Clone)]
+    struct ThrottledConnector(BinanceConnector);
+
+    impl ExchangeConnector for ThrottledConnector {
+        type Error = <BinanceConnector as ExchangeConnector>::Error;
+
+        fn exchange(&self) -> ExchangeId {
+            self.0.exchange()
+        }
+
+        fn url(&self) -> &str {
+            self.0.url()
+        }
+
+        fn subscribe_messages(
+            &self,
+            instruments: &[InstrumentId],
+        ) -> Result<Vec<String>, Self::Error> {
+            self.0.subscribe_messages(instruments)
+        }
+
+        fn decode(
+            &mut self,
+            frame: &str,
+            receipt_time: DateTime<Utc>,
+        ) -> Result<Vec<MarketEvent>, Self::Error> {
+            self.0.decode(frame, receipt_time)
+        }
+
+        fn rate_limit(&self) -> Option<RateLimit> {
+            Some(RateLimit {
+                messages: 1,
+                per: Duration::from_secs(60),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_frames_arrive_during_throttled_subscribe_burst() {
+        let server =
+            MockWebSocketServer::start_held_open(FRAMES.lines().map(str::to_string).collect())
+                .await
+                .unwrap();
+        let connector = ThrottledConnector(BinanceConnector::new().with_url(server.url()));
+        let mut stream = WebSocketMarketDataStream::from_connector(connector);
+        for instrument in [
+            spot_instrument("BTC", "USDT"),
+            spot_instrument("ETH", "BTC"),
+            spot_instrument("SOL", "USDT"),
+        ] {
+            stream.subscribe(&[instrument]).await.unwrap();
+        }
+
+        // Only the first subscription is due; the others wait in the queue while the
+        // server's frames are still read
+        let mut events = 0;
+        while events < 9 {
+            tokio::time::timeout(Duration::from_secs(5), stream.next())
+                .await
+                .expect("frames blocked behind the rate limit")
+                .unwrap()
+                .unwrap();
+            events += 1;
+        }
+        assert_eq!(server.received_messages().len(), 1);
+    }
+}
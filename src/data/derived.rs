@@ -0,0 +1,681 @@
+//! Derived instruments
+//!
+//! A `DerivedInstrument` is priced from the `OrderBookL1` quotes of other instruments,
+//! its legs. Two shapes are supported:
+//!
+//! - linear combinations `sum(weight * price)`, covering weighted baskets as well as
+//!   spreads such as a perpetual-spot basis (weights `1` and `-1`)
+//! - ratios `numerator / denominator`, such as BTCUSDT/ETHUSDT
+//!
+//! The derived bid is what selling one unit across the legs' touches would fetch and
+//! the derived ask what buying one would cost, so quotes are tradeable rather than
+//! mid-based. Quotes of subscribed derived instruments are emitted as ordinary
+//! `MarketEvent`s on `ExchangeId::Synthetic` with the derived instrument's own
+//! `InstrumentId`, and orders on a derived instrument are split into leg orders by
+//! `DerivedInstrument::leg_order`.
+
+use super::{
+    ExchangeId, InstrumentId, MarketDataKind, MarketDataStream, MarketEvent, OrderBookL1, Side,
+};
+use crate::execution::{OrderRequest, OrderType};
+use rust_decimal::Decimal;
+use std::collections::{HashMap, VecDeque};
+
+/// Errors produced when legging an order
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum DerivedError {
+    /// Leg without a usable quote yet
+    #[error("no quote for leg {0}")]
+    MissingQuote(String),
+    /// Order type that cannot be split across legs
+    #[error("unsupported order type {0:?}")]
+    UnsupportedOrderType(OrderType),
+}
+
+/// Component of a derived instrument
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leg {
+    /// Venue the leg trades on
+    pub exchange: ExchangeId,
+    /// Leg instrument
+    pub instrument: InstrumentId,
+    /// Units of the leg per unit of the derived instrument; negative for short legs.
+    /// Unused by ratios.
+    pub weight: Decimal,
+}
+
+impl Leg {
+    /// Leg with a weight of one
+    pub fn new(exchange: ExchangeId, instrument: InstrumentId) -> Self {
+        Self {
+            exchange,
+            instrument,
+            weight: Decimal::ONE,
+        }
+    }
+
+    /// Set the weight
+    pub fn with_weight(mut self, weight: Decimal) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    fn matches(&self, event: &MarketEvent) -> bool {
+        event.exchange == self.exchange && event.instrument == self.instrument
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum DerivedKind {
+    Linear,
+    Ratio,
+}
+
+/// Leg order parameters implied by the current quotes
+struct LegFill {
+    side: Side,
+    quantity: Decimal,
+    price: Decimal,
+}
+
+/// Instrument priced from the quotes of its legs
+#[derive(Debug, Clone, PartialEq)]
+pub struct DerivedInstrument {
+    instrument: InstrumentId,
+    kind: DerivedKind,
+    legs: Vec<Leg>,
+    quotes: Vec<Option<OrderBookL1>>,
+}
+
+impl DerivedInstrument {
+    /// Weighted basket `sum(weight * price)`
+    pub fn basket(instrument: InstrumentId, legs: Vec<Leg>) -> Self {
+        Self {
+            instrument,
+            kind: DerivedKind::Linear,
+            quotes: vec![None; legs.len()],
+            legs,
+        }
+    }
+
+    /// Spread `long - short`, e.g. a perpetual-spot basis
+    pub fn spread(instrument: InstrumentId, long: Leg, short: Leg) -> Self {
+        let short_weight = -short.weight;
+        Self::basket(instrument, vec![long, short.with_weight(short_weight)])
+    }
+
+    /// Ratio `numerator / denominator`; one unit is one unit of the numerator hedged
+    /// with the same notional of the denominator
+    pub fn ratio(instrument: InstrumentId, numerator: Leg, denominator: Leg) -> Self {
+        Self {
+            instrument,
+            kind: DerivedKind::Ratio,
+            quotes: vec![None; 2],
+            legs: vec![numerator, denominator],
+        }
+    }
+
+    /// Identifier of the derived instrument
+    pub fn instrument(&self) -> &InstrumentId {
+        &self.instrument
+    }
+
+    /// Component legs
+    pub fn legs(&self) -> &[Leg] {
+        &self.legs
+    }
+
+    /// Feed an event, returning a new derived quote when it updated a leg
+    pub fn update(&mut self, event: &MarketEvent) -> Option<MarketEvent> {
+        let MarketDataKind::OrderBookL1(quote) = &event.kind else {
+            return None;
+        };
+        let index = self.legs.iter().position(|leg| leg.matches(event))?;
+        self.quotes[index] = Some(quote.clone());
+        let quote = self.quote()?;
+        Some(MarketEvent {
+            exchange: ExchangeId::Synthetic,
+            instrument: self.instrument.clone(),
+            kind: MarketDataKind::OrderBookL1(quote),
+            exchange_time: event.exchange_time,
+            receipt_time: event.receipt_time,
+        })
+    }
+
+    /// Derived quote from the latest leg quotes, once every leg has one
+    pub fn quote(&self) -> Option<OrderBookL1> {
+        let (bid_price, bid_quantity) = self.touch(Side::Sell)?;
+        let (ask_price, ask_quantity) = self.touch(Side::Buy)?;
+        Some(OrderBookL1 {
+            bid_price,
+            bid_quantity,
+            ask_price,
+            ask_quantity,
+            timestamp: self
+                .quotes
+                .iter()
+                .flatten()
+                .map(|quote| quote.timestamp)
+                .max()?,
+            update_id: None,
+        })
+    }
+
+    /// Price and size for trading one unit of the derived instrument on `side`
+    fn touch(&self, side: Side) -> Option<(Decimal, Decimal)> {
+        let fills = self.fills(side, Decimal::ONE).ok()?;
+        // Units of the derived instrument the thinnest leg can support
+        let mut size: Option<Decimal> = None;
+        for (fill, quote) in fills.iter().zip(self.quotes.iter().flatten()) {
+            if fill.quantity.is_zero() {
+                continue;
+            }
+            let available = match fill.side {
+                Side::Buy => quote.ask_quantity,
+                Side::Sell => quote.bid_quantity,
+            };
+            let units = available / fill.quantity;
+            size = Some(size.map_or(units, |size| size.min(units)));
+        }
+        let price = match self.kind {
+            DerivedKind::Linear => self
+                .legs
+                .iter()
+                .zip(&fills)
+                .map(|(leg, fill)| leg.weight * fill.price)
+                .sum(),
+            DerivedKind::Ratio => fills[0].price.checked_div(fills[1].price)?,
+        };
+        Some((price, size.unwrap_or_default()))
+    }
+
+    /// Leg sides, quantities and touch prices for trading `quantity` units on `side`
+    fn fills(&self, side: Side, quantity: Decimal) -> Result<Vec<LegFill>, DerivedError> {
+        let opposite = |side: Side| match side {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        };
+        let touch = |index: usize, side: Side| -> Result<Decimal, DerivedError> {
+            let quote = self.quotes[index].as_ref().ok_or_else(|| {
+                DerivedError::MissingQuote(self.legs[index].instrument.exchange_symbol.clone())
+            })?;
+            let price = match side {
+                Side::Buy => quote.ask_price,
+                Side::Sell => quote.bid_price,
+            };
+            if price <= Decimal::ZERO {
+                return Err(DerivedError::MissingQuote(
+                    self.legs[index].instrument.exchange_symbol.clone(),
+                ));
+            }
+            Ok(price)
+        };
+
+        match self.kind {
+            DerivedKind::Linear => self
+                .legs
+                .iter()
+                .enumerate()
+                .map(|(index, leg)| {
+                    let leg_side = if leg.weight >= Decimal::ZERO {
+                        side
+                    } else {
+                        opposite(side)
+                    };
+                    Ok(LegFill {
+                        side: leg_side,
+                        quantity: quantity * leg.weight.abs(),
+                        price: touch(index, leg_side)?,
+                    })
+                })
+                .collect(),
+            DerivedKind::Ratio => {
+                let numerator = touch(0, side)?;
+                let denominator = touch(1, opposite(side))?;
+                Ok(vec![
+                    LegFill {
+                        side,
+                        quantity,
+                        price: numerator,
+                    },
+                    LegFill {
+                        side: opposite(side),
+                        quantity: quantity * numerator / denominator,
+                        price: denominator,
+                    },
+                ])
+            }
+        }
+    }
+
+    /// Split an order on the derived instrument into orders on its legs.
+    ///
+    /// Legs are priced at their current touch. For limit orders the first weighted leg
+    /// absorbs the difference between the order's limit and the derived touch, so the
+    /// leg prices combine to exactly the limit price; a passive order therefore rests
+    /// on that leg while the others hedge at the touch. Legs of zero weight get no
+    /// order.
+    pub fn leg_order(&self, order: &OrderRequest) -> Result<Vec<OrderRequest>, DerivedError> {
+        let mut fills = self.fills(order.side, order.quantity)?;
+        let limit = match order.order_type {
+            OrderType::Market => None,
+            OrderType::Limit => order.price,
+            other => return Err(DerivedError::UnsupportedOrderType(other)),
+        };
+
+        if let Some(limit) = limit {
+            match self.kind {
+                DerivedKind::Linear => {
+                    let touch: Decimal = self
+                        .legs
+                        .iter()
+                        .zip(&fills)
+                        .map(|(leg, fill)| leg.weight * fill.price)
+                        .sum();
+                    if let Some(index) = self.legs.iter().position(|leg| !leg.weight.is_zero()) {
+                        fills[index].price += (limit - touch) / self.legs[index].weight;
+                    }
+                }
+                DerivedKind::Ratio => {
+                    fills[0].price = limit * fills[1].price;
+                    fills[1].quantity = order.quantity * limit;
+                }
+            }
+        }
+
+        Ok(self
+            .legs
+            .iter()
+            .zip(fills)
+            .enumerate()
+            .filter(|(_, (_, fill))| !fill.quantity.is_zero())
+            .map(|(index, (leg, fill))| OrderRequest {
+                client_order_id: format!("{}-{}", order.client_order_id, index),
+                instrument: leg.instrument.clone(),
+                side: fill.side,
+                order_type: order.order_type,
+                quantity: fill.quantity,
+                price: limit.map(|_| fill.price),
+                stop_price: None,
+                time_in_force: order.time_in_force,
+                created_at: order.created_at,
+            })
+            .collect())
+    }
+}
+
+/// Forwards an inner stream and adds quotes for derived instruments after leg updates
+pub struct DerivedInstrumentStream<S> {
+    inner: S,
+    instruments: Vec<DerivedInstrument>,
+    pending: VecDeque<MarketEvent>,
+    /// Subscriptions of the inner stream, counting every derived or direct request
+    /// that needs each instrument
+    subscriptions: HashMap<InstrumentId, usize>,
+    /// Derived instruments with quotes requested, counting requests
+    derived_subscriptions: HashMap<InstrumentId, usize>,
+}
+
+impl<S> DerivedInstrumentStream<S> {
+    /// Wrap `inner` without any derived instruments
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            instruments: Vec::new(),
+            pending: VecDeque::new(),
+            subscriptions: HashMap::new(),
+            derived_subscriptions: HashMap::new(),
+        }
+    }
+
+    /// Add a derived instrument
+    pub fn with_instrument(mut self, instrument: DerivedInstrument) -> Self {
+        self.instruments.push(instrument);
+        self
+    }
+
+    /// Derived instrument by identifier, e.g. to leg an order against current quotes
+    pub fn instrument(&self, instrument: &InstrumentId) -> Option<&DerivedInstrument> {
+        self.instruments
+            .iter()
+            .find(|derived| derived.instrument() == instrument)
+    }
+
+    /// Leg instruments of `instrument` if it is derived
+    fn legs_of(&self, instrument: &InstrumentId) -> Option<Vec<InstrumentId>> {
+        self.instrument(instrument).map(|derived| {
+            derived
+                .legs()
+                .iter()
+                .map(|leg| leg.instrument.clone())
+                .collect()
+        })
+    }
+
+    /// Count new subscriptions, returning the instruments the inner stream lacks
+    fn acquire(&mut self, instruments: &[InstrumentId]) -> Vec<InstrumentId> {
+        let mut needed = Vec::new();
+        for instrument in instruments {
+            match self.legs_of(instrument) {
+                Some(legs) => {
+                    needed.extend(legs);
+                    *self
+                        .derived_subscriptions
+                        .entry(instrument.clone())
+                        .or_default() += 1;
+                }
+                None => needed.push(instrument.clone()),
+            }
+        }
+        let mut added = Vec::new();
+        for instrument in needed {
+            let count = self.subscriptions.entry(instrument.clone()).or_default();
+            *count += 1;
+            if *count == 1 {
+                added.push(instrument);
+            }
+        }
+        added
+    }
+
+    /// Release subscriptions, returning the instruments nothing needs any more; derived
+    /// instruments that were never subscribed release nothing
+    fn release(&mut self, instruments: &[InstrumentId]) -> Vec<InstrumentId> {
+        let mut released = Vec::new();
+        for instrument in instruments {
+            let Some(legs) = self.legs_of(instrument) else {
+                released.push(instrument.clone());
+                continue;
+            };
+            let Some(count) = self.derived_subscriptions.get_mut(instrument) else {
+                continue;
+            };
+            released.extend(legs);
+            *count -= 1;
+            if *count == 0 {
+                self.derived_subscriptions.remove(instrument);
+            }
+        }
+        let mut removed = Vec::new();
+        for instrument in released {
+            let Some(count) = self.subscriptions.get_mut(&instrument) else {
+                continue;
+            };
+            *count -= 1;
+            if *count == 0 {
+                self.subscriptions.remove(&instrument);
+                removed.push(instrument);
+            }
+        }
+        removed
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> MarketDataStream for DerivedInstrumentStream<S>
+where
+    S: MarketDataStream + Send,
+    S::Error: Send,
+{
+    type Error = S::Error;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        if let Some(event) = self.pending.pop_front() {
+            return Ok(Some(event));
+        }
+        let Some(event) = self.inner.next().await? else {
+            return Ok(None);
+        };
+        // Unsubscribed derived instruments still track leg quotes for `leg_order`
+        for derived in &mut self.instruments {
+            if let Some(quote) = derived.update(&event) {
+                if self.derived_subscriptions.contains_key(&quote.instrument) {
+                    self.pending.push_back(quote);
+                }
+            }
+        }
+        Ok(Some(event))
+    }
+
+    /// Subscribing to a derived instrument subscribes to its legs and emits its quotes
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        let instruments = self.acquire(instruments);
+        if instruments.is_empty() {
+            return Ok(());
+        }
+        self.inner.subscribe(&instruments).await
+    }
+
+    /// Legs stay subscribed while another derived instrument or a direct subscription
+    /// still needs them
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        let instruments = self.release(instruments);
+        if instruments.is_empty() {
+            return Ok(());
+        }
+        self.inner.unsubscribe(&instruments).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{spot_instrument, MockMarketDataStream};
+    use crate::execution::TimeInForce;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    fn quote(symbol: &str, bid: Decimal, ask: Decimal, size: Decimal) -> MarketEvent {
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: spot_instrument(symbol, "USDT"),
+            kind: MarketDataKind::OrderBookL1(OrderBookL1 {
+                bid_price: bid,
+                bid_quantity: size,
+                ask_price: ask,
+                ask_quantity: size,
+                timestamp: Utc::now(),
+                update_id: None,
+            }),
+            exchange_time: Utc::now(),
+            receipt_time: Utc::now(),
+        }
+    }
+
+    fn order(side: Side, order_type: OrderType, price: Option<Decimal>) -> OrderRequest {
+        OrderRequest {
+            client_order_id: "basis".to_string(),
+            instrument: spot_instrument("BASIS", "USDT"),
+            side,
+            order_type,
+            quantity: dec!(2),
+            price,
+            stop_price: None,
+            time_in_force: TimeInForce::GTC,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_basis_spread_quote_and_legging() {
+        let mut basis = DerivedInstrument::spread(
+            spot_instrument("BASIS", "USDT"),
+            Leg::new(ExchangeId::Binance, spot_instrument("PERP", "USDT")),
+            Leg::new(ExchangeId::Binance, spot_instrument("SPOT", "USDT")),
+        );
+        assert!(basis
+            .update(&quote("PERP", dec!(101), dec!(102), dec!(3)))
+            .is_none());
+        let event = basis
+            .update(&quote("SPOT", dec!(99), dec!(100), dec!(5)))
+            .unwrap();
+        assert_eq!(event.exchange, ExchangeId::Synthetic);
+        let MarketDataKind::OrderBookL1(quote) = event.kind else {
+            panic!("expected L1");
+        };
+        // Sell perp at 101 and buy spot at 100, or buy perp at 102 and sell spot at 99
+        assert_eq!((quote.bid_price, quote.ask_price), (dec!(1), dec!(3)));
+        assert_eq!(quote.bid_quantity, dec!(3));
+
+        let legs = basis
+            .leg_order(&order(Side::Buy, OrderType::Limit, Some(dec!(2.5))))
+            .unwrap();
+        assert_eq!(legs[0].client_order_id, "basis-0");
+        assert_eq!(
+            (legs[0].side, legs[0].quantity, legs[0].price),
+            (Side::Buy, dec!(2), Some(dec!(101.5)))
+        );
+        assert_eq!(
+            (legs[1].side, legs[1].quantity, legs[1].price),
+            (Side::Sell, dec!(2), Some(dec!(99)))
+        );
+
+        let market = basis
+            .leg_order(&order(Side::Sell, OrderType::Market, None))
+            .unwrap();
+        assert_eq!((market[0].side, market[1].side), (Side::Sell, Side::Buy));
+        assert!(market.iter().all(|leg| leg.price.is_none()));
+    }
+
+    #[test]
+    fn test_zero_weight_legs_get_no_order() {
+        let mut basket = DerivedInstrument::basket(
+            spot_instrument("BASKET", "USDT"),
+            vec![
+                Leg::new(ExchangeId::Binance, spot_instrument("PERP", "USDT")).with_weight(dec!(0)),
+                Leg::new(ExchangeId::Binance, spot_instrument("SPOT", "USDT")),
+            ],
+        );
+        basket.update(&quote("PERP", dec!(101), dec!(102), dec!(3)));
+        basket.update(&quote("SPOT", dec!(99), dec!(100), dec!(5)));
+
+        let legs = basket
+            .leg_order(&order(Side::Buy, OrderType::Limit, Some(dec!(99.5))))
+            .unwrap();
+        assert_eq!(legs.len(), 1);
+        assert_eq!(legs[0].client_order_id, "basis-1");
+        assert_eq!(legs[0].instrument, spot_instrument("SPOT", "USDT"));
+        assert_eq!(legs[0].price, Some(dec!(99.5)));
+    }
+
+    #[test]
+    fn test_shared_legs_stay_subscribed() {
+        let perp = spot_instrument("PERP", "USDT");
+        let spot = spot_instrument("SPOT", "USDT");
+        let basis = DerivedInstrument::spread(
+            spot_instrument("BASIS", "USDT"),
+            Leg::new(ExchangeId::Binance, perp.clone()),
+            Leg::new(ExchangeId::Binance, spot.clone()),
+        );
+        let mut stream = DerivedInstrumentStream::new(MockMarketDataStream::new(Vec::new()))
+            .with_instrument(basis);
+
+        assert_eq!(
+            stream.acquire(&[spot_instrument("BASIS", "USDT"), spot.clone()]),
+            vec![perp.clone(), spot.clone()]
+        );
+        assert_eq!(
+            stream.release(&[spot_instrument("BASIS", "USDT")]),
+            vec![perp]
+        );
+        assert_eq!(
+            stream.release(std::slice::from_ref(&spot)),
+            vec![spot.clone()]
+        );
+        assert!(stream.subscriptions.is_empty());
+
+        // Releasing a derived instrument that was never subscribed keeps its legs
+        stream.acquire(std::slice::from_ref(&spot));
+        assert!(stream
+            .release(&[spot_instrument("BASIS", "USDT")])
+            .is_empty());
+        assert_eq!(stream.subscriptions.get(&spot), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn test_ratio_stream() {
+        let ratio = DerivedInstrument::ratio(
+            spot_instrument("BTCETH", "USDT"),
+            Leg::new(ExchangeId::Binance, spot_instrument("BTC", "USDT")),
+            Leg::new(ExchangeId::Binance, spot_instrument("ETH", "USDT")),
+        );
+        let inner = MockMarketDataStream::new(vec![
+            quote("BTC", dec!(60000), dec!(60010), dec!(1)),
+            quote("ETH", dec!(3000), dec!(3001), dec!(10)),
+            quote("SOL", dec!(150), dec!(151), dec!(10)),
+        ]);
+        let mut stream = DerivedInstrumentStream::new(inner).with_instrument(ratio);
+        stream
+            .subscribe(&[spot_instrument("BTCETH", "USDT")])
+            .await
+            .unwrap();
+        assert_eq!(
+            stream
+                .subscriptions
+                .keys()
+                .collect::<std::collections::HashSet<_>>(),
+            [
+                spot_instrument("BTC", "USDT"),
+                spot_instrument("ETH", "USDT")
+            ]
+            .iter()
+            .collect()
+        );
+
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            events.push(event);
+        }
+        assert_eq!(events.len(), 4);
+        let MarketDataKind::OrderBookL1(quote) = &events[2].kind else {
+            panic!("expected derived quote after the ETH quote");
+        };
+        assert_eq!(events[2].instrument, spot_instrument("BTCETH", "USDT"));
+        assert_eq!(quote.bid_price, dec!(60000) / dec!(3001));
+        assert_eq!(quote.ask_price, dec!(60010) / dec!(3000));
+        // Ten ETH hedge fewer than one BTC at these prices
+        assert_eq!(quote.bid_quantity, dec!(10) / (dec!(60000) / dec!(3001)));
+
+        let ratio = stream
+            .instrument(&spot_instrument("BTCETH", "USDT"))
+            .unwrap();
+        let legs = ratio
+            .leg_order(&order(Side::Buy, OrderType::Limit, Some(dec!(20))))
+            .unwrap();
+        assert_eq!(legs[0].price, Some(dec!(60000)));
+        assert_eq!((legs[1].side, legs[1].quantity), (Side::Sell, dec!(40)));
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribed_derived_instruments_emit_no_quotes() {
+        let ratio = DerivedInstrument::ratio(
+            spot_instrument("BTCETH", "USDT"),
+            Leg::new(ExchangeId::Binance, spot_instrument("BTC", "USDT")),
+            Leg::new(ExchangeId::Binance, spot_instrument("ETH", "USDT")),
+        );
+        let inner = MockMarketDataStream::new(vec![
+            quote("BTC", dec!(60000), dec!(60010), dec!(1)),
+            quote("ETH", dec!(3000), dec!(3001), dec!(10)),
+        ]);
+        let mut stream = DerivedInstrumentStream::new(inner).with_instrument(ratio);
+        stream
+            .subscribe(&[
+                spot_instrument("BTC", "USDT"),
+                spot_instrument("ETH", "USDT"),
+            ])
+            .await
+            .unwrap();
+
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            events.push(event);
+        }
+        assert_eq!(events.len(), 2);
+        // Leg quotes are still tracked for legging orders
+        assert!(stream
+            .instrument(&spot_instrument("BTCETH", "USDT"))
+            .unwrap()
+            .leg_order(&order(Side::Buy, OrderType::Market, None))
+            .is_ok());
+    }
+}
@@ -0,0 +1,332 @@
+//! FIX market data messages
+//!
+//! Builds MarketDataRequest (V) messages and normalizes MarketDataSnapshotFullRefresh
+//! (W) and MarketDataIncrementalRefresh (X) into `MarketEvent`s. Every instrument is
+//! requested under its exchange symbol as MDReqID, so entries are matched to
+//! instruments by Symbol (55) or, when a venue omits it, by MDReqID.
+//!
+//! A snapshot becomes an `OrderBookDelta` with `snapshot` set; runs of incremental book
+//! entries for the same instrument become one delta each. Trade entries of either
+//! message become `PublicTrade`s, in the order the venue sent them.
+
+use super::message::{msg_type, parse_timestamp, parse_value, tags, FixMessage};
+use super::FixError;
+use crate::data::{
+    ExchangeId, InstrumentId, LevelAction, LevelUpdate, MarketDataKind, MarketEvent,
+    OrderBookDelta, PublicTrade, Side, SubscriptionKind,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// MDEntryType (269) values
+mod entry_type {
+    pub const BID: &str = "0";
+    pub const OFFER: &str = "1";
+    pub const TRADE: &str = "2";
+}
+
+/// Field of a repeating group entry
+fn entry_field(entry: &[(u32, String)], tag: u32) -> Option<&str> {
+    entry
+        .iter()
+        .find(|(field, _)| *field == tag)
+        .map(|(_, value)| value.as_str())
+}
+
+fn require_entry_field(entry: &[(u32, String)], tag: u32) -> Result<&str, FixError> {
+    entry_field(entry, tag).ok_or(FixError::MissingField(tag))
+}
+
+/// Delta being collected from consecutive incremental book entries
+struct PendingDelta {
+    instrument: InstrumentId,
+    updates: Vec<LevelUpdate>,
+}
+
+/// Translates between instruments and FIX market data messages
+#[derive(Debug, Clone)]
+pub struct FixMarketDataDecoder {
+    exchange: ExchangeId,
+    by_symbol: HashMap<String, InstrumentId>,
+}
+
+impl FixMarketDataDecoder {
+    /// Create a decoder tagging events with `exchange`
+    pub fn new(exchange: ExchangeId) -> Self {
+        Self {
+            exchange,
+            by_symbol: HashMap::new(),
+        }
+    }
+
+    /// MarketDataRequest subscribing to, or unsubscribing from, `instrument`; a `depth`
+    /// of 0 requests the full book.
+    ///
+    /// `SubscriptionKind::BookDeltas` requests bids and offers and
+    /// `SubscriptionKind::Trades` requests trades; other kinds are ignored.
+    pub fn request(
+        &mut self,
+        instrument: &InstrumentId,
+        depth: u32,
+        kinds: &[SubscriptionKind],
+        subscribe: bool,
+    ) -> FixMessage {
+        let symbol = &instrument.exchange_symbol;
+        if subscribe {
+            self.by_symbol.insert(symbol.clone(), instrument.clone());
+        }
+        let mut entry_types = Vec::new();
+        if kinds.contains(&SubscriptionKind::BookDeltas) {
+            entry_types.extend([entry_type::BID, entry_type::OFFER]);
+        }
+        if kinds.contains(&SubscriptionKind::Trades) {
+            entry_types.push(entry_type::TRADE);
+        }
+        let mut request = FixMessage::new(msg_type::MARKET_DATA_REQUEST)
+            .with(tags::MD_REQ_ID, symbol)
+            .with(
+                tags::SUBSCRIPTION_REQUEST_TYPE,
+                if subscribe { 1 } else { 2 },
+            )
+            .with(tags::MARKET_DEPTH, depth)
+            .with(tags::MD_UPDATE_TYPE, 1)
+            .with(tags::NO_MD_ENTRY_TYPES, entry_types.len());
+        for entry_type in entry_types {
+            request.push(tags::MD_ENTRY_TYPE, entry_type);
+        }
+        request
+            .with(tags::NO_RELATED_SYM, 1)
+            .with(tags::SYMBOL, symbol)
+    }
+
+    /// Normalize an application message received at `receipt_time`.
+    ///
+    /// Messages other than W, X and Y yield no events; a MarketDataRequestReject is
+    /// returned as `FixError::RequestRejected`.
+    pub fn decode(
+        &self,
+        message: &FixMessage,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Vec<MarketEvent>, FixError> {
+        match message.msg_type() {
+            msg_type::MARKET_DATA_SNAPSHOT => self.decode_snapshot(message, receipt_time),
+            msg_type::MARKET_DATA_INCREMENTAL => self.decode_incremental(message, receipt_time),
+            msg_type::MARKET_DATA_REQUEST_REJECT => Err(FixError::RequestRejected {
+                request_id: message.get(tags::MD_REQ_ID).unwrap_or_default().to_string(),
+                reason: message
+                    .get(tags::TEXT)
+                    .or(message.get(tags::MD_REQ_REJ_REASON))
+                    .unwrap_or_default()
+                    .to_string(),
+            }),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    fn decode_snapshot(
+        &self,
+        message: &FixMessage,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Vec<MarketEvent>, FixError> {
+        let exchange_time = Self::exchange_time(message)?;
+        let instrument = self.instrument(message.get(tags::SYMBOL), message)?;
+        let mut events = Vec::new();
+        let mut updates = Vec::new();
+        for (index, entry) in message
+            .group(tags::NO_MD_ENTRIES, tags::MD_ENTRY_TYPE)
+            .into_iter()
+            .enumerate()
+        {
+            match require_entry_field(entry, tags::MD_ENTRY_TYPE)? {
+                entry_type::TRADE => {
+                    let trade = Self::trade(entry, message, index, exchange_time)?;
+                    events.push(self.event(&instrument, trade, exchange_time, receipt_time));
+                }
+                _ => {
+                    if let Some(update) = Self::level_update(entry, LevelAction::Insert)? {
+                        updates.push(update);
+                    }
+                }
+            }
+        }
+        let delta = MarketDataKind::OrderBookDelta(OrderBookDelta {
+            updates,
+            first_update_id: None,
+            last_update_id: None,
+            snapshot: true,
+            timestamp: exchange_time,
+        });
+        events.insert(
+            0,
+            self.event(&instrument, delta, exchange_time, receipt_time),
+        );
+        Ok(events)
+    }
+
+    fn decode_incremental(
+        &self,
+        message: &FixMessage,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Vec<MarketEvent>, FixError> {
+        let exchange_time = Self::exchange_time(message)?;
+        let mut events = Vec::new();
+        let mut pending: Option<PendingDelta> = None;
+        for (index, entry) in message
+            .group(tags::NO_MD_ENTRIES, tags::MD_UPDATE_ACTION)
+            .into_iter()
+            .enumerate()
+        {
+            let instrument = self.instrument(entry_field(entry, tags::SYMBOL), message)?;
+            if pending
+                .as_ref()
+                .is_some_and(|delta| delta.instrument != instrument)
+            {
+                events.extend(self.flush(pending.take(), exchange_time, receipt_time));
+            }
+
+            if require_entry_field(entry, tags::MD_ENTRY_TYPE)? == entry_type::TRADE {
+                events.extend(self.flush(pending.take(), exchange_time, receipt_time));
+                let trade = Self::trade(entry, message, index, exchange_time)?;
+                events.push(self.event(&instrument, trade, exchange_time, receipt_time));
+                continue;
+            }
+            let action = match require_entry_field(entry, tags::MD_UPDATE_ACTION)? {
+                "0" => LevelAction::Insert,
+                "1" => LevelAction::Update,
+                "2" => LevelAction::Delete,
+                other => {
+                    return Err(FixError::InvalidField {
+                        tag: tags::MD_UPDATE_ACTION,
+                        value: other.to_string(),
+                    })
+                }
+            };
+            if let Some(update) = Self::level_update(entry, action)? {
+                pending
+                    .get_or_insert_with(|| PendingDelta {
+                        instrument,
+                        updates: Vec::new(),
+                    })
+                    .updates
+                    .push(update);
+            }
+        }
+        events.extend(self.flush(pending, exchange_time, receipt_time));
+        Ok(events)
+    }
+
+    /// Instrument of an entry's Symbol, falling back to the message's MDReqID
+    fn instrument(
+        &self,
+        symbol: Option<&str>,
+        message: &FixMessage,
+    ) -> Result<InstrumentId, FixError> {
+        let key = symbol
+            .or(message.get(tags::MD_REQ_ID))
+            .ok_or(FixError::MissingField(tags::SYMBOL))?;
+        self.by_symbol
+            .get(key)
+            .cloned()
+            .ok_or_else(|| FixError::InvalidField {
+                tag: tags::SYMBOL,
+                value: key.to_string(),
+            })
+    }
+
+    /// Original sending time of resent messages, otherwise the SendingTime
+    fn exchange_time(message: &FixMessage) -> Result<DateTime<Utc>, FixError> {
+        match message.get(tags::ORIG_SENDING_TIME) {
+            Some(original) => parse_timestamp(original),
+            None => message.sending_time(),
+        }
+    }
+
+    /// Bid or offer entry as a level update; other entry types are skipped
+    fn level_update(
+        entry: &[(u32, String)],
+        action: LevelAction,
+    ) -> Result<Option<LevelUpdate>, FixError> {
+        let side = match require_entry_field(entry, tags::MD_ENTRY_TYPE)? {
+            entry_type::BID => Side::Buy,
+            entry_type::OFFER => Side::Sell,
+            _ => return Ok(None),
+        };
+        let price = require_entry_field(entry, tags::MD_ENTRY_PX)?;
+        let quantity = match entry_field(entry, tags::MD_ENTRY_SIZE) {
+            Some(size) => parse_value(tags::MD_ENTRY_SIZE, size)?,
+            None if action == LevelAction::Delete => Decimal::ZERO,
+            None => return Err(FixError::MissingField(tags::MD_ENTRY_SIZE)),
+        };
+        Ok(Some(LevelUpdate {
+            side,
+            price: parse_value(tags::MD_ENTRY_PX, price)?,
+            quantity,
+            action,
+        }))
+    }
+
+    /// Trade entry; the id is the MDEntryID if present, otherwise derived from the
+    /// message sequence number and the entry's position
+    fn trade(
+        entry: &[(u32, String)],
+        message: &FixMessage,
+        index: usize,
+        timestamp: DateTime<Utc>,
+    ) -> Result<MarketDataKind, FixError> {
+        let id = match entry_field(entry, tags::MD_ENTRY_ID) {
+            Some(id) => id.to_string(),
+            None => format!("{}-{}", message.seq_num()?, index),
+        };
+        let side = match entry_field(entry, tags::SIDE) {
+            Some("2") => Side::Sell,
+            _ => Side::Buy,
+        };
+        Ok(MarketDataKind::Trade(PublicTrade {
+            id,
+            price: parse_value(
+                tags::MD_ENTRY_PX,
+                require_entry_field(entry, tags::MD_ENTRY_PX)?,
+            )?,
+            quantity: parse_value(
+                tags::MD_ENTRY_SIZE,
+                require_entry_field(entry, tags::MD_ENTRY_SIZE)?,
+            )?,
+            side,
+            timestamp,
+        }))
+    }
+
+    fn flush(
+        &self,
+        pending: Option<PendingDelta>,
+        exchange_time: DateTime<Utc>,
+        receipt_time: DateTime<Utc>,
+    ) -> Option<MarketEvent> {
+        let pending = pending?;
+        let delta = MarketDataKind::OrderBookDelta(OrderBookDelta {
+            updates: pending.updates,
+            first_update_id: None,
+            last_update_id: None,
+            snapshot: false,
+            timestamp: exchange_time,
+        });
+        Some(self.event(&pending.instrument, delta, exchange_time, receipt_time))
+    }
+
+    fn event(
+        &self,
+        instrument: &InstrumentId,
+        kind: MarketDataKind,
+        exchange_time: DateTime<Utc>,
+        receipt_time: DateTime<Utc>,
+    ) -> MarketEvent {
+        MarketEvent {
+            exchange: self.exchange,
+            instrument: instrument.clone(),
+            kind,
+            exchange_time,
+            receipt_time,
+        }
+    }
+}
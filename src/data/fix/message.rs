@@ -0,0 +1,324 @@
+//! FIX 4.4 tag=value encoding
+//!
+//! A `FixMessage` holds the fields between BodyLength (9) and CheckSum (10) in wire
+//! order, starting with MsgType (35). BeginString, BodyLength and CheckSum are added by
+//! `encode` and verified and stripped by `parse`.
+
+use super::FixError;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::str::FromStr;
+
+/// Field separator
+pub const SOH: u8 = 0x01;
+
+/// BeginString of every message
+pub const BEGIN_STRING: &str = "FIX.4.4";
+
+/// Longest message accepted from a counterparty
+const MAX_BODY_LEN: usize = 1 << 20;
+
+/// Tags used by the session and market data layers
+pub mod tags {
+    pub const BEGIN_SEQ_NO: u32 = 7;
+    pub const BODY_LENGTH: u32 = 9;
+    pub const CHECK_SUM: u32 = 10;
+    pub const END_SEQ_NO: u32 = 16;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const NEW_SEQ_NO: u32 = 36;
+    pub const POSS_DUP_FLAG: u32 = 43;
+    pub const REF_SEQ_NUM: u32 = 45;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SIDE: u32 = 54;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const ENCRYPT_METHOD: u32 = 98;
+    pub const HEART_BT_INT: u32 = 108;
+    pub const TEST_REQ_ID: u32 = 112;
+    pub const ORIG_SENDING_TIME: u32 = 122;
+    pub const GAP_FILL_FLAG: u32 = 123;
+    pub const RESET_SEQ_NUM_FLAG: u32 = 141;
+    pub const NO_RELATED_SYM: u32 = 146;
+    pub const MD_REQ_ID: u32 = 262;
+    pub const SUBSCRIPTION_REQUEST_TYPE: u32 = 263;
+    pub const MARKET_DEPTH: u32 = 264;
+    pub const MD_UPDATE_TYPE: u32 = 265;
+    pub const NO_MD_ENTRY_TYPES: u32 = 267;
+    pub const NO_MD_ENTRIES: u32 = 268;
+    pub const MD_ENTRY_TYPE: u32 = 269;
+    pub const MD_ENTRY_PX: u32 = 270;
+    pub const MD_ENTRY_SIZE: u32 = 271;
+    pub const MD_ENTRY_ID: u32 = 278;
+    pub const MD_UPDATE_ACTION: u32 = 279;
+    pub const MD_REQ_REJ_REASON: u32 = 281;
+    pub const USERNAME: u32 = 553;
+    pub const PASSWORD: u32 = 554;
+}
+
+/// MsgType values
+pub mod msg_type {
+    pub const HEARTBEAT: &str = "0";
+    pub const TEST_REQUEST: &str = "1";
+    pub const RESEND_REQUEST: &str = "2";
+    pub const REJECT: &str = "3";
+    pub const SEQUENCE_RESET: &str = "4";
+    pub const LOGOUT: &str = "5";
+    pub const LOGON: &str = "A";
+    pub const MARKET_DATA_REQUEST: &str = "V";
+    pub const MARKET_DATA_SNAPSHOT: &str = "W";
+    pub const MARKET_DATA_INCREMENTAL: &str = "X";
+    pub const MARKET_DATA_REQUEST_REJECT: &str = "Y";
+
+    /// Whether a message type belongs to the session layer
+    pub fn is_admin(msg_type: &str) -> bool {
+        matches!(msg_type, "0" | "1" | "2" | "3" | "4" | "5" | "A")
+    }
+}
+
+/// FIX message body in wire order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixMessage {
+    fields: Vec<(u32, String)>,
+}
+
+impl FixMessage {
+    /// Create a message of the given MsgType
+    pub fn new(msg_type: &str) -> Self {
+        Self {
+            fields: vec![(tags::MSG_TYPE, msg_type.to_string())],
+        }
+    }
+
+    /// Append a field
+    pub fn with(mut self, tag: u32, value: impl ToString) -> Self {
+        self.push(tag, value);
+        self
+    }
+
+    /// Append a field
+    pub fn push(&mut self, tag: u32, value: impl ToString) {
+        self.fields.push((tag, value.to_string()));
+    }
+
+    /// Set the first occurrence of a field, appending it if absent
+    pub fn set(&mut self, tag: u32, value: impl ToString) {
+        match self.fields.iter_mut().find(|(field, _)| *field == tag) {
+            Some((_, existing)) => *existing = value.to_string(),
+            None => self.push(tag, value),
+        }
+    }
+
+    /// Fields in wire order
+    pub fn fields(&self) -> &[(u32, String)] {
+        &self.fields
+    }
+
+    /// MsgType (35)
+    pub fn msg_type(&self) -> &str {
+        self.get(tags::MSG_TYPE).unwrap_or_default()
+    }
+
+    /// First value of a field
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| *field == tag)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// First value of a required field
+    pub fn require(&self, tag: u32) -> Result<&str, FixError> {
+        self.get(tag).ok_or(FixError::MissingField(tag))
+    }
+
+    /// First value of a required field, parsed
+    pub fn parse_field<T: FromStr>(&self, tag: u32) -> Result<T, FixError> {
+        parse_value(tag, self.require(tag)?)
+    }
+
+    /// Whether a Y/N flag field is set to Y
+    pub fn flag(&self, tag: u32) -> bool {
+        self.get(tag) == Some("Y")
+    }
+
+    /// MsgSeqNum (34)
+    pub fn seq_num(&self) -> Result<u64, FixError> {
+        self.parse_field(tags::MSG_SEQ_NUM)
+    }
+
+    /// SendingTime (52)
+    pub fn sending_time(&self) -> Result<DateTime<Utc>, FixError> {
+        parse_timestamp(self.require(tags::SENDING_TIME)?)
+    }
+
+    /// Entries of a repeating group.
+    ///
+    /// Every occurrence of `delimiter`, the first field of an entry, after `count_tag`
+    /// starts a new entry; the group runs to the end of the message.
+    pub fn group(&self, count_tag: u32, delimiter: u32) -> Vec<&[(u32, String)]> {
+        let Some(start) = self.fields.iter().position(|(tag, _)| *tag == count_tag) else {
+            return Vec::new();
+        };
+        let fields = &self.fields[start + 1..];
+        let starts: Vec<usize> = fields
+            .iter()
+            .enumerate()
+            .filter(|(_, (tag, _))| *tag == delimiter)
+            .map(|(index, _)| index)
+            .collect();
+        starts
+            .iter()
+            .enumerate()
+            .map(|(n, start)| {
+                let end = starts.get(n + 1).copied().unwrap_or(fields.len());
+                &fields[*start..end]
+            })
+            .collect()
+    }
+
+    /// Serialize with BeginString, BodyLength and CheckSum
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(self.fields.len() * 16);
+        for (tag, value) in &self.fields {
+            body.extend_from_slice(tag.to_string().as_bytes());
+            body.push(b'=');
+            body.extend_from_slice(value.as_bytes());
+            body.push(SOH);
+        }
+        let mut message = format!("8={}\x019={}\x01", BEGIN_STRING, body.len()).into_bytes();
+        message.extend_from_slice(&body);
+        let checksum = checksum(&message);
+        message.extend_from_slice(format!("10={:03}\x01", checksum).as_bytes());
+        message
+    }
+
+    /// Parse the first complete message in `buffer`, returning it and the number of
+    /// bytes it occupied, or `None` if more input is needed
+    pub fn parse(buffer: &[u8]) -> Result<Option<(FixMessage, usize)>, FixError> {
+        let prefix = format!("8={}\x019=", BEGIN_STRING);
+        if buffer.len() < prefix.len() {
+            return Ok(None);
+        }
+        if !buffer.starts_with(prefix.as_bytes()) {
+            return Err(FixError::Malformed(
+                "message does not start with BeginString".into(),
+            ));
+        }
+        let Some(length_end) = buffer[prefix.len()..].iter().position(|b| *b == SOH) else {
+            return Ok(None);
+        };
+        let length_end = prefix.len() + length_end;
+        let body_len: usize = parse_value(
+            tags::BODY_LENGTH,
+            &String::from_utf8_lossy(&buffer[prefix.len()..length_end]),
+        )?;
+        if body_len > MAX_BODY_LEN {
+            return Err(FixError::Malformed(format!("body length {}", body_len)));
+        }
+        let body_start = length_end + 1;
+        let body_end = body_start + body_len;
+        // CheckSum is always "10=NNN<SOH>"
+        let total = body_end + 7;
+        if buffer.len() < total {
+            return Ok(None);
+        }
+        let trailer = &buffer[body_end..total];
+        if !trailer.starts_with(b"10=") || trailer[6] != SOH {
+            return Err(FixError::Malformed(
+                "BodyLength does not end at CheckSum".into(),
+            ));
+        }
+        let expected: u8 = parse_value(tags::CHECK_SUM, &String::from_utf8_lossy(&trailer[3..6]))?;
+        let computed = checksum(&buffer[..body_end]);
+        if expected != computed {
+            return Err(FixError::Checksum { expected, computed });
+        }
+
+        let body = std::str::from_utf8(&buffer[body_start..body_end])
+            .map_err(|_| FixError::Malformed("body is not UTF-8".into()))?;
+        let mut fields = Vec::new();
+        for field in body.split('\x01').filter(|field| !field.is_empty()) {
+            let (tag, value) = field
+                .split_once('=')
+                .ok_or_else(|| FixError::Malformed(format!("field without '=': {}", field)))?;
+            let tag = tag
+                .parse()
+                .map_err(|_| FixError::Malformed(format!("invalid tag: {}", tag)))?;
+            fields.push((tag, value.to_string()));
+        }
+        if fields.first().map(|(tag, _)| *tag) != Some(tags::MSG_TYPE) {
+            return Err(FixError::Malformed(
+                "MsgType is not the first body field".into(),
+            ));
+        }
+        Ok(Some((FixMessage { fields }, total)))
+    }
+}
+
+/// Sum of all bytes modulo 256
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Parse a field value
+pub(crate) fn parse_value<T: FromStr>(tag: u32, value: &str) -> Result<T, FixError> {
+    value.parse().map_err(|_| FixError::InvalidField {
+        tag,
+        value: value.to_string(),
+    })
+}
+
+/// Format a UTCTimestamp with milliseconds
+pub fn format_timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%d-%H:%M:%S%.3f").to_string()
+}
+
+/// Parse a UTCTimestamp with or without fractional seconds
+pub fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, FixError> {
+    NaiveDateTime::parse_from_str(value, "%Y%m%d-%H:%M:%S%.f")
+        .map(|time| time.and_utc())
+        .map_err(|_| FixError::InvalidField {
+            tag: tags::SENDING_TIME,
+            value: value.to_string(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_parse_round_trip() {
+        let message = FixMessage::new(msg_type::HEARTBEAT)
+            .with(tags::SENDER_COMP_ID, "CLIENT")
+            .with(tags::TARGET_COMP_ID, "VENUE")
+            .with(tags::MSG_SEQ_NUM, 2)
+            .with(tags::SENDING_TIME, "20240318-14:02:11.005");
+        let encoded = message.encode();
+        assert_eq!(
+            String::from_utf8(encoded.clone())
+                .unwrap()
+                .replace('\x01', "|"),
+            "8=FIX.4.4|9=54|35=0|49=CLIENT|56=VENUE|34=2|52=20240318-14:02:11.005|10=010|"
+        );
+
+        // Partial input waits for more, extra input is left for the next message
+        assert!(FixMessage::parse(&encoded[..encoded.len() - 1])
+            .unwrap()
+            .is_none());
+        let mut stream = encoded.clone();
+        stream.extend_from_slice(&encoded[..10]);
+        let (parsed, used) = FixMessage::parse(&stream).unwrap().unwrap();
+        assert_eq!((parsed, used), (message.clone(), encoded.len()));
+        assert_eq!(message.seq_num().unwrap(), 2);
+
+        let mut corrupted = encoded;
+        corrupted[20] = b'1';
+        assert!(matches!(
+            FixMessage::parse(&corrupted),
+            Err(FixError::Checksum { .. })
+        ));
+    }
+}
@@ -0,0 +1,585 @@
+//! FIX 4.4 market data client
+//!
+//! `message` encodes and parses tag=value messages, `session` implements the session
+//! layer (logon, heartbeats, test requests, sequence numbers, resend requests and gap
+//! fills) without doing any I/O, and `market_data` translates MarketDataRequest,
+//! snapshot and incremental refresh messages to and from `MarketEvent`s.
+//!
+//! `FixMarketDataStream` ties them together over a TCP connection to a FIX acceptor.
+//! The connection and logon happen on the first `subscribe`, so logon failures are
+//! returned to the caller; afterwards a background task drives the session and sends
+//! a Logout when the stream is dropped. Rejected requests are returned from `next`
+//! and the stream carries on; a lost connection, a heartbeat timeout or a Logout from
+//! the counterparty is returned from `next` before the stream ends.
+//!
+//! Book entries and trades are requested for every instrument unless the stream is
+//! created with `from_config`, which requests bids and offers for
+//! `SubscriptionKind::BookDeltas` and trades for `SubscriptionKind::Trades` per
+//! instrument and rejects other kinds. Updates are incremental, so
+//! `update_frequency_ms` does not apply.
+
+pub mod market_data;
+pub mod message;
+pub mod session;
+
+pub use market_data::FixMarketDataDecoder;
+pub use message::FixMessage;
+
+use message::{msg_type, tags};
+pub use session::{FixSession, FixSessionConfig, SessionOutput, SessionState};
+
+use super::{ExchangeId, InstrumentId, MarketDataStream, MarketEvent, SubscriptionKind};
+use crate::config::DataConfig;
+use chrono::Utc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Time allowed for the counterparty to answer our Logon
+pub const LOGON_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the session checks heartbeats and test requests
+const TIMER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Market data requested for instruments of a stream not created from a configuration
+const DEFAULT_KINDS: &[SubscriptionKind] =
+    &[SubscriptionKind::BookDeltas, SubscriptionKind::Trades];
+
+/// Errors produced by the FIX client
+#[derive(Debug, thiserror::Error)]
+pub enum FixError {
+    /// Connection failed or was lost
+    #[error("FIX connection error: {0}")]
+    Io(#[from] std::io::Error),
+    /// Input is not a well-formed FIX message
+    #[error("malformed FIX message: {0}")]
+    Malformed(String),
+    /// CheckSum (10) does not match the message
+    #[error("FIX checksum mismatch: expected {expected}, computed {computed}")]
+    Checksum { expected: u8, computed: u8 },
+    /// A required field is absent
+    #[error("missing FIX field {0}")]
+    MissingField(u32),
+    /// A field has a value that cannot be interpreted
+    #[error("invalid value {value:?} for FIX field {tag}")]
+    InvalidField { tag: u32, value: String },
+    /// Message was not addressed from our counterparty to us
+    #[error("unexpected FIX comp ids: sender {sender}, target {target}")]
+    CompIdMismatch { sender: String, target: String },
+    /// Sequence number below the expected one without PossDupFlag
+    #[error("FIX sequence number too low: expected {expected}, received {received}")]
+    SequenceTooLow { expected: u64, received: u64 },
+    /// Counterparty did not answer a TestRequest
+    #[error("FIX heartbeat timeout")]
+    HeartbeatTimeout,
+    /// Counterparty did not answer our Logon in time
+    #[error("FIX logon timed out")]
+    LogonTimeout,
+    /// Counterparty logged out
+    #[error("FIX session logged out: {0}")]
+    LoggedOut(String),
+    /// MarketDataRequest rejected by the venue
+    #[error("FIX market data request {request_id} rejected: {reason}")]
+    RequestRejected { request_id: String, reason: String },
+    /// Subscription that cannot be expressed as MarketDataRequest entry types
+    #[error("unsupported FIX subscription: {0:?}")]
+    UnsupportedSubscription(SubscriptionKind),
+}
+
+/// Request forwarded to the session task, with the market data requested
+#[derive(Debug)]
+enum Command {
+    Subscribe(InstrumentId, Vec<SubscriptionKind>),
+    Unsubscribe(InstrumentId, Vec<SubscriptionKind>),
+}
+
+/// Market data stream over a FIX 4.4 session
+pub struct FixMarketDataStream {
+    address: String,
+    session: FixSessionConfig,
+    exchange: ExchangeId,
+    market_depth: u32,
+    /// Configuration the stream was created from, selecting the entry types requested
+    config: Option<DataConfig>,
+    commands: Option<mpsc::UnboundedSender<Command>>,
+    receiver: Option<mpsc::Receiver<Result<MarketEvent, FixError>>>,
+    instruments: Vec<InstrumentId>,
+}
+
+impl FixMarketDataStream {
+    /// Create a stream connecting to the acceptor at `address` (`host:port`), tagging
+    /// events with `exchange`
+    pub fn new(
+        address: impl Into<String>,
+        session: FixSessionConfig,
+        exchange: ExchangeId,
+    ) -> Self {
+        Self {
+            address: address.into(),
+            session,
+            exchange,
+            market_depth: 0,
+            config: None,
+            commands: None,
+            receiver: None,
+            instruments: Vec::new(),
+        }
+    }
+
+    /// Create a stream requesting the market data listed in the configuration,
+    /// including per-instrument overrides
+    pub fn from_config(
+        address: impl Into<String>,
+        session: FixSessionConfig,
+        exchange: ExchangeId,
+        config: &DataConfig,
+    ) -> Result<Self, FixError> {
+        if let Some(kind) = config
+            .all_subscriptions()
+            .into_iter()
+            .find(|kind| !DEFAULT_KINDS.contains(kind))
+        {
+            return Err(FixError::UnsupportedSubscription(kind));
+        }
+        let mut stream = Self::new(address, session, exchange);
+        stream.config = Some(config.clone());
+        Ok(stream)
+    }
+
+    /// Book levels per side to request, 0 for the full book
+    pub fn with_market_depth(mut self, market_depth: u32) -> Self {
+        self.market_depth = market_depth;
+        self
+    }
+
+    /// Market data requested for `instrument`
+    fn kinds_for(&self, instrument: &InstrumentId) -> Vec<SubscriptionKind> {
+        match &self.config {
+            Some(config) => config.subscriptions_for(instrument).to_vec(),
+            None => DEFAULT_KINDS.to_vec(),
+        }
+    }
+
+    /// Connect, log on and start the session task
+    async fn connect(&mut self) -> Result<(), FixError> {
+        let mut socket = TcpStream::connect(&self.address).await?;
+        socket.set_nodelay(true)?;
+        let mut session = FixSession::new(self.session.clone(), Instant::now());
+        socket
+            .write_all(&session.logon(Instant::now()).encode())
+            .await?;
+
+        // Anything following the counterparty's Logon is left for the session task
+        let mut buffer = Vec::new();
+        tokio::time::timeout(LOGON_TIMEOUT, async {
+            while session.state() != SessionState::Active {
+                let Some((message, len)) = FixMessage::parse(&buffer)? else {
+                    if socket.read_buf(&mut buffer).await? == 0 {
+                        return Err(FixError::LoggedOut("connection closed".to_string()));
+                    }
+                    continue;
+                };
+                buffer.drain(..len);
+                let text = message.get(tags::TEXT).map(str::to_string);
+                let output = session.on_message(message, Instant::now())?;
+                for reply in output.replies {
+                    socket.write_all(&reply.encode()).await?;
+                }
+                if session.state() == SessionState::Closed {
+                    return Err(FixError::LoggedOut(text.unwrap_or_default()));
+                }
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|_| FixError::LogonTimeout)??;
+
+        let (command_sender, commands) = mpsc::unbounded_channel();
+        let (sender, receiver) = mpsc::channel(100);
+        self.commands = Some(command_sender);
+        self.receiver = Some(receiver);
+        let decoder = FixMarketDataDecoder::new(self.exchange);
+        tokio::spawn(run_session(
+            socket,
+            session,
+            buffer,
+            decoder,
+            self.market_depth,
+            commands,
+            sender,
+        ));
+        Ok(())
+    }
+
+    /// Forward a command to the session task
+    fn send(&self, command: Command) -> Result<(), FixError> {
+        self.commands
+            .as_ref()
+            .and_then(|commands| commands.send(command).ok())
+            .ok_or_else(|| FixError::LoggedOut("session closed".to_string()))
+    }
+}
+
+#[async_trait::async_trait]
+impl MarketDataStream for FixMarketDataStream {
+    type Error = FixError;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        match &mut self.receiver {
+            Some(receiver) => receiver.recv().await.transpose(),
+            None => Ok(None),
+        }
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        if self
+            .commands
+            .as_ref()
+            .is_none_or(|commands| commands.is_closed())
+        {
+            self.connect().await?;
+        }
+        for instrument in instruments {
+            self.send(Command::Subscribe(
+                instrument.clone(),
+                self.kinds_for(instrument),
+            ))?;
+        }
+        self.instruments.extend_from_slice(instruments);
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.instruments.retain(|i| !instruments.contains(i));
+        if self.commands.is_some() {
+            for instrument in instruments {
+                self.send(Command::Unsubscribe(
+                    instrument.clone(),
+                    self.kinds_for(instrument),
+                ))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl futures::Stream for FixMarketDataStream {
+    type Item = Result<MarketEvent, FixError>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        super::stream::poll_result_receiver(&mut self.receiver, cx)
+    }
+}
+
+/// Drive a logged on session until either side logs out or the connection fails,
+/// passing the reason it ended on to the stream
+async fn run_session(
+    socket: TcpStream,
+    session: FixSession,
+    buffer: Vec<u8>,
+    decoder: FixMarketDataDecoder,
+    market_depth: u32,
+    commands: mpsc::UnboundedReceiver<Command>,
+    sender: mpsc::Sender<Result<MarketEvent, FixError>>,
+) {
+    let result = drive_session(
+        socket,
+        session,
+        buffer,
+        decoder,
+        market_depth,
+        commands,
+        &sender,
+    )
+    .await;
+    if let Err(error) = result {
+        warn!(%error, "closing FIX session");
+        let _ = sender.send(Err(error)).await;
+    }
+}
+
+/// Session loop of `run_session`; returns `Ok` once the stream is dropped
+async fn drive_session(
+    socket: TcpStream,
+    mut session: FixSession,
+    mut buffer: Vec<u8>,
+    mut decoder: FixMarketDataDecoder,
+    market_depth: u32,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    sender: &mpsc::Sender<Result<MarketEvent, FixError>>,
+) -> Result<(), FixError> {
+    let (mut read, mut write) = socket.into_split();
+    let mut timer = tokio::time::interval(TIMER_INTERVAL);
+    let mut received = true;
+
+    loop {
+        let mut outgoing = Vec::new();
+        let mut events = Vec::new();
+        let mut closing = false;
+
+        if received {
+            received = false;
+            outgoing = drain_buffer(&mut buffer, &mut session, &decoder, &mut events)?;
+        } else {
+            tokio::select! {
+                result = read.read_buf(&mut buffer) => match result? {
+                    0 => return Err(FixError::LoggedOut("connection closed".to_string())),
+                    _ => received = true,
+                },
+                command = commands.recv() => {
+                    let now = Instant::now();
+                    match command {
+                        Some(Command::Subscribe(instrument, kinds)) => {
+                            let request = decoder.request(&instrument, market_depth, &kinds, true);
+                            outgoing.push(session.send(request, now));
+                        }
+                        Some(Command::Unsubscribe(instrument, kinds)) => {
+                            let request =
+                                decoder.request(&instrument, market_depth, &kinds, false);
+                            outgoing.push(session.send(request, now));
+                        }
+                        // The stream was dropped
+                        None => {
+                            closing = true;
+                            outgoing.push(session.logout(None, now));
+                        }
+                    }
+                }
+                _ = timer.tick() => outgoing = session.on_timer(Instant::now())?,
+            }
+        }
+
+        for message in outgoing {
+            write.write_all(&message.encode()).await?;
+        }
+        for event in events {
+            if sender.send(event).await.is_err() {
+                return Ok(());
+            }
+        }
+        if closing || session.state() == SessionState::Closed {
+            return Ok(());
+        }
+    }
+}
+
+/// Process every complete message in `buffer`, returning the replies to send and
+/// adding the decoded market data to `events`.
+///
+/// Rejected requests and a Logout from the counterparty are added as errors.
+fn drain_buffer(
+    buffer: &mut Vec<u8>,
+    session: &mut FixSession,
+    decoder: &FixMarketDataDecoder,
+    events: &mut Vec<Result<MarketEvent, FixError>>,
+) -> Result<Vec<FixMessage>, FixError> {
+    let mut replies = Vec::new();
+    while let Some((message, len)) = FixMessage::parse(buffer)? {
+        buffer.drain(..len);
+        let logout = (message.msg_type() == msg_type::LOGOUT
+            && session.state() != SessionState::LogoutSent)
+            .then(|| message.get(tags::TEXT).unwrap_or_default().to_string());
+        let output = session.on_message(message, Instant::now())?;
+        replies.extend(output.replies);
+        for message in output.messages {
+            match decoder.decode(&message, Utc::now()) {
+                Ok(decoded) => events.extend(decoded.into_iter().map(Ok)),
+                Err(error @ FixError::RequestRejected { .. }) => events.push(Err(error)),
+                Err(error) => warn!(%error, "dropping FIX market data message"),
+            }
+        }
+        if let Some(text) = logout {
+            events.push(Err(FixError::LoggedOut(text)));
+        }
+    }
+    Ok(replies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::message::{msg_type, tags};
+    use super::*;
+    use crate::data::mock_server::MockFixAcceptor;
+    use crate::data::{InstrumentKind, LevelAction, MarketDataKind, Side};
+    use rust_decimal::Decimal;
+
+    fn btc_usd() -> InstrumentId {
+        InstrumentId {
+            base: "BTC".to_string(),
+            quote: "USD".to_string(),
+            exchange_symbol: "BTC-USD".to_string(),
+            kind: InstrumentKind::Spot,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_market_data_over_session_with_resend() {
+        let snapshot = FixMessage::new(msg_type::MARKET_DATA_SNAPSHOT)
+            .with(tags::MD_REQ_ID, "BTC-USD")
+            .with(tags::SYMBOL, "BTC-USD")
+            .with(tags::NO_MD_ENTRIES, 2)
+            .with(tags::MD_ENTRY_TYPE, 0)
+            .with(tags::MD_ENTRY_PX, "100")
+            .with(tags::MD_ENTRY_SIZE, "1")
+            .with(tags::MD_ENTRY_TYPE, 1)
+            .with(tags::MD_ENTRY_PX, "101")
+            .with(tags::MD_ENTRY_SIZE, "2");
+        let update = FixMessage::new(msg_type::MARKET_DATA_INCREMENTAL)
+            .with(tags::MD_REQ_ID, "BTC-USD")
+            .with(tags::NO_MD_ENTRIES, 1)
+            .with(tags::MD_UPDATE_ACTION, 1)
+            .with(tags::MD_ENTRY_TYPE, 0)
+            .with(tags::SYMBOL, "BTC-USD")
+            .with(tags::MD_ENTRY_PX, "100")
+            .with(tags::MD_ENTRY_SIZE, "3");
+        let trade = FixMessage::new(msg_type::MARKET_DATA_INCREMENTAL)
+            .with(tags::NO_MD_ENTRIES, 1)
+            .with(tags::MD_UPDATE_ACTION, 0)
+            .with(tags::MD_ENTRY_TYPE, 2)
+            .with(tags::MD_ENTRY_ID, "T1")
+            .with(tags::SYMBOL, "BTC-USD")
+            .with(tags::MD_ENTRY_PX, "100.5")
+            .with(tags::MD_ENTRY_SIZE, "0.5")
+            .with(tags::SIDE, 2);
+        // The incremental update is lost on its first transmission
+        let acceptor = MockFixAcceptor::start("VENUE", vec![snapshot, update, trade], vec![1])
+            .await
+            .unwrap();
+
+        let config = FixSessionConfig::new("CLIENT", "VENUE").with_credentials("user", "secret");
+        let mut stream =
+            FixMarketDataStream::new(acceptor.addr().to_string(), config, ExchangeId::Coinbase)
+                .with_market_depth(10);
+        stream.subscribe(&[btc_usd()]).await.unwrap();
+
+        let mut events = Vec::new();
+        for _ in 0..3 {
+            let event = tokio::time::timeout(Duration::from_secs(5), stream.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            assert_eq!(event.instrument, btc_usd());
+            assert_eq!(event.exchange, ExchangeId::Coinbase);
+            events.push(event.kind);
+        }
+        let MarketDataKind::OrderBookDelta(book) = &events[0] else {
+            panic!("expected snapshot, got {:?}", events[0]);
+        };
+        assert!(book.snapshot);
+        assert_eq!(book.updates.len(), 2);
+        let MarketDataKind::OrderBookDelta(delta) = &events[1] else {
+            panic!("expected delta, got {:?}", events[1]);
+        };
+        assert!(!delta.snapshot);
+        assert_eq!(delta.updates[0].action, LevelAction::Update);
+        assert_eq!(delta.updates[0].quantity, Decimal::from(3));
+        let MarketDataKind::Trade(trade) = &events[2] else {
+            panic!("expected trade, got {:?}", events[2]);
+        };
+        assert_eq!((trade.id.as_str(), trade.side), ("T1", Side::Sell));
+        assert_eq!(trade.price, Decimal::new(1005, 1));
+
+        let received = acceptor.received();
+        assert_eq!(received[0].get(tags::USERNAME), Some("user"));
+        let request = received
+            .iter()
+            .find(|message| message.msg_type() == msg_type::MARKET_DATA_REQUEST)
+            .unwrap();
+        assert_eq!(request.get(tags::SUBSCRIPTION_REQUEST_TYPE), Some("1"));
+        assert_eq!(request.get(tags::MARKET_DEPTH), Some("10"));
+        let resend = received
+            .iter()
+            .find(|message| message.msg_type() == msg_type::RESEND_REQUEST)
+            .unwrap();
+        assert_eq!(resend.get(tags::BEGIN_SEQ_NO), Some("3"));
+
+        stream.unsubscribe(&[btc_usd()]).await.unwrap();
+        let unsubscribed = || {
+            acceptor.received().iter().any(|message| {
+                message.msg_type() == msg_type::MARKET_DATA_REQUEST
+                    && message.get(tags::SUBSCRIPTION_REQUEST_TYPE) == Some("2")
+            })
+        };
+        for _ in 0..100 {
+            if unsubscribed() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(unsubscribed());
+    }
+
+    #[tokio::test]
+    async fn test_from_config_requests_configured_entry_types() {
+        let acceptor = MockFixAcceptor::start("VENUE", Vec::new(), vec![])
+            .await
+            .unwrap();
+        let session = FixSessionConfig::new("CLIENT", "VENUE");
+        let mut config = crate::config::SystemConfig::default().data;
+        config.subscriptions = vec![SubscriptionKind::Trades];
+        let mut stream = FixMarketDataStream::from_config(
+            acceptor.addr().to_string(),
+            session.clone(),
+            ExchangeId::Coinbase,
+            &config,
+        )
+        .unwrap();
+        stream.subscribe(&[btc_usd()]).await.unwrap();
+
+        let request = || {
+            acceptor
+                .received()
+                .into_iter()
+                .find(|message| message.msg_type() == msg_type::MARKET_DATA_REQUEST)
+        };
+        for _ in 0..100 {
+            if request().is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let request = request().unwrap();
+        assert_eq!(request.get(tags::NO_MD_ENTRY_TYPES), Some("1"));
+        assert_eq!(request.get(tags::MD_ENTRY_TYPE), Some("2"));
+
+        config.subscriptions = vec![SubscriptionKind::L1];
+        assert!(matches!(
+            FixMarketDataStream::from_config("localhost:0", session, ExchangeId::Coinbase, &config),
+            Err(FixError::UnsupportedSubscription(SubscriptionKind::L1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_and_logout_are_returned() {
+        let reject = FixMessage::new(msg_type::MARKET_DATA_REQUEST_REJECT)
+            .with(tags::MD_REQ_ID, "BTC-USD")
+            .with(tags::TEXT, "unknown symbol");
+        let logout = FixMessage::new(msg_type::LOGOUT).with(tags::TEXT, "maintenance");
+        let acceptor = MockFixAcceptor::start("VENUE", vec![reject, logout], vec![])
+            .await
+            .unwrap();
+
+        let config = FixSessionConfig::new("CLIENT", "VENUE");
+        let mut stream =
+            FixMarketDataStream::new(acceptor.addr().to_string(), config, ExchangeId::Coinbase);
+        stream.subscribe(&[btc_usd()]).await.unwrap();
+
+        let timeout = Duration::from_secs(5);
+        assert!(matches!(
+            tokio::time::timeout(timeout, stream.next()).await.unwrap(),
+            Err(FixError::RequestRejected { reason, .. }) if reason == "unknown symbol"
+        ));
+        assert!(matches!(
+            tokio::time::timeout(timeout, stream.next()).await.unwrap(),
+            Err(FixError::LoggedOut(text)) if text == "maintenance"
+        ));
+        let end = tokio::time::timeout(timeout, stream.next()).await.unwrap();
+        assert!(end.unwrap().is_none());
+    }
+}
@@ -0,0 +1,501 @@
+//! FIX 4.4 session layer
+//!
+//! `FixSession` performs no I/O: it sequences outgoing messages and consumes incoming
+//! ones, returning the replies to send and the application messages released in
+//! sequence order. Time is passed in by the caller, so heartbeat handling can be
+//! tested deterministically.
+//!
+//! Incoming messages ahead of the expected sequence number are queued and a
+//! ResendRequest is sent for the gap. Duplicates flagged with PossDupFlag are ignored.
+//! Outgoing application messages are kept for the lifetime of the session, so resend
+//! requests from the counterparty are answered with the original messages, and
+//! session-level messages in the requested range are covered by a SequenceReset gap
+//! fill. Sequence numbers are reset at every logon.
+//!
+//! The same state machine serves initiators and acceptors: a session that receives a
+//! Logon before sending one answers it.
+
+use super::message::{format_timestamp, msg_type, tags, FixMessage};
+use super::FixError;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Identity and timing of a FIX session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixSessionConfig {
+    /// Our SenderCompID
+    pub sender_comp_id: String,
+    /// Counterparty's CompID
+    pub target_comp_id: String,
+    /// Heartbeat interval, sent as whole seconds in the Logon
+    pub heartbeat_interval: Duration,
+    /// Username (553) sent in the Logon
+    pub username: Option<String>,
+    /// Password (554) sent in the Logon
+    pub password: Option<String>,
+}
+
+impl FixSessionConfig {
+    /// Create a configuration with a 30 second heartbeat
+    pub fn new(sender_comp_id: impl Into<String>, target_comp_id: impl Into<String>) -> Self {
+        Self {
+            sender_comp_id: sender_comp_id.into(),
+            target_comp_id: target_comp_id.into(),
+            heartbeat_interval: Duration::from_secs(30),
+            username: None,
+            password: None,
+        }
+    }
+
+    /// Heartbeat interval to request
+    pub fn with_heartbeat_interval(mut self, heartbeat_interval: Duration) -> Self {
+        self.heartbeat_interval = heartbeat_interval;
+        self
+    }
+
+    /// Credentials sent in the Logon
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.username = Some(username.into());
+        self.password = Some(password.into());
+        self
+    }
+}
+
+/// Lifecycle of a session
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SessionState {
+    /// No Logon exchanged yet
+    Disconnected,
+    /// Logon sent, waiting for the counterparty's
+    LogonSent,
+    /// Logged on
+    Active,
+    /// Logout sent, waiting for the counterparty's
+    LogoutSent,
+    /// Logged out
+    Closed,
+}
+
+/// Outcome of an incoming message
+#[derive(Debug, Default)]
+pub struct SessionOutput {
+    /// Messages to send, in order
+    pub replies: Vec<FixMessage>,
+    /// Application messages released in sequence order
+    pub messages: Vec<FixMessage>,
+}
+
+/// Application message kept for resends
+#[derive(Debug, Clone)]
+struct SentMessage {
+    body: FixMessage,
+    sending_time: String,
+}
+
+/// FIX session state machine
+#[derive(Debug)]
+pub struct FixSession {
+    config: FixSessionConfig,
+    state: SessionState,
+    next_outgoing: u64,
+    next_incoming: u64,
+    sent: BTreeMap<u64, SentMessage>,
+    queued: BTreeMap<u64, FixMessage>,
+    resend_pending: bool,
+    last_sent: Instant,
+    last_received: Instant,
+    test_request: Option<(String, Instant)>,
+}
+
+impl FixSession {
+    /// Create a session that has not logged on yet
+    pub fn new(config: FixSessionConfig, now: Instant) -> Self {
+        Self {
+            config,
+            state: SessionState::Disconnected,
+            next_outgoing: 1,
+            next_incoming: 1,
+            sent: BTreeMap::new(),
+            queued: BTreeMap::new(),
+            resend_pending: false,
+            last_sent: now,
+            last_received: now,
+            test_request: None,
+        }
+    }
+
+    /// Current lifecycle state
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// Sequence number of the next message we send
+    pub fn next_outgoing_seq(&self) -> u64 {
+        self.next_outgoing
+    }
+
+    /// Sequence number expected on the next incoming message
+    pub fn next_incoming_seq(&self) -> u64 {
+        self.next_incoming
+    }
+
+    /// Logon resetting both sequence numbers
+    pub fn logon(&mut self, now: Instant) -> FixMessage {
+        self.next_outgoing = 1;
+        self.next_incoming = 1;
+        self.sent.clear();
+        self.queued.clear();
+        self.state = SessionState::LogonSent;
+        let logon = self.logon_body(true);
+        self.send(logon, now)
+    }
+
+    /// Logout, optionally explaining why
+    pub fn logout(&mut self, text: Option<&str>, now: Instant) -> FixMessage {
+        self.state = SessionState::LogoutSent;
+        let mut logout = FixMessage::new(msg_type::LOGOUT);
+        if let Some(text) = text {
+            logout.push(tags::TEXT, text);
+        }
+        self.send(logout, now)
+    }
+
+    /// Add the standard header to a message body and assign it the next sequence
+    /// number; application messages are kept for resends
+    pub fn send(&mut self, body: FixMessage, now: Instant) -> FixMessage {
+        let seq = self.next_outgoing;
+        self.next_outgoing += 1;
+        self.last_sent = now;
+        let message = self.with_header(&body, seq, None);
+        if !msg_type::is_admin(body.msg_type()) {
+            let sending_time = message
+                .get(tags::SENDING_TIME)
+                .unwrap_or_default()
+                .to_string();
+            self.sent.insert(seq, SentMessage { body, sending_time });
+        }
+        message
+    }
+
+    /// Consume an incoming message
+    pub fn on_message(
+        &mut self,
+        message: FixMessage,
+        now: Instant,
+    ) -> Result<SessionOutput, FixError> {
+        self.last_received = now;
+        let sender = message.require(tags::SENDER_COMP_ID)?;
+        let target = message.require(tags::TARGET_COMP_ID)?;
+        if sender != self.config.target_comp_id || target != self.config.sender_comp_id {
+            return Err(FixError::CompIdMismatch {
+                sender: sender.to_string(),
+                target: target.to_string(),
+            });
+        }
+
+        let mut output = SessionOutput::default();
+        let seq = message.seq_num()?;
+        let kind = message.msg_type();
+        if kind == msg_type::LOGON && message.flag(tags::RESET_SEQ_NUM_FLAG) {
+            self.next_incoming = seq;
+            self.queued.clear();
+        }
+        // Reset mode ignores the sequence number of the reset itself
+        if kind == msg_type::SEQUENCE_RESET && !message.flag(tags::GAP_FILL_FLAG) {
+            self.next_incoming = self
+                .next_incoming
+                .max(message.parse_field(tags::NEW_SEQ_NO)?);
+            self.release(&mut output, now)?;
+            return Ok(output);
+        }
+
+        if seq < self.next_incoming {
+            if message.flag(tags::POSS_DUP_FLAG) {
+                return Ok(output);
+            }
+            return Err(FixError::SequenceTooLow {
+                expected: self.next_incoming,
+                received: seq,
+            });
+        }
+        if seq > self.next_incoming {
+            self.queued.insert(seq, message);
+            if !self.resend_pending {
+                self.resend_pending = true;
+                let request = FixMessage::new(msg_type::RESEND_REQUEST)
+                    .with(tags::BEGIN_SEQ_NO, self.next_incoming)
+                    .with(tags::END_SEQ_NO, 0);
+                output.replies.push(self.send(request, now));
+            }
+            return Ok(output);
+        }
+
+        self.process(message, &mut output, now)?;
+        self.release(&mut output, now)?;
+        Ok(output)
+    }
+
+    /// Heartbeats, test requests and the heartbeat timeout.
+    ///
+    /// A TestRequest is sent when nothing arrived for 1.2 heartbeat intervals, and the
+    /// session fails if nothing arrives within another interval after it.
+    pub fn on_timer(&mut self, now: Instant) -> Result<Vec<FixMessage>, FixError> {
+        if self.state != SessionState::Active {
+            return Ok(Vec::new());
+        }
+        let interval = self.config.heartbeat_interval;
+        if let Some((_, sent_at)) = &self.test_request {
+            if self.last_received > *sent_at {
+                self.test_request = None;
+            } else if now.duration_since(*sent_at) >= interval {
+                return Err(FixError::HeartbeatTimeout);
+            }
+        }
+
+        let mut messages = Vec::new();
+        if self.test_request.is_none()
+            && now.duration_since(self.last_received) >= interval + interval / 5
+        {
+            let id = format!("TEST{}", self.next_outgoing);
+            let request = FixMessage::new(msg_type::TEST_REQUEST).with(tags::TEST_REQ_ID, &id);
+            messages.push(self.send(request, now));
+            self.test_request = Some((id, now));
+        }
+        if now.duration_since(self.last_sent) >= interval {
+            messages.push(self.send(FixMessage::new(msg_type::HEARTBEAT), now));
+        }
+        Ok(messages)
+    }
+
+    /// Handle a message carrying the expected sequence number
+    fn process(
+        &mut self,
+        message: FixMessage,
+        output: &mut SessionOutput,
+        now: Instant,
+    ) -> Result<(), FixError> {
+        self.next_incoming += 1;
+        match message.msg_type() {
+            msg_type::LOGON => {
+                if self.state != SessionState::LogonSent {
+                    // Acceptor side: adopt the initiator's heartbeat and answer
+                    let seconds: u64 = message.parse_field(tags::HEART_BT_INT)?;
+                    self.config.heartbeat_interval = Duration::from_secs(seconds.max(1));
+                    let reset = message.flag(tags::RESET_SEQ_NUM_FLAG);
+                    if reset {
+                        self.next_outgoing = 1;
+                        self.sent.clear();
+                    }
+                    let logon = self.logon_body(reset);
+                    output.replies.push(self.send(logon, now));
+                }
+                self.state = SessionState::Active;
+            }
+            msg_type::HEARTBEAT => {}
+            msg_type::TEST_REQUEST => {
+                let heartbeat = FixMessage::new(msg_type::HEARTBEAT)
+                    .with(tags::TEST_REQ_ID, message.require(tags::TEST_REQ_ID)?);
+                output.replies.push(self.send(heartbeat, now));
+            }
+            msg_type::RESEND_REQUEST => {
+                let begin: u64 = message.parse_field(tags::BEGIN_SEQ_NO)?;
+                let end: u64 = message.parse_field(tags::END_SEQ_NO)?;
+                output.replies.extend(self.resend(begin, end));
+            }
+            msg_type::REJECT => {
+                warn!(
+                    ref_seq = message.get(tags::REF_SEQ_NUM),
+                    text = message.get(tags::TEXT),
+                    "FIX session reject"
+                );
+            }
+            msg_type::SEQUENCE_RESET => {
+                let new_seq: u64 = message.parse_field(tags::NEW_SEQ_NO)?;
+                self.next_incoming = self.next_incoming.max(new_seq);
+            }
+            msg_type::LOGOUT => {
+                if self.state != SessionState::LogoutSent {
+                    let logout = FixMessage::new(msg_type::LOGOUT);
+                    output.replies.push(self.send(logout, now));
+                }
+                self.state = SessionState::Closed;
+            }
+            _ => output.messages.push(message),
+        }
+        Ok(())
+    }
+
+    /// Process queued messages that are now in sequence
+    fn release(&mut self, output: &mut SessionOutput, now: Instant) -> Result<(), FixError> {
+        loop {
+            // Messages covered by a gap fill are dropped
+            while let Some(entry) = self.queued.first_entry() {
+                if *entry.key() >= self.next_incoming {
+                    break;
+                }
+                entry.remove();
+            }
+            match self.queued.remove(&self.next_incoming) {
+                Some(message) => self.process(message, output, now)?,
+                None => break,
+            }
+        }
+        if self.queued.is_empty() {
+            self.resend_pending = false;
+        }
+        Ok(())
+    }
+
+    /// Answer a ResendRequest for `begin..=end`, where an `end` of 0 means up to the
+    /// last message sent
+    fn resend(&self, begin: u64, end: u64) -> Vec<FixMessage> {
+        let last = self.next_outgoing - 1;
+        let end = if end == 0 { last } else { end.min(last) };
+        let mut messages = Vec::new();
+        let mut gap_start = None;
+        for seq in begin..=end {
+            match self.sent.get(&seq) {
+                Some(sent) => {
+                    if let Some(start) = gap_start.take() {
+                        messages.push(self.gap_fill(start, seq));
+                    }
+                    messages.push(self.with_header(&sent.body, seq, Some(&sent.sending_time)));
+                }
+                None => {
+                    gap_start.get_or_insert(seq);
+                }
+            }
+        }
+        if let Some(start) = gap_start {
+            messages.push(self.gap_fill(start, end + 1));
+        }
+        messages
+    }
+
+    /// SequenceReset skipping session messages from `seq` up to `new_seq`
+    fn gap_fill(&self, seq: u64, new_seq: u64) -> FixMessage {
+        let body = FixMessage::new(msg_type::SEQUENCE_RESET)
+            .with(tags::GAP_FILL_FLAG, "Y")
+            .with(tags::NEW_SEQ_NO, new_seq);
+        self.with_header(&body, seq, Some(""))
+    }
+
+    /// Standard header followed by the body's fields; a resend carries PossDupFlag and
+    /// the original sending time, if known
+    fn with_header(&self, body: &FixMessage, seq: u64, resend: Option<&str>) -> FixMessage {
+        let mut message = FixMessage::new(body.msg_type())
+            .with(tags::SENDER_COMP_ID, &self.config.sender_comp_id)
+            .with(tags::TARGET_COMP_ID, &self.config.target_comp_id)
+            .with(tags::MSG_SEQ_NUM, seq);
+        if resend.is_some() {
+            message.push(tags::POSS_DUP_FLAG, "Y");
+        }
+        message.push(tags::SENDING_TIME, format_timestamp(Utc::now()));
+        if let Some(original) = resend.filter(|original| !original.is_empty()) {
+            message.push(tags::ORIG_SENDING_TIME, original);
+        }
+        for (tag, value) in &body.fields()[1..] {
+            message.push(*tag, value);
+        }
+        message
+    }
+
+    fn logon_body(&self, reset: bool) -> FixMessage {
+        let mut logon = FixMessage::new(msg_type::LOGON)
+            .with(tags::ENCRYPT_METHOD, 0)
+            .with(
+                tags::HEART_BT_INT,
+                self.config.heartbeat_interval.as_secs().max(1),
+            );
+        if reset {
+            logon.push(tags::RESET_SEQ_NUM_FLAG, "Y");
+        }
+        if let Some(username) = &self.config.username {
+            logon.push(tags::USERNAME, username);
+        }
+        if let Some(password) = &self.config.password {
+            logon.push(tags::PASSWORD, password);
+        }
+        logon
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deliver every message from one session to the other, returning the replies
+    fn deliver(to: &mut FixSession, messages: Vec<FixMessage>, now: Instant) -> SessionOutput {
+        let mut output = SessionOutput::default();
+        for message in messages {
+            let next = to.on_message(message, now).unwrap();
+            output.replies.extend(next.replies);
+            output.messages.extend(next.messages);
+        }
+        output
+    }
+
+    #[test]
+    fn test_logon_heartbeats_and_gap_fill() {
+        let start = Instant::now();
+        let secs = |secs: f64| start + Duration::from_secs_f64(secs);
+        let config = FixSessionConfig::new("CLIENT", "VENUE")
+            .with_heartbeat_interval(Duration::from_secs(10))
+            .with_credentials("user", "secret");
+        let mut client = FixSession::new(config, start);
+        let mut venue = FixSession::new(FixSessionConfig::new("VENUE", "CLIENT"), start);
+
+        let logon = client.logon(start);
+        assert_eq!(logon.get(tags::USERNAME), Some("user"));
+        let reply = deliver(&mut venue, vec![logon], start);
+        assert_eq!(venue.state(), SessionState::Active);
+        deliver(&mut client, reply.replies, start);
+        assert_eq!(client.state(), SessionState::Active);
+
+        // Heartbeat after an idle interval, TestRequest once the venue is 1.2 intervals quiet
+        let sent = client.on_timer(secs(10.0)).unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].msg_type(), msg_type::HEARTBEAT);
+        deliver(&mut venue, sent, secs(10.0));
+        let sent = client.on_timer(secs(12.0)).unwrap();
+        assert_eq!(sent[0].msg_type(), msg_type::TEST_REQUEST);
+        let reply = deliver(&mut venue, sent, secs(12.0));
+        assert_eq!(reply.replies[0].get(tags::TEST_REQ_ID), Some("TEST3"));
+        deliver(&mut client, reply.replies, secs(12.5));
+        assert!(client.on_timer(secs(20.0)).unwrap().is_empty());
+
+        // The venue's heartbeat (seq 3) is lost and its market data (seq 4) arrives early
+        let _lost = venue.send(FixMessage::new(msg_type::HEARTBEAT), secs(21.0));
+        let data = venue.send(
+            FixMessage::new(msg_type::MARKET_DATA_INCREMENTAL).with(tags::MD_REQ_ID, "1"),
+            secs(21.0),
+        );
+        let output = deliver(&mut client, vec![data], secs(21.0));
+        assert!(output.messages.is_empty());
+        assert_eq!(output.replies[0].msg_type(), msg_type::RESEND_REQUEST);
+        assert_eq!(output.replies[0].get(tags::BEGIN_SEQ_NO), Some("3"));
+
+        // The venue gap-fills the heartbeat and resends the market data
+        let resent = deliver(&mut venue, output.replies, secs(21.0)).replies;
+        assert_eq!(resent[0].msg_type(), msg_type::SEQUENCE_RESET);
+        assert_eq!(resent[0].get(tags::NEW_SEQ_NO), Some("4"));
+        assert!(resent[1].flag(tags::POSS_DUP_FLAG));
+        let output = deliver(&mut client, resent, secs(21.0));
+        assert_eq!(output.messages.len(), 1);
+        assert_eq!(output.messages[0].get(tags::MD_REQ_ID), Some("1"));
+        assert_eq!(client.next_incoming_seq(), 5);
+
+        // Silence after a TestRequest fails the session
+        client.on_timer(secs(40.0)).unwrap();
+        assert!(matches!(
+            client.on_timer(secs(50.0)),
+            Err(FixError::HeartbeatTimeout)
+        ));
+    }
+}
@@ -0,0 +1,458 @@
+//! Fixed-point prices and quantities
+//!
+//! `Decimal` is a 128-bit type with software arithmetic, which is costly for work done
+//! on every tick. `Price` and `Qty` instead hold an `i64` count of ticks and lots of
+//! one instrument, so comparisons and arithmetic are plain integer operations.
+//! Arithmetic is checked and returns `None` on overflow.
+//!
+//! Values are only meaningful together with the instrument's `InstrumentScale`, which
+//! converts to and from `Decimal` at the edges of the system: when market data is
+//! normalized and when orders are sent. `PublicTrade`, `OrderBookL1`, `PriceLevel` and
+//! `OrderRequest` are generic over their price and quantity types and gain
+//! `to_fixed`/`to_decimal` conversions here. `OrderBook` keeps its levels in fixed
+//! point, and `DefaultRiskManager` checks orders in fixed point for instruments
+//! registered with a scale.
+
+use super::{OrderBookL1, PriceLevel, PublicTrade};
+use crate::execution::OrderRequest;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Errors produced when converting `Decimal` values to fixed point
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum FixedPointError {
+    /// Price that is not a multiple of the tick size
+    #[error("price {price} is not a multiple of tick size {tick_size}")]
+    OffTick { price: Decimal, tick_size: Decimal },
+    /// Quantity that is not a multiple of the lot size
+    #[error("quantity {quantity} is not a multiple of lot size {lot_size}")]
+    OffLot {
+        quantity: Decimal,
+        lot_size: Decimal,
+    },
+    /// Value whose tick or lot count does not fit in an `i64`
+    #[error("{0} is out of fixed-point range")]
+    Overflow(Decimal),
+    /// Scale with a zero or negative tick or lot size
+    #[error("tick size {tick_size} and lot size {lot_size} must be positive")]
+    NonPositiveScale {
+        tick_size: Decimal,
+        lot_size: Decimal,
+    },
+    /// Scale whose tick-lot notional is finer than a `Decimal` can represent
+    #[error("tick size {tick_size} times lot size {lot_size} is finer than a decimal can hold")]
+    ScaleTooFine {
+        tick_size: Decimal,
+        lot_size: Decimal,
+    },
+}
+
+/// Price as a whole number of ticks
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Deserialize, Serialize,
+)]
+#[serde(transparent)]
+pub struct Price(i64);
+
+impl Price {
+    /// Zero price
+    pub const ZERO: Price = Price(0);
+
+    /// Price of `ticks` ticks
+    pub const fn from_ticks(ticks: i64) -> Self {
+        Price(ticks)
+    }
+
+    /// Number of ticks
+    pub const fn ticks(self) -> i64 {
+        self.0
+    }
+
+    /// Add two prices
+    pub fn checked_add(self, other: Price) -> Option<Price> {
+        self.0.checked_add(other.0).map(Price)
+    }
+
+    /// Subtract a price, e.g. to get a spread
+    pub fn checked_sub(self, other: Price) -> Option<Price> {
+        self.0.checked_sub(other.0).map(Price)
+    }
+
+    /// Move the price by a signed number of ticks
+    pub fn checked_add_ticks(self, ticks: i64) -> Option<Price> {
+        self.0.checked_add(ticks).map(Price)
+    }
+
+    /// Notional of `quantity` at this price in tick-lot units; cannot overflow
+    pub fn notional(self, quantity: Qty) -> i128 {
+        self.0 as i128 * quantity.0 as i128
+    }
+}
+
+/// Quantity as a whole number of lots
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Deserialize, Serialize,
+)]
+#[serde(transparent)]
+pub struct Qty(i64);
+
+impl Qty {
+    /// Zero quantity
+    pub const ZERO: Qty = Qty(0);
+
+    /// Quantity of `lots` lots
+    pub const fn from_lots(lots: i64) -> Self {
+        Qty(lots)
+    }
+
+    /// Number of lots
+    pub const fn lots(self) -> i64 {
+        self.0
+    }
+
+    /// Whether the quantity is zero
+    pub const fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Add two quantities
+    pub fn checked_add(self, other: Qty) -> Option<Qty> {
+        self.0.checked_add(other.0).map(Qty)
+    }
+
+    /// Subtract a quantity
+    pub fn checked_sub(self, other: Qty) -> Option<Qty> {
+        self.0.checked_sub(other.0).map(Qty)
+    }
+
+    /// Multiply by an integer factor
+    pub fn checked_mul(self, factor: i64) -> Option<Qty> {
+        self.0.checked_mul(factor).map(Qty)
+    }
+}
+
+/// Tick and lot size of an instrument, converting between `Decimal` and fixed point
+///
+/// Both sizes are positive; deserializing a scale checks this like `new` does.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "ScaleSizes")]
+pub struct InstrumentScale {
+    tick_size: Decimal,
+    lot_size: Decimal,
+}
+
+/// Unchecked form of `InstrumentScale` that deserialization goes through
+#[derive(Deserialize)]
+struct ScaleSizes {
+    tick_size: Decimal,
+    lot_size: Decimal,
+}
+
+impl TryFrom<ScaleSizes> for InstrumentScale {
+    type Error = FixedPointError;
+
+    fn try_from(sizes: ScaleSizes) -> Result<Self, Self::Error> {
+        Self::new(sizes.tick_size, sizes.lot_size)
+    }
+}
+
+impl InstrumentScale {
+    /// Create a scale; fails unless both sizes are positive
+    pub fn new(tick_size: Decimal, lot_size: Decimal) -> Result<Self, FixedPointError> {
+        if tick_size <= Decimal::ZERO || lot_size <= Decimal::ZERO {
+            return Err(FixedPointError::NonPositiveScale {
+                tick_size,
+                lot_size,
+            });
+        }
+        Ok(Self {
+            tick_size,
+            lot_size,
+        })
+    }
+
+    /// Minimum price increment
+    pub fn tick_size(&self) -> Decimal {
+        self.tick_size
+    }
+
+    /// Minimum quantity increment
+    pub fn lot_size(&self) -> Decimal {
+        self.lot_size
+    }
+
+    /// Convert a price that lies on the tick grid
+    pub fn price(&self, price: Decimal) -> Result<Price, FixedPointError> {
+        let ticks = price / self.tick_size;
+        if !ticks.fract().is_zero() {
+            return Err(FixedPointError::OffTick {
+                price,
+                tick_size: self.tick_size,
+            });
+        }
+        ticks
+            .to_i64()
+            .map(Price)
+            .ok_or(FixedPointError::Overflow(price))
+    }
+
+    /// Convert a quantity that lies on the lot grid
+    pub fn qty(&self, quantity: Decimal) -> Result<Qty, FixedPointError> {
+        let lots = quantity / self.lot_size;
+        if !lots.fract().is_zero() {
+            return Err(FixedPointError::OffLot {
+                quantity,
+                lot_size: self.lot_size,
+            });
+        }
+        lots.to_i64()
+            .map(Qty)
+            .ok_or(FixedPointError::Overflow(quantity))
+    }
+
+    /// Decimal value of a price
+    pub fn price_to_decimal(&self, price: Price) -> Decimal {
+        Decimal::from(price.0) * self.tick_size
+    }
+
+    /// Decimal value of a quantity
+    pub fn qty_to_decimal(&self, quantity: Qty) -> Decimal {
+        Decimal::from(quantity.0) * self.lot_size
+    }
+
+    /// Decimal value of a notional from `Price::notional`
+    pub fn notional_to_decimal(&self, notional: i128) -> Decimal {
+        Decimal::from_i128_with_scale(notional, 0) * self.tick_size * self.lot_size
+    }
+}
+
+impl PublicTrade {
+    /// Fixed-point form of the trade
+    pub fn to_fixed(
+        &self,
+        scale: &InstrumentScale,
+    ) -> Result<PublicTrade<Price, Qty>, FixedPointError> {
+        Ok(PublicTrade {
+            id: self.id.clone(),
+            price: scale.price(self.price)?,
+            quantity: scale.qty(self.quantity)?,
+            side: self.side,
+            timestamp: self.timestamp,
+        })
+    }
+}
+
+impl PublicTrade<Price, Qty> {
+    /// Decimal form of the trade
+    pub fn to_decimal(&self, scale: &InstrumentScale) -> PublicTrade {
+        PublicTrade {
+            id: self.id.clone(),
+            price: scale.price_to_decimal(self.price),
+            quantity: scale.qty_to_decimal(self.quantity),
+            side: self.side,
+            timestamp: self.timestamp,
+        }
+    }
+}
+
+impl OrderBookL1 {
+    /// Fixed-point form of the quote
+    pub fn to_fixed(
+        &self,
+        scale: &InstrumentScale,
+    ) -> Result<OrderBookL1<Price, Qty>, FixedPointError> {
+        Ok(OrderBookL1 {
+            bid_price: scale.price(self.bid_price)?,
+            bid_quantity: scale.qty(self.bid_quantity)?,
+            ask_price: scale.price(self.ask_price)?,
+            ask_quantity: scale.qty(self.ask_quantity)?,
+            timestamp: self.timestamp,
+            update_id: self.update_id,
+        })
+    }
+}
+
+impl OrderBookL1<Price, Qty> {
+    /// Decimal form of the quote
+    pub fn to_decimal(&self, scale: &InstrumentScale) -> OrderBookL1 {
+        OrderBookL1 {
+            bid_price: scale.price_to_decimal(self.bid_price),
+            bid_quantity: scale.qty_to_decimal(self.bid_quantity),
+            ask_price: scale.price_to_decimal(self.ask_price),
+            ask_quantity: scale.qty_to_decimal(self.ask_quantity),
+            timestamp: self.timestamp,
+            update_id: self.update_id,
+        }
+    }
+}
+
+impl PriceLevel {
+    /// Fixed-point form of the level
+    pub fn to_fixed(
+        &self,
+        scale: &InstrumentScale,
+    ) -> Result<PriceLevel<Price, Qty>, FixedPointError> {
+        Ok(PriceLevel {
+            price: scale.price(self.price)?,
+            quantity: scale.qty(self.quantity)?,
+        })
+    }
+}
+
+impl PriceLevel<Price, Qty> {
+    /// Decimal form of the level
+    pub fn to_decimal(&self, scale: &InstrumentScale) -> PriceLevel {
+        PriceLevel {
+            price: scale.price_to_decimal(self.price),
+            quantity: scale.qty_to_decimal(self.quantity),
+        }
+    }
+}
+
+impl OrderRequest {
+    /// Fixed-point form of the order
+    pub fn to_fixed(
+        &self,
+        scale: &InstrumentScale,
+    ) -> Result<OrderRequest<Price, Qty>, FixedPointError> {
+        Ok(OrderRequest {
+            client_order_id: self.client_order_id.clone(),
+            instrument: self.instrument.clone(),
+            side: self.side,
+            order_type: self.order_type,
+            quantity: scale.qty(self.quantity)?,
+            price: self.price.map(|price| scale.price(price)).transpose()?,
+            stop_price: self
+                .stop_price
+                .map(|price| scale.price(price))
+                .transpose()?,
+            time_in_force: self.time_in_force,
+            created_at: self.created_at,
+        })
+    }
+}
+
+impl OrderRequest<Price, Qty> {
+    /// Decimal form of the order, e.g. for sending to an exchange
+    pub fn to_decimal(&self, scale: &InstrumentScale) -> OrderRequest {
+        OrderRequest {
+            client_order_id: self.client_order_id.clone(),
+            instrument: self.instrument.clone(),
+            side: self.side,
+            order_type: self.order_type,
+            quantity: scale.qty_to_decimal(self.quantity),
+            price: self.price.map(|price| scale.price_to_decimal(price)),
+            stop_price: self.stop_price.map(|price| scale.price_to_decimal(price)),
+            time_in_force: self.time_in_force,
+            created_at: self.created_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{InstrumentId, InstrumentKind, Side};
+    use crate::execution::{OrderType, TimeInForce};
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_round_trip_and_grid_errors() {
+        let scale = InstrumentScale::new(dec!(0.01), dec!(0.00001)).unwrap();
+        let quote = OrderBookL1 {
+            bid_price: dec!(67890.12),
+            bid_quantity: dec!(1.5),
+            ask_price: dec!(67890.13),
+            ask_quantity: dec!(0.00025),
+            timestamp: Utc::now(),
+            update_id: Some(7),
+        };
+        let fixed = quote.to_fixed(&scale).unwrap();
+        assert_eq!(fixed.bid_price.ticks(), 6_789_012);
+        assert_eq!(fixed.ask_quantity.lots(), 25);
+        assert_eq!(
+            fixed.ask_price.checked_sub(fixed.bid_price),
+            Some(Price::from_ticks(1))
+        );
+        assert_eq!(fixed.to_decimal(&scale), quote);
+
+        let order = OrderRequest {
+            client_order_id: "1".to_string(),
+            instrument: InstrumentId {
+                base: "BTC".to_string(),
+                quote: "USDT".to_string(),
+                exchange_symbol: "BTCUSDT".to_string(),
+                kind: InstrumentKind::Spot,
+            },
+            side: Side::Buy,
+            order_type: OrderType::Limit,
+            quantity: dec!(0.01),
+            price: Some(dec!(67890.10)),
+            stop_price: None,
+            time_in_force: TimeInForce::GTC,
+            created_at: Utc::now(),
+        };
+        let fixed = order.to_fixed(&scale).unwrap();
+        let notional = fixed.price.unwrap().notional(fixed.quantity);
+        assert_eq!(scale.notional_to_decimal(notional), dec!(678.901));
+        assert_eq!(fixed.to_decimal(&scale), order);
+
+        assert_eq!(
+            scale.price(dec!(1.005)),
+            Err(FixedPointError::OffTick {
+                price: dec!(1.005),
+                tick_size: dec!(0.01)
+            })
+        );
+        assert!(matches!(
+            scale.qty(dec!(0.000001)),
+            Err(FixedPointError::OffLot { .. })
+        ));
+        assert!(matches!(
+            scale.price(dec!(1e20)),
+            Err(FixedPointError::Overflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let price = Price::from_ticks(i64::MAX - 1);
+        assert_eq!(
+            price.checked_add_ticks(1),
+            Some(Price::from_ticks(i64::MAX))
+        );
+        assert_eq!(price.checked_add(Price::from_ticks(2)), None);
+        assert_eq!(
+            Qty::from_lots(i64::MIN).checked_sub(Qty::from_lots(1)),
+            None
+        );
+        assert_eq!(Qty::from_lots(3).checked_mul(4), Some(Qty::from_lots(12)));
+        assert_eq!(
+            Price::from_ticks(i64::MAX).notional(Qty::from_lots(2)),
+            i64::MAX as i128 * 2
+        );
+    }
+
+    #[test]
+    fn test_non_positive_scale_is_rejected() {
+        assert!(matches!(
+            InstrumentScale::new(Decimal::ZERO, dec!(1)),
+            Err(FixedPointError::NonPositiveScale { .. })
+        ));
+        assert!(InstrumentScale::new(dec!(0.01), dec!(-1)).is_err());
+
+        // Deserializing goes through the same check
+        let scale: InstrumentScale =
+            serde_json::from_str(r#"{"tick_size":"0.01","lot_size":"0.001"}"#).unwrap();
+        assert_eq!(scale.tick_size(), dec!(0.01));
+        assert_eq!(
+            serde_json::to_string(&scale).unwrap(),
+            r#"{"tick_size":"0.01","lot_size":"0.001"}"#
+        );
+        assert!(
+            serde_json::from_str::<InstrumentScale>(r#"{"tick_size":"0","lot_size":"1"}"#).is_err()
+        );
+    }
+}
@@ -0,0 +1,365 @@
+//! Raw WebSocket frame capture
+//!
+//! A frame capture holds the text and binary frames a connection received, with their
+//! receipt times, exactly as they arrived and before any decoding:
+//!
+//! ```text
+//! file header  magic "MDRAWv1\0"
+//! record       receipt_nanos i64 | kind u8 (0 text, 1 binary) | len u32 | payload
+//! ```
+//!
+//! All integers are little endian. Captures are appended to, so reconnects keep
+//! writing to the same file. An incomplete final record left by a crash is ignored
+//! by the reader and cut off when the capture is reopened for appending.
+//!
+//! `replay_frames` feeds a capture through an `ExchangeConnector` with the original
+//! receipt times, reproducing the events and decode errors of the live connection.
+
+use super::connector::ExchangeConnector;
+use super::MarketEvent;
+use chrono::{DateTime, Utc};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 8] = b"MDRAWv1\0";
+const RECORD_HEADER_LEN: usize = 13;
+
+/// Kind of a WebSocket data frame
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FrameKind {
+    Text,
+    Binary,
+}
+
+/// Frame as received from the venue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawFrame {
+    pub receipt_time: DateTime<Utc>,
+    pub kind: FrameKind,
+    pub payload: Vec<u8>,
+}
+
+impl RawFrame {
+    /// Payload of a text frame
+    pub fn text(&self) -> Option<&str> {
+        match self.kind {
+            FrameKind::Text => std::str::from_utf8(&self.payload).ok(),
+            FrameKind::Binary => None,
+        }
+    }
+}
+
+/// Appends received frames to a capture file
+pub struct FrameCaptureWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl FrameCaptureWriter {
+    /// Open a capture for appending, creating it if it does not exist.
+    ///
+    /// A torn final record is truncated so new frames follow the last complete one.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let len = file.metadata()?.len();
+        if len == 0 {
+            file.write_all(MAGIC)?;
+        } else {
+            read_magic(&mut file)?;
+            let end = complete_len(&mut file, len)?;
+            if end < len {
+                file.set_len(end)?;
+            }
+        }
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+        })
+    }
+
+    /// Path of the capture file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append a frame
+    pub fn write(
+        &mut self,
+        receipt_time: DateTime<Utc>,
+        kind: FrameKind,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        let len = u32::try_from(payload.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too large"))?;
+        let nanos = receipt_time.timestamp_nanos_opt().unwrap_or(i64::MAX);
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        header[0..8].copy_from_slice(&nanos.to_le_bytes());
+        header[8] = match kind {
+            FrameKind::Text => 0,
+            FrameKind::Binary => 1,
+        };
+        header[9..13].copy_from_slice(&len.to_le_bytes());
+        self.writer.write_all(&header)?;
+        self.writer.write_all(payload)
+    }
+
+    /// Flush buffered frames to disk
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads the frames of a capture file in order
+pub struct FrameCaptureReader {
+    reader: BufReader<File>,
+}
+
+impl FrameCaptureReader {
+    /// Open a capture file
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        read_magic(&mut file)?;
+        Ok(Self {
+            reader: BufReader::new(file),
+        })
+    }
+
+    /// Next frame, or `None` at the end of the capture
+    pub fn next_frame(&mut self) -> io::Result<Option<RawFrame>> {
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        if !read_full(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+        let nanos = i64::from_le_bytes(header[0..8].try_into().unwrap());
+        let kind = match header[8] {
+            0 => FrameKind::Text,
+            1 => FrameKind::Binary,
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown frame kind {}", other),
+                ))
+            }
+        };
+        let len = u32::from_le_bytes(header[9..13].try_into().unwrap()) as usize;
+        // Grow with the bytes actually present rather than trusting a torn length
+        let mut payload = Vec::new();
+        (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut payload)?;
+        if payload.len() < len {
+            return Ok(None);
+        }
+        Ok(Some(RawFrame {
+            receipt_time: DateTime::from_timestamp_nanos(nanos),
+            kind,
+            payload,
+        }))
+    }
+}
+
+impl Iterator for FrameCaptureReader {
+    type Item = io::Result<RawFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+fn read_magic(file: &mut File) -> io::Result<()> {
+    let mut magic = [0u8; 8];
+    file.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a frame capture file",
+        ));
+    }
+    Ok(())
+}
+
+/// Length of the capture up to the end of its last complete record
+fn complete_len(file: &mut File, len: u64) -> io::Result<u64> {
+    let mut offset = MAGIC.len() as u64;
+    let mut header = [0u8; RECORD_HEADER_LEN];
+    loop {
+        file.seek(SeekFrom::Start(offset))?;
+        if !read_full(file, &mut header)? {
+            return Ok(offset);
+        }
+        let payload_len = u32::from_le_bytes(header[9..13].try_into().unwrap());
+        let end = offset + RECORD_HEADER_LEN as u64 + u64::from(payload_len);
+        if end > len {
+            return Ok(offset);
+        }
+        offset = end;
+    }
+}
+
+/// Fill `buf`, returning `false` if the input ends first
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Frame a connector failed to decode during a replay
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameFailure {
+    /// Position of the frame in the capture, starting at 0
+    pub index: usize,
+    pub receipt_time: DateTime<Utc>,
+    pub frame: String,
+    pub error: String,
+}
+
+/// Outcome of replaying a capture through a connector
+#[derive(Debug, Clone, Default)]
+pub struct FrameReplay {
+    /// Frames read from the capture
+    pub frames: usize,
+    /// Binary frames, which connectors do not decode
+    pub binary_frames: usize,
+    /// Events decoded, in capture order
+    pub events: Vec<MarketEvent>,
+    /// Frames that failed to decode
+    pub failures: Vec<FrameFailure>,
+}
+
+/// Decode captured frames with `connector` as the live connection did
+pub fn replay_frames<C: ExchangeConnector>(
+    connector: &mut C,
+    frames: impl IntoIterator<Item = io::Result<RawFrame>>,
+) -> io::Result<FrameReplay> {
+    let mut replay = FrameReplay::default();
+    for (index, frame) in frames.into_iter().enumerate() {
+        let frame = frame?;
+        replay.frames += 1;
+        let Some(text) = frame.text() else {
+            replay.binary_frames += 1;
+            continue;
+        };
+        match connector.decode(text, frame.receipt_time) {
+            Ok(events) => replay.events.extend(events),
+            Err(error) => replay.failures.push(FrameFailure {
+                index,
+                receipt_time: frame.receipt_time,
+                frame: text.to_string(),
+                error: error.to_string(),
+            }),
+        }
+    }
+    Ok(replay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::binance::BinanceConnector;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_capture_round_trip_and_replay() {
+        let path =
+            std::env::temp_dir().join(format!("frames-round-trip-{}.raw", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let at = |millis: i64| {
+            Utc.timestamp_millis_opt(1_710_770_531_000 + millis)
+                .unwrap()
+        };
+        let trade = r#"{"stream":"btcusdt@trade","data":{"e":"trade","E":1710770531005,"s":"BTCUSDT","t":1,"p":"67890.12","q":"0.01","T":1710770531004,"m":true}}"#;
+
+        let mut writer = FrameCaptureWriter::open(&path).unwrap();
+        writer
+            .write(at(0), FrameKind::Text, br#"{"result":null,"id":1}"#)
+            .unwrap();
+        writer
+            .write(at(1), FrameKind::Binary, &[0, 159, 255])
+            .unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        // Reopening appends after the existing frames
+        let mut writer = FrameCaptureWriter::open(&path).unwrap();
+        writer
+            .write(at(2), FrameKind::Text, br#"{"unexpected":true}"#)
+            .unwrap();
+        writer
+            .write(at(3), FrameKind::Text, trade.as_bytes())
+            .unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        // A torn final record is dropped
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[1, 2, 3]).unwrap();
+        drop(file);
+
+        let frames: Vec<RawFrame> = FrameCaptureReader::open(&path)
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[1].kind, FrameKind::Binary);
+        assert_eq!(frames[1].payload, [0, 159, 255]);
+        assert_eq!(frames[3].receipt_time, at(3));
+
+        let replay = replay_frames(
+            &mut BinanceConnector::new(),
+            FrameCaptureReader::open(&path).unwrap(),
+        )
+        .unwrap();
+        assert_eq!((replay.frames, replay.binary_frames), (4, 1));
+        assert_eq!(replay.events.len(), 1);
+        assert_eq!(replay.events[0].receipt_time, at(3));
+        assert_eq!(replay.failures.len(), 1);
+        assert_eq!(replay.failures[0].index, 2);
+        assert_eq!(replay.failures[0].frame, r#"{"unexpected":true}"#);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_append_after_torn_record() {
+        let path = std::env::temp_dir().join(format!("frames-torn-{}.raw", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let at = |millis: i64| {
+            Utc.timestamp_millis_opt(1_710_770_531_000 + millis)
+                .unwrap()
+        };
+
+        let mut writer = FrameCaptureWriter::open(&path).unwrap();
+        writer.write(at(0), FrameKind::Text, b"first").unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        // A crash mid-record leaves a header whose length runs past the end of the file
+        let mut torn = [0u8; RECORD_HEADER_LEN + 2];
+        torn[9..13].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&torn).unwrap();
+        drop(file);
+
+        // The next session resumes after the last complete record
+        let mut writer = FrameCaptureWriter::open(&path).unwrap();
+        writer.write(at(1), FrameKind::Text, b"second").unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        let frames: Vec<RawFrame> = FrameCaptureReader::open(&path)
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        let payloads: Vec<&[u8]> = frames
+            .iter()
+            .map(|frame| frame.payload.as_slice())
+            .collect();
+        assert_eq!(payloads, [&b"first"[..], &b"second"[..]]);
+        assert_eq!(frames[1].receipt_time, at(1));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
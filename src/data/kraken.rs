@@ -0,0 +1,727 @@
+//! Kraken WebSocket v2 market data
+//!
+//! Subscribes to the `trade` and `book` channels of the Kraken v2 public feed and
+//! normalizes them into `MarketEvent`s. Book updates are applied to a local book per
+//! symbol and validated against the CRC32 checksum Kraken sends with every update;
+//! a mismatch discards the book and `KrakenConnector::recover` resubscribes to it for
+//! a fresh snapshot. `KrakenMarketDataStream` runs the connector over the shared
+//! WebSocket transport.
+//!
+//! `from_config` maps `SubscriptionKind::Trades` to `trade` and `SubscriptionKind::L2`
+//! to `book` at the smallest Kraken depth covering every requested depth; other kinds
+//! are rejected. Kraken has no update rate setting, so `update_frequency_ms` is left to
+//! `ConflatedMarketDataStream::from_config`.
+
+use super::connector::{ExchangeConnector, Heartbeat, WebSocketMarketDataStream};
+use super::{
+    book::LevelBook, ExchangeId, InstrumentId, InstrumentKind, MarketDataKind, MarketEvent,
+    PublicTrade, Side, SubscriptionKind,
+};
+use crate::config::DataConfig;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Default Kraken v2 public WebSocket endpoint
+pub const KRAKEN_WS_URL: &str = "wss://ws.kraken.com/v2";
+
+/// Default book depth, the smallest Kraken accepts
+pub const KRAKEN_DEFAULT_DEPTH: usize = 10;
+
+/// Book depths Kraken accepts
+const KRAKEN_DEPTHS: [usize; 5] = [10, 25, 100, 500, 1000];
+
+/// Number of levels per side covered by the book checksum
+const CHECKSUM_DEPTH: usize = 10;
+
+/// Errors produced while normalizing Kraken messages
+#[derive(Debug, thiserror::Error)]
+pub enum KrakenError {
+    /// Message was not valid JSON or did not match the expected schema
+    #[error("invalid Kraken message: {0}")]
+    Json(#[from] serde_json::Error),
+    /// A price or quantity could not be represented as a `Decimal`
+    #[error("invalid Kraken number: {0}")]
+    Decimal(#[from] rust_decimal::Error),
+    /// The local book disagrees with the exchange and has been discarded
+    #[error(
+        "Kraken book checksum mismatch for {symbol}: expected {expected}, computed {computed}"
+    )]
+    ChecksumMismatch {
+        symbol: String,
+        expected: u32,
+        computed: u32,
+    },
+    /// Subscription without a Kraken channel equivalent
+    #[error("unsupported Kraken subscription: {0:?}")]
+    UnsupportedSubscription(SubscriptionKind),
+}
+
+/// Kraken channels that can be subscribed per symbol
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum KrakenChannel {
+    /// Public trades (`trade`)
+    Trade,
+    /// Book at the connector's depth, published as `OrderBookL2` (`book`)
+    Book,
+}
+
+impl KrakenChannel {
+    /// Channel name used in subscription messages
+    pub fn name(&self) -> &'static str {
+        match self {
+            KrakenChannel::Trade => "trade",
+            KrakenChannel::Book => "book",
+        }
+    }
+}
+
+/// Kraken v2 real-time market data stream
+pub type KrakenMarketDataStream = WebSocketMarketDataStream<KrakenConnector>;
+
+impl WebSocketMarketDataStream<KrakenConnector> {
+    /// Create a new Kraken market data stream
+    pub fn new() -> Self {
+        Self::from_connector(KrakenConnector::new())
+    }
+}
+
+/// Kraken v2 `ExchangeConnector`
+#[derive(Debug, Clone)]
+pub struct KrakenConnector {
+    url: String,
+    channels: Vec<KrakenChannel>,
+    /// Overrides of `channels` by symbol
+    instrument_channels: HashMap<String, Vec<KrakenChannel>>,
+    parser: KrakenParser,
+}
+
+impl KrakenConnector {
+    /// Create a connector for the public endpoint subscribing to trades and the book at
+    /// the default depth
+    pub fn new() -> Self {
+        Self {
+            url: KRAKEN_WS_URL.to_string(),
+            channels: vec![KrakenChannel::Trade, KrakenChannel::Book],
+            instrument_channels: HashMap::new(),
+            parser: KrakenParser::new(KRAKEN_DEFAULT_DEPTH, HashMap::new()),
+        }
+    }
+
+    /// Create a connector subscribing to the market data listed in the configuration,
+    /// including per-instrument overrides
+    pub fn from_config(config: &DataConfig) -> Result<Self, KrakenError> {
+        let mut depth = KRAKEN_DEFAULT_DEPTH;
+        let mut channels = |kinds: &[SubscriptionKind]| {
+            let mut channels = Vec::new();
+            for kind in kinds {
+                let channel = match *kind {
+                    SubscriptionKind::Trades => KrakenChannel::Trade,
+                    SubscriptionKind::L2 { depth: levels } => {
+                        let levels = KRAKEN_DEPTHS
+                            .into_iter()
+                            .find(|supported| *supported >= levels)
+                            .ok_or(KrakenError::UnsupportedSubscription(*kind))?;
+                        depth = depth.max(levels);
+                        KrakenChannel::Book
+                    }
+                    other => return Err(KrakenError::UnsupportedSubscription(other)),
+                };
+                if !channels.contains(&channel) {
+                    channels.push(channel);
+                }
+            }
+            Ok(channels)
+        };
+        let mut connector = Self::new().with_channels(channels(&config.subscriptions)?);
+        for subscription in &config.instrument_subscriptions {
+            connector = connector
+                .with_instrument_channels(&subscription.instrument, channels(&subscription.kinds)?);
+        }
+        Ok(connector.with_depth(depth))
+    }
+
+    /// Replace the channels subscribed for each symbol
+    pub fn with_channels(mut self, channels: Vec<KrakenChannel>) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Subscribe to other channels for one instrument
+    pub fn with_instrument_channels(
+        mut self,
+        instrument: &InstrumentId,
+        channels: Vec<KrakenChannel>,
+    ) -> Self {
+        self.instrument_channels
+            .insert(instrument.exchange_symbol.clone(), channels);
+        self
+    }
+
+    /// Channels subscribed for `instrument`
+    pub fn channels_for(&self, instrument: &InstrumentId) -> &[KrakenChannel] {
+        self.instrument_channels
+            .get(&instrument.exchange_symbol)
+            .unwrap_or(&self.channels)
+    }
+
+    /// Symbols of `instruments` subscribed to `channel`, e.g. `BTC/USD`
+    fn symbols(&self, instruments: &[InstrumentId], channel: KrakenChannel) -> Vec<String> {
+        instruments
+            .iter()
+            .filter(|instrument| self.channels_for(instrument).contains(&channel))
+            .map(|instrument| instrument.exchange_symbol.clone())
+            .collect()
+    }
+
+    /// Subscription messages for every channel with at least one symbol
+    fn channel_messages(&self, method: &str, instruments: &[InstrumentId]) -> Vec<String> {
+        [KrakenChannel::Trade, KrakenChannel::Book]
+            .into_iter()
+            .filter_map(|channel| {
+                let symbols = self.symbols(instruments, channel);
+                (!symbols.is_empty())
+                    .then(|| self.subscription_message(method, channel.name(), &symbols))
+            })
+            .collect()
+    }
+
+    /// Connect to a different endpoint, e.g. a local mock server
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Book depth to subscribe to (10, 25, 100, 500 or 1000)
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.parser.depth = depth;
+        self
+    }
+
+    /// Set the decimal precision used for checksums of a symbol such as `BTC/USD`.
+    ///
+    /// Precisions are otherwise learned from the `instrument` channel.
+    pub fn with_precision(mut self, symbol: impl Into<String>, price: u32, qty: u32) -> Self {
+        self.parser
+            .precisions
+            .insert(symbol.into(), KrakenPrecision { price, qty });
+        self
+    }
+
+    /// Build a subscription message for the given channel
+    fn subscription_message(&self, method: &str, channel: &str, symbols: &[String]) -> String {
+        let params = match channel {
+            "instrument" => serde_json::json!({ "channel": channel }),
+            "book" => serde_json::json!({
+                "channel": channel,
+                "symbol": symbols,
+                "depth": self.parser.depth,
+            }),
+            _ => serde_json::json!({ "channel": channel, "symbol": symbols }),
+        };
+        serde_json::json!({ "method": method, "params": params }).to_string()
+    }
+}
+
+impl Default for KrakenConnector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExchangeConnector for KrakenConnector {
+    type Error = KrakenError;
+
+    fn exchange(&self) -> ExchangeId {
+        ExchangeId::Kraken
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The instrument snapshot carries the precisions needed to verify book checksums;
+    /// one subscription per connection covers every symbol
+    fn connect_messages(&self) -> Vec<String> {
+        let books = self.channels.contains(&KrakenChannel::Book)
+            || self
+                .instrument_channels
+                .values()
+                .any(|channels| channels.contains(&KrakenChannel::Book));
+        if !books {
+            return Vec::new();
+        }
+        vec![self.subscription_message("subscribe", "instrument", &[])]
+    }
+
+    fn subscribe_messages(&self, instruments: &[InstrumentId]) -> Result<Vec<String>, Self::Error> {
+        Ok(self.channel_messages("subscribe", instruments))
+    }
+
+    fn unsubscribe_messages(
+        &self,
+        instruments: &[InstrumentId],
+    ) -> Result<Vec<String>, Self::Error> {
+        Ok(self.channel_messages("unsubscribe", instruments))
+    }
+
+    fn decode(
+        &mut self,
+        frame: &str,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Vec<MarketEvent>, Self::Error> {
+        self.parser.parse_message(frame, receipt_time)
+    }
+
+    /// A book that failed its checksum is fetched again by resubscribing
+    fn recover(&mut self, error: &Self::Error) -> Vec<String> {
+        let KrakenError::ChecksumMismatch { symbol, .. } = error else {
+            return Vec::new();
+        };
+        tracing::warn!(%symbol, "resubscribing to Kraken book");
+        let symbols = [symbol.clone()];
+        ["unsubscribe", "subscribe"]
+            .into_iter()
+            .map(|method| self.subscription_message(method, "book", &symbols))
+            .collect()
+    }
+
+    /// Kraken sends a heartbeat every second while a subscription is quiet
+    fn heartbeat(&self) -> Heartbeat {
+        Heartbeat {
+            ping: Some((Duration::from_secs(30), r#"{"method":"ping"}"#.to_string())),
+            idle_timeout: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+/// Decimal places Kraken uses when formatting prices and quantities for a pair
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct KrakenPrecision {
+    price: u32,
+    qty: u32,
+}
+
+/// Stateful normalizer for Kraken v2 messages
+#[derive(Debug, Clone)]
+pub(crate) struct KrakenParser {
+    depth: usize,
+    /// Books keyed by symbol; a symbol is absent until its snapshot arrives
+    books: HashMap<String, LevelBook>,
+    precisions: HashMap<String, KrakenPrecision>,
+}
+
+impl KrakenParser {
+    fn new(depth: usize, precisions: HashMap<String, KrakenPrecision>) -> Self {
+        Self {
+            depth,
+            books: HashMap::new(),
+            precisions,
+        }
+    }
+
+    /// Parse a single WebSocket text frame into zero or more market events
+    pub(crate) fn parse_message(
+        &mut self,
+        message: &str,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Vec<MarketEvent>, KrakenError> {
+        let envelope: KrakenEnvelope<'_> = serde_json::from_str(message)?;
+        let (Some(channel), Some(data)) = (envelope.channel, envelope.data) else {
+            // Method responses, heartbeats and pongs carry no market data
+            return Ok(Vec::new());
+        };
+
+        match channel {
+            "trade" => {
+                let trades: Vec<KrakenTrade<'_>> = serde_json::from_str(data.get())?;
+                trades
+                    .into_iter()
+                    .map(|trade| {
+                        Ok(MarketEvent {
+                            exchange: ExchangeId::Kraken,
+                            instrument: instrument_from_symbol(&trade.symbol),
+                            kind: MarketDataKind::Trade(PublicTrade {
+                                id: trade.trade_id.to_string(),
+                                price: parse_decimal(trade.price)?,
+                                quantity: parse_decimal(trade.qty)?,
+                                side: trade.side.into(),
+                                timestamp: trade.timestamp,
+                            }),
+                            exchange_time: trade.timestamp,
+                            receipt_time,
+                        })
+                    })
+                    .collect()
+            }
+            "book" => {
+                let is_snapshot = envelope.kind == Some("snapshot");
+                let books: Vec<KrakenBook<'_>> = serde_json::from_str(data.get())?;
+                let mut output = Vec::with_capacity(books.len());
+                for update in books {
+                    if let Some(event) = self.apply_book(update, is_snapshot, receipt_time)? {
+                        output.push(event);
+                    }
+                }
+                Ok(output)
+            }
+            "instrument" => {
+                let instruments: KrakenInstrumentData = serde_json::from_str(data.get())?;
+                for pair in instruments.pairs {
+                    self.precisions.insert(
+                        pair.symbol,
+                        KrakenPrecision {
+                            price: pair.price_precision,
+                            qty: pair.qty_precision,
+                        },
+                    );
+                }
+                Ok(Vec::new())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Apply a book snapshot or update and verify the resulting checksum
+    fn apply_book(
+        &mut self,
+        update: KrakenBook<'_>,
+        is_snapshot: bool,
+        receipt_time: DateTime<Utc>,
+    ) -> Result<Option<MarketEvent>, KrakenError> {
+        let book = if is_snapshot {
+            let book = self.books.entry(update.symbol.clone()).or_default();
+            book.clear();
+            book
+        } else {
+            match self.books.get_mut(&update.symbol) {
+                Some(book) => book,
+                // Updates before a snapshot cannot be applied
+                None => return Ok(None),
+            }
+        };
+
+        for level in &update.bids {
+            book.apply(
+                Side::Buy,
+                parse_decimal(level.price)?,
+                parse_decimal(level.qty)?,
+            );
+        }
+        for level in &update.asks {
+            book.apply(
+                Side::Sell,
+                parse_decimal(level.price)?,
+                parse_decimal(level.qty)?,
+            );
+        }
+        // Kraken does not send deletes for levels that fall out of the subscribed depth
+        book.truncate(self.depth);
+
+        let computed = book_checksum(book, self.precisions.get(&update.symbol));
+        if computed != update.checksum {
+            self.books.remove(&update.symbol);
+            return Err(KrakenError::ChecksumMismatch {
+                symbol: update.symbol,
+                expected: update.checksum,
+                computed,
+            });
+        }
+
+        let exchange_time = update.timestamp.unwrap_or(receipt_time);
+        Ok(Some(MarketEvent {
+            exchange: ExchangeId::Kraken,
+            instrument: instrument_from_symbol(&update.symbol),
+            kind: MarketDataKind::OrderBookL2(book.to_l2(self.depth, exchange_time)),
+            exchange_time,
+            receipt_time,
+        }))
+    }
+}
+
+/// CRC32 of the top ten asks followed by the top ten bids, as defined by Kraken
+fn book_checksum(book: &LevelBook, precision: Option<&KrakenPrecision>) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut push = |value: Decimal, places: Option<u32>| {
+        let formatted = match places {
+            Some(places) => format!("{:.*}", places as usize, value),
+            None => value.to_string(),
+        };
+        let digits = formatted.replace('.', "");
+        hasher.update(digits.trim_start_matches('0').as_bytes());
+    };
+
+    for (price, quantity) in book.asks().take(CHECKSUM_DEPTH) {
+        push(price, precision.map(|precision| precision.price));
+        push(quantity, precision.map(|precision| precision.qty));
+    }
+    for (price, quantity) in book.bids().take(CHECKSUM_DEPTH) {
+        push(price, precision.map(|precision| precision.price));
+        push(quantity, precision.map(|precision| precision.qty));
+    }
+    hasher.finalize()
+}
+
+/// Parse a JSON number without going through `f64`
+fn parse_decimal(raw: &RawValue) -> Result<Decimal, rust_decimal::Error> {
+    let text = raw.get();
+    Decimal::from_str(text).or_else(|_| Decimal::from_scientific(text))
+}
+
+/// Map a Kraken symbol such as `BTC/USD` to an instrument
+fn instrument_from_symbol(symbol: &str) -> InstrumentId {
+    let (base, quote) = symbol.split_once('/').unwrap_or((symbol, ""));
+    InstrumentId {
+        base: base.to_string(),
+        quote: quote.to_string(),
+        exchange_symbol: symbol.to_string(),
+        kind: InstrumentKind::Spot,
+    }
+}
+
+#[derive(Deserialize)]
+struct KrakenEnvelope<'a> {
+    #[serde(borrow, default)]
+    channel: Option<&'a str>,
+    #[serde(rename = "type", borrow, default)]
+    kind: Option<&'a str>,
+    #[serde(borrow, default)]
+    data: Option<&'a RawValue>,
+}
+
+#[derive(Deserialize)]
+struct KrakenTrade<'a> {
+    symbol: String,
+    side: KrakenSide,
+    #[serde(borrow)]
+    price: &'a RawValue,
+    #[serde(borrow)]
+    qty: &'a RawValue,
+    trade_id: u64,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum KrakenSide {
+    Buy,
+    Sell,
+}
+
+impl From<KrakenSide> for Side {
+    fn from(side: KrakenSide) -> Self {
+        match side {
+            KrakenSide::Buy => Side::Buy,
+            KrakenSide::Sell => Side::Sell,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct KrakenBook<'a> {
+    symbol: String,
+    #[serde(borrow, default)]
+    bids: Vec<KrakenLevel<'a>>,
+    #[serde(borrow, default)]
+    asks: Vec<KrakenLevel<'a>>,
+    checksum: u32,
+    #[serde(default)]
+    timestamp: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct KrakenLevel<'a> {
+    #[serde(borrow)]
+    price: &'a RawValue,
+    #[serde(borrow)]
+    qty: &'a RawValue,
+}
+
+#[derive(Deserialize)]
+struct KrakenInstrumentData {
+    pairs: Vec<KrakenPair>,
+}
+
+#[derive(Deserialize)]
+struct KrakenPair {
+    symbol: String,
+    price_precision: u32,
+    qty_precision: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::mock_server::MockWebSocketServer;
+    use crate::data::MarketDataStream;
+    use rust_decimal_macros::dec;
+
+    const SESSION: &str = include_str!("../../tests/fixtures/kraken/session.jsonl");
+
+    #[test]
+    fn test_parse_recorded_session() {
+        let mut parser = KrakenParser::new(KRAKEN_DEFAULT_DEPTH, HashMap::new());
+        let events: Vec<MarketEvent> = SESSION
+            .lines()
+            .flat_map(|line| parser.parse_message(line, Utc::now()).unwrap())
+            .collect();
+
+        assert_eq!(events.len(), 5);
+        assert!(events
+            .iter()
+            .all(|event| event.exchange == ExchangeId::Kraken));
+
+        let MarketDataKind::Trade(trade) = &events[1].kind else {
+            panic!("expected trade, got {:?}", events[1].kind);
+        };
+        assert_eq!(trade.id, "72311455");
+        assert_eq!(trade.price, dec!(67890.2));
+        assert_eq!(trade.quantity, dec!(0.0125));
+        assert_eq!(trade.side, Side::Buy);
+
+        let MarketDataKind::OrderBookL2(book) = &events[4].kind else {
+            panic!("expected book, got {:?}", events[4].kind);
+        };
+        assert_eq!(book.bids.len(), KRAKEN_DEFAULT_DEPTH);
+        assert_eq!(book.bids[1].price, dec!(67889.9));
+        assert_eq!(book.bids[9].price, dec!(67887.1));
+        assert_eq!(book.asks[0].price, dec!(67890.6));
+    }
+
+    #[test]
+    fn test_checksum_mismatch_discards_book() {
+        let precision = KrakenPrecision { price: 1, qty: 8 };
+        let precisions = HashMap::from([("BTC/USD".to_string(), precision)]);
+        let mut parser = KrakenParser::new(KRAKEN_DEFAULT_DEPTH, precisions);
+        let lines: Vec<&str> = SESSION.lines().collect();
+        assert_eq!(parser.parse_message(lines[3], Utc::now()).unwrap().len(), 1);
+
+        let corrupted = lines[6].replace("\"checksum\":", "\"checksum\":1");
+        assert!(matches!(
+            parser.parse_message(&corrupted, Utc::now()),
+            Err(KrakenError::ChecksumMismatch { .. })
+        ));
+        // Further updates are ignored until the next snapshot
+        assert!(parser
+            .parse_message(lines[7], Utc::now())
+            .unwrap()
+            .is_empty());
+        assert_eq!(parser.parse_message(lines[3], Utc::now()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_checksum_mismatch_resubscribes_book() {
+        let mut connector = KrakenConnector::new().with_precision("BTC/USD", 1, 8);
+        let lines: Vec<&str> = SESSION.lines().collect();
+        connector.decode(lines[3], Utc::now()).unwrap();
+
+        let corrupted = lines[6].replace("\"checksum\":", "\"checksum\":1");
+        let error = connector.decode(&corrupted, Utc::now()).unwrap_err();
+        let messages = connector.recover(&error);
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("\"unsubscribe\"") && messages[0].contains("BTC/USD"));
+        assert!(messages[1].contains("\"subscribe\"") && messages[1].contains("\"book\""));
+        assert!(connector
+            .recover(&KrakenError::Decimal(
+                rust_decimal::Error::ExceedsMaximumPossibleValue
+            ))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_from_config_maps_channels_per_instrument() {
+        let btc = instrument_from_symbol("BTC/USD");
+        let eth = instrument_from_symbol("ETH/USD");
+        let mut config = crate::config::SystemConfig::default().data;
+        config.subscriptions = vec![SubscriptionKind::Trades];
+        config.instrument_subscriptions = vec![crate::data::InstrumentSubscription {
+            instrument: eth.clone(),
+            kinds: vec![SubscriptionKind::L2 { depth: 20 }],
+        }];
+        let connector = KrakenConnector::from_config(&config).unwrap();
+        assert_eq!(connector.channels_for(&btc), [KrakenChannel::Trade]);
+        assert_eq!(connector.channels_for(&eth), [KrakenChannel::Book]);
+
+        let connect = connector.connect_messages();
+        assert_eq!(connect.len(), 1);
+        assert!(connect[0].contains("\"instrument\""));
+        let messages = connector.subscribe_messages(&[btc, eth]).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("\"trade\"") && !messages[0].contains("ETH/USD"));
+        assert!(messages[1].contains("\"book\"") && !messages[1].contains("BTC/USD"));
+        // The smallest Kraken depth covering 20 levels
+        assert!(messages[1].contains("\"depth\":25"));
+
+        for unsupported in [SubscriptionKind::L1, SubscriptionKind::L2 { depth: 5000 }] {
+            config.subscriptions = vec![unsupported];
+            assert!(matches!(
+                KrakenConnector::from_config(&config),
+                Err(KrakenError::UnsupportedSubscription(kind)) if kind == unsupported
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_against_mock_server() {
+        let server = MockWebSocketServer::from_fixture(SESSION).await.unwrap();
+        let mut stream =
+            KrakenMarketDataStream::from_connector(KrakenConnector::new().with_url(server.url()));
+        let instrument = instrument_from_symbol("BTC/USD");
+        stream
+            .subscribe(std::slice::from_ref(&instrument))
+            .await
+            .unwrap();
+
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            events.push(event);
+        }
+
+        assert_eq!(events.len(), 5);
+        assert!(events.iter().all(|event| event.instrument == instrument));
+        let received = server.received_messages();
+        assert_eq!(received.len(), 3);
+        assert!(received[2].contains("\"depth\":10"));
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_share_one_connection() {
+        let server = MockWebSocketServer::start_held_open(Vec::new())
+            .await
+            .unwrap();
+        let mut stream =
+            KrakenMarketDataStream::from_connector(KrakenConnector::new().with_url(server.url()));
+        let btc = instrument_from_symbol("BTC/USD");
+        let eth = instrument_from_symbol("ETH/USD");
+        stream.subscribe(std::slice::from_ref(&btc)).await.unwrap();
+        stream.subscribe(std::slice::from_ref(&eth)).await.unwrap();
+        stream
+            .unsubscribe(std::slice::from_ref(&btc))
+            .await
+            .unwrap();
+
+        // Both subscriptions and the unsubscription go out over the first connection,
+        // which subscribes to the instrument channel only once
+        let received = server.wait_for_messages(7).await;
+        assert_eq!(server.connections(), 1);
+        assert_eq!(stream.instruments(), [eth]);
+        assert_eq!(
+            received
+                .iter()
+                .filter(|message| message.contains("\"instrument\""))
+                .count(),
+            1
+        );
+        assert!(received[3..5]
+            .iter()
+            .all(|message| message.contains("ETH/USD")));
+        assert_eq!(received.len(), 7);
+        assert!(received[5..]
+            .iter()
+            .all(|message| message.contains("\"unsubscribe\"") && message.contains("BTC/USD")));
+    }
+}
@@ -0,0 +1,386 @@
+//! Level 3 order book
+//!
+//! `L3OrderBook` tracks every resting order from `OrderBookL3Update` messages and
+//! aggregates them into price levels. Orders keep their time priority within a level
+//! so the queue ahead of any order can be measured, which is what queue-aware
+//! strategies and fill simulation need. An order loses priority when its price changes
+//! or its size increases, matching the rules of the venues that publish L3 data.
+
+use super::{
+    DataQualityEvent, DataQualityIssue, L3Action, MarketDataKind, MarketEvent, OrderBookL2,
+    OrderBookL3Update, PriceLevel, Side,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+
+/// Resting order tracked by the book
+#[derive(Debug, Clone, PartialEq)]
+pub struct L3Order {
+    /// Exchange order id
+    pub order_id: String,
+    /// Side of the order
+    pub side: Side,
+    /// Limit price
+    pub price: Decimal,
+    /// Remaining quantity
+    pub quantity: Decimal,
+    /// Time priority; lower values are ahead in the queue
+    priority: u64,
+}
+
+/// Position of an order in its price level queue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuePosition {
+    /// Orders ahead at the same price
+    pub orders_ahead: usize,
+    /// Quantity ahead at the same price
+    pub quantity_ahead: Decimal,
+    /// Total quantity at the price level, including the order itself
+    pub level_quantity: Decimal,
+}
+
+/// Orders resting at one price in time priority
+#[derive(Debug, Clone, Default)]
+struct L3Level {
+    queue: BTreeMap<u64, String>,
+    quantity: Decimal,
+}
+
+/// Order-by-order book for a single instrument
+#[derive(Debug, Clone, Default)]
+pub struct L3OrderBook {
+    orders: HashMap<String, L3Order>,
+    bids: BTreeMap<Reverse<Decimal>, L3Level>,
+    asks: BTreeMap<Decimal, L3Level>,
+    next_priority: u64,
+    last_sequence: Option<u64>,
+    timestamp: Option<DateTime<Utc>>,
+}
+
+impl L3OrderBook {
+    /// Create an empty book
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove every order
+    pub fn clear(&mut self) {
+        self.orders.clear();
+        self.bids.clear();
+        self.asks.clear();
+        self.last_sequence = None;
+    }
+
+    /// Apply a level 3 event; a sequence gap clears the book and other event kinds are
+    /// ignored.
+    ///
+    /// Returns whether the book changed.
+    pub fn update(&mut self, event: &MarketEvent) -> bool {
+        match &event.kind {
+            MarketDataKind::OrderBookL3(update) => self.apply(update),
+            MarketDataKind::DataQuality(DataQualityEvent {
+                issue: DataQualityIssue::SequenceGap { .. },
+                ..
+            }) => {
+                self.clear();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Apply an order message, returning whether the book changed.
+    ///
+    /// Messages for unknown orders are ignored, since venues also report orders that
+    /// never rested on the book. Adding an id that is already resting replaces it.
+    pub fn apply(&mut self, update: &OrderBookL3Update) -> bool {
+        if update.sequence.is_some() {
+            self.last_sequence = update.sequence;
+        }
+        self.timestamp = Some(update.timestamp);
+
+        match update.action {
+            L3Action::Add => {
+                self.remove(&update.order_id);
+                self.insert(L3Order {
+                    order_id: update.order_id.clone(),
+                    side: update.side,
+                    price: update.price,
+                    quantity: update.quantity,
+                    priority: 0,
+                });
+                true
+            }
+            L3Action::Modify => {
+                let Some(mut order) = self.remove(&update.order_id) else {
+                    return false;
+                };
+                // Only a pure size reduction keeps time priority
+                let keeps_priority =
+                    update.price == order.price && update.quantity <= order.quantity;
+                order.price = update.price;
+                order.quantity = update.quantity;
+                if keeps_priority {
+                    self.reinsert(order);
+                } else {
+                    self.insert(order);
+                }
+                true
+            }
+            L3Action::Cancel => self.remove(&update.order_id).is_some(),
+            L3Action::Execute => {
+                let Some(mut order) = self.remove(&update.order_id) else {
+                    return false;
+                };
+                order.quantity -= update.quantity;
+                self.reinsert(order);
+                true
+            }
+        }
+    }
+
+    /// Add an order at the back of its level queue
+    fn insert(&mut self, mut order: L3Order) {
+        order.priority = self.next_priority;
+        self.next_priority += 1;
+        self.reinsert(order);
+    }
+
+    /// Add an order keeping its existing priority; orders with nothing left are dropped
+    fn reinsert(&mut self, order: L3Order) {
+        if order.quantity <= Decimal::ZERO {
+            return;
+        }
+        let level = match order.side {
+            Side::Buy => self.bids.entry(Reverse(order.price)).or_default(),
+            Side::Sell => self.asks.entry(order.price).or_default(),
+        };
+        level.queue.insert(order.priority, order.order_id.clone());
+        level.quantity += order.quantity;
+        self.orders.insert(order.order_id.clone(), order);
+    }
+
+    fn remove(&mut self, order_id: &str) -> Option<L3Order> {
+        let order = self.orders.remove(order_id)?;
+        let emptied = match self.level_mut(order.side, order.price) {
+            Some(level) => {
+                level.queue.remove(&order.priority);
+                level.quantity -= order.quantity;
+                level.queue.is_empty()
+            }
+            None => false,
+        };
+        if emptied {
+            match order.side {
+                Side::Buy => self.bids.remove(&Reverse(order.price)),
+                Side::Sell => self.asks.remove(&order.price),
+            };
+        }
+        Some(order)
+    }
+
+    fn level(&self, side: Side, price: Decimal) -> Option<&L3Level> {
+        match side {
+            Side::Buy => self.bids.get(&Reverse(price)),
+            Side::Sell => self.asks.get(&price),
+        }
+    }
+
+    fn level_mut(&mut self, side: Side, price: Decimal) -> Option<&mut L3Level> {
+        match side {
+            Side::Buy => self.bids.get_mut(&Reverse(price)),
+            Side::Sell => self.asks.get_mut(&price),
+        }
+    }
+
+    /// Look up a resting order
+    pub fn order(&self, order_id: &str) -> Option<&L3Order> {
+        self.orders.get(order_id)
+    }
+
+    /// Number of resting orders
+    pub fn order_count(&self) -> usize {
+        self.orders.len()
+    }
+
+    /// Sequence number of the last applied message
+    pub fn last_sequence(&self) -> Option<u64> {
+        self.last_sequence
+    }
+
+    /// Timestamp of the last applied message
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        self.timestamp
+    }
+
+    /// Where an order sits in its price level queue
+    pub fn queue_position(&self, order_id: &str) -> Option<QueuePosition> {
+        let order = self.orders.get(order_id)?;
+        let level = self.level(order.side, order.price)?;
+        let ahead = level.queue.range(..order.priority);
+        let mut position = QueuePosition {
+            orders_ahead: 0,
+            quantity_ahead: Decimal::ZERO,
+            level_quantity: level.quantity,
+        };
+        for (_, id) in ahead {
+            position.orders_ahead += 1;
+            position.quantity_ahead += self.orders[id].quantity;
+        }
+        Some(position)
+    }
+
+    /// Orders resting at a price in time priority
+    pub fn orders_at(&self, side: Side, price: Decimal) -> impl Iterator<Item = &L3Order> + '_ {
+        self.level(side, price)
+            .into_iter()
+            .flat_map(|level| level.queue.values())
+            .map(|id| &self.orders[id])
+    }
+
+    /// Aggregated bid levels from best to worst
+    pub fn bids(&self) -> impl Iterator<Item = PriceLevel> + '_ {
+        self.bids.iter().map(|(price, level)| PriceLevel {
+            price: price.0,
+            quantity: level.quantity,
+        })
+    }
+
+    /// Aggregated ask levels from best to worst
+    pub fn asks(&self) -> impl Iterator<Item = PriceLevel> + '_ {
+        self.asks.iter().map(|(price, level)| PriceLevel {
+            price: *price,
+            quantity: level.quantity,
+        })
+    }
+
+    /// Best aggregated bid
+    pub fn best_bid(&self) -> Option<PriceLevel> {
+        self.bids().next()
+    }
+
+    /// Best aggregated ask
+    pub fn best_ask(&self) -> Option<PriceLevel> {
+        self.asks().next()
+    }
+
+    /// Aggregate the top `depth` levels of each side into an L2 book
+    pub fn to_l2(&self, depth: usize) -> OrderBookL2 {
+        OrderBookL2 {
+            bids: self.bids().take(depth).collect(),
+            asks: self.asks().take(depth).collect(),
+            timestamp: self.timestamp.unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn message(
+        order_id: &str,
+        action: L3Action,
+        side: Side,
+        price: Decimal,
+        quantity: Decimal,
+    ) -> OrderBookL3Update {
+        OrderBookL3Update {
+            order_id: order_id.to_string(),
+            action,
+            side,
+            price,
+            quantity,
+            sequence: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_queue_position_and_aggregation() {
+        let mut book = L3OrderBook::new();
+        for (id, price, quantity) in [
+            ("a", dec!(100), dec!(1)),
+            ("b", dec!(100), dec!(2)),
+            ("c", dec!(100), dec!(3)),
+            ("d", dec!(99), dec!(4)),
+        ] {
+            book.apply(&message(id, L3Action::Add, Side::Buy, price, quantity));
+        }
+        book.apply(&message("x", L3Action::Add, Side::Sell, dec!(101), dec!(5)));
+
+        let position = book.queue_position("c").unwrap();
+        assert_eq!(position.orders_ahead, 2);
+        assert_eq!(position.quantity_ahead, dec!(3));
+        assert_eq!(position.level_quantity, dec!(6));
+
+        // Partial execution and size reduction keep priority
+        book.apply(&message(
+            "a",
+            L3Action::Execute,
+            Side::Buy,
+            dec!(100),
+            dec!(0.5),
+        ));
+        book.apply(&message(
+            "b",
+            L3Action::Modify,
+            Side::Buy,
+            dec!(100),
+            dec!(1),
+        ));
+        assert_eq!(book.queue_position("c").unwrap().quantity_ahead, dec!(1.5));
+
+        // Increasing size sends the order to the back of the queue
+        book.apply(&message(
+            "a",
+            L3Action::Modify,
+            Side::Buy,
+            dec!(100),
+            dec!(2),
+        ));
+        let queue: Vec<_> = book
+            .orders_at(Side::Buy, dec!(100))
+            .map(|order| order.order_id.as_str())
+            .collect();
+        assert_eq!(queue, ["b", "c", "a"]);
+
+        // Fully executed and cancelled orders leave the book
+        book.apply(&message(
+            "b",
+            L3Action::Execute,
+            Side::Buy,
+            dec!(100),
+            dec!(1),
+        ));
+        book.apply(&message(
+            "d",
+            L3Action::Cancel,
+            Side::Buy,
+            dec!(99),
+            dec!(0),
+        ));
+        assert!(!book.apply(&message(
+            "zz",
+            L3Action::Cancel,
+            Side::Buy,
+            dec!(99),
+            dec!(0)
+        )));
+        assert_eq!(book.order_count(), 3);
+        assert_eq!(book.queue_position("c").unwrap().orders_ahead, 0);
+
+        let l2 = book.to_l2(5);
+        assert_eq!(
+            l2.bids,
+            vec![PriceLevel {
+                price: dec!(100),
+                quantity: dec!(5)
+            }]
+        );
+        assert_eq!(book.best_ask().unwrap().quantity, dec!(5));
+    }
+}
@@ -0,0 +1,690 @@
+//! Feed latency and clock skew monitoring
+//!
+//! `FeedLatencyMonitor` measures `receipt_time - exchange_time` for every event, per
+//! exchange and per stream (`MarketDataKind::name`). A rolling window of samples backs
+//! the latency distribution in `LatencyReport`, while an exponentially weighted
+//! baseline flags spikes in constant time per event.
+//!
+//! One-way measurements cannot separate network delay from clock offset: the observed
+//! latency is the true delay plus how far the local clock runs ahead of the exchange.
+//! The smallest latency in the window is therefore an upper bound on the offset, and a
+//! negative minimum proves the local clock is behind. The estimate reported is that
+//! minimum less the assumed minimum network delay. Events stamped with the receipt
+//! time because the venue sends no timestamp, such as Binance book tickers and partial
+//! depth, only count as signs of life and are kept out of the latency samples.
+//!
+//! An exchange that stops sending events is degraded once `max_silence` has passed;
+//! `MonitoredMarketDataStream` checks for this on a timer while the inner stream is idle.
+//! Like every other measurement, silence is judged on receipt times: the check moves
+//! on from the last event's receipt time by the wall time elapsed since it arrived, so
+//! replayed feeds are not reported silent because their receipt times lie in the past.
+//!
+//! Exchange status is published through a shared `FeedHealth` handle, which the risk
+//! manager consults before approving orders.
+
+use super::{ExchangeId, InstrumentId, MarketDataKind, MarketDataStream, MarketEvent};
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+/// Monitor thresholds
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyMonitorConfig {
+    /// Samples kept per stream for the latency distribution
+    pub window: usize,
+    /// Samples required before spikes are reported
+    pub min_samples: usize,
+    /// A sample is a spike when it exceeds the baseline by this many mean deviations
+    pub spike_factor: f64,
+    /// Samples closer than this to the baseline are never spikes
+    pub spike_floor: Duration,
+    /// Baseline latency above which an exchange is degraded
+    pub max_latency: Duration,
+    /// How long an exchange stays degraded after its last spike
+    pub recovery: Duration,
+    /// Absolute clock offset estimate above which an exchange is degraded
+    pub max_clock_offset: Duration,
+    /// Network delay assumed for the fastest message when estimating clock offset
+    pub min_network_delay: Duration,
+    /// Time without events after which an exchange is degraded
+    pub max_silence: Duration,
+}
+
+impl Default for LatencyMonitorConfig {
+    fn default() -> Self {
+        Self {
+            window: 1000,
+            min_samples: 50,
+            spike_factor: 8.0,
+            spike_floor: Duration::from_millis(50),
+            max_latency: Duration::from_millis(250),
+            recovery: Duration::from_secs(5),
+            max_clock_offset: Duration::from_millis(500),
+            min_network_delay: Duration::ZERO,
+            max_silence: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Latency distribution in microseconds; negative values indicate clock skew
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LatencyStats {
+    pub samples: usize,
+    pub min_micros: i64,
+    pub mean_micros: i64,
+    pub p50_micros: i64,
+    pub p90_micros: i64,
+    pub p99_micros: i64,
+    pub max_micros: i64,
+}
+
+impl LatencyStats {
+    fn from_samples(samples: &VecDeque<i64>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<i64> = samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let percentile = |p: usize| sorted[(sorted.len() - 1) * p / 100];
+        Some(Self {
+            samples: sorted.len(),
+            min_micros: sorted[0],
+            mean_micros: sorted.iter().sum::<i64>() / sorted.len() as i64,
+            p50_micros: percentile(50),
+            p90_micros: percentile(90),
+            p99_micros: percentile(99),
+            max_micros: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+/// Latency of one stream of one exchange
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct StreamLatency {
+    pub exchange: ExchangeId,
+    pub stream: String,
+    pub stats: LatencyStats,
+}
+
+/// Latency summary across all monitored streams
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+pub struct LatencyReport {
+    pub streams: Vec<StreamLatency>,
+    pub exchanges: Vec<FeedStatus>,
+}
+
+/// Health of an exchange feed
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FeedStatus {
+    pub exchange: ExchangeId,
+    /// Whether trading on this feed is considered unsafe
+    pub degraded: bool,
+    /// Why the feed is degraded
+    pub reason: Option<String>,
+    /// Estimated local clock offset ahead of the exchange, in microseconds, once an
+    /// event carrying an exchange timestamp has been seen
+    pub clock_offset_micros: Option<i64>,
+    /// Smoothed latency across the exchange's streams, in microseconds
+    pub baseline_micros: i64,
+}
+
+/// Shared, cloneable view of feed health for consumers such as the risk manager
+#[derive(Debug, Clone, Default)]
+pub struct FeedHealth {
+    statuses: Arc<RwLock<BTreeMap<ExchangeId, FeedStatus>>>,
+}
+
+impl FeedHealth {
+    /// Create an empty handle where every feed is healthy
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Status of an exchange, if it has been observed
+    pub fn status(&self, exchange: ExchangeId) -> Option<FeedStatus> {
+        self.statuses.read().get(&exchange).cloned()
+    }
+
+    /// Whether an exchange feed is degraded
+    pub fn is_degraded(&self, exchange: ExchangeId) -> bool {
+        self.statuses
+            .read()
+            .get(&exchange)
+            .is_some_and(|status| status.degraded)
+    }
+
+    /// Statuses of all degraded feeds
+    pub fn degraded(&self) -> Vec<FeedStatus> {
+        self.statuses
+            .read()
+            .values()
+            .filter(|status| status.degraded)
+            .cloned()
+            .collect()
+    }
+
+    fn set(&self, status: FeedStatus) {
+        self.statuses.write().insert(status.exchange, status);
+    }
+}
+
+/// Notable change reported by `FeedLatencyMonitor::observe`
+#[derive(Debug, Clone, PartialEq)]
+pub enum LatencyAlert {
+    /// Single sample far above the stream's baseline
+    Spike {
+        exchange: ExchangeId,
+        stream: &'static str,
+        latency_micros: i64,
+        baseline_micros: i64,
+    },
+    /// Exchange feed became degraded
+    Degraded {
+        exchange: ExchangeId,
+        reason: String,
+    },
+    /// Exchange feed recovered
+    Recovered { exchange: ExchangeId },
+}
+
+/// Rolling latency state of one stream
+#[derive(Debug, Clone, Default)]
+struct StreamState {
+    samples: VecDeque<i64>,
+    /// Increasing latencies of the window with their sample index; the front is the minimum
+    minima: VecDeque<(usize, i64)>,
+    observed: usize,
+    /// Exponentially weighted mean latency
+    mean: f64,
+    /// Exponentially weighted mean absolute deviation
+    deviation: f64,
+}
+
+/// Per exchange state
+#[derive(Debug, Clone, Default)]
+struct ExchangeState {
+    last_event: Option<DateTime<Utc>>,
+    degraded_until: Option<DateTime<Utc>>,
+    degraded: bool,
+}
+
+/// Tracks feed latency distributions, clock offset and spikes
+#[derive(Debug, Clone)]
+pub struct FeedLatencyMonitor {
+    config: LatencyMonitorConfig,
+    streams: HashMap<(ExchangeId, &'static str), StreamState>,
+    exchanges: HashMap<ExchangeId, ExchangeState>,
+    health: FeedHealth,
+}
+
+/// Weight of the newest sample in the smoothed baseline
+const EWMA_ALPHA: f64 = 0.05;
+
+impl FeedLatencyMonitor {
+    /// Create a monitor with the given thresholds
+    pub fn new(config: LatencyMonitorConfig) -> Self {
+        Self {
+            config,
+            streams: HashMap::new(),
+            exchanges: HashMap::new(),
+            health: FeedHealth::new(),
+        }
+    }
+
+    /// Shared health handle updated by this monitor
+    pub fn health(&self) -> FeedHealth {
+        self.health.clone()
+    }
+
+    /// Record an event's latency, returning any alerts it triggers
+    pub fn observe(&mut self, event: &MarketEvent) -> Vec<LatencyAlert> {
+        // Quality notifications are generated locally and carry no feed latency
+        if matches!(event.kind, MarketDataKind::DataQuality(_)) {
+            return Vec::new();
+        }
+        self.exchanges.entry(event.exchange).or_default().last_event = Some(event.receipt_time);
+        // Events stamped on receipt say nothing about latency or clock offset
+        let latency = match event
+            .exchange_clock()
+            .and_then(|exchange_time| (event.receipt_time - exchange_time).num_microseconds())
+        {
+            Some(latency) => latency,
+            None => {
+                return self
+                    .update_status(event.exchange, event.receipt_time, false)
+                    .into_iter()
+                    .collect()
+            }
+        };
+        let stream = event.kind.name();
+        let mut alerts = Vec::new();
+
+        let state = self.streams.entry((event.exchange, stream)).or_default();
+        if state.samples.len() == self.config.window {
+            state.samples.pop_front();
+        }
+        state.samples.push_back(latency);
+        while state.minima.back().is_some_and(|(_, min)| *min >= latency) {
+            state.minima.pop_back();
+        }
+        state.minima.push_back((state.observed, latency));
+        if state
+            .minima
+            .front()
+            .is_some_and(|(index, _)| index + self.config.window <= state.observed)
+        {
+            state.minima.pop_front();
+        }
+
+        let sample = latency as f64;
+        let excess = sample - state.mean;
+        let threshold = (state.deviation * self.config.spike_factor)
+            .max(self.config.spike_floor.as_micros() as f64);
+        let spike = state.observed >= self.config.min_samples && excess > threshold;
+        if spike {
+            alerts.push(LatencyAlert::Spike {
+                exchange: event.exchange,
+                stream,
+                latency_micros: latency,
+                baseline_micros: state.mean as i64,
+            });
+        }
+        if state.observed == 0 {
+            state.mean = sample;
+        } else {
+            state.mean += EWMA_ALPHA * excess;
+            state.deviation += EWMA_ALPHA * (excess.abs() - state.deviation);
+        }
+        state.observed += 1;
+
+        alerts.extend(self.update_status(event.exchange, event.receipt_time, spike));
+        alerts
+    }
+
+    /// Re-evaluate every exchange at `now`, degrading feeds that have gone silent
+    pub fn check(&mut self, now: DateTime<Utc>) -> Vec<LatencyAlert> {
+        let mut exchanges: Vec<ExchangeId> = self.exchanges.keys().copied().collect();
+        exchanges.sort();
+        exchanges
+            .into_iter()
+            .filter_map(|exchange| self.update_status(exchange, now, false))
+            .collect()
+    }
+
+    /// Publish an exchange's status, returning an alert if it changed state
+    fn update_status(
+        &mut self,
+        exchange: ExchangeId,
+        now: DateTime<Utc>,
+        spike: bool,
+    ) -> Option<LatencyAlert> {
+        let status = self.exchange_status(exchange, now, spike);
+        let state = self.exchanges.entry(exchange).or_default();
+        let alert = (status.degraded != state.degraded).then(|| {
+            state.degraded = status.degraded;
+            match &status.reason {
+                Some(reason) if status.degraded => LatencyAlert::Degraded {
+                    exchange,
+                    reason: reason.clone(),
+                },
+                _ => LatencyAlert::Recovered { exchange },
+            }
+        });
+        if self.health.status(exchange).as_ref() != Some(&status) {
+            self.health.set(status);
+        }
+        alert
+    }
+
+    /// Recompute an exchange's status at `now`
+    fn exchange_status(
+        &mut self,
+        exchange: ExchangeId,
+        now: DateTime<Utc>,
+        spike: bool,
+    ) -> FeedStatus {
+        let mut min_latency: Option<i64> = None;
+        let mut baseline = 0.0f64;
+        let mut observed = 0;
+        for ((stream_exchange, _), state) in &self.streams {
+            if *stream_exchange != exchange {
+                continue;
+            }
+            if let Some((_, min)) = state.minima.front() {
+                min_latency = Some(min_latency.map_or(*min, |latency| latency.min(*min)));
+            }
+            baseline = baseline.max(state.mean);
+            observed = observed.max(state.observed);
+        }
+        let clock_offset =
+            min_latency.map(|min| min - self.config.min_network_delay.as_micros() as i64);
+
+        let state = self.exchanges.entry(exchange).or_default();
+        if spike {
+            state.degraded_until = chrono::Duration::from_std(self.config.recovery)
+                .ok()
+                .map(|recovery| now + recovery);
+        }
+
+        let silence = state.last_event.map(|last| now - last);
+        let reason = if let Some(silence) = silence.filter(|silence| {
+            silence
+                .to_std()
+                .is_ok_and(|silence| silence > self.config.max_silence)
+        }) {
+            Some(format!("no events for {}ms", silence.num_milliseconds()))
+        } else if state.degraded_until.is_some_and(|until| now < until) {
+            Some("recent latency spike".to_string())
+        } else if observed >= self.config.min_samples
+            && baseline > self.config.max_latency.as_micros() as f64
+        {
+            Some(format!("latency baseline {}us", baseline as i64))
+        } else {
+            clock_offset
+                .filter(|clock_offset| {
+                    observed >= self.config.min_samples
+                        && clock_offset.unsigned_abs()
+                            > self.config.max_clock_offset.as_micros() as u64
+                })
+                .map(|clock_offset| format!("clock offset {}us", clock_offset))
+        };
+
+        FeedStatus {
+            exchange,
+            degraded: reason.is_some(),
+            reason,
+            clock_offset_micros: clock_offset,
+            baseline_micros: baseline as i64,
+        }
+    }
+
+    /// Latency distributions of every stream and the status of every exchange
+    pub fn report(&self) -> LatencyReport {
+        let mut streams: Vec<StreamLatency> = self
+            .streams
+            .iter()
+            .filter_map(|((exchange, stream), state)| {
+                Some(StreamLatency {
+                    exchange: *exchange,
+                    stream: stream.to_string(),
+                    stats: LatencyStats::from_samples(&state.samples)?,
+                })
+            })
+            .collect();
+        streams.sort_by(|a, b| (a.exchange, &a.stream).cmp(&(b.exchange, &b.stream)));
+        LatencyReport {
+            streams,
+            exchanges: self.health.statuses.read().values().cloned().collect(),
+        }
+    }
+}
+
+impl Default for FeedLatencyMonitor {
+    fn default() -> Self {
+        Self::new(LatencyMonitorConfig::default())
+    }
+}
+
+/// Forwards events from an inner stream while feeding them to a latency monitor.
+///
+/// Silent feeds are detected by checking the monitor every `check_interval`, abandoning
+/// a pending `next` on the inner stream when the check is due, so the inner stream's
+/// `next` must be cancel-safe.
+pub struct MonitoredMarketDataStream<S> {
+    inner: S,
+    monitor: FeedLatencyMonitor,
+    check_interval: Duration,
+    next_check: tokio::time::Instant,
+    /// Receipt time of the last inner event and when it arrived
+    last_event: Option<(DateTime<Utc>, tokio::time::Instant)>,
+}
+
+impl<S> MonitoredMarketDataStream<S> {
+    /// Monitor everything `inner` produces
+    pub fn new(inner: S, monitor: FeedLatencyMonitor) -> Self {
+        let check_interval = Duration::from_secs(1);
+        Self {
+            inner,
+            monitor,
+            check_interval,
+            next_check: tokio::time::Instant::now() + check_interval,
+            last_event: None,
+        }
+    }
+
+    /// Set how often silent feeds are checked for
+    pub fn with_check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval.max(Duration::from_millis(1));
+        self.next_check = tokio::time::Instant::now() + self.check_interval;
+        self
+    }
+
+    /// Monitor state, e.g. for reporting
+    pub fn monitor(&self) -> &FeedLatencyMonitor {
+        &self.monitor
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> MarketDataStream for MonitoredMarketDataStream<S>
+where
+    S: MarketDataStream + Send,
+    S::Error: Send,
+{
+    type Error = S::Error;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        loop {
+            let next = tokio::time::timeout_at(self.next_check, self.inner.next()).await;
+            if tokio::time::Instant::now() >= self.next_check {
+                self.next_check = tokio::time::Instant::now() + self.check_interval;
+                // Nothing can have gone silent before the first event
+                if let Some((receipt_time, arrived)) = self.last_event {
+                    let idle = chrono::Duration::from_std(arrived.elapsed())
+                        .unwrap_or(chrono::Duration::MAX);
+                    for alert in self.monitor.check(receipt_time + idle) {
+                        warn!("Market data feed alert: {:?}", alert);
+                    }
+                }
+            }
+            let Ok(event) = next else {
+                continue;
+            };
+            let event = event?;
+            if let Some(event) = &event {
+                self.last_event = Some((event.receipt_time, tokio::time::Instant::now()));
+                for alert in self.monitor.observe(event) {
+                    warn!("Market data feed alert: {:?}", alert);
+                }
+            }
+            return Ok(event);
+        }
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.inner.subscribe(instruments).await
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.inner.unsubscribe(instruments).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{
+        InstrumentKind, MarketDataKind, OrderBookL1, PublicTrade, Side, StreamMarketDataStream,
+    };
+    use chrono::{Duration as ChronoDuration, TimeZone};
+    use futures::StreamExt;
+    use rust_decimal::Decimal;
+
+    fn trade(exchange: ExchangeId, sent_ms: i64, latency_ms: i64) -> MarketEvent {
+        let exchange_time = Utc
+            .timestamp_millis_opt(1_700_000_000_000 + sent_ms)
+            .unwrap();
+        MarketEvent {
+            exchange,
+            instrument: InstrumentId {
+                base: "BTC".to_string(),
+                quote: "USDT".to_string(),
+                exchange_symbol: "BTCUSDT".to_string(),
+                kind: InstrumentKind::Spot,
+            },
+            kind: MarketDataKind::Trade(PublicTrade {
+                id: sent_ms.to_string(),
+                price: Decimal::ONE,
+                quantity: Decimal::ONE,
+                side: Side::Buy,
+                timestamp: exchange_time,
+            }),
+            exchange_time,
+            receipt_time: exchange_time + ChronoDuration::milliseconds(latency_ms),
+        }
+    }
+
+    /// bookTicker update, which Binance sends without an event time
+    fn book_ticker(sent_ms: i64) -> MarketEvent {
+        let mut event = trade(ExchangeId::Binance, sent_ms, 0);
+        event.kind = MarketDataKind::OrderBookL1(OrderBookL1 {
+            bid_price: Decimal::ONE,
+            bid_quantity: Decimal::ONE,
+            ask_price: Decimal::TWO,
+            ask_quantity: Decimal::ONE,
+            timestamp: event.receipt_time,
+            update_id: None,
+        });
+        event
+    }
+
+    #[test]
+    fn test_spike_degrades_feed_until_recovery() {
+        let mut monitor = FeedLatencyMonitor::default();
+        let health = monitor.health();
+        for i in 0..100 {
+            let alerts = monitor.observe(&trade(ExchangeId::Binance, i * 10, 5 + i % 3));
+            assert!(alerts.is_empty());
+        }
+        assert!(!health.is_degraded(ExchangeId::Binance));
+
+        let alerts = monitor.observe(&trade(ExchangeId::Binance, 1_000, 400));
+        assert!(matches!(
+            alerts[0],
+            LatencyAlert::Spike {
+                latency_micros: 400_000,
+                ..
+            }
+        ));
+        assert!(matches!(alerts[1], LatencyAlert::Degraded { .. }));
+        assert!(health.is_degraded(ExchangeId::Binance));
+        assert!(!health.is_degraded(ExchangeId::Kraken));
+
+        // Normal traffic after the recovery period clears the flag
+        let alerts = monitor.observe(&trade(ExchangeId::Binance, 7_000, 5));
+        assert_eq!(
+            alerts,
+            vec![LatencyAlert::Recovered {
+                exchange: ExchangeId::Binance
+            }]
+        );
+        assert!(health.degraded().is_empty());
+
+        let report = monitor.report();
+        let stats = &report.streams[0].stats;
+        assert_eq!(report.streams[0].stream, "trade");
+        assert_eq!(stats.samples, 102);
+        assert_eq!(stats.max_micros, 400_000);
+        assert_eq!(stats.p50_micros, 6_000);
+    }
+
+    #[test]
+    fn test_clock_offset_estimate() {
+        let mut monitor = FeedLatencyMonitor::new(LatencyMonitorConfig {
+            min_samples: 10,
+            ..LatencyMonitorConfig::default()
+        });
+        // Local clock two seconds behind the exchange
+        for i in 0..20 {
+            monitor.observe(&trade(ExchangeId::Kraken, i * 100, -2_000 + i % 4));
+        }
+        let status = monitor.health().status(ExchangeId::Kraken).unwrap();
+        assert_eq!(status.clock_offset_micros, Some(-2_000_000));
+        assert!(status.degraded);
+        assert!(status.reason.unwrap().starts_with("clock offset"));
+    }
+
+    #[test]
+    fn test_silent_feed_degrades_and_receipt_stamps_are_ignored() {
+        let mut monitor = FeedLatencyMonitor::new(LatencyMonitorConfig {
+            min_samples: 10,
+            ..LatencyMonitorConfig::default()
+        });
+        for i in 0..20 {
+            monitor.observe(&trade(ExchangeId::Binance, i * 100, 5));
+        }
+        // Receipt-stamped events keep the feed alive without skewing the offset estimate
+        let stamped = book_ticker(2_000);
+        assert!(monitor.observe(&stamped).is_empty());
+        assert_eq!(monitor.report().streams[0].stats.samples, 20);
+        let status = monitor.health().status(ExchangeId::Binance).unwrap();
+        assert_eq!(status.clock_offset_micros, Some(5_000));
+
+        let alerts = monitor.check(stamped.receipt_time + ChronoDuration::seconds(10));
+        assert!(alerts.is_empty());
+        let alerts = monitor.check(stamped.receipt_time + ChronoDuration::seconds(31));
+        assert!(matches!(
+            &alerts[..],
+            [LatencyAlert::Degraded { reason, .. }] if reason.starts_with("no events")
+        ));
+        assert!(monitor.health().is_degraded(ExchangeId::Binance));
+
+        let alerts = monitor.observe(&trade(ExchangeId::Binance, 40_000, 5));
+        assert_eq!(
+            alerts,
+            vec![LatencyAlert::Recovered {
+                exchange: ExchangeId::Binance
+            }]
+        );
+    }
+
+    #[test]
+    fn test_receipt_stamped_feed_has_no_clock_offset() {
+        let mut monitor = FeedLatencyMonitor::default();
+        monitor.observe(&book_ticker(0));
+        let status = monitor.health().status(ExchangeId::Binance).unwrap();
+        assert_eq!(status.clock_offset_micros, None);
+        assert!(!status.degraded);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replayed_feed_is_not_silent() {
+        // Receipt times years in the past, as in a replay, then a pause in the data
+        let events: Vec<MarketEvent> = (0..3)
+            .map(|i| trade(ExchangeId::Binance, i * 100, 5))
+            .collect();
+        let source = StreamMarketDataStream::new(Box::pin(
+            futures::stream::iter(events)
+                .chain(futures::stream::pending())
+                .map(Ok::<_, std::convert::Infallible>),
+        ));
+        let monitor = FeedLatencyMonitor::default();
+        let health = monitor.health();
+        let mut stream = MonitoredMarketDataStream::new(source, monitor)
+            .with_check_interval(Duration::from_millis(500));
+        for _ in 0..3 {
+            stream.next().await.unwrap();
+        }
+
+        let idle = tokio::time::timeout(Duration::from_secs(5), stream.next()).await;
+        assert!(idle.is_err());
+        assert!(!health.is_degraded(ExchangeId::Binance));
+        let idle = tokio::time::timeout(Duration::from_secs(30), stream.next()).await;
+        assert!(idle.is_err());
+        assert!(health.is_degraded(ExchangeId::Binance));
+    }
+}
@@ -0,0 +1,594 @@
+//! Multi-venue market data merging
+//!
+//! `MergedMarketDataStream` combines several `MarketDataStream`s into a single stream
+//! ordered by receipt time, and tracks a consolidated best bid/offer per normalized
+//! instrument across the merged venues.
+//!
+//! A venue's quotes leave the consolidated book when its source ends, and optionally
+//! once they are older than a maximum age, so a disconnected venue cannot keep setting
+//! the best price. Consolidated quotes are published on `ExchangeId::Synthetic`; the
+//! venues holding the best prices are reported in the quote itself.
+
+use super::{
+    ConsolidatedQuote, ExchangeId, InstrumentId, InstrumentKind, MarketDataKind, MarketDataStream,
+    MarketEvent,
+};
+use chrono::{DateTime, Utc};
+use futures::future::FutureExt;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::time::Duration;
+
+/// Error type shared by all merged sources
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Type-erased market data stream with boxed errors
+pub type BoxedMarketDataStream = Box<dyn MarketDataStream<Error = BoxError> + Send>;
+
+/// Adapter that boxes the error type of any market data stream
+struct ErasedStream<S>(S);
+
+#[async_trait::async_trait]
+impl<S> MarketDataStream for ErasedStream<S>
+where
+    S: MarketDataStream + Send,
+    S::Error: Into<BoxError>,
+{
+    type Error = BoxError;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        self.0.next().await.map_err(Into::into)
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.0.subscribe(instruments).await.map_err(Into::into)
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.0.unsubscribe(instruments).await.map_err(Into::into)
+    }
+}
+
+/// A merged source and its next undelivered event
+struct MergeSource {
+    stream: BoxedMarketDataStream,
+    head: Option<MarketEvent>,
+    exhausted: bool,
+    /// Venues this source has published book updates for
+    venues: BTreeSet<ExchangeId>,
+    /// Receipt time of the last event released from this source
+    last_receipt: Option<DateTime<Utc>>,
+}
+
+/// Stream merging several market data streams in receipt time order.
+///
+/// An event is released once every other live source has an event buffered, or once
+/// the reorder window has elapsed without the remaining sources producing one. With
+/// in-memory or replayed sources the output is therefore strictly ordered, while a
+/// quiet live venue delays the others by at most the reorder window.
+///
+/// Sources must have cancel-safe `next` implementations, as pending calls on other
+/// sources are dropped when one of them produces an event. Channel-backed streams such
+/// as the WebSocket connectors and `ReplayMarketDataStream` are; wrapping streams are
+/// cancel-safe when the stream they wrap is.
+pub struct MergedMarketDataStream {
+    sources: Vec<MergeSource>,
+    reorder_window: Duration,
+    consolidated: ConsolidatedBbo,
+    /// Consolidated quotes waiting to be delivered after the event that triggered them
+    pending: VecDeque<MarketEvent>,
+}
+
+impl MergedMarketDataStream {
+    /// Create an empty merged stream
+    pub fn new() -> Self {
+        Self {
+            sources: Vec::new(),
+            reorder_window: Duration::ZERO,
+            consolidated: ConsolidatedBbo::default(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Add a source stream
+    pub fn with_stream<S>(mut self, stream: S) -> Self
+    where
+        S: MarketDataStream + Send + 'static,
+        S::Error: Into<BoxError>,
+    {
+        self.sources.push(MergeSource {
+            stream: Box::new(ErasedStream(stream)),
+            head: None,
+            exhausted: false,
+            venues: BTreeSet::new(),
+            last_receipt: None,
+        });
+        self
+    }
+
+    /// How long to wait for quiet sources before releasing an event out of a partial merge
+    pub fn with_reorder_window(mut self, reorder_window: Duration) -> Self {
+        self.reorder_window = reorder_window;
+        self
+    }
+
+    /// Drop venue quotes older than `max_age` from the consolidated book
+    pub fn with_max_quote_age(mut self, max_age: Duration) -> Self {
+        self.consolidated = self.consolidated.with_max_age(max_age);
+        self
+    }
+
+    /// Current consolidated best bid/offer state
+    pub fn consolidated(&self) -> &ConsolidatedBbo {
+        &self.consolidated
+    }
+
+    /// Index of the buffered event with the earliest receipt time
+    fn earliest_head(&self) -> Option<usize> {
+        self.sources
+            .iter()
+            .enumerate()
+            .filter_map(|(index, source)| {
+                source
+                    .head
+                    .as_ref()
+                    .map(|event| (index, event.receipt_time))
+            })
+            .min_by_key(|(index, receipt_time)| (*receipt_time, *index))
+            .map(|(index, _)| index)
+    }
+
+    /// Take the earliest buffered event and update the consolidated quote with it
+    fn release(&mut self, index: usize) -> Option<MarketEvent> {
+        let event = self.sources[index].head.take()?;
+        self.sources[index].last_receipt = Some(event.receipt_time);
+        if matches!(
+            event.kind,
+            MarketDataKind::OrderBookL1(_) | MarketDataKind::OrderBookL2(_)
+        ) {
+            self.sources[index].venues.insert(event.exchange);
+        }
+        if let Some(quote) = self.consolidated.update(&event) {
+            self.pending.push_back(quote);
+        }
+        Some(event)
+    }
+}
+
+impl Default for MergedMarketDataStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl MarketDataStream for MergedMarketDataStream {
+    type Error = BoxError;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        if let Some(quote) = self.pending.pop_front() {
+            return Ok(Some(quote));
+        }
+
+        loop {
+            let waiting: Vec<usize> = self
+                .sources
+                .iter()
+                .enumerate()
+                .filter(|(_, source)| source.head.is_none() && !source.exhausted)
+                .map(|(index, _)| index)
+                .collect();
+
+            let earliest = self.earliest_head();
+            if waiting.is_empty() {
+                return Ok(earliest.and_then(|index| self.release(index)));
+            }
+
+            let reorder_window = self.reorder_window;
+            let (index, result) = {
+                let pending = self
+                    .sources
+                    .iter_mut()
+                    .enumerate()
+                    .filter(|(index, _)| waiting.contains(index))
+                    .map(|(index, source)| {
+                        source
+                            .stream
+                            .next()
+                            .map(move |result| (index, result))
+                            .boxed()
+                    });
+                let any = futures::future::select_all(pending);
+
+                if earliest.is_some() {
+                    match tokio::time::timeout(reorder_window, any).await {
+                        Ok((next, _, _)) => next,
+                        Err(_) => break,
+                    }
+                } else {
+                    any.await.0
+                }
+            };
+
+            match result? {
+                Some(event) => self.sources[index].head = Some(event),
+                None => {
+                    // Quotes of a finished source would otherwise stay best forever
+                    let source = &mut self.sources[index];
+                    source.exhausted = true;
+                    let ended = source.last_receipt.unwrap_or_default();
+                    for exchange in std::mem::take(&mut source.venues) {
+                        self.pending
+                            .extend(self.consolidated.remove_venue(exchange, ended));
+                    }
+                    if let Some(quote) = self.pending.pop_front() {
+                        return Ok(Some(quote));
+                    }
+                }
+            }
+        }
+
+        // Reorder window elapsed with some sources still quiet
+        Ok(self.earliest_head().and_then(|index| self.release(index)))
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        for source in &mut self.sources {
+            source.stream.subscribe(instruments).await?;
+        }
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        for source in &mut self.sources {
+            source.stream.unsubscribe(instruments).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Top of book last seen on a single venue
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct VenueQuote {
+    bid: Option<(Decimal, Decimal)>,
+    ask: Option<(Decimal, Decimal)>,
+    received: DateTime<Utc>,
+}
+
+/// Normalized base, quote and instrument kind, so spot and futures books stay apart
+type PairKey = (String, String, InstrumentKind);
+
+/// Consolidated best bid/offer across venues, keyed by normalized base/quote pair
+#[derive(Debug, Clone, Default)]
+pub struct ConsolidatedBbo {
+    venues: HashMap<PairKey, BTreeMap<ExchangeId, VenueQuote>>,
+    quotes: HashMap<PairKey, ConsolidatedQuote>,
+    max_age: Option<chrono::Duration>,
+}
+
+impl ConsolidatedBbo {
+    /// Create an empty consolidated book
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ignore venue quotes received more than `max_age` before the latest update
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(chrono::Duration::from_std(max_age).unwrap_or(chrono::Duration::MAX));
+        self
+    }
+
+    /// Current consolidated spot quote for a base/quote pair, if every side is quoted somewhere
+    pub fn quote(&self, base: &str, quote: &str) -> Option<&ConsolidatedQuote> {
+        self.quote_for(base, quote, InstrumentKind::Spot)
+    }
+
+    /// Current consolidated quote for a base/quote pair of the given instrument kind
+    pub fn quote_for(
+        &self,
+        base: &str,
+        quote: &str,
+        kind: InstrumentKind,
+    ) -> Option<&ConsolidatedQuote> {
+        self.quotes
+            .get(&(base.to_uppercase(), quote.to_uppercase(), kind))
+    }
+
+    /// Apply a venue's book update.
+    ///
+    /// Returns a `ConsolidatedQuote` event when the best price or the venue holding it
+    /// changes on either side.
+    pub fn update(&mut self, event: &MarketEvent) -> Option<MarketEvent> {
+        let venue_quote = match &event.kind {
+            MarketDataKind::OrderBookL1(book) => VenueQuote {
+                bid: Some((book.bid_price, book.bid_quantity)),
+                ask: Some((book.ask_price, book.ask_quantity)),
+                received: event.receipt_time,
+            },
+            MarketDataKind::OrderBookL2(book) => VenueQuote {
+                bid: book.bids.first().map(|level| (level.price, level.quantity)),
+                ask: book.asks.first().map(|level| (level.price, level.quantity)),
+                received: event.receipt_time,
+            },
+            _ => return None,
+        };
+
+        let key = (
+            event.instrument.base.to_uppercase(),
+            event.instrument.quote.to_uppercase(),
+            event.instrument.kind,
+        );
+        self.venues
+            .entry(key.clone())
+            .or_default()
+            .insert(event.exchange, venue_quote);
+        self.consolidate(key, event.exchange_time, event.receipt_time)
+    }
+
+    /// Drop every quote of a venue, e.g. when its stream ends, returning the
+    /// consolidated quotes that changed as a result
+    pub fn remove_venue(&mut self, exchange: ExchangeId, now: DateTime<Utc>) -> Vec<MarketEvent> {
+        let keys: Vec<PairKey> = self
+            .venues
+            .iter_mut()
+            .filter_map(|(key, venues)| venues.remove(&exchange).map(|_| key.clone()))
+            .collect();
+        keys.into_iter()
+            .filter_map(|key| self.consolidate(key, now, now))
+            .collect()
+    }
+
+    /// Recompute the consolidated quote of a pair, returning an event if the best price
+    /// or the venue holding it changed on either side
+    fn consolidate(
+        &mut self,
+        key: PairKey,
+        exchange_time: DateTime<Utc>,
+        receipt_time: DateTime<Utc>,
+    ) -> Option<MarketEvent> {
+        let venues = self.venues.get_mut(&key)?;
+        if let Some(max_age) = self.max_age {
+            venues.retain(|_, quote| receipt_time - quote.received <= max_age);
+        }
+
+        let best_quote = best(venues, |quote| quote.bid, |a, b| a > b).zip(best(
+            venues,
+            |quote| quote.ask,
+            |a, b| a < b,
+        ));
+        // Without a bid and an ask anywhere there is no consolidated quote
+        let Some((bid, ask)) = best_quote else {
+            self.quotes.remove(&key);
+            return None;
+        };
+        let (bid_exchange, (bid_price, bid_quantity)) = bid;
+        let (ask_exchange, (ask_price, ask_quantity)) = ask;
+        let quote = ConsolidatedQuote {
+            bid_price,
+            bid_quantity,
+            bid_exchange,
+            ask_price,
+            ask_quantity,
+            ask_exchange,
+            timestamp: exchange_time,
+        };
+
+        let changed = self.quotes.get(&key).is_none_or(|current| {
+            (
+                current.bid_price,
+                current.bid_exchange,
+                current.ask_price,
+                current.ask_exchange,
+            ) != (
+                quote.bid_price,
+                quote.bid_exchange,
+                quote.ask_price,
+                quote.ask_exchange,
+            )
+        });
+        let (base, quote_currency, kind) = key.clone();
+        self.quotes.insert(key, quote.clone());
+        if !changed {
+            return None;
+        }
+
+        Some(MarketEvent {
+            exchange: ExchangeId::Synthetic,
+            instrument: consolidated_instrument(base, quote_currency, kind),
+            kind: MarketDataKind::ConsolidatedQuote(quote),
+            exchange_time,
+            receipt_time,
+        })
+    }
+}
+
+/// Pick the venue with the best price on one side, preferring larger size on ties
+fn best(
+    venues: &BTreeMap<ExchangeId, VenueQuote>,
+    side: impl Fn(&VenueQuote) -> Option<(Decimal, Decimal)>,
+    better: impl Fn(Decimal, Decimal) -> bool,
+) -> Option<(ExchangeId, (Decimal, Decimal))> {
+    venues
+        .iter()
+        .filter_map(|(exchange, quote)| side(quote).map(|level| (*exchange, level)))
+        .reduce(|current, candidate| {
+            let (price, quantity) = candidate.1;
+            let (best_price, best_quantity) = current.1;
+            if better(price, best_price) || (price == best_price && quantity > best_quantity) {
+                candidate
+            } else {
+                current
+            }
+        })
+}
+
+/// Instrument identifying a consolidated quote, e.g. `BTC/USD`
+fn consolidated_instrument(base: String, quote: String, kind: InstrumentKind) -> InstrumentId {
+    InstrumentId {
+        exchange_symbol: format!("{}/{}", base, quote),
+        base,
+        quote,
+        kind,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{spot_instrument, MockMarketDataStream, OrderBookL1, PublicTrade, Side};
+    use chrono::{DateTime, TimeZone, Utc};
+    use rust_decimal_macros::dec;
+
+    fn at(millis: i64) -> DateTime<Utc> {
+        Utc.timestamp_millis_opt(1_700_000_000_000 + millis)
+            .unwrap()
+    }
+
+    fn quote(
+        exchange: ExchangeId,
+        symbol: &str,
+        millis: i64,
+        bid: Decimal,
+        ask: Decimal,
+    ) -> MarketEvent {
+        MarketEvent {
+            exchange,
+            instrument: InstrumentId {
+                exchange_symbol: symbol.to_string(),
+                ..spot_instrument("BTC", "USD")
+            },
+            kind: MarketDataKind::OrderBookL1(OrderBookL1 {
+                bid_price: bid,
+                bid_quantity: dec!(1),
+                ask_price: ask,
+                ask_quantity: dec!(1),
+                timestamp: at(millis),
+                update_id: None,
+            }),
+            exchange_time: at(millis),
+            receipt_time: at(millis),
+        }
+    }
+
+    fn trade(exchange: ExchangeId, symbol: &str, millis: i64) -> MarketEvent {
+        MarketEvent {
+            exchange,
+            instrument: InstrumentId {
+                exchange_symbol: symbol.to_string(),
+                ..spot_instrument("BTC", "USD")
+            },
+            kind: MarketDataKind::Trade(PublicTrade {
+                id: millis.to_string(),
+                price: dec!(100),
+                quantity: dec!(1),
+                side: Side::Buy,
+                timestamp: at(millis),
+            }),
+            exchange_time: at(millis),
+            receipt_time: at(millis),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_merge_orders_by_receipt_time() {
+        let coinbase = MockMarketDataStream::new(vec![
+            trade(ExchangeId::Coinbase, "BTC-USD", 1),
+            trade(ExchangeId::Coinbase, "BTC-USD", 4),
+            trade(ExchangeId::Coinbase, "BTC-USD", 5),
+        ]);
+        let kraken = MockMarketDataStream::new(vec![
+            trade(ExchangeId::Kraken, "BTC/USD", 2),
+            trade(ExchangeId::Kraken, "BTC/USD", 3),
+            trade(ExchangeId::Kraken, "BTC/USD", 6),
+        ]);
+        let mut merged = MergedMarketDataStream::new()
+            .with_stream(coinbase)
+            .with_stream(kraken);
+
+        let mut times = Vec::new();
+        while let Some(event) = merged.next().await.unwrap() {
+            times.push(event.receipt_time);
+        }
+        assert_eq!(times, (1..=6).map(at).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_consolidated_quote_emitted_on_best_price_change() {
+        let coinbase = MockMarketDataStream::new(vec![
+            quote(ExchangeId::Coinbase, "BTC-USD", 1, dec!(100), dec!(102)),
+            quote(ExchangeId::Coinbase, "BTC-USD", 3, dec!(100), dec!(102)),
+        ]);
+        let kraken = MockMarketDataStream::new(vec![
+            quote(ExchangeId::Kraken, "BTC/USD", 2, dec!(101), dec!(103)),
+            quote(ExchangeId::Kraken, "BTC/USD", 4, dec!(99), dec!(101)),
+        ]);
+        let mut merged = MergedMarketDataStream::new()
+            .with_stream(coinbase)
+            .with_stream(kraken);
+
+        let mut consolidated = Vec::new();
+        while let Some(event) = merged.next().await.unwrap() {
+            if let MarketDataKind::ConsolidatedQuote(quote) = event.kind {
+                assert_eq!(event.instrument.exchange_symbol, "BTC/USD");
+                assert_eq!(event.exchange, ExchangeId::Synthetic);
+                consolidated.push(quote);
+            }
+        }
+
+        // The unchanged Coinbase quote at t=3 does not produce a new consolidated quote
+        assert_eq!(consolidated.len(), 4);
+        assert_eq!(consolidated[1].bid_price, dec!(101));
+        assert_eq!(consolidated[1].bid_exchange, ExchangeId::Kraken);
+        assert_eq!(consolidated[1].ask_exchange, ExchangeId::Coinbase);
+        // Coinbase ends before Kraken's last quote, taking its best ask with it
+        assert_eq!(consolidated[2].ask_price, dec!(103));
+        assert_eq!(consolidated[2].ask_exchange, ExchangeId::Kraken);
+        assert_eq!(consolidated[2].timestamp, at(3));
+        assert_eq!(consolidated[3].bid_price, dec!(99));
+        assert_eq!(consolidated[3].ask_price, dec!(101));
+    }
+
+    #[test]
+    fn test_stale_venue_quotes_expire() {
+        let mut bbo = ConsolidatedBbo::new().with_max_age(Duration::from_millis(100));
+        bbo.update(&quote(
+            ExchangeId::Coinbase,
+            "BTC-USD",
+            0,
+            dec!(101),
+            dec!(102),
+        ));
+        let event = bbo
+            .update(&quote(
+                ExchangeId::Kraken,
+                "BTC/USD",
+                50,
+                dec!(100),
+                dec!(103),
+            ))
+            .is_none();
+        assert!(event, "Coinbase still holds both sides");
+
+        // Coinbase's quote is 150ms old by Kraken's next update
+        let event = bbo
+            .update(&quote(
+                ExchangeId::Kraken,
+                "BTC/USD",
+                150,
+                dec!(100),
+                dec!(103),
+            ))
+            .unwrap();
+        let MarketDataKind::ConsolidatedQuote(quote) = event.kind else {
+            panic!("expected consolidated quote");
+        };
+        assert_eq!(
+            (quote.bid_exchange, quote.ask_exchange),
+            (ExchangeId::Kraken, ExchangeId::Kraken)
+        );
+        assert_eq!(bbo.quote("btc", "usd").unwrap().bid_price, dec!(100));
+
+        assert!(bbo.remove_venue(ExchangeId::Kraken, at(200)).is_empty());
+        assert!(bbo.quote("BTC", "USD").is_none());
+    }
+}
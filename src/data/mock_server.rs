@@ -0,0 +1,445 @@
+//! Local test servers for exercising connectors offline; only built for unit tests
+//!
+//! The WebSocket server accepts connections on a loopback port, waits for the client's
+//! first message (normally a subscription request), replays a fixed list of frames and
+//! then closes the connection, or holds it open when started with `start_held_open`.
+//! Every text message the client sends is recorded so tests can assert on
+//! subscription payloads.
+//!
+//! The HTTP server answers each request with the next canned response and records the
+//! request targets, which is enough to test paging and retry logic of REST clients.
+//!
+//! The FIX acceptor answers a Logon through its own `FixSession`, sends a fixed list of
+//! application messages after each MarketDataRequest subscription and records every
+//! message the client sends. Selected messages can be withheld on their first
+//! transmission so the client's gap detection and resend handling get exercised.
+//!
+//! The snapshot server plays the TCP recovery channel of a multicast feed, answering
+//! each connection with the next snapshot and closing it.
+
+use super::fix::message::{msg_type, tags};
+use super::fix::{FixMessage, FixSession, FixSessionConfig};
+use super::multicast::Snapshot;
+use futures::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+/// Mock WebSocket server that replays recorded frames
+pub struct MockWebSocketServer {
+    addr: SocketAddr,
+    received: Arc<Mutex<Vec<String>>>,
+    connections: Arc<AtomicUsize>,
+    handle: JoinHandle<()>,
+}
+
+impl MockWebSocketServer {
+    /// Start a server on an ephemeral loopback port that replays `frames` to each client
+    pub async fn start(frames: Vec<String>) -> std::io::Result<Self> {
+        Self::serve(frames, true).await
+    }
+
+    /// Start a server that replays `frames` and then keeps the connection open until
+    /// the client closes it, for tests sending several requests over one connection
+    pub async fn start_held_open(frames: Vec<String>) -> std::io::Result<Self> {
+        Self::serve(frames, false).await
+    }
+
+    async fn serve(frames: Vec<String>, close: bool) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let connections = Arc::new(AtomicUsize::new(0));
+        let frames = Arc::new(frames);
+
+        let handle = {
+            let received = received.clone();
+            let connections = connections.clone();
+            tokio::spawn(async move {
+                while let Ok((tcp_stream, _)) = listener.accept().await {
+                    connections.fetch_add(1, Ordering::Relaxed);
+                    let received = received.clone();
+                    let frames = frames.clone();
+                    tokio::spawn(async move {
+                        let Ok(ws_stream) = tokio_tungstenite::accept_async(tcp_stream).await
+                        else {
+                            return;
+                        };
+                        let (mut write, mut read) = ws_stream.split();
+                        let (first_tx, first_rx) = tokio::sync::oneshot::channel();
+
+                        // Record everything the client sends for the lifetime of the connection
+                        tokio::spawn(async move {
+                            let mut first_tx = Some(first_tx);
+                            while let Some(Ok(msg)) = read.next().await {
+                                if let Message::Text(text) = msg {
+                                    received.lock().push(text.to_string());
+                                    if let Some(tx) = first_tx.take() {
+                                        let _ = tx.send(());
+                                    }
+                                }
+                            }
+                        });
+
+                        if first_rx.await.is_err() {
+                            return;
+                        }
+                        for frame in frames.iter() {
+                            if write
+                                .send(Message::Text(frame.clone().into()))
+                                .await
+                                .is_err()
+                            {
+                                return;
+                            }
+                        }
+                        // Otherwise the recording task keeps the connection open until the
+                        // client closes it
+                        if close {
+                            let _ = write.send(Message::Close(None)).await;
+                        }
+                    });
+                }
+            })
+        };
+
+        Ok(Self {
+            addr,
+            received,
+            connections,
+            handle,
+        })
+    }
+
+    /// Start a server replaying a newline-delimited fixture, skipping blank lines
+    pub async fn from_fixture(fixture: &str) -> std::io::Result<Self> {
+        let frames = fixture
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect();
+        Self::start(frames).await
+    }
+
+    /// WebSocket URL of the server
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+
+    /// Text messages received from clients so far
+    pub fn received_messages(&self) -> Vec<String> {
+        self.received.lock().clone()
+    }
+
+    /// Connections accepted so far
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    /// Wait until at least `count` messages have been received, or a second has passed
+    pub async fn wait_for_messages(&self, count: usize) -> Vec<String> {
+        for _ in 0..100 {
+            if self.received.lock().len() >= count {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        self.received_messages()
+    }
+}
+
+impl Drop for MockWebSocketServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Canned response served by `MockHttpServer`
+#[derive(Debug, Clone)]
+pub struct MockHttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl MockHttpResponse {
+    /// `200 OK` with a JSON body
+    pub fn json(body: impl Into<String>) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.into(),
+        }
+    }
+
+    /// Empty response with the given status
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    /// Add a response header
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// Mock HTTP/1.1 server answering requests with queued responses in order
+pub struct MockHttpServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<String>>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockHttpServer {
+    /// Start a server on an ephemeral loopback port; requests beyond the queue get a 404
+    pub async fn start(responses: Vec<MockHttpResponse>) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let responses = Arc::new(Mutex::new(VecDeque::from(responses)));
+
+        let handle = {
+            let requests = requests.clone();
+            tokio::spawn(async move {
+                while let Ok((mut tcp_stream, _)) = listener.accept().await {
+                    // Read the request head; the clients under test never send a body
+                    let mut head = Vec::new();
+                    let mut buffer = [0u8; 1024];
+                    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+                        match tcp_stream.read(&mut buffer).await {
+                            Ok(0) | Err(_) => break,
+                            Ok(n) => head.extend_from_slice(&buffer[..n]),
+                        }
+                    }
+                    let head = String::from_utf8_lossy(&head);
+                    let Some(target) = head
+                        .lines()
+                        .next()
+                        .and_then(|line| line.split_whitespace().nth(1))
+                    else {
+                        continue;
+                    };
+                    requests.lock().push(target.to_string());
+
+                    let response = responses
+                        .lock()
+                        .pop_front()
+                        .unwrap_or_else(|| MockHttpResponse::status(404));
+                    let mut raw = format!(
+                        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+                        response.status,
+                        response.body.len()
+                    );
+                    for (name, value) in &response.headers {
+                        raw.push_str(&format!("{}: {}\r\n", name, value));
+                    }
+                    raw.push_str("\r\n");
+                    raw.push_str(&response.body);
+                    let _ = tcp_stream.write_all(raw.as_bytes()).await;
+                    let _ = tcp_stream.shutdown().await;
+                }
+            })
+        };
+
+        Ok(Self {
+            addr,
+            requests,
+            handle,
+        })
+    }
+
+    /// Base URL of the server
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Request targets (path and query) received so far
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().clone()
+    }
+}
+
+impl Drop for MockHttpServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Mock FIX acceptor serving canned market data
+pub struct MockFixAcceptor {
+    addr: SocketAddr,
+    received: Arc<Mutex<Vec<FixMessage>>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockFixAcceptor {
+    /// Start an acceptor on an ephemeral loopback port using `comp_id` as its
+    /// SenderCompID. Each subscription is answered with `responses`, except that the
+    /// responses at the indices in `dropped` are sequenced but not written, so they
+    /// only reach the client through a resend.
+    pub async fn start(
+        comp_id: &str,
+        responses: Vec<FixMessage>,
+        dropped: Vec<usize>,
+    ) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let comp_id = comp_id.to_string();
+        let responses = Arc::new(responses);
+
+        let handle = {
+            let received = received.clone();
+            tokio::spawn(async move {
+                while let Ok((tcp_stream, _)) = listener.accept().await {
+                    tokio::spawn(Self::serve(
+                        tcp_stream,
+                        comp_id.clone(),
+                        responses.clone(),
+                        dropped.clone(),
+                        received.clone(),
+                    ));
+                }
+            })
+        };
+
+        Ok(Self {
+            addr,
+            received,
+            handle,
+        })
+    }
+
+    async fn serve(
+        mut tcp_stream: tokio::net::TcpStream,
+        comp_id: String,
+        responses: Arc<Vec<FixMessage>>,
+        dropped: Vec<usize>,
+        received: Arc<Mutex<Vec<FixMessage>>>,
+    ) {
+        let mut buffer = Vec::new();
+        let mut session: Option<FixSession> = None;
+        loop {
+            let Ok(Some((message, len))) = FixMessage::parse(&buffer) else {
+                match tcp_stream.read_buf(&mut buffer).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(_) => continue,
+                }
+            };
+            buffer.drain(..len);
+            received.lock().push(message.clone());
+
+            let now = std::time::Instant::now();
+            let session = session.get_or_insert_with(|| {
+                let target = message.get(tags::SENDER_COMP_ID).unwrap_or_default();
+                FixSession::new(FixSessionConfig::new(comp_id.as_str(), target), now)
+            });
+            let Ok(output) = session.on_message(message, now) else {
+                return;
+            };
+            let mut outgoing = output.replies;
+            for request in output.messages {
+                let subscribe = request.msg_type() == msg_type::MARKET_DATA_REQUEST
+                    && request.get(tags::SUBSCRIPTION_REQUEST_TYPE) == Some("1");
+                if !subscribe {
+                    continue;
+                }
+                for (index, response) in responses.iter().enumerate() {
+                    let response = session.send(response.clone(), now);
+                    if !dropped.contains(&index) {
+                        outgoing.push(response);
+                    }
+                }
+            }
+            for message in outgoing {
+                if tcp_stream.write_all(&message.encode()).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Address of the acceptor
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Messages received from clients so far
+    pub fn received(&self) -> Vec<FixMessage> {
+        self.received.lock().clone()
+    }
+}
+
+impl Drop for MockFixAcceptor {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Mock snapshot channel of a multicast feed
+pub struct MockSnapshotServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<usize>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockSnapshotServer {
+    /// Start a server on an ephemeral loopback port serving `snapshots` in order; the
+    /// last one is repeated once the others are used up
+    pub async fn start(snapshots: Vec<Snapshot>) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let requests = Arc::new(Mutex::new(0));
+
+        let handle = {
+            let requests = requests.clone();
+            tokio::spawn(async move {
+                while let Ok((mut tcp_stream, _)) = listener.accept().await {
+                    let served = {
+                        let mut requests = requests.lock();
+                        *requests += 1;
+                        *requests - 1
+                    };
+                    let Some(snapshot) = snapshots.get(served).or(snapshots.last()) else {
+                        continue;
+                    };
+                    let _ = tcp_stream.write_all(&snapshot.encode()).await;
+                    let _ = tcp_stream.shutdown().await;
+                }
+            })
+        };
+
+        Ok(Self {
+            addr,
+            requests,
+            handle,
+        })
+    }
+
+    /// Address of the server
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Number of snapshot requests served so far
+    pub fn requests(&self) -> usize {
+        *self.requests.lock()
+    }
+}
+
+impl Drop for MockSnapshotServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
@@ -9,6 +9,64 @@ use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
+pub mod analytics;
+pub mod bars;
+pub mod binance;
+pub mod book;
+pub mod bus;
+pub mod capture;
+pub mod coinbase;
+pub mod connector;
+pub mod derived;
+pub mod fix;
+pub mod fixed;
+pub mod frames;
+pub mod kraken;
+pub mod l3;
+pub mod latency;
+pub mod merge;
+#[cfg(test)]
+mod mock_server;
+pub mod multicast;
+pub mod quality;
+pub mod recorder;
+pub mod replay;
+pub mod stream;
+pub mod subscription;
+pub mod synthetic;
+
+pub use analytics::{BookAnalytics, BookAnalyticsConfig, BookAnalyticsError, BookAnalyticsStream};
+pub use bars::{BarAggregator, BarAggregatorStream, BarError, BarKind};
+pub use binance::{BinanceFuturesMarketDataStream, BinanceMarketDataStream};
+pub use book::{OrderBook, OrderBookError};
+pub use bus::{BusSubscriber, MarketDataBus, SlowConsumerPolicy, SubscriberStats};
+pub use capture::{CaptureReader, CaptureWriter, Compression};
+pub use coinbase::{CoinbaseFullChannelStream, CoinbaseMarketDataStream};
+pub use connector::{ExchangeConnector, Heartbeat, RateLimit, WebSocketMarketDataStream};
+pub use derived::{DerivedError, DerivedInstrument, DerivedInstrumentStream, Leg};
+pub use fix::{FixError, FixMarketDataStream, FixSessionConfig};
+pub use fixed::{FixedPointError, InstrumentScale, Price, Qty};
+pub use frames::{FrameCaptureReader, FrameCaptureWriter, FrameReplay, RawFrame};
+pub use kraken::KrakenMarketDataStream;
+pub use l3::{L3Order, L3OrderBook, QueuePosition};
+pub use latency::{
+    FeedHealth, FeedLatencyMonitor, FeedStatus, LatencyMonitorConfig, LatencyReport,
+    MonitoredMarketDataStream,
+};
+pub use merge::{ConsolidatedBbo, MergedMarketDataStream};
+pub use multicast::{MulticastError, MulticastMarketDataStream};
+pub use quality::{DataQualityFilter, QualityAction, QualityConfig, QualityFilteredStream};
+pub use recorder::{MarketDataRecorder, RecorderConfig, RecordingMarketDataStream};
+pub use replay::{ReplayMarketDataStream, ReplaySpeed};
+pub use stream::{MarketDataEvents, MarketDataStreamExt, StreamMarketDataStream};
+pub use subscription::{
+    ConflatedMarketDataStream, Conflator, InstrumentSubscription, SubscriptionKind,
+};
+pub use synthetic::{
+    BookParams, HawkesParams, PriceModel, Scenario, SyntheticConfig, SyntheticError,
+    SyntheticMarketDataStream,
+};
+
 /// Market data kind enum
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub enum MarketDataKind {
@@ -18,19 +76,64 @@ pub enum MarketDataKind {
     OrderBookL1(OrderBookL1),
     /// Level 2 order book data (full order book)
     OrderBookL2(OrderBookL2),
+    /// Incremental level 2 order book changes
+    OrderBookDelta(OrderBookDelta),
+    /// Level 3 (order-by-order) book message
+    OrderBookL3(OrderBookL3Update),
     /// Candlestick data
     Candle(Candle),
+    /// Best bid/ask across all venues quoting an instrument
+    ConsolidatedQuote(ConsolidatedQuote),
+    /// Rolling 24 hour ticker statistics
+    Ticker(Ticker),
+    /// Problem detected in the feed for the event's instrument
+    DataQuality(DataQualityEvent),
+    /// Funding rate of a perpetual contract
+    FundingRate(FundingRate),
+    /// Mark price of a derivative contract
+    MarkPrice(MarkPrice),
+    /// Open contracts of a derivative
+    OpenInterest(OpenInterest),
+    /// Forced liquidation order
+    Liquidation(Liquidation),
+    /// Microstructure features derived from the instrument's book
+    BookFeatures(BookFeatures),
+}
+
+impl MarketDataKind {
+    /// Short stable name of the variant, e.g. `orderbook_l1`, for keys and metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            MarketDataKind::Trade(_) => "trade",
+            MarketDataKind::OrderBookL1(_) => "orderbook_l1",
+            MarketDataKind::OrderBookL2(_) => "orderbook_l2",
+            MarketDataKind::OrderBookDelta(_) => "orderbook_delta",
+            MarketDataKind::OrderBookL3(_) => "orderbook_l3",
+            MarketDataKind::Candle(_) => "candle",
+            MarketDataKind::ConsolidatedQuote(_) => "consolidated_quote",
+            MarketDataKind::Ticker(_) => "ticker",
+            MarketDataKind::DataQuality(_) => "data_quality",
+            MarketDataKind::FundingRate(_) => "funding_rate",
+            MarketDataKind::MarkPrice(_) => "mark_price",
+            MarketDataKind::OpenInterest(_) => "open_interest",
+            MarketDataKind::Liquidation(_) => "liquidation",
+            MarketDataKind::BookFeatures(_) => "book_features",
+        }
+    }
 }
 
 /// Public trade information
+///
+/// Prices and quantities are `Decimal` by default; `PublicTrade<Price, Qty>` is the
+/// fixed-point form used on the hot path.
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
-pub struct PublicTrade {
+pub struct PublicTrade<P = Decimal, Q = Decimal> {
     /// Trade ID
     pub id: String,
     /// Price of the trade
-    pub price: Decimal,
+    pub price: P,
     /// Quantity of the trade
-    pub quantity: Decimal,
+    pub quantity: Q,
     /// Side of the trade (buy/sell)
     pub side: Side,
     /// Timestamp of the trade
@@ -46,28 +149,50 @@ pub enum Side {
     Sell,
 }
 
-/// Level 1 order book (best bid/ask)
+/// Level 1 order book (best bid/ask), generic over price and quantity like `PublicTrade`
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
-pub struct OrderBookL1 {
+pub struct OrderBookL1<P = Decimal, Q = Decimal> {
     /// Best bid price
-    pub bid_price: Decimal,
+    pub bid_price: P,
     /// Best bid quantity
-    pub bid_quantity: Decimal,
+    pub bid_quantity: Q,
     /// Best ask price
-    pub ask_price: Decimal,
+    pub ask_price: P,
     /// Best ask quantity
+    pub ask_quantity: Q,
+    /// Timestamp
+    pub timestamp: DateTime<Utc>,
+    /// Exchange book update id, if the venue provides one
+    #[serde(default)]
+    pub update_id: Option<u64>,
+}
+
+/// Cross-venue best bid/ask for a normalized instrument
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ConsolidatedQuote {
+    /// Best bid price across venues
+    pub bid_price: Decimal,
+    /// Quantity at the best bid on the best bid venue
+    pub bid_quantity: Decimal,
+    /// Venue with the best bid
+    pub bid_exchange: ExchangeId,
+    /// Best ask price across venues
+    pub ask_price: Decimal,
+    /// Quantity at the best ask on the best ask venue
     pub ask_quantity: Decimal,
+    /// Venue with the best ask
+    pub ask_exchange: ExchangeId,
     /// Timestamp
     pub timestamp: DateTime<Utc>,
 }
 
-/// Price level in an order book
+/// Price level in an order book, generic over price and quantity like `PublicTrade`
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
-pub struct PriceLevel {
+pub struct PriceLevel<P = Decimal, Q = Decimal> {
     /// Price of the level
-    pub price: Decimal,
+    pub price: P,
     /// Quantity at the level
-    pub quantity: Decimal,
+    pub quantity: Q,
 }
 
 /// Level 2 order book (full order book)
@@ -81,6 +206,78 @@ pub struct OrderBookL2 {
     pub timestamp: DateTime<Utc>,
 }
 
+/// Kind of change applied to a price level
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum LevelAction {
+    /// New price level
+    Insert,
+    /// New quantity at an existing level; venues that do not distinguish inserts use this
+    Update,
+    /// Level removed; the quantity is ignored
+    Delete,
+}
+
+/// Change to a single price level
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LevelUpdate {
+    /// Book side, `Buy` for bids and `Sell` for asks
+    pub side: Side,
+    /// Price of the level
+    pub price: Decimal,
+    /// New total quantity at the level
+    pub quantity: Decimal,
+    /// Kind of change
+    pub action: LevelAction,
+}
+
+/// Incremental level 2 order book update
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct OrderBookDelta {
+    /// Level changes in the order the venue sent them
+    pub updates: Vec<LevelUpdate>,
+    /// First exchange update id covered by this delta
+    pub first_update_id: Option<u64>,
+    /// Last exchange update id covered by this delta
+    pub last_update_id: Option<u64>,
+    /// Whether the delta replaces the whole book rather than modifying it
+    pub snapshot: bool,
+    /// Timestamp
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Kind of level 3 order message
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum L3Action {
+    /// Order placed on the book
+    Add,
+    /// Resting order changed size or price
+    Modify,
+    /// Order removed from the book without trading
+    Cancel,
+    /// Resting order traded against an incoming order
+    Execute,
+}
+
+/// Single order-by-order book message keyed by exchange order id
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct OrderBookL3Update {
+    /// Exchange order id
+    pub order_id: String,
+    /// Kind of message
+    pub action: L3Action,
+    /// Side of the resting order
+    pub side: Side,
+    /// Order price; the execution price for `Execute`
+    pub price: Decimal,
+    /// Size for `Add`, new remaining size for `Modify`, executed size for `Execute`;
+    /// ignored for `Cancel`
+    pub quantity: Decimal,
+    /// Exchange sequence number, if the venue provides one
+    pub sequence: Option<u64>,
+    /// Timestamp
+    pub timestamp: DateTime<Utc>,
+}
+
 /// Candlestick data
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Candle {
@@ -100,6 +297,130 @@ pub struct Candle {
     pub duration_secs: u64,
 }
 
+/// Rolling 24 hour ticker statistics
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Ticker {
+    /// Open price 24 hours ago
+    pub open: Decimal,
+    /// Highest price in the window
+    pub high: Decimal,
+    /// Lowest price in the window
+    pub low: Decimal,
+    /// Last price
+    pub close: Decimal,
+    /// Base asset volume
+    pub volume: Decimal,
+    /// Quote asset volume
+    pub quote_volume: Decimal,
+    /// Timestamp
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Market data problem found by a `DataQualityFilter`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum DataQualityIssue {
+    /// Best bid above best ask
+    CrossedBook { bid_price: Decimal, ask_price: Decimal },
+    /// Best bid equal to best ask
+    LockedBook { price: Decimal },
+    /// Trade priced outside the rolling band around recent trades
+    OutlierTrade { price: Decimal, reference: Decimal },
+    /// Trade id already seen for the instrument
+    DuplicateTrade { id: String },
+    /// No update for the instrument since `last_update`
+    Stale { last_update: DateTime<Utc> },
+    /// Messages were missed; books built from the feed must be discarded until the
+    /// venue's next snapshot
+    SequenceGap { expected: u64, received: u64 },
+}
+
+/// Data quality notification for an instrument
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DataQualityEvent {
+    /// What was wrong
+    pub issue: DataQualityIssue,
+    /// Whether the offending event was removed from the stream
+    pub dropped: bool,
+    /// Timestamp
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Perpetual funding rate
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FundingRate {
+    /// Rate paid from longs to shorts (negative: shorts pay longs) per funding interval
+    pub rate: Decimal,
+    /// When the rate is next applied
+    pub next_funding_time: DateTime<Utc>,
+    /// Timestamp
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Mark price of a derivative, used for margining and liquidation
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MarkPrice {
+    /// Mark price
+    pub price: Decimal,
+    /// Spot index price the mark price tracks, if published
+    pub index_price: Option<Decimal>,
+    /// Timestamp
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Total open contracts of a derivative
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct OpenInterest {
+    /// Open interest in contracts (base asset for linear contracts)
+    pub quantity: Decimal,
+    /// Timestamp
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Forced liquidation of a position
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Liquidation {
+    /// Side of the liquidation order; `Sell` closes a long position
+    pub side: Side,
+    /// Order price
+    pub price: Decimal,
+    /// Order quantity
+    pub quantity: Decimal,
+    /// Average fill price
+    pub average_price: Decimal,
+    /// Quantity filled so far
+    pub filled_quantity: Decimal,
+    /// Timestamp
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Order book microstructure features, see `analytics::BookAnalytics`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BookFeatures {
+    /// Mid price
+    pub mid: Decimal,
+    /// Best ask minus best bid
+    pub spread: Decimal,
+    /// Spread in ticks, if the instrument's tick size is known
+    pub spread_ticks: Option<Decimal>,
+    /// Top of book quantity imbalance in [-1, 1], positive when bids are larger
+    pub top_imbalance: Decimal,
+    /// Quantity imbalance over the configured number of levels
+    pub depth_imbalance: Decimal,
+    /// Mid weighted by the opposite side's top of book quantity
+    pub microprice: Decimal,
+    /// Microprice generalised to the configured depth: each side's average price
+    /// weighted by the opposite side's total quantity
+    pub weighted_mid: Decimal,
+    /// Imbalance with level quantities decayed geometrically away from the touch
+    pub book_pressure: Decimal,
+    /// Order-flow imbalance contributed by this update
+    pub ofi: Decimal,
+    /// Order-flow imbalance summed over the configured window of updates
+    pub ofi_window: Decimal,
+    /// Timestamp of the book the features were computed from
+    pub timestamp: DateTime<Utc>,
+}
+
 /// Market event
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, From)]
 pub struct MarketEvent<Kind = MarketDataKind> {
@@ -115,13 +436,37 @@ pub struct MarketEvent<Kind = MarketDataKind> {
     pub receipt_time: DateTime<Utc>,
 }
 
+impl MarketEvent {
+    /// Exchange time usable as the venue's clock, or `None` for events stamped locally.
+    ///
+    /// Binance bookTicker and partial depth messages carry no event time, so their
+    /// `OrderBookL1` and `OrderBookL2` events are stamped on receipt, and consolidated
+    /// quotes, book features and quality reports are produced locally. Every other
+    /// event carries exchange time, including archived, backfilled and replayed data
+    /// whose receipt time equals it.
+    pub fn exchange_clock(&self) -> Option<DateTime<Utc>> {
+        let local = match &self.kind {
+            MarketDataKind::OrderBookL1(_) | MarketDataKind::OrderBookL2(_) => {
+                self.exchange == ExchangeId::Binance
+            }
+            MarketDataKind::ConsolidatedQuote(_)
+            | MarketDataKind::BookFeatures(_)
+            | MarketDataKind::DataQuality(_) => true,
+            _ => false,
+        };
+        (!local).then_some(self.exchange_time)
+    }
+}
+
 /// Exchange identifier
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
 pub enum ExchangeId {
     Binance,
     Coinbase,
     Kraken,
     Ftx,
+    /// Instruments and quotes derived locally from other venues' data
+    Synthetic,
     // Add more exchanges as needed
 }
 
@@ -131,6 +476,31 @@ pub struct InstrumentId {
     pub base: String,
     pub quote: String,
     pub exchange_symbol: String,
+    /// Spot or derivative contract, so spot and futures on the same pair differ
+    #[serde(default)]
+    pub kind: InstrumentKind,
+}
+
+/// Spot instrument with a `{base}{quote}` exchange symbol, shared by unit tests
+#[cfg(test)]
+pub(crate) fn spot_instrument(base: &str, quote: &str) -> InstrumentId {
+    InstrumentId {
+        base: base.to_string(),
+        quote: quote.to_string(),
+        exchange_symbol: format!("{base}{quote}"),
+        kind: InstrumentKind::Spot,
+    }
+}
+
+/// Contract type of an instrument
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum InstrumentKind {
+    #[default]
+    Spot,
+    /// Perpetual swap settled through periodic funding payments
+    Perpetual,
+    /// Future settling at `expiry`
+    DatedFuture { expiry: DateTime<Utc> },
 }
 
 /// Market data stream trait
@@ -187,206 +557,15 @@ impl MarketDataStream for MockMarketDataStream {
     }
 }
 
-/// Binance real-time market data stream
-pub struct BinanceMarketDataStream {
-    receiver: Option<tokio::sync::mpsc::Receiver<MarketEvent>>,
-    instruments: Vec<InstrumentId>,
-}
+impl futures::Stream for MockMarketDataStream {
+    type Item = Result<MarketEvent, std::io::Error>;
 
-impl BinanceMarketDataStream {
-    /// Create a new Binance market data stream
-    pub fn new() -> Self {
-        Self {
-            receiver: None,
-            instruments: Vec::new(),
-        }
-    }
-    
-    /// Parse Binance WebSocket message into MarketEvent
-    fn parse_websocket_message(message: &str) -> Result<MarketEvent, Box<dyn std::error::Error + Send + Sync>> {
-        use serde_json::Value;
-        use std::str::FromStr;
-        
-        let v: Value = serde_json::from_str(message)?;
-        
-        // Handle different types of Binance messages
-        if let Some(stream) = v.get("stream").and_then(|s| s.as_str()) {
-            let data = v.get("data").ok_or("Missing data field")?;
-            
-            let timestamp = Utc::now();
-            let exchange = ExchangeId::Binance;
-            
-            // Parse trade data
-            if stream.ends_with("@trade") {
-                let instrument_symbol = stream.trim_end_matches("@trade");
-                let instrument = InstrumentId {
-                    base: instrument_symbol[..instrument_symbol.len()-4].to_uppercase(),
-                    quote: instrument_symbol[instrument_symbol.len()-4..].to_uppercase(),
-                    exchange_symbol: instrument_symbol.to_uppercase(),
-                };
-                
-                let price = Decimal::from_str(data.get("p").and_then(|p| p.as_str()).unwrap_or("0"))?;
-                let quantity = Decimal::from_str(data.get("q").and_then(|q| q.as_str()).unwrap_or("0"))?;
-                let side = if data.get("m").and_then(|m| m.as_bool()).unwrap_or(false) {
-                    Side::Sell
-                } else {
-                    Side::Buy
-                };
-                
-                let trade = PublicTrade {
-                    id: data.get("t").and_then(|t| t.as_u64()).unwrap_or(0).to_string(),
-                    price,
-                    quantity,
-                    side,
-                    timestamp,
-                };
-                
-                return Ok(MarketEvent {
-                    exchange,
-                    instrument,
-                    kind: MarketDataKind::Trade(trade),
-                    exchange_time: timestamp,
-                    receipt_time: timestamp,
-                });
-            }
-            // Parse order book data
-            else if stream.ends_with("@depth20") {
-                let instrument_symbol = stream.trim_end_matches("@depth20");
-                let instrument = InstrumentId {
-                    base: instrument_symbol[..instrument_symbol.len()-4].to_uppercase(),
-                    quote: instrument_symbol[instrument_symbol.len()-4..].to_uppercase(),
-                    exchange_symbol: instrument_symbol.to_uppercase(),
-                };
-                
-                if let Some(bids) = data.get("bids").and_then(|b| b.as_array()) {
-                    if let Some(asks) = data.get("asks").and_then(|a| a.as_array()) {
-                        // Get best bid/ask for L1 order book
-                        if let Some(best_bid_array) = bids.first().and_then(|b| b.as_array()) {
-                            if let Some(best_ask_array) = asks.first().and_then(|a| a.as_array()) {
-                                if best_bid_array.len() >= 2 && best_ask_array.len() >= 2 {
-                                    let bid_price_str = best_bid_array[0].as_str().unwrap_or("0");
-                                    let bid_quantity_str = best_bid_array[1].as_str().unwrap_or("0");
-                                    let ask_price_str = best_ask_array[0].as_str().unwrap_or("0");
-                                    let ask_quantity_str = best_ask_array[1].as_str().unwrap_or("0");
-                                    
-                                    let bid_price = Decimal::from_str(bid_price_str)?;
-                                    let bid_quantity = Decimal::from_str(bid_quantity_str)?;
-                                    let ask_price = Decimal::from_str(ask_price_str)?;
-                                    let ask_quantity = Decimal::from_str(ask_quantity_str)?;
-                                    
-                                    let orderbook = OrderBookL1 {
-                                        bid_price,
-                                        bid_quantity,
-                                        ask_price,
-                                        ask_quantity,
-                                        timestamp,
-                                    };
-                                    
-                                    return Ok(MarketEvent {
-                                        exchange,
-                                        instrument,
-                                        kind: MarketDataKind::OrderBookL1(orderbook),
-                                        exchange_time: timestamp,
-                                        receipt_time: timestamp,
-                                    });
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        
-        Err("Unknown message format".into())
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let event = self.events.get(self.current_index).cloned();
+        self.current_index += event.is_some() as usize;
+        std::task::Poll::Ready(event.map(Ok))
     }
 }
-
-#[async_trait::async_trait]
-impl MarketDataStream for BinanceMarketDataStream {
-    type Error = Box<dyn std::error::Error + Send + Sync>;
-    
-    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
-        if let Some(receiver) = &mut self.receiver {
-            match receiver.recv().await {
-                Some(event) => Ok(Some(event)),
-                None => Ok(None),
-            }
-        } else {
-            Ok(None)
-        }
-    }
-    
-    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
-        use tokio_tungstenite::tungstenite::protocol::Message;
-        use futures::{SinkExt, StreamExt};
-        use tokio::sync::mpsc;
-        
-        // Store instruments
-        self.instruments.extend_from_slice(instruments);
-        
-        // Create channel for sending market events
-        let (sender, receiver) = mpsc::channel(100);
-        self.receiver = Some(receiver);
-        
-        // Connect to Binance WebSocket
-        let ws_url = "wss://stream.binance.com:9443/ws";
-        let (mut ws_stream, _) = tokio_tungstenite::connect_async(ws_url).await?;
-        
-        // Subscribe to trade and order book streams
-        let mut subscription_messages = Vec::new();
-        for instrument in instruments {
-            let symbol = instrument.exchange_symbol.to_lowercase();
-            let subscription = serde_json::json!({
-                "method": "SUBSCRIBE",
-                "params": [format!("{}@trade", symbol), format!("{}@depth20", symbol)],
-                "id": 1
-            });
-            subscription_messages.push(subscription);
-        }
-        
-        // Send subscription messages
-        for subscription in subscription_messages {
-            let msg = Message::Text(serde_json::to_string(&subscription)?.into());
-            ws_stream.send(msg).await?;
-        }
-        
-        // Start listening for messages in a background task
-        tokio::spawn(async move {
-            let (mut write, mut read) = ws_stream.split();
-            
-            // Forward messages from the read stream to the sender
-            while let Some(msg) = read.next().await {
-                match msg {
-                    Ok(Message::Text(text)) => {
-                        // Parse the message and convert to MarketEvent
-                        if let Ok(event) = Self::parse_websocket_message(&text) {
-                            if sender.send(event).await.is_err() {
-                                break;
-                            }
-                        }
-                    }
-                    Ok(Message::Ping(data)) => {
-                        if let Err(_) = write.send(Message::Pong(data)).await {
-                            break;
-                        }
-                    }
-                    Ok(Message::Close(_)) => break,
-                    Err(_) => break,
-                    _ => {}
-                }
-            }
-        });
-        
-        Ok(())
-    }
-    
-    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
-        // For simplicity in this demo, we won't implement unsubscribe
-        // In a production system, you would send unsubscribe messages to the WebSocket
-        
-        // Remove instruments from our list
-        self.instruments.retain(|i| !instruments.contains(i));
-        
-        Ok(())
-    }
-}
\ No newline at end of file
@@ -0,0 +1,467 @@
+//! Sequencing and gap recovery of the multicast feed
+//!
+//! `FeedHandler` performs no I/O: it consumes decoded packets and snapshots and
+//! returns the resulting `MarketEvent`s together with whether a snapshot is needed.
+//! It starts out recovering, since book updates are only meaningful on top of a
+//! snapshot.
+//!
+//! Messages below the expected sequence number are duplicates, e.g. from the second
+//! line of an A/B feed, and are dropped. A message above it means packets were lost:
+//! the handler stops publishing, buffers everything that arrives and asks for a
+//! snapshot. The snapshot replaces every subscribed book and buffered messages after
+//! its sequence number are then applied; if the buffer does not continue where the
+//! snapshot ends, another snapshot is needed.
+
+use super::protocol::{BookUpdate, FeedMessage, Packet, Snapshot};
+use crate::data::{
+    ExchangeId, InstrumentId, InstrumentScale, LevelUpdate, MarketDataKind, MarketEvent,
+    OrderBookDelta, PublicTrade,
+};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Messages buffered while recovering before the oldest are dropped
+pub const MAX_BUFFERED_MESSAGES: usize = 100_000;
+
+/// Instrument carried by the feed
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedInstrument {
+    /// Instrument id used on the wire
+    pub id: u32,
+    /// Normalized instrument
+    pub instrument: InstrumentId,
+    /// Tick and lot size converting wire prices and quantities
+    pub scale: InstrumentScale,
+}
+
+/// Outcome of a packet or snapshot
+#[derive(Debug, Default, PartialEq)]
+pub struct FeedOutput {
+    /// Events in feed order
+    pub events: Vec<MarketEvent>,
+    /// Whether a snapshot should be requested
+    pub request_snapshot: bool,
+}
+
+/// Delta being collected from consecutive book updates of one instrument
+struct PendingDelta {
+    instrument: u32,
+    first_sequence: u64,
+    last_sequence: u64,
+    timestamp: i64,
+    updates: Vec<LevelUpdate>,
+}
+
+/// Sequencer and normalizer of the multicast feed
+#[derive(Debug, Clone)]
+pub struct FeedHandler {
+    exchange: ExchangeId,
+    instruments: HashMap<u32, FeedInstrument>,
+    subscribed: HashSet<u32>,
+    /// Next sequence number to apply, unknown until the first snapshot
+    expected: Option<u64>,
+    recovering: bool,
+    buffered: BTreeMap<u64, FeedMessage>,
+}
+
+impl FeedHandler {
+    /// Create a handler tagging events with `exchange`
+    pub fn new(
+        exchange: ExchangeId,
+        instruments: impl IntoIterator<Item = FeedInstrument>,
+    ) -> Self {
+        Self {
+            exchange,
+            instruments: instruments
+                .into_iter()
+                .map(|instrument| (instrument.id, instrument))
+                .collect(),
+            subscribed: HashSet::new(),
+            expected: None,
+            recovering: true,
+            buffered: BTreeMap::new(),
+        }
+    }
+
+    /// Publish events of an instrument. Its book is only known from the next snapshot,
+    /// so the handler starts recovering and asks for one.
+    pub fn subscribe(&mut self, id: u32) -> FeedOutput {
+        self.subscribed.insert(id);
+        self.recovering = true;
+        FeedOutput {
+            events: Vec::new(),
+            request_snapshot: true,
+        }
+    }
+
+    /// Stop publishing events of an instrument
+    pub fn unsubscribe(&mut self, id: u32) {
+        self.subscribed.remove(&id);
+    }
+
+    /// Whether the handler is waiting for a snapshot
+    pub fn is_recovering(&self) -> bool {
+        self.recovering
+    }
+
+    /// Next sequence number to apply, unknown until the first snapshot
+    pub fn expected_sequence(&self) -> Option<u64> {
+        self.expected
+    }
+
+    /// Consume a packet received at `receipt_time`
+    pub fn on_packet(&mut self, packet: &Packet, receipt_time: DateTime<Utc>) -> FeedOutput {
+        let mut output = FeedOutput::default();
+        if self.recovering {
+            self.buffer(packet);
+            return output;
+        }
+        let Some(mut expected) = self.expected else {
+            return output;
+        };
+        // A heartbeat ahead of us announces lost messages
+        if packet.messages.is_empty() && packet.sequence > expected {
+            self.start_recovery(&mut output);
+            return output;
+        }
+
+        let mut pending = None;
+        for (offset, message) in packet.messages.iter().enumerate() {
+            let sequence = packet.sequence + offset as u64;
+            if sequence < expected {
+                continue;
+            }
+            if sequence > expected {
+                self.flush(pending.take(), receipt_time, &mut output.events);
+                self.start_recovery(&mut output);
+                self.buffer(&Packet {
+                    sequence,
+                    messages: packet.messages[offset..].to_vec(),
+                });
+                return output;
+            }
+            self.apply(
+                sequence,
+                message,
+                &mut pending,
+                receipt_time,
+                &mut output.events,
+            );
+            expected += 1;
+        }
+        self.flush(pending, receipt_time, &mut output.events);
+        output
+    }
+
+    /// Consume a snapshot received at `receipt_time`, replacing every subscribed book
+    /// and applying buffered messages that follow it
+    pub fn on_snapshot(&mut self, snapshot: &Snapshot, receipt_time: DateTime<Utc>) -> FeedOutput {
+        let mut output = FeedOutput::default();
+        let mut books: BTreeMap<u32, Vec<LevelUpdate>> =
+            self.subscribed.iter().map(|id| (*id, Vec::new())).collect();
+        for level in &snapshot.levels {
+            if let (Some(updates), Some(feed)) = (
+                books.get_mut(&level.instrument),
+                self.instruments.get(&level.instrument),
+            ) {
+                updates.push(Self::level_update(feed, level));
+            }
+        }
+        let exchange_time = timestamp(snapshot.timestamp);
+        for (id, updates) in books {
+            let Some(feed) = self.instruments.get(&id) else {
+                continue;
+            };
+            let delta = MarketDataKind::OrderBookDelta(OrderBookDelta {
+                updates,
+                first_update_id: None,
+                last_update_id: Some(snapshot.sequence),
+                snapshot: true,
+                timestamp: exchange_time,
+            });
+            output
+                .events
+                .push(self.event(feed, delta, exchange_time, receipt_time));
+        }
+
+        self.expected = Some(snapshot.sequence + 1);
+        self.recovering = false;
+        let buffered = std::mem::take(&mut self.buffered);
+        let mut pending = None;
+        let mut messages = buffered.into_iter();
+        let mut expected = snapshot.sequence + 1;
+        for (sequence, message) in messages.by_ref() {
+            if sequence < expected {
+                continue;
+            }
+            if sequence > expected {
+                self.buffered.insert(sequence, message);
+                break;
+            }
+            self.apply(
+                sequence,
+                &message,
+                &mut pending,
+                receipt_time,
+                &mut output.events,
+            );
+            expected += 1;
+        }
+        self.flush(pending, receipt_time, &mut output.events);
+        if !self.buffered.is_empty() {
+            self.buffered.extend(messages);
+            self.start_recovery(&mut output);
+        }
+        output
+    }
+
+    fn start_recovery(&mut self, output: &mut FeedOutput) {
+        self.recovering = true;
+        output.request_snapshot = true;
+    }
+
+    /// Keep a packet's messages for after the next snapshot
+    fn buffer(&mut self, packet: &Packet) {
+        for (offset, message) in packet.messages.iter().enumerate() {
+            self.buffered
+                .insert(packet.sequence + offset as u64, *message);
+        }
+        while self.buffered.len() > MAX_BUFFERED_MESSAGES {
+            self.buffered.pop_first();
+        }
+    }
+
+    /// Apply the message with the expected sequence number
+    fn apply(
+        &mut self,
+        sequence: u64,
+        message: &FeedMessage,
+        pending: &mut Option<PendingDelta>,
+        receipt_time: DateTime<Utc>,
+        events: &mut Vec<MarketEvent>,
+    ) {
+        self.expected = Some(sequence + 1);
+        match message {
+            FeedMessage::BookUpdate(update) => {
+                let Some(feed) = self.subscribed_instrument(update.instrument) else {
+                    return;
+                };
+                let level = Self::level_update(feed, update);
+                match pending {
+                    Some(delta) if delta.instrument == update.instrument => {
+                        delta.last_sequence = sequence;
+                        delta.timestamp = update.timestamp;
+                        delta.updates.push(level);
+                    }
+                    _ => {
+                        self.flush(pending.take(), receipt_time, events);
+                        *pending = Some(PendingDelta {
+                            instrument: update.instrument,
+                            first_sequence: sequence,
+                            last_sequence: sequence,
+                            timestamp: update.timestamp,
+                            updates: vec![level],
+                        });
+                    }
+                }
+            }
+            FeedMessage::Trade(trade) => {
+                self.flush(pending.take(), receipt_time, events);
+                let Some(feed) = self.subscribed_instrument(trade.instrument) else {
+                    return;
+                };
+                let exchange_time = timestamp(trade.timestamp);
+                let kind = MarketDataKind::Trade(PublicTrade {
+                    id: trade.trade_id.to_string(),
+                    price: feed.scale.price_to_decimal(trade.price),
+                    quantity: feed.scale.qty_to_decimal(trade.quantity),
+                    side: trade.side,
+                    timestamp: exchange_time,
+                });
+                events.push(self.event(feed, kind, exchange_time, receipt_time));
+            }
+            FeedMessage::Unknown(_) => {}
+        }
+    }
+
+    fn flush(
+        &self,
+        pending: Option<PendingDelta>,
+        receipt_time: DateTime<Utc>,
+        events: &mut Vec<MarketEvent>,
+    ) {
+        let Some(pending) = pending else {
+            return;
+        };
+        let Some(feed) = self.instruments.get(&pending.instrument) else {
+            return;
+        };
+        let exchange_time = timestamp(pending.timestamp);
+        let delta = MarketDataKind::OrderBookDelta(OrderBookDelta {
+            updates: pending.updates,
+            first_update_id: Some(pending.first_sequence),
+            last_update_id: Some(pending.last_sequence),
+            snapshot: false,
+            timestamp: exchange_time,
+        });
+        events.push(self.event(feed, delta, exchange_time, receipt_time));
+    }
+
+    fn subscribed_instrument(&self, id: u32) -> Option<&FeedInstrument> {
+        if self.subscribed.contains(&id) {
+            self.instruments.get(&id)
+        } else {
+            None
+        }
+    }
+
+    fn level_update(feed: &FeedInstrument, update: &BookUpdate) -> LevelUpdate {
+        LevelUpdate {
+            side: update.side,
+            price: feed.scale.price_to_decimal(update.price),
+            quantity: feed.scale.qty_to_decimal(update.quantity),
+            action: update.action,
+        }
+    }
+
+    fn event(
+        &self,
+        feed: &FeedInstrument,
+        kind: MarketDataKind,
+        exchange_time: DateTime<Utc>,
+        receipt_time: DateTime<Utc>,
+    ) -> MarketEvent {
+        MarketEvent {
+            exchange: self.exchange,
+            instrument: feed.instrument.clone(),
+            kind,
+            exchange_time,
+            receipt_time,
+        }
+    }
+}
+
+fn timestamp(nanos: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_nanos(nanos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::multicast::protocol::Trade;
+    use crate::data::{InstrumentKind, LevelAction, Price, Qty, Side};
+    use rust_decimal::Decimal;
+
+    fn feed_instrument() -> FeedInstrument {
+        FeedInstrument {
+            id: 1,
+            instrument: InstrumentId {
+                base: "BTC".to_string(),
+                quote: "USD".to_string(),
+                exchange_symbol: "BTC-USD".to_string(),
+                kind: InstrumentKind::Spot,
+            },
+            scale: InstrumentScale::new(Decimal::new(1, 2), Decimal::new(1, 3)).unwrap(),
+        }
+    }
+
+    fn update(instrument: u32, ticks: i64, lots: i64) -> FeedMessage {
+        FeedMessage::BookUpdate(BookUpdate {
+            instrument,
+            side: Side::Buy,
+            action: LevelAction::Update,
+            price: Price::from_ticks(ticks),
+            quantity: Qty::from_lots(lots),
+            timestamp: 1_700_000_000_000_000_000,
+        })
+    }
+
+    fn trade(trade_id: u64) -> FeedMessage {
+        FeedMessage::Trade(Trade {
+            instrument: 1,
+            side: Side::Sell,
+            trade_id,
+            price: Price::from_ticks(10_000),
+            quantity: Qty::from_lots(500),
+            timestamp: 1_700_000_000_000_000_000,
+        })
+    }
+
+    fn packet(sequence: u64, messages: Vec<FeedMessage>) -> Packet {
+        Packet { sequence, messages }
+    }
+
+    fn snapshot(sequence: u64) -> Snapshot {
+        let FeedMessage::BookUpdate(mut level) = update(1, 10_000, 1_000) else {
+            unreachable!()
+        };
+        level.action = LevelAction::Insert;
+        Snapshot {
+            sequence,
+            timestamp: 1_700_000_000_000_000_000,
+            levels: vec![level],
+        }
+    }
+
+    fn kinds(output: &FeedOutput) -> Vec<&MarketDataKind> {
+        output.events.iter().map(|event| &event.kind).collect()
+    }
+
+    #[test]
+    fn test_gap_buffers_until_snapshot() {
+        let now = Utc::now();
+        let mut handler = FeedHandler::new(ExchangeId::Synthetic, [feed_instrument()]);
+        assert!(handler.subscribe(1).request_snapshot);
+
+        // Packets before the first snapshot are buffered; 10 is covered by the snapshot
+        assert!(handler
+            .on_packet(&packet(10, vec![update(1, 9_999, 1)]), now)
+            .events
+            .is_empty());
+        handler.on_packet(
+            &packet(
+                11,
+                vec![update(1, 10_000, 2), update(1, 9_999, 0), update(2, 1, 1)],
+            ),
+            now,
+        );
+        let output = handler.on_snapshot(&snapshot(10), now);
+        let [MarketDataKind::OrderBookDelta(book), MarketDataKind::OrderBookDelta(delta)] =
+            kinds(&output)[..]
+        else {
+            panic!("unexpected events {:?}", output.events);
+        };
+        assert!(book.snapshot);
+        assert_eq!(book.updates[0].price, Decimal::new(10_000, 2));
+        assert_eq!(book.updates[0].quantity, Decimal::ONE);
+        assert_eq!(
+            (delta.first_update_id, delta.last_update_id),
+            (Some(11), Some(12))
+        );
+        assert_eq!(delta.updates.len(), 2);
+        assert_eq!(handler.expected_sequence(), Some(14));
+
+        // Duplicates are dropped, trades pass through
+        let output = handler.on_packet(&packet(13, vec![update(2, 1, 1), trade(7)]), now);
+        let [MarketDataKind::Trade(public)] = kinds(&output)[..] else {
+            panic!("unexpected events {:?}", output.events);
+        };
+        assert_eq!(
+            (public.id.as_str(), public.quantity),
+            ("7", Decimal::new(5, 1))
+        );
+
+        // Sequence 15 is lost; 16 starts recovery and a stale snapshot needs another
+        let output = handler.on_packet(&packet(16, vec![trade(8)]), now);
+        assert!(output.request_snapshot && output.events.is_empty());
+        assert!(handler.on_snapshot(&snapshot(14), now).request_snapshot);
+        assert!(handler.is_recovering());
+        let output = handler.on_snapshot(&snapshot(15), now);
+        assert!(!output.request_snapshot);
+        assert_eq!(output.events.len(), 2);
+        assert!(matches!(output.events[1].kind, MarketDataKind::Trade(_)));
+
+        // A heartbeat ahead of the expected sequence also reveals a gap
+        assert!(handler.on_packet(&packet(18, vec![]), now).request_snapshot);
+    }
+}
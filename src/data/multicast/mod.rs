@@ -0,0 +1,564 @@
+//! Sequenced binary UDP multicast feeds
+//!
+//! Co-located venues publish market data as binary, sequenced multicast (ITCH, SBE
+//! and similar), which avoids the framing and parsing cost of WebSocket JSON feeds.
+//! `protocol` defines the wire format of such a feed and its TCP snapshot channel,
+//! and `handler` sequences packets, detects gaps and recovers from snapshots without
+//! doing any I/O.
+//!
+//! `MulticastMarketDataStream` joins the multicast group on the first `subscribe` and
+//! drives the handler from a background task. Snapshots are downloaded concurrently,
+//! so packets arriving meanwhile are buffered rather than lost, and failed downloads
+//! are retried until one succeeds. A socket error or a failed snapshot task is
+//! returned from `next` before the stream ends.
+//!
+//! A feed carries every book update and trade of its instruments. A stream created
+//! with `from_config` accepts `SubscriptionKind::BookDeltas` and
+//! `SubscriptionKind::Trades` and drops the events an instrument's configuration did
+//! not ask for.
+
+pub mod handler;
+pub mod protocol;
+
+pub use handler::{FeedHandler, FeedInstrument, FeedOutput};
+pub use protocol::{BookUpdate, FeedMessage, Packet, Snapshot, Trade};
+
+use super::subscription::is_subscribed;
+use super::{
+    ExchangeId, InstrumentId, InstrumentScale, MarketDataStream, MarketEvent, SubscriptionKind,
+};
+use crate::config::DataConfig;
+use chrono::Utc;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// Time allowed for a snapshot download
+pub const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay before a failed snapshot download is retried
+pub const SNAPSHOT_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Largest datagram the feed can carry
+const MAX_DATAGRAM_LEN: usize = 65_536;
+
+/// Errors produced by the multicast feed
+#[derive(Debug, thiserror::Error)]
+pub enum MulticastError {
+    /// Socket or snapshot connection error
+    #[error("multicast feed I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// Packet or snapshot that does not follow the wire format
+    #[error("malformed multicast feed data: {0}")]
+    Malformed(String),
+    /// Instrument without a feed id
+    #[error("instrument {0:?} is not carried by the feed")]
+    UnknownInstrument(InstrumentId),
+    /// Snapshot channel did not answer in time
+    #[error("snapshot request timed out")]
+    SnapshotTimeout,
+    /// Market data kind the feed does not carry
+    #[error("unsupported multicast subscription: {0:?}")]
+    UnsupportedSubscription(SubscriptionKind),
+    /// Snapshot download task panicked
+    #[error("snapshot task failed: {0}")]
+    SnapshotTask(#[from] tokio::task::JoinError),
+}
+
+/// Request forwarded to the feed task
+#[derive(Debug)]
+enum Command {
+    Subscribe(u32),
+    Unsubscribe(u32),
+}
+
+/// Market data stream reading a sequenced multicast feed
+pub struct MulticastMarketDataStream {
+    group: Ipv4Addr,
+    port: u16,
+    interface: Ipv4Addr,
+    snapshot_address: String,
+    exchange: ExchangeId,
+    instruments: Vec<FeedInstrument>,
+    recv_buffer_size: Option<usize>,
+    /// Configuration selecting the events delivered per instrument, all when absent
+    config: Option<DataConfig>,
+    commands: Option<mpsc::UnboundedSender<Command>>,
+    receiver: Option<mpsc::Receiver<Result<MarketEvent, MulticastError>>>,
+    subscribed: Vec<InstrumentId>,
+}
+
+impl MulticastMarketDataStream {
+    /// Create a stream for the feed published on `group:port`, recovering from the
+    /// snapshot channel at `snapshot_address` (`host:port`)
+    pub fn new(
+        group: Ipv4Addr,
+        port: u16,
+        snapshot_address: impl Into<String>,
+        exchange: ExchangeId,
+    ) -> Self {
+        Self {
+            group,
+            port,
+            interface: Ipv4Addr::UNSPECIFIED,
+            snapshot_address: snapshot_address.into(),
+            exchange,
+            instruments: Vec::new(),
+            recv_buffer_size: None,
+            config: None,
+            commands: None,
+            receiver: None,
+            subscribed: Vec::new(),
+        }
+    }
+
+    /// Create a stream delivering the kinds listed in the configuration, including
+    /// per-instrument overrides
+    pub fn from_config(
+        group: Ipv4Addr,
+        port: u16,
+        snapshot_address: impl Into<String>,
+        exchange: ExchangeId,
+        config: &DataConfig,
+    ) -> Result<Self, MulticastError> {
+        if let Some(kind) = config.all_subscriptions().into_iter().find(|kind| {
+            !matches!(
+                kind,
+                SubscriptionKind::BookDeltas | SubscriptionKind::Trades
+            )
+        }) {
+            return Err(MulticastError::UnsupportedSubscription(kind));
+        }
+        let mut stream = Self::new(group, port, snapshot_address, exchange);
+        stream.config = Some(config.clone());
+        Ok(stream)
+    }
+
+    /// Join the group on a specific local interface instead of the default one
+    pub fn with_interface(mut self, interface: Ipv4Addr) -> Self {
+        self.interface = interface;
+        self
+    }
+
+    /// Map a feed instrument id to an instrument and its tick and lot size
+    pub fn with_instrument(
+        mut self,
+        id: u32,
+        instrument: InstrumentId,
+        scale: InstrumentScale,
+    ) -> Self {
+        self.instruments.push(FeedInstrument {
+            id,
+            instrument,
+            scale,
+        });
+        self
+    }
+
+    /// Socket receive buffer size, which absorbs bursts while the task is busy
+    pub fn with_recv_buffer_size(mut self, bytes: usize) -> Self {
+        self.recv_buffer_size = Some(bytes);
+        self
+    }
+
+    /// Bind the feed port and join the group
+    fn open_socket(&self) -> Result<UdpSocket, MulticastError> {
+        use socket2::{Domain, Protocol, Socket, Type};
+
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        // Lets an A and a B line handler, or a capture tool, share the port
+        socket.set_reuse_address(true)?;
+        if let Some(bytes) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(bytes)?;
+        }
+        socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, self.port).into())?;
+        socket.join_multicast_v4(&self.group, &self.interface)?;
+        socket.set_nonblocking(true)?;
+        Ok(UdpSocket::from_std(socket.into())?)
+    }
+
+    fn feed_id(&self, instrument: &InstrumentId) -> Result<u32, MulticastError> {
+        self.instruments
+            .iter()
+            .find(|feed| &feed.instrument == instrument)
+            .map(|feed| feed.id)
+            .ok_or_else(|| MulticastError::UnknownInstrument(instrument.clone()))
+    }
+
+    /// Forward a command to the feed task
+    fn send(&self, command: Command) {
+        if let Some(commands) = &self.commands {
+            let _ = commands.send(command);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl MarketDataStream for MulticastMarketDataStream {
+    type Error = MulticastError;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        match &mut self.receiver {
+            Some(receiver) => receiver.recv().await.transpose(),
+            None => Ok(None),
+        }
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        let ids = instruments
+            .iter()
+            .map(|instrument| self.feed_id(instrument))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if self
+            .commands
+            .as_ref()
+            .is_none_or(|commands| commands.is_closed())
+        {
+            let socket = self.open_socket()?;
+            let (command_sender, commands) = mpsc::unbounded_channel();
+            let (sender, receiver) = mpsc::channel(1024);
+            self.commands = Some(command_sender);
+            self.receiver = Some(receiver);
+            let handler = FeedHandler::new(self.exchange, self.instruments.clone());
+            tokio::spawn(run_feed(
+                socket,
+                self.snapshot_address.clone(),
+                handler,
+                self.config.clone(),
+                commands,
+                sender,
+            ));
+            // Books of instruments subscribed before a restart need a snapshot too
+            for instrument in &self.subscribed {
+                self.send(Command::Subscribe(self.feed_id(instrument)?));
+            }
+        }
+        for id in ids {
+            self.send(Command::Subscribe(id));
+        }
+        self.subscribed.extend_from_slice(instruments);
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.subscribed.retain(|i| !instruments.contains(i));
+        for instrument in instruments {
+            if let Ok(id) = self.feed_id(instrument) {
+                self.send(Command::Unsubscribe(id));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl futures::Stream for MulticastMarketDataStream {
+    type Item = Result<MarketEvent, MulticastError>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        super::stream::poll_result_receiver(&mut self.receiver, cx)
+    }
+}
+
+/// Receive packets and snapshots until the stream is dropped, sending the error that
+/// ends the feed otherwise
+async fn run_feed(
+    socket: UdpSocket,
+    snapshot_address: String,
+    mut handler: FeedHandler,
+    config: Option<DataConfig>,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    sender: mpsc::Sender<Result<MarketEvent, MulticastError>>,
+) {
+    let mut datagram = vec![0u8; MAX_DATAGRAM_LEN];
+    let mut snapshot: Option<tokio::task::JoinHandle<Snapshot>> = None;
+
+    let result = 'feed: loop {
+        let output = tokio::select! {
+            result = socket.recv(&mut datagram) => match result {
+                Ok(len) => {
+                    let receipt_time = Utc::now();
+                    match Packet::decode(&datagram[..len]) {
+                        Ok(packet) => handler.on_packet(&packet, receipt_time),
+                        // Dropped packets are recovered like lost ones
+                        Err(error) => {
+                            debug!(%error, "dropping multicast packet");
+                            continue;
+                        }
+                    }
+                }
+                Err(error) => break Err(MulticastError::Io(error)),
+            },
+            result = async { snapshot.as_mut().expect("snapshot in flight").await }, if snapshot.is_some() => {
+                snapshot = None;
+                match result {
+                    Ok(received) => handler.on_snapshot(&received, Utc::now()),
+                    Err(error) => break Err(MulticastError::SnapshotTask(error)),
+                }
+            }
+            command = commands.recv() => match command {
+                Some(Command::Subscribe(id)) => handler.subscribe(id),
+                Some(Command::Unsubscribe(id)) => {
+                    handler.unsubscribe(id);
+                    continue;
+                }
+                None => break Ok(()),
+            },
+        };
+
+        if output.request_snapshot && snapshot.is_none() {
+            snapshot = Some(tokio::spawn(fetch_snapshot(snapshot_address.clone())));
+        }
+        for event in output.events {
+            if let Some(config) = &config {
+                if !is_subscribed(config.subscriptions_for(&event.instrument), &event.kind) {
+                    continue;
+                }
+            }
+            if sender.send(Ok(event)).await.is_err() {
+                break 'feed Ok(());
+            }
+        }
+    };
+
+    if let Some(snapshot) = snapshot {
+        snapshot.abort();
+    }
+    if let Err(error) = result {
+        warn!(%error, "closing multicast feed");
+        let _ = sender.send(Err(error)).await;
+    }
+}
+
+/// Download a snapshot, retrying until one succeeds
+async fn fetch_snapshot(address: String) -> Snapshot {
+    loop {
+        match read_snapshot(&address).await {
+            Ok(snapshot) => return snapshot,
+            Err(error) => {
+                warn!(%error, %address, "snapshot request failed, retrying");
+                tokio::time::sleep(SNAPSHOT_RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+async fn read_snapshot(address: &str) -> Result<Snapshot, MulticastError> {
+    let download = async {
+        let mut connection = TcpStream::connect(address).await?;
+        let mut bytes = Vec::new();
+        connection.read_to_end(&mut bytes).await?;
+        Ok::<_, MulticastError>(bytes)
+    };
+    let bytes = tokio::time::timeout(SNAPSHOT_TIMEOUT, download)
+        .await
+        .map_err(|_| MulticastError::SnapshotTimeout)??;
+    Snapshot::decode(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::mock_server::MockSnapshotServer;
+    use crate::data::{InstrumentKind, LevelAction, MarketDataKind, Price, Qty, Side};
+    use rust_decimal::Decimal;
+
+    const GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 42, 1);
+
+    fn btc_usd() -> InstrumentId {
+        InstrumentId {
+            base: "BTC".to_string(),
+            quote: "USD".to_string(),
+            exchange_symbol: "BTC-USD".to_string(),
+            kind: InstrumentKind::Spot,
+        }
+    }
+
+    fn level(ticks: i64, lots: i64, action: LevelAction) -> BookUpdate {
+        BookUpdate {
+            instrument: 1,
+            side: Side::Buy,
+            action,
+            price: Price::from_ticks(ticks),
+            quantity: Qty::from_lots(lots),
+            timestamp: 1_700_000_000_000_000_000,
+        }
+    }
+
+    fn snapshot(sequence: u64, lots: i64) -> Snapshot {
+        Snapshot {
+            sequence,
+            timestamp: 1_700_000_000_000_000_000,
+            levels: vec![level(10_000, lots, LevelAction::Insert)],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recovers_lost_packet_from_snapshot() {
+        // The first snapshot precedes the packets, the second covers the lost one
+        let server = MockSnapshotServer::start(vec![snapshot(10, 1), snapshot(13, 4)])
+            .await
+            .unwrap();
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let scale = InstrumentScale::new(Decimal::new(1, 2), Decimal::ONE).unwrap();
+        let mut stream = MulticastMarketDataStream::new(
+            GROUP,
+            port,
+            server.addr().to_string(),
+            ExchangeId::Synthetic,
+        )
+        .with_interface(Ipv4Addr::LOCALHOST)
+        .with_instrument(1, btc_usd(), scale);
+        stream.subscribe(&[btc_usd()]).await.unwrap();
+
+        let sender =
+            socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::DGRAM, None).unwrap();
+        sender.set_multicast_if_v4(&Ipv4Addr::LOCALHOST).unwrap();
+        sender.set_multicast_loop_v4(true).unwrap();
+        let destination = SocketAddrV4::new(GROUP, port).into();
+        let trade = FeedMessage::Trade(Trade {
+            instrument: 1,
+            side: Side::Sell,
+            trade_id: 1,
+            price: Price::from_ticks(10_000),
+            quantity: Qty::from_lots(1),
+            timestamp: 1_700_000_000_000_000_000,
+        });
+        let packets = [
+            Packet {
+                sequence: 11,
+                messages: vec![FeedMessage::BookUpdate(level(
+                    10_000,
+                    2,
+                    LevelAction::Update,
+                ))],
+            },
+            Packet {
+                sequence: 12,
+                messages: vec![trade],
+            },
+            // Sequence 13 is never sent
+            Packet {
+                sequence: 14,
+                messages: vec![FeedMessage::BookUpdate(level(
+                    10_000,
+                    5,
+                    LevelAction::Update,
+                ))],
+            },
+        ];
+        for packet in &packets {
+            sender.send_to(&packet.encode(), &destination).unwrap();
+        }
+
+        let mut events = Vec::new();
+        for _ in 0..5 {
+            let event = tokio::time::timeout(Duration::from_secs(5), stream.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            assert_eq!(event.instrument, btc_usd());
+            events.push(event.kind);
+        }
+        let quantities: Vec<(bool, Decimal)> = events
+            .iter()
+            .filter_map(|kind| match kind {
+                MarketDataKind::OrderBookDelta(delta) => {
+                    Some((delta.snapshot, delta.updates[0].quantity))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            quantities,
+            vec![
+                (true, Decimal::ONE),
+                (false, Decimal::from(2)),
+                (true, Decimal::from(4)),
+                (false, Decimal::from(5)),
+            ]
+        );
+        assert!(matches!(events[2], MarketDataKind::Trade(_)));
+        assert_eq!(server.requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_from_config_drops_unrequested_kinds() {
+        let server = MockSnapshotServer::start(vec![snapshot(10, 1)])
+            .await
+            .unwrap();
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut config = crate::config::SystemConfig::default().data;
+        config.subscriptions = vec![SubscriptionKind::Trades];
+        let scale = InstrumentScale::new(Decimal::new(1, 2), Decimal::ONE).unwrap();
+        let mut stream = MulticastMarketDataStream::from_config(
+            GROUP,
+            port,
+            server.addr().to_string(),
+            ExchangeId::Synthetic,
+            &config,
+        )
+        .unwrap()
+        .with_interface(Ipv4Addr::LOCALHOST)
+        .with_instrument(1, btc_usd(), scale);
+        stream.subscribe(&[btc_usd()]).await.unwrap();
+
+        let sender =
+            socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::DGRAM, None).unwrap();
+        sender.set_multicast_if_v4(&Ipv4Addr::LOCALHOST).unwrap();
+        sender.set_multicast_loop_v4(true).unwrap();
+        let destination = SocketAddrV4::new(GROUP, port).into();
+        let packet = Packet {
+            sequence: 11,
+            messages: vec![
+                FeedMessage::BookUpdate(level(10_000, 2, LevelAction::Update)),
+                FeedMessage::Trade(Trade {
+                    instrument: 1,
+                    side: Side::Sell,
+                    trade_id: 1,
+                    price: Price::from_ticks(10_000),
+                    quantity: Qty::from_lots(1),
+                    timestamp: 1_700_000_000_000_000_000,
+                }),
+            ],
+        };
+        sender.send_to(&packet.encode(), &destination).unwrap();
+
+        // The snapshot and the book update are dropped
+        let event = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event.kind, MarketDataKind::Trade(_)));
+
+        config.subscriptions = vec![SubscriptionKind::L1];
+        assert!(matches!(
+            MulticastMarketDataStream::from_config(
+                GROUP,
+                port,
+                server.addr().to_string(),
+                ExchangeId::Synthetic,
+                &config,
+            ),
+            Err(MulticastError::UnsupportedSubscription(
+                SubscriptionKind::L1
+            ))
+        ));
+    }
+}
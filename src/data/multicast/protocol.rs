@@ -0,0 +1,363 @@
+//! Binary wire format of the sequenced multicast feed
+//!
+//! The layout follows MoldUDP64 framing with SBE-style fixed-width, little-endian
+//! message bodies. Every datagram is a packet:
+//!
+//! ```text
+//! sequence u64 | count u16 | count x (length u16 | message)
+//! ```
+//!
+//! `sequence` numbers the first message; each following message takes the next
+//! number. A packet without messages is a heartbeat announcing the next sequence
+//! number, so a lost final packet is detected even when the feed is quiet.
+//!
+//! Messages start with a template id, and unknown templates are skipped by length so
+//! the feed can add messages without breaking older handlers:
+//!
+//! ```text
+//! 1 book update  instrument u32 | side u8 | action u8 | price i64 | qty i64 | time i64
+//! 2 trade        instrument u32 | side u8 | trade id u64 | price i64 | qty i64 | time i64
+//! ```
+//!
+//! Prices and quantities are tick and lot counts of the instrument, times are
+//! nanoseconds since the Unix epoch, side 0 is buy (bid) and 1 is sell (ask), and
+//! actions 0, 1 and 2 insert, update and delete a level. A trade's side is the
+//! aggressor's.
+//!
+//! The TCP snapshot channel answers each connection with the state of every book and
+//! closes it:
+//!
+//! ```text
+//! sequence u64 | time i64 | count u32 | count x (length u16 | book update)
+//! ```
+//!
+//! `sequence` is the last feed message reflected in the snapshot.
+
+use super::MulticastError;
+use crate::data::{LevelAction, Price, Qty, Side};
+
+/// Bytes before the first message of a packet
+pub const PACKET_HEADER_LEN: usize = 10;
+
+/// Bytes before the first level of a snapshot
+pub const SNAPSHOT_HEADER_LEN: usize = 20;
+
+/// Template id of book updates
+const BOOK_UPDATE: u8 = 1;
+
+/// Template id of trades
+const TRADE: u8 = 2;
+
+/// Change to one price level
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BookUpdate {
+    /// Feed instrument id
+    pub instrument: u32,
+    /// Book side, `Buy` for bids
+    pub side: Side,
+    /// Kind of change
+    pub action: LevelAction,
+    /// Level price
+    pub price: Price,
+    /// New total quantity at the level
+    pub quantity: Qty,
+    /// Exchange time in nanoseconds since the Unix epoch
+    pub timestamp: i64,
+}
+
+/// Trade between two orders
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Trade {
+    /// Feed instrument id
+    pub instrument: u32,
+    /// Aggressor side
+    pub side: Side,
+    /// Exchange trade id
+    pub trade_id: u64,
+    /// Trade price
+    pub price: Price,
+    /// Traded quantity
+    pub quantity: Qty,
+    /// Exchange time in nanoseconds since the Unix epoch
+    pub timestamp: i64,
+}
+
+/// Message of the feed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FeedMessage {
+    BookUpdate(BookUpdate),
+    Trade(Trade),
+    /// Message of a template this handler does not know; it still takes a sequence
+    /// number
+    Unknown(u8),
+}
+
+/// Datagram of the feed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    /// Sequence number of the first message, or the next one for a heartbeat
+    pub sequence: u64,
+    /// Messages in sequence order
+    pub messages: Vec<FeedMessage>,
+}
+
+/// Book state served by the snapshot channel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Last feed sequence number reflected in the levels
+    pub sequence: u64,
+    /// Exchange time of the snapshot in nanoseconds since the Unix epoch
+    pub timestamp: i64,
+    /// Every level of every book, as inserts
+    pub levels: Vec<BookUpdate>,
+}
+
+impl FeedMessage {
+    /// Append the message, without its length prefix
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            FeedMessage::BookUpdate(update) => {
+                out.push(BOOK_UPDATE);
+                out.extend_from_slice(&update.instrument.to_le_bytes());
+                out.push(encode_side(update.side));
+                out.push(match update.action {
+                    LevelAction::Insert => 0,
+                    LevelAction::Update => 1,
+                    LevelAction::Delete => 2,
+                });
+                out.extend_from_slice(&update.price.ticks().to_le_bytes());
+                out.extend_from_slice(&update.quantity.lots().to_le_bytes());
+                out.extend_from_slice(&update.timestamp.to_le_bytes());
+            }
+            FeedMessage::Trade(trade) => {
+                out.push(TRADE);
+                out.extend_from_slice(&trade.instrument.to_le_bytes());
+                out.push(encode_side(trade.side));
+                out.extend_from_slice(&trade.trade_id.to_le_bytes());
+                out.extend_from_slice(&trade.price.ticks().to_le_bytes());
+                out.extend_from_slice(&trade.quantity.lots().to_le_bytes());
+                out.extend_from_slice(&trade.timestamp.to_le_bytes());
+            }
+            FeedMessage::Unknown(template) => out.push(*template),
+        }
+    }
+
+    /// Decode one message body
+    fn decode(body: &[u8]) -> Result<Self, MulticastError> {
+        let mut reader = Reader::new(body);
+        let message = match reader.u8()? {
+            BOOK_UPDATE => FeedMessage::BookUpdate(BookUpdate {
+                instrument: reader.u32()?,
+                side: decode_side(reader.u8()?)?,
+                action: match reader.u8()? {
+                    0 => LevelAction::Insert,
+                    1 => LevelAction::Update,
+                    2 => LevelAction::Delete,
+                    other => {
+                        return Err(MulticastError::Malformed(format!("level action {}", other)))
+                    }
+                },
+                price: Price::from_ticks(reader.i64()?),
+                quantity: Qty::from_lots(reader.i64()?),
+                timestamp: reader.i64()?,
+            }),
+            TRADE => FeedMessage::Trade(Trade {
+                instrument: reader.u32()?,
+                side: decode_side(reader.u8()?)?,
+                trade_id: reader.u64()?,
+                price: Price::from_ticks(reader.i64()?),
+                quantity: Qty::from_lots(reader.i64()?),
+                timestamp: reader.i64()?,
+            }),
+            template => FeedMessage::Unknown(template),
+        };
+        Ok(message)
+    }
+}
+
+impl Packet {
+    /// Serialize to a datagram
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(PACKET_HEADER_LEN + self.messages.len() * 48);
+        out.extend_from_slice(&self.sequence.to_le_bytes());
+        out.extend_from_slice(&(self.messages.len() as u16).to_le_bytes());
+        for message in &self.messages {
+            encode_framed(message, &mut out);
+        }
+        out
+    }
+
+    /// Parse a datagram
+    pub fn decode(datagram: &[u8]) -> Result<Self, MulticastError> {
+        let mut reader = Reader::new(datagram);
+        let sequence = reader.u64()?;
+        let count = reader.u16()?;
+        let messages = (0..count)
+            .map(|_| reader.framed())
+            .collect::<Result<_, _>>()?;
+        Ok(Self { sequence, messages })
+    }
+}
+
+impl Snapshot {
+    /// Serialize to the snapshot channel format
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(SNAPSHOT_HEADER_LEN + self.levels.len() * 34);
+        out.extend_from_slice(&self.sequence.to_le_bytes());
+        out.extend_from_slice(&self.timestamp.to_le_bytes());
+        out.extend_from_slice(&(self.levels.len() as u32).to_le_bytes());
+        for level in &self.levels {
+            encode_framed(&FeedMessage::BookUpdate(*level), &mut out);
+        }
+        out
+    }
+
+    /// Parse a complete snapshot response
+    pub fn decode(bytes: &[u8]) -> Result<Self, MulticastError> {
+        let mut reader = Reader::new(bytes);
+        let sequence = reader.u64()?;
+        let timestamp = reader.i64()?;
+        let count = reader.u32()?;
+        let mut levels = Vec::with_capacity(count.min(1 << 16) as usize);
+        for _ in 0..count {
+            match reader.framed()? {
+                FeedMessage::BookUpdate(level) => levels.push(level),
+                other => {
+                    return Err(MulticastError::Malformed(format!(
+                        "unexpected snapshot message {:?}",
+                        other
+                    )))
+                }
+            }
+        }
+        Ok(Self {
+            sequence,
+            timestamp,
+            levels,
+        })
+    }
+}
+
+fn encode_framed(message: &FeedMessage, out: &mut Vec<u8>) {
+    let start = out.len();
+    out.extend_from_slice(&[0, 0]);
+    message.encode_into(out);
+    let len = (out.len() - start - 2) as u16;
+    out[start..start + 2].copy_from_slice(&len.to_le_bytes());
+}
+
+fn encode_side(side: Side) -> u8 {
+    match side {
+        Side::Buy => 0,
+        Side::Sell => 1,
+    }
+}
+
+fn decode_side(side: u8) -> Result<Side, MulticastError> {
+    match side {
+        0 => Ok(Side::Buy),
+        1 => Ok(Side::Sell),
+        other => Err(MulticastError::Malformed(format!("side {}", other))),
+    }
+}
+
+/// Little-endian cursor over a buffer
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], MulticastError> {
+        let Some((head, rest)) = self.bytes.split_first_chunk::<N>() else {
+            return Err(MulticastError::Malformed("truncated message".to_string()));
+        };
+        self.bytes = rest;
+        Ok(*head)
+    }
+
+    fn u8(&mut self) -> Result<u8, MulticastError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, MulticastError> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, MulticastError> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, MulticastError> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn i64(&mut self) -> Result<i64, MulticastError> {
+        self.take().map(i64::from_le_bytes)
+    }
+
+    /// Length-prefixed message
+    fn framed(&mut self) -> Result<FeedMessage, MulticastError> {
+        let len = self.u16()? as usize;
+        if self.bytes.len() < len {
+            return Err(MulticastError::Malformed("truncated message".to_string()));
+        }
+        let (body, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        FeedMessage::decode(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_round_trip_skips_unknown_templates() {
+        let update = BookUpdate {
+            instrument: 7,
+            side: Side::Sell,
+            action: LevelAction::Delete,
+            price: Price::from_ticks(10_001),
+            quantity: Qty::ZERO,
+            timestamp: 1_700_000_000_000_000_123,
+        };
+        let trade = Trade {
+            instrument: 7,
+            side: Side::Buy,
+            trade_id: 42,
+            price: Price::from_ticks(10_000),
+            quantity: Qty::from_lots(3),
+            timestamp: 1_700_000_000_000_000_456,
+        };
+        let packet = Packet {
+            sequence: 100,
+            messages: vec![FeedMessage::BookUpdate(update), FeedMessage::Trade(trade)],
+        };
+        let encoded = packet.encode();
+        assert_eq!(encoded.len(), PACKET_HEADER_LEN + 2 + 31 + 2 + 38);
+        assert_eq!(Packet::decode(&encoded).unwrap(), packet);
+
+        // A longer message of a newer template is skipped by its length
+        let mut extended = 102u64.to_le_bytes().to_vec();
+        extended.extend_from_slice(&2u16.to_le_bytes());
+        extended.extend_from_slice(&[3, 0, 9, 9, 9]);
+        encode_framed(&FeedMessage::Trade(trade), &mut extended);
+        let decoded = Packet::decode(&extended).unwrap();
+        assert_eq!(
+            decoded.messages,
+            vec![FeedMessage::Unknown(9), FeedMessage::Trade(trade)]
+        );
+        assert!(Packet::decode(&encoded[..encoded.len() - 1]).is_err());
+
+        let snapshot = Snapshot {
+            sequence: 99,
+            timestamp: 1,
+            levels: vec![update],
+        };
+        assert_eq!(Snapshot::decode(&snapshot.encode()).unwrap(), snapshot);
+    }
+}
@@ -0,0 +1,606 @@
+//! Market data quality filters
+//!
+//! `DataQualityFilter` sits between the connectors and the engine and checks each event
+//! before strategies see it:
+//!
+//! - crossed (and optionally locked) `OrderBookL1` quotes
+//! - trades priced outside a band around the median of recent trades
+//! - trade ids already seen for the instrument
+//! - instruments with no update for longer than a configured interval
+//!
+//! Every problem produces a `MarketDataKind::DataQuality` event for the instrument.
+//! Depending on `QualityAction` the offending event is then dropped or forwarded as is.
+//!
+//! Staleness is measured against the receipt time of the events flowing through the
+//! filter, so it behaves the same in replay. A feed that goes completely silent is
+//! caught by calling `check_stale` from a timer, which `QualityFilteredStream` does
+//! once the inner stream has been idle for the staleness interval, at the last receipt
+//! time advanced by the wall time elapsed since that event arrived.
+
+use super::{
+    DataQualityEvent, DataQualityIssue, ExchangeId, InstrumentId, MarketDataKind, MarketDataStream,
+    MarketEvent, OrderBookL1, PublicTrade,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+/// What to do with an event that fails a check
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QualityAction {
+    /// Remove the event from the stream
+    Drop,
+    /// Forward the event after the data quality notification
+    Flag,
+}
+
+/// Data quality thresholds
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityConfig {
+    /// Handling of events that fail a check
+    pub action: QualityAction,
+    /// Treat bid == ask as invalid, not only bid > ask
+    pub reject_locked: bool,
+    /// Maximum relative distance of a trade price from the rolling median, e.g. 0.05
+    pub outlier_band: Option<Decimal>,
+    /// Trades kept per instrument for the rolling median
+    pub outlier_window: usize,
+    /// Trades required before outliers are checked
+    pub outlier_min_samples: usize,
+    /// Trade ids remembered per instrument for duplicate detection
+    pub duplicate_window: usize,
+    /// Interval without updates after which an instrument is stale
+    pub stale_after: Option<Duration>,
+}
+
+impl QualityConfig {
+    /// Default thresholds
+    pub fn new() -> Self {
+        Self {
+            action: QualityAction::Drop,
+            reject_locked: true,
+            outlier_band: Some(Decimal::new(5, 2)),
+            outlier_window: 50,
+            outlier_min_samples: 10,
+            duplicate_window: 10_000,
+            stale_after: Some(Duration::from_secs(30)),
+        }
+    }
+
+    /// Set the handling of events that fail a check
+    pub fn with_action(mut self, action: QualityAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Set whether locked quotes are invalid
+    pub fn with_reject_locked(mut self, reject_locked: bool) -> Self {
+        self.reject_locked = reject_locked;
+        self
+    }
+
+    /// Set the outlier band as a fraction of the rolling median; `None` disables the check
+    pub fn with_outlier_band(mut self, band: Option<Decimal>) -> Self {
+        self.outlier_band = band;
+        self
+    }
+
+    /// Set the number of recent trades the median is taken over
+    pub fn with_outlier_window(mut self, window: usize) -> Self {
+        self.outlier_window = window.max(1);
+        self.outlier_min_samples = self.outlier_min_samples.min(self.outlier_window);
+        self
+    }
+
+    /// Set the number of trade ids remembered per instrument
+    pub fn with_duplicate_window(mut self, window: usize) -> Self {
+        self.duplicate_window = window;
+        self
+    }
+
+    /// Set the staleness interval; `None` disables the check
+    pub fn with_stale_after(mut self, stale_after: Option<Duration>) -> Self {
+        self.stale_after = stale_after;
+        self
+    }
+}
+
+impl Default for QualityConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Quality state of one instrument on one exchange
+#[derive(Debug, Default)]
+struct InstrumentState {
+    /// Recent trade prices, oldest first
+    prices: VecDeque<Decimal>,
+    trade_ids: HashSet<String>,
+    trade_id_order: VecDeque<String>,
+    last_update: Option<DateTime<Utc>>,
+    stale: bool,
+}
+
+/// Validates market events and reports data quality problems
+#[derive(Debug)]
+pub struct DataQualityFilter {
+    config: QualityConfig,
+    instruments: HashMap<(ExchangeId, InstrumentId), InstrumentState>,
+    /// Earliest time any instrument can become stale
+    next_stale_check: Option<DateTime<Utc>>,
+    issues: u64,
+}
+
+impl DataQualityFilter {
+    /// Create a filter with the given thresholds
+    pub fn new(config: QualityConfig) -> Self {
+        Self {
+            config,
+            instruments: HashMap::new(),
+            next_stale_check: None,
+            issues: 0,
+        }
+    }
+
+    /// Number of problems detected so far
+    pub fn issues(&self) -> u64 {
+        self.issues
+    }
+
+    /// Check an event, returning the events to forward in order.
+    ///
+    /// The result holds any data quality notifications followed by the event itself
+    /// unless it was dropped.
+    pub fn process(&mut self, event: MarketEvent) -> Vec<MarketEvent> {
+        let mut output = self.check_stale(event.receipt_time);
+        if matches!(event.kind, MarketDataKind::DataQuality(_)) {
+            output.push(event);
+            return output;
+        }
+
+        let state = self
+            .instruments
+            .entry((event.exchange, event.instrument.clone()))
+            .or_default();
+        state.last_update = Some(event.receipt_time);
+        state.stale = false;
+
+        let issue = match &event.kind {
+            MarketDataKind::OrderBookL1(quote) => check_quote(&self.config, quote),
+            MarketDataKind::Trade(trade) => check_trade(&self.config, state, trade),
+            _ => None,
+        };
+        if let Some(stale_after) = self.chrono_stale_after() {
+            let deadline = event.receipt_time + stale_after;
+            self.next_stale_check = Some(
+                self.next_stale_check
+                    .map_or(deadline, |next| next.min(deadline)),
+            );
+        }
+
+        match issue {
+            Some(issue) => {
+                let dropped = self.config.action == QualityAction::Drop;
+                output.push(self.notification(&event, issue, dropped, event.receipt_time));
+                if !dropped {
+                    output.push(event);
+                }
+            }
+            None => output.push(event),
+        }
+        output
+    }
+
+    /// Report instruments that have not updated within the staleness interval as of `now`.
+    ///
+    /// Each instrument is reported once until it updates again.
+    pub fn check_stale(&mut self, now: DateTime<Utc>) -> Vec<MarketEvent> {
+        let Some(stale_after) = self.chrono_stale_after() else {
+            return Vec::new();
+        };
+        if self.next_stale_check.is_none_or(|next| now < next) {
+            return Vec::new();
+        }
+
+        let mut stale = Vec::new();
+        let mut next_check: Option<DateTime<Utc>> = None;
+        for ((exchange, instrument), state) in self.instruments.iter_mut() {
+            let Some(last_update) = state.last_update else {
+                continue;
+            };
+            if state.stale {
+                continue;
+            }
+            let deadline = last_update + stale_after;
+            if now >= deadline {
+                state.stale = true;
+                stale.push(MarketEvent {
+                    exchange: *exchange,
+                    instrument: instrument.clone(),
+                    kind: MarketDataKind::DataQuality(DataQualityEvent {
+                        issue: DataQualityIssue::Stale { last_update },
+                        dropped: false,
+                        timestamp: now,
+                    }),
+                    exchange_time: now,
+                    receipt_time: now,
+                });
+            } else {
+                next_check = Some(next_check.map_or(deadline, |next| next.min(deadline)));
+            }
+        }
+        self.next_stale_check = next_check;
+        self.issues += stale.len() as u64;
+        stale
+    }
+
+    fn chrono_stale_after(&self) -> Option<chrono::Duration> {
+        self.config
+            .stale_after
+            .and_then(|stale_after| chrono::Duration::from_std(stale_after).ok())
+    }
+
+    fn notification(
+        &mut self,
+        event: &MarketEvent,
+        issue: DataQualityIssue,
+        dropped: bool,
+        timestamp: DateTime<Utc>,
+    ) -> MarketEvent {
+        self.issues += 1;
+        tracing::debug!(
+            exchange = ?event.exchange,
+            symbol = %event.instrument.exchange_symbol,
+            ?issue,
+            "market data quality issue"
+        );
+        MarketEvent {
+            exchange: event.exchange,
+            instrument: event.instrument.clone(),
+            kind: MarketDataKind::DataQuality(DataQualityEvent {
+                issue,
+                dropped,
+                timestamp,
+            }),
+            exchange_time: event.exchange_time,
+            receipt_time: event.receipt_time,
+        }
+    }
+}
+
+impl Default for DataQualityFilter {
+    fn default() -> Self {
+        Self::new(QualityConfig::default())
+    }
+}
+
+fn check_quote(config: &QualityConfig, quote: &OrderBookL1) -> Option<DataQualityIssue> {
+    // A missing side is reported as zero by some venues and is not a crossed book
+    if quote.bid_price.is_zero() || quote.ask_price.is_zero() {
+        return None;
+    }
+    if quote.bid_price > quote.ask_price {
+        Some(DataQualityIssue::CrossedBook {
+            bid_price: quote.bid_price,
+            ask_price: quote.ask_price,
+        })
+    } else if quote.bid_price == quote.ask_price && config.reject_locked {
+        Some(DataQualityIssue::LockedBook {
+            price: quote.bid_price,
+        })
+    } else {
+        None
+    }
+}
+
+fn check_trade(
+    config: &QualityConfig,
+    state: &mut InstrumentState,
+    trade: &PublicTrade,
+) -> Option<DataQualityIssue> {
+    if config.duplicate_window > 0 && !trade.id.is_empty() {
+        if state.trade_ids.contains(&trade.id) {
+            return Some(DataQualityIssue::DuplicateTrade {
+                id: trade.id.clone(),
+            });
+        }
+        state.trade_ids.insert(trade.id.clone());
+        state.trade_id_order.push_back(trade.id.clone());
+        if state.trade_id_order.len() > config.duplicate_window {
+            if let Some(oldest) = state.trade_id_order.pop_front() {
+                state.trade_ids.remove(&oldest);
+            }
+        }
+    }
+
+    let band = config.outlier_band?;
+    let reference = (state.prices.len() >= config.outlier_min_samples)
+        .then(|| median(&state.prices))
+        .flatten();
+
+    // Outliers still enter the window so a genuine level shift is accepted once it
+    // makes up half of the recent trades
+    state.prices.push_back(trade.price);
+    if state.prices.len() > config.outlier_window {
+        state.prices.pop_front();
+    }
+
+    let reference = reference?;
+    if (trade.price - reference).abs() > reference * band {
+        Some(DataQualityIssue::OutlierTrade {
+            price: trade.price,
+            reference,
+        })
+    } else {
+        None
+    }
+}
+
+fn median(prices: &VecDeque<Decimal>) -> Option<Decimal> {
+    let mut sorted: Vec<Decimal> = prices.iter().copied().collect();
+    sorted.sort_unstable();
+    sorted.get(sorted.len() / 2).copied()
+}
+
+/// Passes events from an inner stream through a `DataQualityFilter`.
+///
+/// When the inner stream produces nothing for the staleness interval, the pending `next`
+/// on it is abandoned and instruments are checked as of the last receipt time plus the
+/// idle wall time, so the inner stream's `next` must be cancel-safe.
+pub struct QualityFilteredStream<S> {
+    inner: S,
+    filter: DataQualityFilter,
+    pending: VecDeque<MarketEvent>,
+    /// When the inner stream last produced an event or the filter was last checked
+    last_activity: tokio::time::Instant,
+    /// Receipt time of the last inner event and when it arrived
+    last_event: Option<(DateTime<Utc>, tokio::time::Instant)>,
+}
+
+impl<S> QualityFilteredStream<S> {
+    /// Filter everything `inner` produces
+    pub fn new(inner: S, filter: DataQualityFilter) -> Self {
+        Self {
+            inner,
+            filter,
+            pending: VecDeque::new(),
+            last_activity: tokio::time::Instant::now(),
+            last_event: None,
+        }
+    }
+
+    /// Filter state
+    pub fn filter(&self) -> &DataQualityFilter {
+        &self.filter
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> MarketDataStream for QualityFilteredStream<S>
+where
+    S: MarketDataStream + Send,
+    S::Error: Send,
+{
+    type Error = S::Error;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Some(event));
+            }
+            let next = match self.filter.config.stale_after {
+                Some(stale_after) => {
+                    let deadline = self.last_activity + stale_after;
+                    tokio::time::timeout_at(deadline, self.inner.next()).await
+                }
+                None => Ok(self.inner.next().await),
+            };
+            self.last_activity = tokio::time::Instant::now();
+            match next {
+                Ok(Ok(Some(event))) => {
+                    self.last_event = Some((event.receipt_time, self.last_activity));
+                    self.pending.extend(self.filter.process(event));
+                }
+                Ok(Ok(None)) => return Ok(None),
+                Ok(Err(error)) => return Err(error),
+                Err(_) => {
+                    if let Some((receipt_time, arrived)) = self.last_event {
+                        let idle = chrono::Duration::from_std(arrived.elapsed())
+                            .unwrap_or(chrono::Duration::MAX);
+                        self.pending
+                            .extend(self.filter.check_stale(receipt_time + idle));
+                    }
+                }
+            }
+        }
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.inner.subscribe(instruments).await
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.inner.unsubscribe(instruments).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{InstrumentKind, MockMarketDataStream, Side};
+    use chrono::{Duration as ChronoDuration, TimeZone};
+    use rust_decimal_macros::dec;
+
+    fn event(symbol: &str, seconds: i64, kind: MarketDataKind) -> MarketEvent {
+        let time = Utc.timestamp_opt(1_700_000_000 + seconds, 0).unwrap();
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: InstrumentId {
+                base: symbol.to_string(),
+                quote: "USDT".to_string(),
+                exchange_symbol: format!("{}USDT", symbol),
+                kind: InstrumentKind::Spot,
+            },
+            kind,
+            exchange_time: time,
+            receipt_time: time,
+        }
+    }
+
+    fn trade(id: &str, price: Decimal) -> MarketDataKind {
+        MarketDataKind::Trade(PublicTrade {
+            id: id.to_string(),
+            price,
+            quantity: dec!(1),
+            side: Side::Buy,
+            timestamp: Utc::now(),
+        })
+    }
+
+    fn quote(bid_price: Decimal, ask_price: Decimal) -> MarketDataKind {
+        MarketDataKind::OrderBookL1(OrderBookL1 {
+            bid_price,
+            bid_quantity: dec!(1),
+            ask_price,
+            ask_quantity: dec!(1),
+            timestamp: Utc::now(),
+            update_id: None,
+        })
+    }
+
+    fn issue(event: &MarketEvent) -> Option<&DataQualityIssue> {
+        match &event.kind {
+            MarketDataKind::DataQuality(quality) => Some(&quality.issue),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bad_ticks_are_dropped() {
+        let mut events = vec![
+            event("BTC", 0, quote(dec!(100), dec!(101))),
+            event("BTC", 0, quote(dec!(102), dec!(101))),
+            event("BTC", 0, quote(dec!(101), dec!(101))),
+        ];
+        for i in 0..10 {
+            events.push(event(
+                "BTC",
+                1,
+                trade(&i.to_string(), dec!(100) + Decimal::from(i % 2)),
+            ));
+        }
+        events.push(event("BTC", 2, trade("9", dec!(100))));
+        events.push(event("BTC", 2, trade("10", dec!(150))));
+        events.push(event("BTC", 2, trade("11", dec!(103))));
+
+        let mut stream = QualityFilteredStream::new(
+            MockMarketDataStream::new(events),
+            DataQualityFilter::default(),
+        );
+        let mut forwarded = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            forwarded.push(event);
+        }
+
+        let issues: Vec<_> = forwarded.iter().filter_map(issue).collect();
+        assert_eq!(
+            issues,
+            vec![
+                &DataQualityIssue::CrossedBook {
+                    bid_price: dec!(102),
+                    ask_price: dec!(101)
+                },
+                &DataQualityIssue::LockedBook { price: dec!(101) },
+                &DataQualityIssue::DuplicateTrade {
+                    id: "9".to_string()
+                },
+                &DataQualityIssue::OutlierTrade {
+                    price: dec!(150),
+                    reference: dec!(101)
+                },
+            ]
+        );
+        // One good quote and eleven good trades remain
+        assert_eq!(forwarded.len(), 4 + 12);
+        assert_eq!(stream.filter().issues(), 4);
+    }
+
+    #[test]
+    fn test_stale_instrument_and_flag_action() {
+        let mut filter = DataQualityFilter::new(
+            QualityConfig::new()
+                .with_action(QualityAction::Flag)
+                .with_stale_after(Some(Duration::from_secs(5))),
+        );
+        filter.process(event("ETH", 0, quote(dec!(10), dec!(11))));
+        filter.process(event("BTC", 0, quote(dec!(100), dec!(101))));
+        assert!(
+            filter
+                .process(event("BTC", 4, quote(dec!(100), dec!(101))))
+                .len()
+                == 1
+        );
+
+        // ETH has been silent for six seconds when the next BTC quote arrives
+        let output = filter.process(event("BTC", 6, quote(dec!(101), dec!(101))));
+        assert_eq!(output.len(), 3);
+        assert_eq!(output[0].instrument.base, "ETH");
+        assert!(matches!(
+            issue(&output[0]),
+            Some(DataQualityIssue::Stale { .. })
+        ));
+        // Flagged quotes are forwarded after their notification
+        assert!(matches!(
+            issue(&output[1]),
+            Some(DataQualityIssue::LockedBook { .. })
+        ));
+        assert!(matches!(output[2].kind, MarketDataKind::OrderBookL1(_)));
+
+        // Reported once, and again after it updates and goes quiet
+        let later = Utc.timestamp_opt(1_700_000_008, 0).unwrap();
+        assert!(filter.check_stale(later).is_empty());
+        filter.process(event("ETH", 9, quote(dec!(10), dec!(11))));
+        let stale = filter.check_stale(later + ChronoDuration::seconds(10));
+        assert_eq!(stale.len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_silent_stream_is_reported_stale() {
+        use futures::StreamExt;
+
+        // Receipt times far in the past, as in a replay, and a day ahead of the local clock
+        let mut ahead = event("ETH", 0, quote(dec!(10), dec!(11)));
+        ahead.receipt_time = Utc::now() + ChronoDuration::days(1);
+        for recent in [event("BTC", 0, quote(dec!(100), dec!(101))), ahead] {
+            let symbol = recent.instrument.base.clone();
+            // One event, then a feed that stays open without sending anything
+            let events = futures::stream::iter([Ok::<_, std::convert::Infallible>(recent)])
+                .chain(futures::stream::pending());
+            let mut stream = QualityFilteredStream::new(
+                crate::data::StreamMarketDataStream::new(events),
+                DataQualityFilter::new(
+                    QualityConfig::new().with_stale_after(Some(Duration::from_secs(5))),
+                ),
+            );
+            assert!(matches!(
+                stream.next().await.unwrap().unwrap().kind,
+                MarketDataKind::OrderBookL1(_)
+            ));
+
+            let started = tokio::time::Instant::now();
+            let stale = tokio::time::timeout(Duration::from_secs(60), stream.next())
+                .await
+                .expect("silent instrument reported stale")
+                .unwrap()
+                .unwrap();
+            assert!(matches!(
+                issue(&stale),
+                Some(DataQualityIssue::Stale { .. })
+            ));
+            assert_eq!(stale.instrument.base, symbol);
+            assert_eq!(started.elapsed(), Duration::from_secs(5));
+        }
+    }
+}
@@ -0,0 +1,462 @@
+//! Market data recording
+//!
+//! `MarketDataRecorder` writes events into capture files laid out as
+//! `<root>/<exchange>/<kind>/<symbol>/<YYYY-MM-DDTHH>.mdc`, one file per exchange,
+//! instrument and hour of receipt time. The instrument kind (`spot`, `perpetual` or
+//! `future-<YYYYMMDD>`) keeps spot and derivative markets sharing a symbol apart.
+//! `RecordingMarketDataStream` taps any `MarketDataStream` and hands events to a
+//! recorder on a background thread so disk writes never stall the consumer. The queue
+//! to the thread is bounded: when the disk falls behind, further events are dropped
+//! and counted rather than buffered without limit. The thread flushes open captures on
+//! a timer, so a quiet instrument's events reach disk without waiting for rotation.
+
+use super::capture::{CaptureWriter, Compression, CAPTURE_EXTENSION};
+use super::{ExchangeId, InstrumentId, InstrumentKind, MarketDataStream, MarketEvent};
+use chrono::{DateTime, Timelike, Utc};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Recorder settings
+#[derive(Debug, Clone)]
+pub struct RecorderConfig {
+    /// Directory capture files are written under
+    pub root: PathBuf,
+    /// Block compression
+    pub compression: Compression,
+    /// Uncompressed block size in bytes
+    pub block_size: usize,
+    /// Events queued for the background thread before further ones are dropped
+    pub queue_capacity: usize,
+    /// How often the background thread flushes open captures
+    pub flush_interval: Duration,
+}
+
+impl RecorderConfig {
+    /// Record uncompressed 64 KiB blocks under `root`, queueing up to 65536 events and
+    /// flushing every second
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            compression: Compression::None,
+            block_size: 64 * 1024,
+            queue_capacity: 65_536,
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Set block compression
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set the uncompressed block size
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Set how many events may wait for the background thread
+    pub fn with_queue_capacity(mut self, queue_capacity: usize) -> Self {
+        self.queue_capacity = queue_capacity;
+        self
+    }
+
+    /// Set how often the background thread flushes open captures
+    pub fn with_flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+}
+
+/// Open capture file for one exchange and instrument
+struct OpenCapture {
+    hour: DateTime<Utc>,
+    writer: CaptureWriter,
+}
+
+/// Writes market events into hourly capture files per exchange and instrument
+pub struct MarketDataRecorder {
+    config: RecorderConfig,
+    captures: HashMap<(ExchangeId, InstrumentId), OpenCapture>,
+}
+
+impl MarketDataRecorder {
+    /// Create a recorder; directories are created as events arrive
+    pub fn new(config: RecorderConfig) -> Self {
+        Self {
+            config,
+            captures: HashMap::new(),
+        }
+    }
+
+    /// Path of the capture file holding events for an exchange, instrument and hour
+    pub fn capture_path(
+        root: &Path,
+        exchange: ExchangeId,
+        instrument: &InstrumentId,
+        hour: DateTime<Utc>,
+    ) -> PathBuf {
+        let kind = match instrument.kind {
+            InstrumentKind::Spot => "spot".to_string(),
+            InstrumentKind::Perpetual => "perpetual".to_string(),
+            InstrumentKind::DatedFuture { expiry } => format!("future-{}", expiry.format("%Y%m%d")),
+        };
+        root.join(format!("{exchange:?}").to_lowercase())
+            .join(kind)
+            .join(instrument.exchange_symbol.replace(['/', '\\'], "-"))
+            .join(format!(
+                "{}.{CAPTURE_EXTENSION}",
+                hour.format("%Y-%m-%dT%H")
+            ))
+    }
+
+    /// Record an event, rotating to a new file when its receipt hour changes
+    pub fn record(&mut self, event: &MarketEvent) -> io::Result<()> {
+        let hour = truncate_to_hour(event.receipt_time);
+        let key = (event.exchange, event.instrument.clone());
+
+        let rotate = self
+            .captures
+            .get(&key)
+            .is_none_or(|capture| capture.hour != hour);
+        if rotate {
+            // Drop the previous writer first so its last block is flushed
+            self.captures.remove(&key);
+            let path =
+                Self::capture_path(&self.config.root, event.exchange, &event.instrument, hour);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let writer =
+                CaptureWriter::open(path, self.config.compression, self.config.block_size)?;
+            self.captures
+                .insert(key.clone(), OpenCapture { hour, writer });
+        }
+
+        self.captures
+            .get_mut(&key)
+            .expect("capture opened above")
+            .writer
+            .write(event)
+    }
+
+    /// Flush buffered events of every open file
+    pub fn flush(&mut self) -> io::Result<()> {
+        for capture in self.captures.values_mut() {
+            capture.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Spawn a background thread that records events sent to the returned handle and
+    /// flushes open captures every `flush_interval`
+    pub fn spawn(mut self) -> RecorderHandle {
+        let (sender, receiver) = mpsc::sync_channel::<MarketEvent>(self.config.queue_capacity);
+        let thread = std::thread::spawn(move || {
+            let interval = self.config.flush_interval;
+            let mut next_flush = Instant::now() + interval;
+            loop {
+                match receiver.recv_timeout(next_flush.saturating_duration_since(Instant::now())) {
+                    Ok(event) => {
+                        if let Err(error) = self.record(&event) {
+                            warn!("Failed to record market event: {}", error);
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if Instant::now() >= next_flush {
+                    if let Err(error) = self.flush() {
+                        warn!("Failed to flush market data captures: {}", error);
+                    }
+                    next_flush = Instant::now() + interval;
+                }
+            }
+            self.flush()
+        });
+        RecorderHandle {
+            sender: Some(sender),
+            thread: Some(thread),
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+fn truncate_to_hour(time: DateTime<Utc>) -> DateTime<Utc> {
+    time.with_minute(0)
+        .and_then(|time| time.with_second(0))
+        .and_then(|time| time.with_nanosecond(0))
+        .unwrap_or(time)
+}
+
+/// Handle to a recorder running on a background thread
+pub struct RecorderHandle {
+    sender: Option<mpsc::SyncSender<MarketEvent>>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl RecorderHandle {
+    /// Queue an event for recording, dropping it if the queue is full
+    pub fn record(&self, event: MarketEvent) {
+        if let Some(sender) = &self.sender {
+            if let Err(TrySendError::Full(_)) = sender.try_send(event) {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Events dropped because the recorder thread fell behind
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Stop the recorder after writing queued events and wait for it to flush
+    pub fn shutdown(mut self) -> io::Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> io::Result<()> {
+        self.sender.take();
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .map_err(|_| io::Error::other("recorder thread panicked"))?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for RecorderHandle {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// Forwards events from an inner stream while recording a copy of each
+pub struct RecordingMarketDataStream<S> {
+    inner: S,
+    recorder: RecorderHandle,
+}
+
+impl<S> RecordingMarketDataStream<S> {
+    /// Record everything `inner` produces using a background recorder
+    pub fn new(inner: S, recorder: MarketDataRecorder) -> Self {
+        Self {
+            inner,
+            recorder: recorder.spawn(),
+        }
+    }
+
+    /// Events dropped because the recorder thread fell behind
+    pub fn dropped_events(&self) -> u64 {
+        self.recorder.dropped_events()
+    }
+
+    /// Stop recording, flushing queued events, and return the inner stream
+    pub fn into_inner(self) -> io::Result<S> {
+        self.recorder.shutdown()?;
+        Ok(self.inner)
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> MarketDataStream for RecordingMarketDataStream<S>
+where
+    S: MarketDataStream + Send,
+    S::Error: Send,
+{
+    type Error = S::Error;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        let event = self.inner.next().await?;
+        if let Some(event) = &event {
+            self.recorder.record(event.clone());
+        }
+        Ok(event)
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.inner.subscribe(instruments).await
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.inner.unsubscribe(instruments).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::capture::CaptureReader;
+    use crate::data::{spot_instrument, MarketDataKind, MockMarketDataStream, PublicTrade, Side};
+    use chrono::{Duration, TimeZone};
+    use rust_decimal::Decimal;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("recorder-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        root
+    }
+
+    fn trade(id: u64, receipt_time: DateTime<Utc>) -> MarketEvent {
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: spot_instrument("BTC", "USDT"),
+            kind: MarketDataKind::Trade(PublicTrade {
+                id: id.to_string(),
+                price: Decimal::new(4_200_000 + id as i64, 2),
+                quantity: Decimal::new(15, 3),
+                side: Side::Buy,
+                timestamp: receipt_time,
+            }),
+            exchange_time: receipt_time,
+            receipt_time,
+        }
+    }
+
+    fn read_all(path: &Path) -> Vec<MarketEvent> {
+        CaptureReader::open(path)
+            .unwrap()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_capture_round_trip_and_seek() {
+        let root = temp_root("seek");
+        std::fs::create_dir_all(&root).unwrap();
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let events: Vec<_> = (0..500)
+            .map(|i| trade(i, start + Duration::milliseconds(i as i64 * 10)))
+            .collect();
+
+        for compression in [Compression::None, Compression::Lz4] {
+            let path = root.join(format!("{compression:?}.mdc"));
+            let mut writer = CaptureWriter::open(&path, compression, 1024).unwrap();
+            for event in &events[..300] {
+                writer.write(event).unwrap();
+            }
+            drop(writer);
+
+            // Reopening appends after the existing blocks
+            let mut writer = CaptureWriter::open(&path, compression, 1024).unwrap();
+            for event in &events[300..] {
+                writer.write(event).unwrap();
+            }
+            drop(writer);
+
+            assert_eq!(read_all(&path), events);
+
+            let mut reader = CaptureReader::open(&path).unwrap();
+            assert!(reader.index().len() > 1);
+            reader.seek(start + Duration::milliseconds(2_345)).unwrap();
+            assert_eq!(reader.next_event().unwrap(), Some(events[235].clone()));
+
+            // A missing index is rebuilt from the block headers
+            std::fs::remove_file(crate::data::capture::index_path(&path)).unwrap();
+            let mut reader = CaptureReader::open(&path).unwrap();
+            reader.seek(start + Duration::milliseconds(4_990)).unwrap();
+            assert_eq!(reader.next_event().unwrap(), Some(events[499].clone()));
+            assert_eq!(reader.next_event().unwrap(), None);
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recording_stream_rotates_hourly() {
+        let root = temp_root("rotate");
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 59, 59).unwrap();
+        let events = vec![
+            trade(1, start),
+            trade(2, start + Duration::milliseconds(500)),
+            trade(3, start + Duration::seconds(2)),
+        ];
+
+        // The perpetual shares the spot symbol but is recorded separately
+        let mut perpetual = trade(4, start);
+        perpetual.instrument.kind = InstrumentKind::Perpetual;
+        let mut all = events.clone();
+        all.insert(1, perpetual.clone());
+
+        let recorder =
+            MarketDataRecorder::new(RecorderConfig::new(&root).with_compression(Compression::Lz4));
+        let mut stream =
+            RecordingMarketDataStream::new(MockMarketDataStream::new(all.clone()), recorder);
+        let mut forwarded = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            forwarded.push(event);
+        }
+        stream.into_inner().unwrap();
+        assert_eq!(forwarded, all);
+
+        let instrument = &events[0].instrument;
+        let first = MarketDataRecorder::capture_path(
+            &root,
+            ExchangeId::Binance,
+            instrument,
+            Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap(),
+        );
+        let second = MarketDataRecorder::capture_path(
+            &root,
+            ExchangeId::Binance,
+            instrument,
+            Utc.with_ymd_and_hms(2024, 1, 1, 13, 0, 0).unwrap(),
+        );
+        assert!(first.ends_with("binance/spot/BTCUSDT/2024-01-01T12.mdc"));
+        assert_eq!(read_all(&first), events[..2]);
+        assert_eq!(read_all(&second), events[2..]);
+        let perpetual_path = MarketDataRecorder::capture_path(
+            &root,
+            ExchangeId::Binance,
+            &perpetual.instrument,
+            Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap(),
+        );
+        assert!(perpetual_path.ends_with("binance/perpetual/BTCUSDT/2024-01-01T12.mdc"));
+        assert_eq!(read_all(&perpetual_path), [perpetual]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_open_captures_are_flushed_on_a_timer() {
+        let root = temp_root("flush");
+        let event = trade(1, Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap());
+        let config =
+            RecorderConfig::new(&root).with_flush_interval(std::time::Duration::from_millis(20));
+        let handle = MarketDataRecorder::new(config).spawn();
+        handle.record(event.clone());
+
+        // The recorder keeps running, so only the timer can have written the block
+        let path = MarketDataRecorder::capture_path(
+            &root,
+            ExchangeId::Binance,
+            &event.instrument,
+            event.receipt_time,
+        );
+        let mut recorded = Vec::new();
+        for _ in 0..100 {
+            if path.exists() {
+                recorded = read_all(&path);
+                if !recorded.is_empty() {
+                    break;
+                }
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(recorded, [event]);
+        assert_eq!(handle.dropped_events(), 0);
+
+        handle.shutdown().unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,546 @@
+//! Historical market data replay
+//!
+//! `ReplayMarketDataStream` plays back capture files written by the recorder and
+//! newline-delimited JSON `MarketEvent`s without loading them into memory. Sources
+//! are read lazily and merged by receipt time, so a directory holding days of hourly
+//! captures across many instruments replays as one ordered stream. The hourly files
+//! of one instrument are chained, so only one of them is open at a time.
+//!
+//! Files are read, decompressed and merged on a dedicated thread that keeps a bounded
+//! number of events ahead of playback, so replay never blocks the async runtime.
+
+use super::capture::{CaptureReader, CAPTURE_EXTENSION};
+use super::{InstrumentId, MarketDataStream, MarketEvent};
+use chrono::{DateTime, Utc};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+/// Events the reader thread may read ahead of playback
+const READ_AHEAD: usize = 1024;
+
+/// Playback pacing
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum ReplaySpeed {
+    /// Emit events as soon as they are read
+    #[default]
+    AsFastAsPossible,
+    /// Preserve the original spacing between events
+    RealTime,
+    /// Compress the original spacing by the given factor, e.g. `10.0` plays ten times faster
+    Multiplier(f64),
+}
+
+impl ReplaySpeed {
+    fn factor(self) -> Option<f64> {
+        match self {
+            ReplaySpeed::AsFastAsPossible => None,
+            ReplaySpeed::RealTime => Some(1.0),
+            ReplaySpeed::Multiplier(factor) if factor > 0.0 => Some(factor),
+            ReplaySpeed::Multiplier(_) => None,
+        }
+    }
+}
+
+/// Recorded events from a single file, or from consecutive capture files
+enum ReplaySource {
+    Captures {
+        reader: Option<CaptureReader>,
+        /// Files still to be opened once `reader` is exhausted
+        files: VecDeque<PathBuf>,
+    },
+    JsonLines {
+        lines: io::Lines<BufReader<File>>,
+        path: PathBuf,
+        line: usize,
+    },
+}
+
+impl ReplaySource {
+    /// Read the next event, seeking newly opened capture files to `start`
+    fn next_event(&mut self, start: Option<DateTime<Utc>>) -> io::Result<Option<MarketEvent>> {
+        match self {
+            ReplaySource::Captures { reader, files } => loop {
+                if let Some(current) = reader {
+                    if let Some(event) = current.next_event()? {
+                        return Ok(Some(event));
+                    }
+                }
+                let Some(path) = files.pop_front() else {
+                    *reader = None;
+                    return Ok(None);
+                };
+                let mut next = CaptureReader::open(path)?;
+                if let Some(start) = start {
+                    next.seek(start)?;
+                }
+                *reader = Some(next);
+            },
+            ReplaySource::JsonLines { lines, path, line } => loop {
+                let Some(text) = lines.next().transpose()? else {
+                    return Ok(None);
+                };
+                *line += 1;
+                if text.trim().is_empty() {
+                    continue;
+                }
+                return serde_json::from_str(&text).map(Some).map_err(|error| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}:{}: {}", path.display(), line, error),
+                    )
+                });
+            },
+        }
+    }
+}
+
+/// Sources merged by receipt time on the reader thread
+struct ReplayMerge {
+    sources: Vec<ReplaySource>,
+    heads: Vec<Option<MarketEvent>>,
+    queue: BinaryHeap<Reverse<(DateTime<Utc>, usize)>>,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+}
+
+impl ReplayMerge {
+    /// Send the events between `start` and `end` in receipt time order, then the error
+    /// that stopped reading if any
+    fn run(mut self, sender: mpsc::Sender<io::Result<MarketEvent>>) {
+        if let Err(error) = self.send_events(&sender) {
+            let _ = sender.blocking_send(Err(error));
+        }
+    }
+
+    /// Returns `Ok` at the end of the sources or once the stream is dropped
+    fn send_events(&mut self, sender: &mpsc::Sender<io::Result<MarketEvent>>) -> io::Result<()> {
+        self.prime()?;
+        while let Some(event) = self.pop()? {
+            if self.end.is_some_and(|end| event.receipt_time > end) {
+                break;
+            }
+            if self.start.is_some_and(|start| event.receipt_time < start) {
+                continue;
+            }
+            if sender.blocking_send(Ok(event)).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Seek every source to the start time and read its first event
+    fn prime(&mut self) -> io::Result<()> {
+        for (index, source) in self.sources.iter_mut().enumerate() {
+            if let (
+                Some(start),
+                ReplaySource::Captures {
+                    reader: Some(reader),
+                    ..
+                },
+            ) = (self.start, &mut *source)
+            {
+                reader.seek(start)?;
+            }
+            let head = source.next_event(self.start)?;
+            if let Some(event) = &head {
+                self.queue.push(Reverse((event.receipt_time, index)));
+            }
+            self.heads.push(head);
+        }
+        Ok(())
+    }
+
+    /// Pop the earliest event across all sources, refilling from its source
+    fn pop(&mut self) -> io::Result<Option<MarketEvent>> {
+        let Some(Reverse((_, index))) = self.queue.pop() else {
+            return Ok(None);
+        };
+        let event = self.heads[index].take();
+        let next = self.sources[index].next_event(self.start)?;
+        if let Some(next) = &next {
+            self.queue.push(Reverse((next.receipt_time, index)));
+        }
+        self.heads[index] = next;
+        Ok(event)
+    }
+}
+
+/// Market data stream replaying recorded events in receipt time order
+pub struct ReplayMarketDataStream {
+    /// Sources not yet handed to the reader thread
+    sources: Vec<ReplaySource>,
+    /// Events from the reader thread, once the first event was requested
+    receiver: Option<mpsc::Receiver<io::Result<MarketEvent>>>,
+    /// Event received but not yet due
+    head: Option<MarketEvent>,
+    speed: ReplaySpeed,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    instruments: Vec<InstrumentId>,
+    /// First replayed event time and the instant it was emitted
+    anchor: Option<(DateTime<Utc>, Instant)>,
+}
+
+impl ReplayMarketDataStream {
+    /// Create an empty replay; add sources with the `with_*` methods
+    pub fn new() -> Self {
+        Self {
+            sources: Vec::new(),
+            receiver: None,
+            head: None,
+            speed: ReplaySpeed::default(),
+            start: None,
+            end: None,
+            instruments: Vec::new(),
+            anchor: None,
+        }
+    }
+
+    /// Add a capture file written by `MarketDataRecorder` or `CaptureWriter`
+    pub fn with_capture_file(mut self, path: impl AsRef<Path>) -> io::Result<Self> {
+        let reader = CaptureReader::open(path)?;
+        self.sources.push(ReplaySource::Captures {
+            reader: Some(reader),
+            files: VecDeque::new(),
+        });
+        Ok(self)
+    }
+
+    /// Add a file with one JSON-encoded `MarketEvent` per line
+    pub fn with_json_lines(mut self, path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let lines = BufReader::new(File::open(&path)?).lines();
+        self.sources.push(ReplaySource::JsonLines {
+            lines,
+            path,
+            line: 0,
+        });
+        Ok(self)
+    }
+
+    /// Add a file, treating `.mdc` files as captures and anything else as JSON lines
+    pub fn with_file(self, path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|ext| ext == CAPTURE_EXTENSION) {
+            self.with_capture_file(path)
+        } else {
+            self.with_json_lines(path)
+        }
+    }
+
+    /// Add every capture file below a directory, such as a recorder root.
+    ///
+    /// Files are opened as replay reaches them; the files of each directory are
+    /// assumed to cover consecutive time ranges in name order, as the recorder's
+    /// hourly files do.
+    pub fn with_directory(mut self, root: impl AsRef<Path>) -> io::Result<Self> {
+        let mut pending = vec![root.as_ref().to_path_buf()];
+        let mut files = Vec::new();
+        while let Some(dir) = pending.pop() {
+            for entry in std::fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else if path.extension().is_some_and(|ext| ext == CAPTURE_EXTENSION) {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        for file in files {
+            match self.sources.last_mut() {
+                Some(ReplaySource::Captures { files, .. })
+                    if files
+                        .back()
+                        .is_some_and(|last| last.parent() == file.parent()) =>
+                {
+                    files.push_back(file)
+                }
+                _ => self.sources.push(ReplaySource::Captures {
+                    reader: None,
+                    files: VecDeque::from([file]),
+                }),
+            }
+        }
+        Ok(self)
+    }
+
+    /// Set playback pacing
+    pub fn with_speed(mut self, speed: ReplaySpeed) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Skip events received before `start`
+    pub fn with_start(mut self, start: DateTime<Utc>) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    /// Stop at the first event received after `end`
+    pub fn with_end(mut self, end: DateTime<Utc>) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    /// Only replay events for the given instruments; all instruments are replayed if empty
+    pub fn with_instruments(mut self, instruments: Vec<InstrumentId>) -> Self {
+        self.instruments = instruments;
+        self
+    }
+
+    /// Hand the sources to a reader thread
+    fn start_reader(&mut self) -> io::Result<mpsc::Receiver<io::Result<MarketEvent>>> {
+        let merge = ReplayMerge {
+            sources: std::mem::take(&mut self.sources),
+            heads: Vec::new(),
+            queue: BinaryHeap::new(),
+            start: self.start,
+            end: self.end,
+        };
+        let (sender, receiver) = mpsc::channel(READ_AHEAD);
+        std::thread::Builder::new()
+            .name("replay-reader".to_string())
+            .spawn(move || merge.run(sender))?;
+        Ok(receiver)
+    }
+
+    fn accepts(&self, event: &MarketEvent) -> bool {
+        self.instruments.is_empty() || self.instruments.contains(&event.instrument)
+    }
+
+    /// Wait until the event is due according to the playback speed
+    async fn pace(&mut self, time: DateTime<Utc>) {
+        let Some(factor) = self.speed.factor() else {
+            return;
+        };
+        let (anchor_time, anchor_instant) = *self.anchor.get_or_insert((time, Instant::now()));
+        let Ok(offset) = (time - anchor_time).to_std() else {
+            return;
+        };
+        tokio::time::sleep_until(anchor_instant + offset.div_f64(factor)).await;
+    }
+}
+
+impl Default for ReplayMarketDataStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl MarketDataStream for ReplayMarketDataStream {
+    type Error = io::Error;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        if self.receiver.is_none() {
+            self.receiver = Some(self.start_reader()?);
+        }
+        // The head is only taken once it is due, so a cancelled call loses nothing
+        loop {
+            let time = match &self.head {
+                Some(event) if self.accepts(event) => event.receipt_time,
+                _ => {
+                    let receiver = self.receiver.as_mut().expect("reader started");
+                    match receiver.recv().await {
+                        Some(event) => self.head = Some(event?),
+                        None => return Ok(None),
+                    }
+                    continue;
+                }
+            };
+            self.pace(time).await;
+            return Ok(self.head.take());
+        }
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        for instrument in instruments {
+            if !self.instruments.contains(instrument) {
+                self.instruments.push(instrument.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.instruments
+            .retain(|instrument| !instruments.contains(instrument));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::capture::{CaptureWriter, Compression};
+    use crate::data::{spot_instrument, ExchangeId, MarketDataKind, PublicTrade, Side};
+    use chrono::{Duration, TimeZone};
+    use rust_decimal::Decimal;
+    use std::io::Write;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("replay-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn trade(symbol: &str, millis: i64) -> MarketEvent {
+        let time =
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + Duration::milliseconds(millis);
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: spot_instrument(&symbol[..3], &symbol[3..]),
+            kind: MarketDataKind::Trade(PublicTrade {
+                id: millis.to_string(),
+                price: Decimal::new(100, 0),
+                quantity: Decimal::ONE,
+                side: Side::Sell,
+                timestamp: time,
+            }),
+            exchange_time: time,
+            receipt_time: time,
+        }
+    }
+
+    /// Write BTC trades to a capture file and ETH trades to a JSON lines file
+    fn write_sources(root: &Path) -> (PathBuf, PathBuf) {
+        let capture = root.join("btc.mdc");
+        let mut writer = CaptureWriter::open(&capture, Compression::Lz4, 256).unwrap();
+        for millis in [0, 100, 200, 300, 400] {
+            writer.write(&trade("BTCUSDT", millis)).unwrap();
+        }
+        drop(writer);
+
+        let json = root.join("eth.jsonl");
+        let mut file = File::create(&json).unwrap();
+        for millis in [50, 150, 250, 350] {
+            writeln!(
+                file,
+                "{}",
+                serde_json::to_string(&trade("ETHUSDT", millis)).unwrap()
+            )
+            .unwrap();
+        }
+        (capture, json)
+    }
+
+    async fn collect(mut stream: ReplayMarketDataStream) -> Vec<MarketEvent> {
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            events.push(event);
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn test_replay_merges_and_filters() {
+        let root = temp_root("merge");
+        let (capture, json) = write_sources(&root);
+        let epoch = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+
+        let stream = ReplayMarketDataStream::new()
+            .with_file(&capture)
+            .unwrap()
+            .with_file(&json)
+            .unwrap();
+        let ids: Vec<_> = collect(stream)
+            .await
+            .into_iter()
+            .map(|event| event.receipt_time)
+            .collect();
+        assert_eq!(ids.len(), 9);
+        assert!(ids.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let stream = ReplayMarketDataStream::new()
+            .with_capture_file(&capture)
+            .unwrap()
+            .with_json_lines(&json)
+            .unwrap()
+            .with_start(epoch + Duration::milliseconds(150))
+            .with_end(epoch + Duration::milliseconds(350))
+            .with_instruments(vec![spot_instrument("ETH", "USDT")]);
+        let events = collect(stream).await;
+        assert_eq!(
+            events,
+            vec![
+                trade("ETHUSDT", 150),
+                trade("ETHUSDT", 250),
+                trade("ETHUSDT", 350)
+            ]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replay_speed() {
+        let root = temp_root("speed");
+        let (_, json) = write_sources(&root);
+
+        for (speed, expected_millis) in [
+            (ReplaySpeed::AsFastAsPossible, 0),
+            (ReplaySpeed::RealTime, 400),
+            (ReplaySpeed::Multiplier(4.0), 100),
+        ] {
+            let stream = ReplayMarketDataStream::new()
+                .with_directory(&root)
+                .unwrap()
+                .with_json_lines(&json)
+                .unwrap()
+                .with_speed(speed);
+            let started = Instant::now();
+            assert_eq!(collect(stream).await.len(), 9);
+            assert_eq!(
+                started.elapsed(),
+                std::time::Duration::from_millis(expected_millis)
+            );
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancelled_next_keeps_event() {
+        let root = temp_root("cancel");
+        let dir = root.join("binance").join("BTCUSDT");
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, millis) in [("00.mdc", [0, 100]), ("01.mdc", [200, 300])] {
+            let mut writer = CaptureWriter::open(dir.join(file), Compression::None, 256).unwrap();
+            for millis in millis {
+                writer.write(&trade("BTCUSDT", millis)).unwrap();
+            }
+        }
+
+        let mut stream = ReplayMarketDataStream::new()
+            .with_directory(&root)
+            .unwrap()
+            .with_speed(ReplaySpeed::RealTime);
+        assert_eq!(stream.sources.len(), 1);
+        assert_eq!(stream.next().await.unwrap(), Some(trade("BTCUSDT", 0)));
+
+        let cancelled = tokio::time::timeout(std::time::Duration::from_millis(50), stream.next());
+        assert!(cancelled.await.is_err());
+        let mut rest = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            rest.push(event);
+        }
+        assert_eq!(
+            rest,
+            vec![
+                trade("BTCUSDT", 100),
+                trade("BTCUSDT", 200),
+                trade("BTCUSDT", 300)
+            ]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
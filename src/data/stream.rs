@@ -0,0 +1,315 @@
+//! Interop between `MarketDataStream` and `futures::Stream`
+//!
+//! `MarketDataStreamExt::into_stream` exposes any market data stream as a
+//! `Stream<Item = Result<MarketEvent, E>>`, so the `futures` and `tokio-stream`
+//! combinators (merge, filter, throttle, timeout, chunks) apply to it. The stream ends
+//! after the source returns `Ok(None)`; errors are yielded as items and polling
+//! continues afterwards.
+//!
+//! `StreamMarketDataStream` goes the other way and turns a `Stream` of events into a
+//! `MarketDataStream`, e.g. to feed combinator output back into the engine.
+//!
+//! Channel-backed and in-memory streams (the WebSocket connectors, the mock and the
+//! synthetic generator) implement `Stream` directly and need no adapter.
+
+use super::{InstrumentId, MarketDataStream, MarketEvent};
+use futures::future::BoxFuture;
+use futures::{FutureExt, Stream, StreamExt};
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+/// Conversion of a `MarketDataStream` into a `futures::Stream`
+pub trait MarketDataStreamExt: MarketDataStream + Sized {
+    /// Expose the stream as a `Stream` of event results
+    fn into_stream(self) -> MarketDataEvents<Self> {
+        MarketDataEvents::new(self)
+    }
+}
+
+impl<S: MarketDataStream> MarketDataStreamExt for S {}
+
+type NextResult<E> = Result<Option<MarketEvent>, E>;
+/// `next` call that hands the source back with its result
+type NextFuture<S> = BoxFuture<'static, (S, NextResult<<S as MarketDataStream>::Error>)>;
+
+enum State<S: MarketDataStream> {
+    Idle {
+        stream: S,
+        finished: bool,
+    },
+    Pending(NextFuture<S>),
+    /// Only observed if a poll panicked
+    Poisoned,
+}
+
+/// `Stream` over the events of a `MarketDataStream`, see `MarketDataStreamExt`
+pub struct MarketDataEvents<S: MarketDataStream> {
+    state: State<S>,
+}
+
+impl<S: MarketDataStream> MarketDataEvents<S> {
+    /// Wrap a market data stream
+    pub fn new(stream: S) -> Self {
+        Self {
+            state: State::Idle {
+                stream,
+                finished: false,
+            },
+        }
+    }
+
+    /// Source stream, unless a `next` call is in flight
+    pub fn get_mut(&mut self) -> Option<&mut S> {
+        match &mut self.state {
+            State::Idle { stream, .. } => Some(stream),
+            _ => None,
+        }
+    }
+
+    /// Unwrap the source stream, unless a `next` call is in flight
+    pub fn into_inner(self) -> Option<S> {
+        match self.state {
+            State::Idle { stream, .. } => Some(stream),
+            _ => None,
+        }
+    }
+}
+
+// The state never pins the source; it is moved into and out of the boxed future
+impl<S: MarketDataStream> Unpin for MarketDataEvents<S> {}
+
+impl<S> Stream for MarketDataEvents<S>
+where
+    S: MarketDataStream + Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Item = Result<MarketEvent, S::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut future = match std::mem::replace(&mut self.state, State::Poisoned) {
+            State::Idle {
+                stream,
+                finished: true,
+            } => {
+                self.state = State::Idle {
+                    stream,
+                    finished: true,
+                };
+                return Poll::Ready(None);
+            }
+            State::Idle { mut stream, .. } => async move {
+                let result = stream.next().await;
+                (stream, result)
+            }
+            .boxed(),
+            State::Pending(future) => future,
+            State::Poisoned => panic!("MarketDataEvents polled after a panic"),
+        };
+
+        match future.poll_unpin(cx) {
+            Poll::Pending => {
+                self.state = State::Pending(future);
+                Poll::Pending
+            }
+            Poll::Ready((stream, result)) => {
+                let item = result.transpose();
+                self.state = State::Idle {
+                    stream,
+                    finished: item.is_none(),
+                };
+                Poll::Ready(item)
+            }
+        }
+    }
+}
+
+/// `MarketDataStream` over a `Stream` of event results.
+///
+/// Every event is passed through until the first subscription; from then on events
+/// are filtered by the subscribed instruments, so unsubscribing from all of them passes
+/// nothing. Wrap infallible streams with `.map(Ok::<_, Infallible>)`.
+pub struct StreamMarketDataStream<St> {
+    inner: St,
+    instruments: HashSet<InstrumentId>,
+    /// Whether a subscription has been made and events are filtered
+    filtering: bool,
+}
+
+impl<St> StreamMarketDataStream<St> {
+    /// Wrap a stream of event results
+    pub fn new(inner: St) -> Self {
+        Self {
+            inner,
+            instruments: HashSet::new(),
+            filtering: false,
+        }
+    }
+
+    /// Unwrap the source stream
+    pub fn into_inner(self) -> St {
+        self.inner
+    }
+}
+
+#[async_trait::async_trait]
+impl<St, E> MarketDataStream for StreamMarketDataStream<St>
+where
+    St: Stream<Item = Result<MarketEvent, E>> + Unpin + Send,
+    E: Send,
+{
+    type Error = E;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        while let Some(event) = StreamExt::next(&mut self.inner).await {
+            let event = event?;
+            if !self.filtering || self.instruments.contains(&event.instrument) {
+                return Ok(Some(event));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.filtering = true;
+        self.instruments.extend(instruments.iter().cloned());
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.filtering = true;
+        for instrument in instruments {
+            self.instruments.remove(instrument);
+        }
+        Ok(())
+    }
+}
+
+/// `Stream::poll_next` for streams backed by an optional event channel
+pub(crate) fn poll_receiver<E>(
+    receiver: &mut Option<mpsc::Receiver<MarketEvent>>,
+    cx: &mut Context<'_>,
+) -> Poll<Option<Result<MarketEvent, E>>> {
+    match receiver {
+        Some(receiver) => receiver.poll_recv(cx).map(|event| event.map(Ok)),
+        None => Poll::Ready(None),
+    }
+}
+
+/// `Stream::poll_next` for streams whose channel also carries errors
+pub(crate) fn poll_result_receiver<E, F: From<E>>(
+    receiver: &mut Option<mpsc::Receiver<Result<MarketEvent, E>>>,
+    cx: &mut Context<'_>,
+) -> Poll<Option<Result<MarketEvent, F>>> {
+    match receiver {
+        Some(receiver) => receiver
+            .poll_recv(cx)
+            .map(|item| item.map(|result| result.map_err(F::from))),
+        None => Poll::Ready(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{
+        spot_instrument, ExchangeId, MarketDataKind, MockMarketDataStream, PublicTrade, Side,
+    };
+    use chrono::{TimeZone, Utc};
+    use futures::TryStreamExt;
+    use rust_decimal::Decimal;
+    use std::convert::Infallible;
+
+    fn trade(symbol: &str, id: u32) -> MarketEvent {
+        let timestamp = Utc.timestamp_opt(1_700_000_000 + id as i64, 0).unwrap();
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: spot_instrument(symbol, "USDT"),
+            kind: MarketDataKind::Trade(PublicTrade {
+                id: id.to_string(),
+                price: Decimal::from(100 + id),
+                quantity: Decimal::ONE,
+                side: Side::Buy,
+                timestamp,
+            }),
+            exchange_time: timestamp,
+            receipt_time: timestamp,
+        }
+    }
+
+    /// Market data stream without a `Stream` impl, failing on its second event
+    struct Flaky(Vec<MarketEvent>, usize);
+
+    #[async_trait::async_trait]
+    impl MarketDataStream for Flaky {
+        type Error = std::io::Error;
+
+        async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+            self.1 += 1;
+            if self.1 == 2 {
+                return Err(std::io::Error::other("glitch"));
+            }
+            Ok(self.0.pop())
+        }
+
+        async fn subscribe(&mut self, _instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn unsubscribe(&mut self, _instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_into_stream_with_combinators() {
+        let flaky = Flaky(vec![trade("ETH", 3), trade("BTC", 2), trade("BTC", 1)], 0);
+        let results: Vec<_> = flaky.into_stream().collect().await;
+        assert_eq!(results.len(), 4);
+        assert!(results[1].is_err());
+        assert_eq!(
+            results[3].as_ref().unwrap().instrument,
+            spot_instrument("ETH", "USDT")
+        );
+
+        // Built-in streams are `Stream`s already
+        let btc = MockMarketDataStream::new(vec![trade("BTC", 1), trade("BTC", 2)]);
+        let eth = MockMarketDataStream::new(vec![trade("ETH", 3)]);
+        let ids: Vec<String> = btc
+            .chain(eth)
+            .try_filter(|event| futures::future::ready(event.instrument.base == "BTC"))
+            .map_ok(|event| match event.kind {
+                MarketDataKind::Trade(trade) => trade.id,
+                _ => unreachable!(),
+            })
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(ids, vec!["1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_market_data_stream_filters_subscriptions() {
+        let events = vec![trade("BTC", 1), trade("ETH", 2), trade("BTC", 3)];
+        let mut stream =
+            StreamMarketDataStream::new(futures::stream::iter(events).map(Ok::<_, Infallible>));
+        stream
+            .subscribe(&[spot_instrument("ETH", "USDT")])
+            .await
+            .unwrap();
+
+        let event = MarketDataStream::next(&mut stream).await.unwrap().unwrap();
+        assert_eq!(event.instrument, spot_instrument("ETH", "USDT"));
+        assert!(MarketDataStream::next(&mut stream).await.unwrap().is_none());
+
+        // Unsubscribing from everything passes nothing rather than everything
+        let events = vec![trade("BTC", 4), trade("ETH", 5)];
+        let mut stream =
+            StreamMarketDataStream::new(futures::stream::iter(events).map(Ok::<_, Infallible>));
+        let btc = [spot_instrument("BTC", "USDT")];
+        stream.subscribe(&btc).await.unwrap();
+        stream.unsubscribe(&btc).await.unwrap();
+        assert!(MarketDataStream::next(&mut stream).await.unwrap().is_none());
+    }
+}
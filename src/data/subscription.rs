@@ -0,0 +1,387 @@
+//! Typed market data subscriptions and update conflation
+//!
+//! `SubscriptionKind` names the market data a strategy wants for an instrument
+//! independently of the venue; `DataConfig` lists default kinds for every instrument
+//! plus per-instrument overrides, and connectors translate them into their own
+//! channels, e.g. `BinanceSubscription::from_kind`. Feeds whose content cannot be
+//! chosen per instrument, such as the Coinbase `full` channel and multicast feeds,
+//! drop events the configuration did not ask for with `is_subscribed`. Kinds a venue
+//! cannot provide are rejected by its `from_config`.
+//!
+//! `Conflator` limits snapshot updates (top of book, book depth, tickers, mark prices)
+//! to one per interval for each exchange, instrument and kind, keeping only the latest
+//! update in between. Trades, deltas, candles and other incremental data always pass
+//! through unchanged. Like `BarAggregator` it is driven by the timestamps it is given;
+//! `ConflatedMarketDataStream` wraps a stream and drives it with receipt times,
+//! advancing past the last one by the wall time elapsed while the stream is idle.
+
+use super::{ExchangeId, InstrumentId, MarketDataKind, MarketDataStream, MarketEvent};
+use crate::config::DataConfig;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Venue independent kind of market data to subscribe to
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum SubscriptionKind {
+    /// Public trades
+    Trades,
+    /// Best bid and ask
+    L1,
+    /// Book snapshots with at least `depth` levels per side
+    L2 { depth: usize },
+    /// Incremental book updates
+    BookDeltas,
+    /// Order-by-order book updates
+    L3,
+    /// Closed candles of the given length
+    Candles { interval_secs: u64 },
+    /// Rolling 24 hour statistics
+    Ticker,
+    /// Funding rates and mark prices of perpetual futures
+    Funding,
+    /// Forced liquidation orders
+    Liquidations,
+    /// Open interest of futures
+    OpenInterest,
+}
+
+/// Market data subscribed for one instrument instead of `DataConfig::subscriptions`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct InstrumentSubscription {
+    /// Instrument the override applies to
+    pub instrument: InstrumentId,
+    /// Market data to subscribe to
+    pub kinds: Vec<SubscriptionKind>,
+}
+
+/// Whether events of `kind` were asked for by `kinds`.
+///
+/// Events no subscription kind describes, such as data quality reports, always are.
+pub fn is_subscribed(kinds: &[SubscriptionKind], kind: &MarketDataKind) -> bool {
+    if matches!(
+        kind,
+        MarketDataKind::DataQuality(_)
+            | MarketDataKind::ConsolidatedQuote(_)
+            | MarketDataKind::BookFeatures(_)
+    ) {
+        return true;
+    }
+    kinds.iter().any(|subscription| {
+        matches!(
+            (subscription, kind),
+            (SubscriptionKind::Trades, MarketDataKind::Trade(_))
+                | (SubscriptionKind::L1, MarketDataKind::OrderBookL1(_))
+                | (SubscriptionKind::L2 { .. }, MarketDataKind::OrderBookL2(_))
+                | (
+                    SubscriptionKind::BookDeltas,
+                    MarketDataKind::OrderBookDelta(_)
+                )
+                | (SubscriptionKind::L3, MarketDataKind::OrderBookL3(_))
+                | (SubscriptionKind::Candles { .. }, MarketDataKind::Candle(_))
+                | (SubscriptionKind::Ticker, MarketDataKind::Ticker(_))
+                | (SubscriptionKind::Funding, MarketDataKind::FundingRate(_))
+                | (SubscriptionKind::Funding, MarketDataKind::MarkPrice(_))
+                | (
+                    SubscriptionKind::Liquidations,
+                    MarketDataKind::Liquidation(_)
+                )
+                | (
+                    SubscriptionKind::OpenInterest,
+                    MarketDataKind::OpenInterest(_)
+                )
+        )
+    })
+}
+
+/// Whether only the latest update of `kind` matters, so intermediate ones may be dropped
+pub fn is_conflatable(kind: &MarketDataKind) -> bool {
+    matches!(
+        kind,
+        MarketDataKind::OrderBookL1(_)
+            | MarketDataKind::OrderBookL2(_)
+            | MarketDataKind::ConsolidatedQuote(_)
+            | MarketDataKind::Ticker(_)
+            | MarketDataKind::MarkPrice(_)
+            | MarketDataKind::BookFeatures(_)
+    )
+}
+
+/// Conflation state of one exchange, instrument and kind
+#[derive(Debug, Clone, Default)]
+struct Slot {
+    last_emitted: Option<DateTime<Utc>>,
+    held: Option<MarketEvent>,
+}
+
+/// Limits snapshot updates to one per interval for each exchange, instrument and kind
+#[derive(Debug, Clone)]
+pub struct Conflator {
+    interval: chrono::Duration,
+    slots: HashMap<(ExchangeId, InstrumentId, &'static str), Slot>,
+}
+
+impl Conflator {
+    /// Create a conflator emitting at most one update per `interval`
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval: chrono::Duration::from_std(interval).unwrap_or(chrono::Duration::MAX),
+            slots: HashMap::new(),
+        }
+    }
+
+    /// Feed an event received at its `receipt_time`, returning it if it may be
+    /// forwarded now.
+    ///
+    /// A snapshot arriving within the interval of the previous one replaces any update
+    /// already held for its slot and is released by `flush`.
+    pub fn update(&mut self, event: MarketEvent) -> Option<MarketEvent> {
+        if !is_conflatable(&event.kind) || self.interval.is_zero() {
+            return Some(event);
+        }
+        let now = event.receipt_time;
+        let key = (event.exchange, event.instrument.clone(), event.kind.name());
+        let slot = self.slots.entry(key).or_default();
+        match slot.last_emitted {
+            Some(last) if now - last < self.interval => {
+                slot.held = Some(event);
+                None
+            }
+            _ => {
+                slot.last_emitted = Some(now);
+                slot.held = None;
+                Some(event)
+            }
+        }
+    }
+
+    /// Release held updates whose interval has elapsed by `now`, oldest slot first
+    pub fn flush(&mut self, now: DateTime<Utc>) -> Vec<MarketEvent> {
+        let interval = self.interval;
+        let mut released: Vec<MarketEvent> = self
+            .slots
+            .values_mut()
+            .filter(|slot| {
+                slot.held.is_some() && slot.last_emitted.is_none_or(|last| now - last >= interval)
+            })
+            .filter_map(|slot| {
+                slot.last_emitted = Some(now);
+                slot.held.take()
+            })
+            .collect();
+        released.sort_by_key(|event| event.receipt_time);
+        released
+    }
+
+    /// Release every held update, e.g. when the source ends
+    pub fn drain(&mut self) -> Vec<MarketEvent> {
+        let mut released: Vec<MarketEvent> = self
+            .slots
+            .values_mut()
+            .filter_map(|slot| slot.held.take())
+            .collect();
+        released.sort_by_key(|event| event.receipt_time);
+        released
+    }
+}
+
+/// Market data stream forwarding an inner stream with snapshot updates conflated.
+///
+/// The conflator only sees receipt times: while the inner stream is idle, time moves on
+/// from the last event's receipt time by the wall time elapsed since it arrived, so
+/// live and replayed streams release held updates alike, whatever the offset between
+/// their receipt times and the local clock. Waiting abandons the pending `next` on the
+/// inner stream, which must therefore be cancel-safe, as channel-backed streams and
+/// `ReplayMarketDataStream` are.
+pub struct ConflatedMarketDataStream<S> {
+    inner: S,
+    conflator: Conflator,
+    flush_interval: Duration,
+    pending: VecDeque<MarketEvent>,
+    finished: bool,
+    /// Receipt time of the last inner event and when it arrived
+    last_event: Option<(DateTime<Utc>, tokio::time::Instant)>,
+}
+
+impl<S> ConflatedMarketDataStream<S> {
+    /// Wrap a stream, forwarding at most one snapshot update per `interval`
+    pub fn new(inner: S, interval: Duration) -> Self {
+        Self {
+            inner,
+            conflator: Conflator::new(interval),
+            flush_interval: interval.max(Duration::from_millis(1)),
+            pending: VecDeque::new(),
+            finished: false,
+            last_event: None,
+        }
+    }
+
+    /// Wrap a stream, conflating to `DataConfig::update_frequency_ms`
+    pub fn from_config(inner: S, config: &DataConfig) -> Self {
+        Self::new(inner, config.update_interval().unwrap_or_default())
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> MarketDataStream for ConflatedMarketDataStream<S>
+where
+    S: MarketDataStream + Send,
+    S::Error: Send,
+{
+    type Error = S::Error;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Some(event));
+            }
+            if self.finished {
+                return Ok(None);
+            }
+
+            match tokio::time::timeout(self.flush_interval, self.inner.next()).await {
+                Ok(Ok(Some(event))) => {
+                    self.last_event = Some((event.receipt_time, tokio::time::Instant::now()));
+                    self.pending
+                        .extend(self.conflator.flush(event.receipt_time));
+                    self.pending.extend(self.conflator.update(event));
+                }
+                Ok(Ok(None)) => {
+                    self.finished = true;
+                    self.pending.extend(self.conflator.drain());
+                }
+                Ok(Err(error)) => return Err(error),
+                Err(_) => {
+                    if let Some((receipt_time, arrived)) = self.last_event {
+                        let idle = chrono::Duration::from_std(arrived.elapsed())
+                            .unwrap_or(chrono::Duration::MAX);
+                        self.pending
+                            .extend(self.conflator.flush(receipt_time + idle));
+                    }
+                }
+            }
+        }
+    }
+
+    async fn subscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.finished = false;
+        self.inner.subscribe(instruments).await
+    }
+
+    async fn unsubscribe(&mut self, instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        self.inner.unsubscribe(instruments).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{InstrumentKind, MockMarketDataStream, OrderBookL1, PublicTrade, Side};
+    use chrono::TimeZone;
+    use rust_decimal::Decimal;
+
+    fn at(millis: i64) -> DateTime<Utc> {
+        Utc.timestamp_millis_opt(1_700_000_000_000 + millis)
+            .unwrap()
+    }
+
+    fn event(millis: i64, kind: MarketDataKind) -> MarketEvent {
+        MarketEvent {
+            exchange: ExchangeId::Binance,
+            instrument: InstrumentId {
+                base: "BTC".to_string(),
+                quote: "USDT".to_string(),
+                exchange_symbol: "BTCUSDT".to_string(),
+                kind: InstrumentKind::Spot,
+            },
+            kind,
+            exchange_time: at(millis),
+            receipt_time: at(millis),
+        }
+    }
+
+    fn quote(millis: i64, bid: i64) -> MarketEvent {
+        event(
+            millis,
+            MarketDataKind::OrderBookL1(OrderBookL1 {
+                bid_price: Decimal::from(bid),
+                bid_quantity: Decimal::ONE,
+                ask_price: Decimal::from(bid + 1),
+                ask_quantity: Decimal::ONE,
+                timestamp: at(millis),
+                update_id: None,
+            }),
+        )
+    }
+
+    fn trade(millis: i64) -> MarketEvent {
+        event(
+            millis,
+            MarketDataKind::Trade(PublicTrade {
+                id: millis.to_string(),
+                price: Decimal::from(100),
+                quantity: Decimal::ONE,
+                side: Side::Buy,
+                timestamp: at(millis),
+            }),
+        )
+    }
+
+    #[test]
+    fn test_conflator_keeps_latest_snapshot_per_interval() {
+        let mut conflator = Conflator::new(Duration::from_millis(100));
+        assert!(conflator.update(quote(0, 100)).is_some());
+        assert!(conflator.update(quote(20, 101)).is_none());
+        assert!(conflator.update(quote(40, 102)).is_none());
+        // Trades are never held back
+        assert!(conflator.update(trade(50)).is_some());
+        assert!(conflator.flush(at(90)).is_empty());
+
+        let released = conflator.flush(at(100));
+        assert_eq!(released, vec![quote(40, 102)]);
+        // The release starts a new interval
+        assert!(conflator.update(quote(150, 103)).is_none());
+        assert!(conflator.update(quote(200, 104)).is_some());
+        assert!(conflator.drain().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stream_conflates_from_config() {
+        let mut config = crate::config::SystemConfig::default().data;
+        config.update_frequency_ms = 100;
+        let events = vec![quote(0, 100), quote(10, 101), trade(20), quote(30, 102)];
+        let inner = MockMarketDataStream::new(events);
+        let mut stream = ConflatedMarketDataStream::from_config(inner, &config);
+
+        let mut received = Vec::new();
+        while let Some(event) = stream.next().await.unwrap() {
+            received.push(event);
+        }
+        assert_eq!(received, vec![quote(0, 100), trade(20), quote(30, 102)]);
+    }
+
+    #[tokio::test]
+    async fn test_idle_stream_releases_on_receipt_time_clock() {
+        use futures::StreamExt;
+
+        // Receipt times far from the local clock, as in a replay
+        let shifted = |mut event: MarketEvent| {
+            event.receipt_time += chrono::Duration::days(36_500);
+            event
+        };
+        let events = [shifted(quote(0, 100)), shifted(quote(10, 101))];
+        let inner = futures::stream::iter(events.clone().map(Ok::<_, std::convert::Infallible>))
+            .chain(futures::stream::pending());
+        let mut stream = ConflatedMarketDataStream::new(
+            crate::data::StreamMarketDataStream::new(inner),
+            Duration::from_millis(50),
+        );
+
+        assert_eq!(stream.next().await.unwrap(), Some(events[0].clone()));
+        let held = tokio::time::timeout(Duration::from_secs(1), stream.next())
+            .await
+            .expect("held update released while the inner stream is idle");
+        assert_eq!(held.unwrap(), Some(events[1].clone()));
+    }
+}
@@ -0,0 +1,690 @@
+//! Synthetic market data
+//!
+//! `SyntheticMarketDataStream` generates reproducible market data for simulation and
+//! stress testing. Given the same `SyntheticConfig`, including its seed, it produces the
+//! same events on every run.
+//!
+//! - The mid price follows geometric Brownian motion, optionally with Merton jumps.
+//! - Event arrival times follow a self-exciting Hawkes process, so activity clusters
+//!   the way real order flow does.
+//! - Each arrival is either a trade at the touch or a book update with a configurable
+//!   spread in ticks and depth in levels.
+//! - `Scenario`s inject flash crashes, liquidity droughts and feed gaps at fixed
+//!   offsets from the start of the run.
+//!
+//! Events are produced as fast as they are consumed; simulated time lives only in the
+//! event timestamps.
+
+use super::{
+    ExchangeId, InstrumentId, MarketDataKind, MarketDataStream, MarketEvent, OrderBookL1,
+    OrderBookL2, PriceLevel, PublicTrade, Side,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use std::time::Duration;
+
+const SECONDS_PER_YEAR: f64 = 365.0 * 24.0 * 3600.0;
+
+/// Errors produced when building a `SyntheticMarketDataStream`
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum SyntheticError {
+    /// Arrival process that is not positive and stationary
+    #[error("arrivals need baseline > 0 and 0 <= excitation < decay: {0:?}")]
+    Arrivals(HawkesParams),
+    /// Trade probability outside `[0, 1]`
+    #[error("trade probability {0} is outside [0, 1]")]
+    TradeProbability(f64),
+    /// Flash crash dropping by a fraction outside `[0, 1]`
+    #[error("flash crash drop is outside [0, 1]: {0:?}")]
+    Scenario(Scenario),
+}
+
+/// Mid price dynamics; rates are annualized
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PriceModel {
+    /// Geometric Brownian motion
+    Gbm { drift: f64, volatility: f64 },
+    /// Geometric Brownian motion with normally distributed log jumps
+    JumpDiffusion {
+        drift: f64,
+        volatility: f64,
+        /// Expected jumps per year
+        jump_intensity: f64,
+        /// Mean log jump size
+        jump_mean: f64,
+        /// Standard deviation of the log jump size
+        jump_std: f64,
+    },
+}
+
+/// Hawkes process intensity parameters
+///
+/// The intensity is `baseline + sum(excitation * exp(-decay * age))` over past
+/// arrivals, in events per second. `excitation < decay` keeps the process stationary,
+/// with a long run rate of `baseline / (1 - excitation / decay)`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HawkesParams {
+    pub baseline: f64,
+    pub excitation: f64,
+    pub decay: f64,
+}
+
+/// Shape of generated books
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BookParams {
+    /// Price increment
+    pub tick_size: Decimal,
+    /// Quantity increment
+    pub lot_size: Decimal,
+    /// Quoted spread in ticks, at least one
+    pub spread_ticks: u32,
+    /// Levels per side; one produces `OrderBookL1` quotes, more produce `OrderBookL2`
+    pub depth: usize,
+    /// Mean quantity per level
+    pub level_quantity: f64,
+}
+
+/// Market disruption injected at an offset from the start of the run
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Scenario {
+    /// Prices drop by `drop` (a fraction) at `at` and recover linearly over `recovery`
+    FlashCrash {
+        at: Duration,
+        drop: f64,
+        recovery: Duration,
+    },
+    /// Spread widens by `spread_factor` and quantities shrink by `depth_factor`
+    LiquidityDrought {
+        at: Duration,
+        duration: Duration,
+        spread_factor: f64,
+        depth_factor: f64,
+    },
+    /// No events are emitted, while the market keeps moving underneath
+    FeedGap { at: Duration, duration: Duration },
+}
+
+/// Generator configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntheticConfig {
+    pub exchange: ExchangeId,
+    pub instrument: InstrumentId,
+    pub seed: u64,
+    pub start_time: DateTime<Utc>,
+    pub start_price: f64,
+    pub price_model: PriceModel,
+    pub arrivals: HawkesParams,
+    pub book: BookParams,
+    /// Fraction of arrivals that are trades rather than book updates
+    pub trade_probability: f64,
+    /// Delay between exchange time and receipt time
+    pub latency: Duration,
+    pub scenarios: Vec<Scenario>,
+    /// Number of events after which the stream ends; unbounded when `None`
+    pub max_events: Option<u64>,
+}
+
+impl SyntheticConfig {
+    /// Defaults resembling a liquid crypto pair: 60% volatility, about 200 events/s
+    pub fn new(instrument: InstrumentId, seed: u64) -> Self {
+        Self {
+            exchange: ExchangeId::Binance,
+            instrument,
+            seed,
+            start_time: DateTime::from_timestamp(1_700_000_000, 0).unwrap_or_default(),
+            start_price: 50_000.0,
+            price_model: PriceModel::Gbm {
+                drift: 0.0,
+                volatility: 0.6,
+            },
+            arrivals: HawkesParams {
+                baseline: 100.0,
+                excitation: 50.0,
+                decay: 100.0,
+            },
+            book: BookParams {
+                tick_size: Decimal::new(1, 2),
+                lot_size: Decimal::new(1, 5),
+                spread_ticks: 1,
+                depth: 1,
+                level_quantity: 1.0,
+            },
+            trade_probability: 0.3,
+            latency: Duration::from_millis(1),
+            scenarios: Vec::new(),
+            max_events: None,
+        }
+    }
+
+    /// Set the venue reported on events
+    pub fn with_exchange(mut self, exchange: ExchangeId) -> Self {
+        self.exchange = exchange;
+        self
+    }
+
+    /// Set the timestamp of the start of the run
+    pub fn with_start_time(mut self, start_time: DateTime<Utc>) -> Self {
+        self.start_time = start_time;
+        self
+    }
+
+    /// Set the initial mid price
+    pub fn with_start_price(mut self, start_price: f64) -> Self {
+        self.start_price = start_price;
+        self
+    }
+
+    /// Set the mid price dynamics
+    pub fn with_price_model(mut self, price_model: PriceModel) -> Self {
+        self.price_model = price_model;
+        self
+    }
+
+    /// Set the arrival process
+    pub fn with_arrivals(mut self, arrivals: HawkesParams) -> Self {
+        self.arrivals = arrivals;
+        self
+    }
+
+    /// Set the book shape
+    pub fn with_book(mut self, book: BookParams) -> Self {
+        self.book = book;
+        self
+    }
+
+    /// Set the fraction of arrivals that are trades
+    pub fn with_trade_probability(mut self, trade_probability: f64) -> Self {
+        self.trade_probability = trade_probability;
+        self
+    }
+
+    /// Set the delay between exchange and receipt time
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Add a scenario
+    pub fn with_scenario(mut self, scenario: Scenario) -> Self {
+        self.scenarios.push(scenario);
+        self
+    }
+
+    /// End the stream after `max_events` events
+    pub fn with_max_events(mut self, max_events: u64) -> Self {
+        self.max_events = Some(max_events);
+        self
+    }
+
+    /// Check the parameters the generator would otherwise turn into infinite waits
+    /// or panics
+    pub fn validate(&self) -> Result<(), SyntheticError> {
+        let HawkesParams {
+            baseline,
+            excitation,
+            decay,
+        } = self.arrivals;
+        if !(baseline > 0.0 && excitation >= 0.0 && excitation < decay) {
+            return Err(SyntheticError::Arrivals(self.arrivals));
+        }
+        if !(0.0..=1.0).contains(&self.trade_probability) {
+            return Err(SyntheticError::TradeProbability(self.trade_probability));
+        }
+        for scenario in &self.scenarios {
+            if let Scenario::FlashCrash { drop, .. } = scenario {
+                if !(0.0..=1.0).contains(drop) {
+                    return Err(SyntheticError::Scenario(*scenario));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// SplitMix64; small, fast and never changes underneath us, which keeps seeds reproducible
+#[derive(Debug, Clone)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `(0, 1]`
+    fn uniform(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal via Box-Muller
+    fn normal(&mut self) -> f64 {
+        let (u1, u2) = (self.uniform(), self.uniform());
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+
+    /// Exponential with the given rate
+    fn exponential(&mut self, rate: f64) -> f64 {
+        -self.uniform().ln() / rate
+    }
+
+    /// Poisson count by inversion; only used with small means
+    fn poisson(&mut self, mean: f64) -> u32 {
+        let limit = (-mean).exp();
+        let mut product = self.uniform();
+        let mut count = 0;
+        while product > limit {
+            product *= self.uniform();
+            count += 1;
+        }
+        count
+    }
+}
+
+/// Seeded synthetic market data source
+#[derive(Debug, Clone)]
+pub struct SyntheticMarketDataStream {
+    config: SyntheticConfig,
+    rng: SplitMix64,
+    /// Seconds since the start of the run
+    clock: f64,
+    /// Hawkes excitation at `clock`
+    excitation: f64,
+    mid: f64,
+    emitted: u64,
+    trade_id: u64,
+    tick: f64,
+    lot: f64,
+}
+
+impl SyntheticMarketDataStream {
+    /// Create a generator; fails if `config` does not pass `SyntheticConfig::validate`
+    pub fn new(config: SyntheticConfig) -> Result<Self, SyntheticError> {
+        config.validate()?;
+        Ok(Self {
+            rng: SplitMix64(config.seed),
+            clock: 0.0,
+            excitation: 0.0,
+            mid: config.start_price,
+            emitted: 0,
+            trade_id: 0,
+            tick: config.book.tick_size.to_f64().unwrap_or(0.01),
+            lot: config.book.lot_size.to_f64().unwrap_or(1.0),
+            config,
+        })
+    }
+
+    /// Current mid price before scenario adjustments
+    pub fn mid_price(&self) -> f64 {
+        self.mid
+    }
+
+    /// Produce the next event, or `None` once `max_events` have been produced
+    pub fn next_event(&mut self) -> Option<MarketEvent> {
+        if self
+            .config
+            .max_events
+            .is_some_and(|max_events| self.emitted >= max_events)
+        {
+            return None;
+        }
+        loop {
+            let dt = self.next_arrival();
+            self.evolve_mid(dt);
+            if self.in_feed_gap() {
+                continue;
+            }
+            self.emitted += 1;
+            return Some(self.generate());
+        }
+    }
+
+    /// Advance the clock to the next Hawkes arrival by Ogata thinning
+    fn next_arrival(&mut self) -> f64 {
+        let HawkesParams {
+            baseline,
+            excitation,
+            decay,
+        } = self.config.arrivals;
+        let start = self.clock;
+        loop {
+            // The intensity only decays between arrivals, so the current value bounds it
+            let bound = baseline + self.excitation;
+            let wait = self.rng.exponential(bound);
+            self.clock += wait;
+            self.excitation *= (-decay * wait).exp();
+            if self.rng.uniform() * bound <= baseline + self.excitation {
+                self.excitation += excitation;
+                return self.clock - start;
+            }
+        }
+    }
+
+    fn evolve_mid(&mut self, dt_seconds: f64) {
+        let dt = dt_seconds / SECONDS_PER_YEAR;
+        let (drift, volatility, jump) = match self.config.price_model {
+            PriceModel::Gbm { drift, volatility } => (drift, volatility, 0.0),
+            PriceModel::JumpDiffusion {
+                drift,
+                volatility,
+                jump_intensity,
+                jump_mean,
+                jump_std,
+            } => {
+                let jumps = self.rng.poisson(jump_intensity * dt);
+                let jump = (0..jumps)
+                    .map(|_| jump_mean + jump_std * self.rng.normal())
+                    .sum();
+                (drift, volatility, jump)
+            }
+        };
+        let diffusion = volatility * dt.sqrt() * self.rng.normal();
+        self.mid *= ((drift - volatility * volatility / 2.0) * dt + diffusion + jump).exp();
+    }
+
+    fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(self.clock)
+    }
+
+    fn in_feed_gap(&self) -> bool {
+        let now = self.elapsed();
+        self.config.scenarios.iter().any(|scenario| {
+            matches!(scenario, Scenario::FeedGap { at, duration } if now >= *at && now < *at + *duration)
+        })
+    }
+
+    /// Mid price after flash crashes
+    fn effective_mid(&self) -> f64 {
+        let now = self.elapsed();
+        let mut mid = self.mid;
+        for scenario in &self.config.scenarios {
+            if let Scenario::FlashCrash { at, drop, recovery } = *scenario {
+                if now >= at && now < at + recovery {
+                    let remaining = 1.0 - (now - at).as_secs_f64() / recovery.as_secs_f64();
+                    mid *= 1.0 - drop * remaining;
+                }
+            }
+        }
+        mid
+    }
+
+    /// Spread and quantity multipliers from active droughts
+    fn liquidity(&self) -> (f64, f64) {
+        let now = self.elapsed();
+        self.config
+            .scenarios
+            .iter()
+            .fold((1.0, 1.0), |(spread, depth), scenario| match *scenario {
+                Scenario::LiquidityDrought {
+                    at,
+                    duration,
+                    spread_factor,
+                    depth_factor,
+                } if now >= at && now < at + duration => {
+                    (spread * spread_factor, depth * depth_factor)
+                }
+                _ => (spread, depth),
+            })
+    }
+
+    fn price(&self, ticks: i64) -> Decimal {
+        Decimal::from(ticks) * self.config.book.tick_size
+    }
+
+    fn quantity(&mut self, mean: f64) -> Decimal {
+        let lots = (mean * self.rng.exponential(1.0) / self.lot)
+            .round()
+            .max(1.0);
+        Decimal::from(lots as i64) * self.config.book.lot_size
+    }
+
+    fn generate(&mut self) -> MarketEvent {
+        let (spread_factor, depth_factor) = self.liquidity();
+        let spread =
+            ((self.config.book.spread_ticks.max(1) as f64 * spread_factor).round() as i64).max(1);
+        let bid = ((self.effective_mid() / self.tick) - spread as f64 / 2.0).floor() as i64;
+        let ask = bid + spread;
+        let level_quantity = self.config.book.level_quantity * depth_factor;
+
+        let exchange_time =
+            self.config.start_time + chrono::Duration::microseconds((self.clock * 1e6) as i64);
+        let receipt_time =
+            exchange_time + chrono::Duration::from_std(self.config.latency).unwrap_or_default();
+
+        let kind = if self.rng.uniform() <= self.config.trade_probability {
+            self.trade_id += 1;
+            let side = if self.rng.uniform() < 0.5 {
+                Side::Buy
+            } else {
+                Side::Sell
+            };
+            MarketDataKind::Trade(PublicTrade {
+                id: self.trade_id.to_string(),
+                price: self.price(if side == Side::Buy { ask } else { bid }),
+                quantity: self.quantity(level_quantity / 4.0),
+                side,
+                timestamp: exchange_time,
+            })
+        } else if self.config.book.depth <= 1 {
+            MarketDataKind::OrderBookL1(OrderBookL1 {
+                bid_price: self.price(bid),
+                bid_quantity: self.quantity(level_quantity),
+                ask_price: self.price(ask),
+                ask_quantity: self.quantity(level_quantity),
+                timestamp: exchange_time,
+                update_id: Some(self.emitted),
+            })
+        } else {
+            let depth = self.config.book.depth as i64;
+            let mut bids = Vec::with_capacity(depth as usize);
+            let mut asks = Vec::with_capacity(depth as usize);
+            for level in 0..depth {
+                bids.push(PriceLevel {
+                    price: self.price(bid - level),
+                    quantity: self.quantity(level_quantity),
+                });
+                asks.push(PriceLevel {
+                    price: self.price(ask + level),
+                    quantity: self.quantity(level_quantity),
+                });
+            }
+            MarketDataKind::OrderBookL2(OrderBookL2 {
+                bids,
+                asks,
+                timestamp: exchange_time,
+            })
+        };
+
+        MarketEvent {
+            exchange: self.config.exchange,
+            instrument: self.config.instrument.clone(),
+            kind,
+            exchange_time,
+            receipt_time,
+        }
+    }
+}
+
+impl Iterator for SyntheticMarketDataStream {
+    type Item = MarketEvent;
+
+    fn next(&mut self) -> Option<MarketEvent> {
+        self.next_event()
+    }
+}
+
+#[async_trait::async_trait]
+impl MarketDataStream for SyntheticMarketDataStream {
+    type Error = std::io::Error;
+
+    async fn next(&mut self) -> Result<Option<MarketEvent>, Self::Error> {
+        Ok(self.next_event())
+    }
+
+    async fn subscribe(&mut self, _instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, _instruments: &[InstrumentId]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl futures::Stream for SyntheticMarketDataStream {
+    type Item = Result<MarketEvent, std::io::Error>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        std::task::Poll::Ready(self.next_event().map(Ok))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::InstrumentKind;
+
+    fn instrument() -> InstrumentId {
+        InstrumentId {
+            base: "BTC".to_string(),
+            quote: "USDT".to_string(),
+            exchange_symbol: "BTCUSDT".to_string(),
+            kind: InstrumentKind::Spot,
+        }
+    }
+
+    fn quote(event: &MarketEvent) -> Option<&OrderBookL1> {
+        match &event.kind {
+            MarketDataKind::OrderBookL1(quote) => Some(quote),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_seeded_generation_is_reproducible() {
+        let config = SyntheticConfig::new(instrument(), 7)
+            .with_price_model(PriceModel::JumpDiffusion {
+                drift: 0.0,
+                volatility: 0.8,
+                jump_intensity: 1e6,
+                jump_mean: 0.0,
+                jump_std: 0.001,
+            })
+            .with_max_events(20_000);
+        let events: Vec<_> = SyntheticMarketDataStream::new(config.clone())
+            .unwrap()
+            .collect();
+        let mut stream = SyntheticMarketDataStream::new(config.clone()).unwrap();
+        let mut streamed = Vec::new();
+        while let Some(event) = MarketDataStream::next(&mut stream).await.unwrap() {
+            streamed.push(event);
+        }
+        assert_eq!(events.len(), 20_000);
+        assert_eq!(events, streamed);
+
+        let other: Vec<_> = SyntheticMarketDataStream::new(SyntheticConfig { seed: 8, ..config })
+            .unwrap()
+            .take(100)
+            .collect();
+        assert_ne!(events[..100], other[..]);
+
+        let tick = Decimal::new(1, 2);
+        for event in &events {
+            assert!(event.receipt_time > event.exchange_time);
+            if let Some(quote) = quote(event) {
+                assert!(quote.bid_price < quote.ask_price);
+                assert!((quote.bid_price / tick).fract().is_zero());
+            }
+        }
+        assert!(events
+            .windows(2)
+            .all(|w| w[0].exchange_time <= w[1].exchange_time));
+
+        // Clustered arrivals average baseline / (1 - excitation / decay) = 200 per second
+        let span = events[events.len() - 1].exchange_time - events[0].exchange_time;
+        let rate = events.len() as f64 / (span.num_milliseconds() as f64 / 1000.0);
+        assert!((150.0..250.0).contains(&rate), "rate {}", rate);
+    }
+
+    #[test]
+    fn test_scenarios() {
+        let config = SyntheticConfig::new(instrument(), 42)
+            .with_trade_probability(0.0)
+            .with_scenario(Scenario::FlashCrash {
+                at: Duration::from_secs(10),
+                drop: 0.2,
+                recovery: Duration::from_secs(5),
+            })
+            .with_scenario(Scenario::LiquidityDrought {
+                at: Duration::from_secs(20),
+                duration: Duration::from_secs(5),
+                spread_factor: 50.0,
+                depth_factor: 0.1,
+            })
+            .with_scenario(Scenario::FeedGap {
+                at: Duration::from_secs(30),
+                duration: Duration::from_secs(2),
+            });
+        let start = config.start_time;
+        let events: Vec<_> = SyntheticMarketDataStream::new(config)
+            .unwrap()
+            .take_while(|event| event.exchange_time < start + chrono::Duration::seconds(40))
+            .collect();
+
+        let window = |from: i64, to: i64| {
+            events
+                .iter()
+                .filter(move |event| {
+                    event.exchange_time >= start + chrono::Duration::milliseconds(from)
+                        && event.exchange_time < start + chrono::Duration::milliseconds(to)
+                })
+                .filter_map(quote)
+        };
+        let before = window(9_000, 10_000).next_back().unwrap().bid_price;
+        let crash = window(10_000, 10_100).next().unwrap().bid_price;
+        assert!(crash < before * Decimal::new(85, 2));
+
+        let spread = |quote: &OrderBookL1| quote.ask_price - quote.bid_price;
+        assert!(window(20_000, 25_000).all(|quote| spread(quote) == Decimal::new(50, 2)));
+        assert!(window(15_000, 20_000).all(|quote| spread(quote) == Decimal::new(1, 2)));
+
+        assert_eq!(window(30_000, 32_000).count(), 0);
+        assert!(window(32_000, 33_000).count() > 0);
+    }
+
+    #[test]
+    fn test_invalid_config_is_rejected() {
+        let config = SyntheticConfig::new(instrument(), 1);
+        let arrivals = HawkesParams {
+            baseline: 100.0,
+            excitation: 100.0,
+            decay: 100.0,
+        };
+        assert_eq!(
+            SyntheticMarketDataStream::new(config.clone().with_arrivals(arrivals)).unwrap_err(),
+            SyntheticError::Arrivals(arrivals)
+        );
+        let idle = HawkesParams {
+            baseline: 0.0,
+            ..config.arrivals
+        };
+        assert!(SyntheticMarketDataStream::new(config.clone().with_arrivals(idle)).is_err());
+        assert_eq!(
+            SyntheticMarketDataStream::new(config.clone().with_trade_probability(1.5)).unwrap_err(),
+            SyntheticError::TradeProbability(1.5)
+        );
+        assert!(
+            SyntheticMarketDataStream::new(config.with_scenario(Scenario::FlashCrash {
+                at: Duration::from_secs(1),
+                drop: -0.1,
+                recovery: Duration::from_secs(1),
+            }))
+            .is_err()
+        );
+    }
+}
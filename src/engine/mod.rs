@@ -181,7 +181,7 @@ where
 mod tests {
     use super::*;
     use crate::{
-        data::{InstrumentId, ExchangeId, Side, PublicTrade, MarketDataKind},
+        data::{InstrumentId, InstrumentKind, ExchangeId, Side, PublicTrade, MarketDataKind},
         strategy::{DefaultStrategy},
         risk::{DefaultRiskManager},
         execution::{MockExecutionClient},
@@ -216,6 +216,7 @@ mod tests {
             base: "BTC".to_string(),
             quote: "USDT".to_string(),
             exchange_symbol: "BTCUSDT".to_string(),
+            kind: InstrumentKind::Spot,
         };
         
         let market_event = crate::data::MarketEvent {
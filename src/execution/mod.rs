@@ -36,8 +36,11 @@ pub enum TimeInForce {
 }
 
 /// Order request
+///
+/// Prices and quantities are `Decimal` by default; `OrderRequest<Price, Qty>` is the
+/// fixed-point form used on the hot path.
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
-pub struct OrderRequest {
+pub struct OrderRequest<P = Decimal, Q = Decimal> {
     /// Client order ID
     pub client_order_id: String,
     /// Instrument to trade
@@ -47,11 +50,11 @@ pub struct OrderRequest {
     /// Order type
     pub order_type: OrderType,
     /// Quantity
-    pub quantity: Decimal,
+    pub quantity: Q,
     /// Price (for limit orders)
-    pub price: Option<Decimal>,
+    pub price: Option<P>,
     /// Stop price (for stop orders)
-    pub stop_price: Option<Decimal>,
+    pub stop_price: Option<P>,
     /// Time in force
     pub time_in_force: TimeInForce,
     /// Timestamp when order was created
@@ -136,6 +139,12 @@ impl MockExecutionClient {
     }
 }
 
+impl Default for MockExecutionClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ExecutionClient for MockExecutionClient {
     type Error = std::io::Error;
     
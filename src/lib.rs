@@ -23,7 +23,10 @@ pub mod config;
 
 // Re-export key types
 pub use engine::{Engine, EngineConfig, EngineState};
-pub use data::{MarketEvent, MarketDataKind, BinanceMarketDataStream, MarketDataStream};
+pub use data::{
+    MarketEvent, MarketDataKind, MarketDataStream,
+    BinanceMarketDataStream, CoinbaseMarketDataStream, KrakenMarketDataStream,
+};
 pub use execution::{ExecutionEvent, OrderRequest, ExecutionClient};
 pub use strategy::{Strategy, DefaultStrategy};
 pub use risk::{RiskManager, DefaultRiskManager, RiskLimits};
@@ -95,7 +98,7 @@ impl<MarketKind> SystemEvent<MarketKind> {
 mod tests {
     use super::*;
     use crate::{
-        data::{InstrumentId, ExchangeId, Side, PublicTrade, MarketDataKind},
+        data::{InstrumentId, InstrumentKind, ExchangeId, Side, PublicTrade, MarketDataKind},
         strategy::DefaultStrategy,
         risk::DefaultRiskManager,
         execution::MockExecutionClient,
@@ -143,6 +146,7 @@ mod tests {
             base: "BTC".to_string(),
             quote: "USDT".to_string(),
             exchange_symbol: "BTCUSDT".to_string(),
+            kind: InstrumentKind::Spot,
         };
         
         // Create test market event
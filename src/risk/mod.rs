@@ -2,13 +2,20 @@
 //! 
 //! This module provides risk management functionality to control and limit
 //! trading risks including position limits, exposure limits, and order rate limits.
+//!
+//! Orders on instruments registered with an `InstrumentScale` are checked in fixed
+//! point: prices and quantities must lie on the tick and lot grid, and notionals are
+//! integer products of ticks and lots, turned into an exact `Decimal` only to compare
+//! against the exposure limit.
 
 use crate::{
+    data::{FeedHealth, FixedPointError, InstrumentId, InstrumentScale},
     execution::OrderRequest,
     strategy::StrategyOutput,
 };
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::Debug;
 
 /// Risk check result
@@ -39,6 +46,16 @@ pub struct RiskLimits {
     pub max_drawdown_percent: Decimal,
 }
 
+impl RiskCheckResult {
+    fn rejected(reason: impl Into<String>) -> Self {
+        Self {
+            approved: false,
+            reason: Some(reason.into()),
+            modified_order: None,
+        }
+    }
+}
+
 impl Default for RiskLimits {
     fn default() -> Self {
         Self {
@@ -64,6 +81,16 @@ pub trait RiskManager {
     fn check_order_risk(&mut self, order: &OrderRequest) -> RiskCheckResult;
 }
 
+/// Fixed-point scale of one instrument, with its notional units as mantissa and scale
+#[derive(Debug, Clone, PartialEq)]
+struct FixedScale {
+    scale: InstrumentScale,
+    /// Value of one tick-lot of notional
+    tick_lot: (i128, u32),
+    /// Value of one lot
+    lot: (i128, u32),
+}
+
 /// Default risk manager implementation
 #[derive(Debug, Clone)]
 pub struct DefaultRiskManager {
@@ -71,21 +98,74 @@ pub struct DefaultRiskManager {
     pub limits: RiskLimits,
     /// Current exposure
     pub current_exposure: Decimal,
+    /// Fixed-point scales of instruments checked in fixed point
+    instruments: HashMap<InstrumentId, FixedScale>,
     /// Order count in the current second
     pub orders_this_second: u32,
     /// Last order timestamp
     pub last_order_time: std::time::Instant,
+    /// Market data feed health; orders are rejected while any feed is degraded
+    pub feed_health: Option<FeedHealth>,
 }
 
 impl Default for DefaultRiskManager {
     fn default() -> Self {
+        Self::new(RiskLimits::default())
+    }
+}
+
+impl DefaultRiskManager {
+    /// Create a risk manager enforcing `limits`
+    pub fn new(limits: RiskLimits) -> Self {
         Self {
-            limits: RiskLimits::default(),
+            limits,
             current_exposure: Decimal::ZERO,
+            instruments: HashMap::new(),
             orders_this_second: 0,
             last_order_time: std::time::Instant::now(),
+            feed_health: None,
         }
     }
+
+    /// Check orders on `instrument` in fixed point.
+    ///
+    /// Fails if a tick-lot of notional is finer than a `Decimal` can represent.
+    pub fn with_instrument_scale(
+        mut self,
+        instrument: InstrumentId,
+        scale: InstrumentScale,
+    ) -> Result<Self, FixedPointError> {
+        let too_fine = || FixedPointError::ScaleTooFine {
+            tick_size: scale.tick_size(),
+            lot_size: scale.lot_size(),
+        };
+        let tick = scale.tick_size().normalize();
+        let lot = scale.lot_size().normalize();
+        let tick_lot = (
+            tick.mantissa()
+                .checked_mul(lot.mantissa())
+                .ok_or_else(too_fine)?,
+            tick.scale() + lot.scale(),
+        );
+        if tick_lot.1 > Decimal::MAX_SCALE {
+            return Err(too_fine());
+        }
+        self.instruments.insert(
+            instrument,
+            FixedScale {
+                scale,
+                tick_lot,
+                lot: (lot.mantissa(), lot.scale()),
+            },
+        );
+        Ok(self)
+    }
+
+    /// Gate orders on market data feed health
+    pub fn with_feed_health(mut self, feed_health: FeedHealth) -> Self {
+        self.feed_health = Some(feed_health);
+        self
+    }
 }
 
 impl RiskManager for DefaultRiskManager {
@@ -108,39 +188,57 @@ impl RiskManager for DefaultRiskManager {
         
         // Check order size limit
         if order.quantity > self.limits.max_order_size {
-            return RiskCheckResult {
-                approved: false,
-                reason: Some("Order size exceeds limit".to_string()),
-                modified_order: None,
-            };
+            return RiskCheckResult::rejected("Order size exceeds limit");
+        }
+
+        // Work out the notional, as an integer product of ticks and lots if possible
+        let notional = match self.instruments.get(&order.instrument) {
+            Some(fixed) => {
+                let order = match order.to_fixed(&fixed.scale) {
+                    Ok(order) => order,
+                    Err(error) => return RiskCheckResult::rejected(error.to_string()),
+                };
+                match order.price {
+                    Some(price) => scaled(price.notional(order.quantity), fixed.tick_lot),
+                    // For market orders, use quantity as proxy
+                    None => scaled(i128::from(order.quantity.lots()), fixed.lot),
+                }
+            }
+            None => match order.price {
+                Some(price) => price.checked_mul(order.quantity),
+                None => Some(order.quantity), // For market orders, use quantity as proxy
+            },
+        };
+        
+        // Orders carry no venue, so any degraded feed blocks trading
+        if let Some(degraded) = self
+            .feed_health
+            .as_ref()
+            .and_then(|health| health.degraded().into_iter().next())
+        {
+            return RiskCheckResult::rejected(format!(
+                "Market data feed degraded: {:?} {}",
+                degraded.exchange,
+                degraded.reason.unwrap_or_default()
+            ));
         }
         
         // Check orders per second limit
         if self.orders_this_second >= self.limits.max_orders_per_second {
-            return RiskCheckResult {
-                approved: false,
-                reason: Some("Order rate limit exceeded".to_string()),
-                modified_order: None,
-            };
+            return RiskCheckResult::rejected("Order rate limit exceeded");
         }
         
         // Check notional exposure
-        let notional = match order.price {
-            Some(price) => price * order.quantity,
-            None => order.quantity, // For market orders, use quantity as proxy
+        let Some(exposure) = notional
+            .and_then(|notional| self.current_exposure.checked_add(notional))
+            .filter(|exposure| *exposure <= self.limits.max_notional_exposure)
+        else {
+            return RiskCheckResult::rejected("Notional exposure limit exceeded");
         };
         
-        if self.current_exposure + notional > self.limits.max_notional_exposure {
-            return RiskCheckResult {
-                approved: false,
-                reason: Some("Notional exposure limit exceeded".to_string()),
-                modified_order: None,
-            };
-        }
-        
         // Increment counters for approved orders
         self.orders_this_second += 1;
-        self.current_exposure += notional;
+        self.current_exposure = exposure;
         
         RiskCheckResult {
             approved: true,
@@ -148,4 +246,110 @@ impl RiskManager for DefaultRiskManager {
             modified_order: None,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Exact value of `count` units of `mantissa * 10^-scale`, if it fits in a `Decimal`
+fn scaled(count: i128, (mantissa, scale): (i128, u32)) -> Option<Decimal> {
+    let value = count.checked_mul(mantissa)?;
+    Decimal::try_from_i128_with_scale(value, scale).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::{OrderType, TimeInForce};
+    use rust_decimal_macros::dec;
+
+    fn order(quantity: Decimal, price: Option<Decimal>) -> OrderRequest {
+        OrderRequest {
+            client_order_id: "1".to_string(),
+            instrument: crate::data::spot_instrument("BTC", "USDT"),
+            side: crate::data::Side::Buy,
+            order_type: OrderType::Limit,
+            quantity,
+            price,
+            stop_price: None,
+            time_in_force: TimeInForce::GTC,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_fixed_point_checks_match_decimal_checks() {
+        let limits = RiskLimits {
+            max_notional_exposure: dec!(1000),
+            max_order_size: dec!(0.5),
+            ..RiskLimits::default()
+        };
+        let mut fixed = DefaultRiskManager::new(limits.clone())
+            .with_instrument_scale(
+                crate::data::spot_instrument("BTC", "USDT"),
+                InstrumentScale::new(dec!(0.01), dec!(0.00001)).unwrap(),
+            )
+            .unwrap();
+        let mut decimal = DefaultRiskManager::new(limits);
+        let orders = [
+            order(dec!(0.01), Some(dec!(67890.12))),
+            order(dec!(0.6), Some(dec!(1))),
+            order(dec!(0.005), Some(dec!(80000))),
+            order(dec!(0.01), None),
+        ];
+        for manager in [&mut fixed, &mut decimal] {
+            let approved: Vec<_> = orders
+                .iter()
+                .map(|order| manager.check_order_risk(order).approved)
+                .collect();
+            assert_eq!(approved, [true, false, false, true]);
+            assert_eq!(manager.current_exposure, dec!(678.9112));
+        }
+
+        // Off-grid orders cannot be checked in fixed point
+        let result = fixed.check_order_risk(&order(dec!(0.01), Some(dec!(1.005))));
+        assert!(!result.approved);
+        assert!(result.reason.unwrap().contains("tick size"));
+    }
+
+    #[test]
+    fn test_large_notionals_are_checked_exactly() {
+        let limits = RiskLimits {
+            max_notional_exposure: dec!(1_000_000_000_000),
+            max_order_size: dec!(1_000_000),
+            ..RiskLimits::default()
+        };
+        let mut fixed = DefaultRiskManager::new(limits.clone())
+            .with_instrument_scale(
+                crate::data::spot_instrument("BTC", "USDT"),
+                InstrumentScale::new(dec!(0.01), dec!(0.00001)).unwrap(),
+            )
+            .unwrap();
+        let mut decimal = DefaultRiskManager::new(limits);
+        for manager in [&mut fixed, &mut decimal] {
+            // Fill the limit exactly, then go one tick-lot over it
+            assert!(
+                manager
+                    .check_order_risk(&order(dec!(500_000), Some(dec!(300_000))))
+                    .approved
+            );
+            assert!(
+                manager
+                    .check_order_risk(&order(dec!(1_000_000), Some(dec!(850_000))))
+                    .approved
+            );
+            assert!(
+                !manager
+                    .check_order_risk(&order(dec!(0.00001), Some(dec!(0.01))))
+                    .approved
+            );
+            assert_eq!(manager.current_exposure, dec!(1_000_000_000_000));
+        }
+    }
+
+    #[test]
+    fn test_scale_finer_than_decimal_is_rejected() {
+        let result = DefaultRiskManager::default().with_instrument_scale(
+            crate::data::spot_instrument("BTC", "USDT"),
+            InstrumentScale::new(dec!(0.000000000000001), dec!(0.000000000000001)).unwrap(),
+        );
+        assert!(matches!(result, Err(FixedPointError::ScaleTooFine { .. })));
+    }
+}
@@ -3,6 +3,7 @@
 //! This module provides performance tracking and metrics collection
 //! for the trading system.
 
+use crate::data::latency::{LatencyReport, StreamLatency};
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
@@ -29,6 +30,9 @@ pub struct PerformanceMetrics {
     pub sharpe_ratio: f64,
     /// Maximum drawdown
     pub max_drawdown: f64,
+    /// Market data feed latency per exchange stream
+    #[serde(default)]
+    pub feed_latency: Vec<StreamLatency>,
 }
 
 impl PerformanceMetrics {
@@ -45,6 +49,7 @@ impl PerformanceMetrics {
             pnl: 0.0,
             sharpe_ratio: 0.0,
             max_drawdown: 0.0,
+            feed_latency: Vec::new(),
         }
     }
     
@@ -83,6 +88,11 @@ impl PerformanceMetrics {
     pub fn update_pnl(&mut self, pnl_change: f64) {
         self.pnl += pnl_change;
     }
+    
+    /// Update market data feed latency from a monitor report
+    pub fn update_feed_latency(&mut self, report: &LatencyReport) {
+        self.feed_latency = report.streams.clone();
+    }
 }
 
 impl Default for PerformanceMetrics {
@@ -134,5 +144,11 @@ impl TradingSummary {
         println!("PnL: ${:.2}", self.metrics.pnl);
         println!("Sharpe Ratio: {:.2}", self.metrics.sharpe_ratio);
         println!("Max Drawdown: {:.2}%", self.metrics.max_drawdown);
+        for feed in &self.metrics.feed_latency {
+            println!(
+                "Feed Latency {:?} {}: p50 {} μs, p99 {} μs",
+                feed.exchange, feed.stream, feed.stats.p50_micros, feed.stats.p99_micros
+            );
+        }
     }
 }
\ No newline at end of file